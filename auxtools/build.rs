@@ -5,4 +5,33 @@ fn main() {
 		.file("src/raw_types/funcs.cpp")
 		.cpp(true)
 		.compile("auxtools-cpp");
+
+	#[cfg(feature = "c-api")]
+	generate_c_header();
+}
+
+// Only c_api.rs is read, so the header doesn't pick up our internal FFI.
+// Build scripts mustn't touch the source tree, so the header goes into OUT_DIR. The copy in include/ is committed
+// by hand, and we warn when it's fallen behind.
+#[cfg(feature = "c-api")]
+fn generate_c_header() {
+	let crate_dir = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+	let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+	let config = cbindgen::Config::from_root_or_default(&crate_dir);
+
+	let generated = out_dir.join("auxtools.h");
+	cbindgen::Builder::new()
+		.with_src(crate_dir.join("src/c_api.rs"))
+		.with_config(config)
+		.generate()
+		.expect("Couldn't generate auxtools.h")
+		.write_to_file(&generated);
+
+	let committed = crate_dir.join("include/auxtools.h");
+	if std::fs::read(&generated).ok() != std::fs::read(&committed).ok() {
+		println!(
+			"cargo:warning=include/auxtools.h is out of date, copy {} over it",
+			generated.display()
+		);
+	}
 }
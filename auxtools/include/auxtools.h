@@ -0,0 +1,62 @@
+#ifndef AUXTOOLS_H
+#define AUXTOOLS_H
+
+/* Generated from src/c_api.rs by cbindgen. Don't edit this by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * A BYOND value, as the two halves BYOND stores it as.
+ */
+typedef struct AuxtoolsValue {
+  unsigned int tag;
+  unsigned int data;
+} AuxtoolsValue;
+
+/**
+ * Reads the number variable `name` of a value into `out_float`.
+ *
+ * # Safety
+ * `name` must be a NUL-terminated string and `out_float` must be writable.
+ */
+bool auxtools_value_get_float(unsigned int tag,
+                              unsigned int data,
+                              const char *name,
+                              float *out_float);
+
+/**
+ * Sets the variable `name` of a value to the string `string`.
+ *
+ * # Safety
+ * `name` and `string` must be NUL-terminated strings.
+ */
+bool auxtools_value_set_string(unsigned int tag,
+                               unsigned int data,
+                               const char *name,
+                               const char *string);
+
+/**
+ * Calls the global proc at `path` with `argc` arguments from `argv`.
+ *
+ * On success `out_ret` holds a reference to the return value, which has to be given back with [auxtools_value_release].
+ *
+ * # Safety
+ * `path` must be a NUL-terminated string, `argv` must point to `argc` values and `out_ret` must be writable.
+ */
+bool auxtools_proc_call(const char *path,
+                        unsigned int argc,
+                        const struct AuxtoolsValue *argv,
+                        struct AuxtoolsValue *out_ret);
+
+/**
+ * Gives back a reference handed out by another function, like the return value of [auxtools_proc_call].
+ *
+ * # Safety
+ * The value must be a reference the caller owns, and can't be used afterwards.
+ */
+void auxtools_value_release(struct AuxtoolsValue value);
+
+#endif /* AUXTOOLS_H */
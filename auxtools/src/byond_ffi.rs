@@ -66,15 +66,89 @@ pub fn byond_return(value: Option<Vec<u8>>) -> *const c_char {
 	}
 }
 
+/// Converts one of the arguments DM passed to a [byond_ffi_fn](crate::byond_ffi_fn) into a typed argument.
+///
+/// DM's `call()` passes every argument as its own string, with numbers in their text form
+/// (which may use an exponent, like `1e+006`) and `TRUE`/`FALSE` as `1`/`0`.
+pub trait FromFfiArg<'a>: Sized {
+	fn from_ffi_arg(arg: &'a str) -> Result<Self, String>;
+}
+
+impl<'a> FromFfiArg<'a> for &'a str {
+	fn from_ffi_arg(arg: &'a str) -> Result<Self, String> {
+		Ok(arg)
+	}
+}
+
+impl<'a> FromFfiArg<'a> for String {
+	fn from_ffi_arg(arg: &'a str) -> Result<Self, String> {
+		Ok(arg.to_owned())
+	}
+}
+
+impl<'a> FromFfiArg<'a> for f32 {
+	fn from_ffi_arg(arg: &'a str) -> Result<Self, String> {
+		arg.trim()
+			.parse()
+			.map_err(|_| format!("expected a number, got {:?}", arg))
+	}
+}
+
+// Null arrives as an empty string
+impl<'a> FromFfiArg<'a> for bool {
+	fn from_ffi_arg(arg: &'a str) -> Result<Self, String> {
+		match arg.trim() {
+			"" | "0" => Ok(false),
+			"1" => Ok(true),
+			_ => Err(format!("expected 1 or 0, got {:?}", arg)),
+		}
+	}
+}
+
+macro_rules! impl_from_ffi_arg_int {
+	($($ty:ty),*) => {
+		$(
+			impl<'a> FromFfiArg<'a> for $ty {
+				fn from_ffi_arg(arg: &'a str) -> Result<Self, String> {
+					let num: f64 = arg
+						.trim()
+						.parse()
+						.map_err(|_| format!("expected a number, got {:?}", arg))?;
+
+					if num.fract() != 0.0 || num < <$ty>::MIN as f64 || num > <$ty>::MAX as f64 {
+						return Err(format!("expected a whole number that fits in {}, got {:?}", stringify!($ty), arg));
+					}
+
+					Ok(num as $ty)
+				}
+			}
+		)*
+	};
+}
+
+impl_from_ffi_arg_int!(i32, u32, u16, u8);
+
 /// Creates a normal byond ffi function that can be called in DM with [call](http://www.byond.com/docs/ref/#/proc/call).
 ///
 /// You should favour [hooks](attr.hook.html) over these when working with auxtools.
 ///
+/// Arguments can be given types, in which case the function also needs a return type that converts into `Vec<u8>`.
+/// Each argument is converted with [FromFfiArg], and using a type that doesn't implement it won't compile.
+/// If DM passes the wrong number of arguments, or one of them can't be converted, the function returns
+/// `FAILED (...)` without running its body.
+///
 /// # Examples
 /// ```ignore
 /// // byond_ffi_fn! { my_proc(_input) {
 /// //     Some("Hello, BYOND!".to_owned())
 /// // }
+///
+/// // byond_ffi_fn! { repeat(count: u32, text: &str) -> String {
+/// //     text.repeat(count as usize)
+/// // }
+/// ```
+/// ```dm
+/// world.log << call("my_library.dll", "repeat")(3, "meow")
 /// ```
 #[macro_export]
 macro_rules! byond_ffi_fn {
@@ -89,6 +163,41 @@ macro_rules! byond_ffi_fn {
         }
     };
 
+    ($name:ident($($arg:ident : $ty:ty),*) -> $ret:ty $body:block) => {
+        #[no_mangle]
+        #[allow(clippy::missing_safety_doc)]
+        extern "C" fn $name(
+            _argc: ::std::os::raw::c_int, _argv: *const *const ::std::os::raw::c_char
+        ) -> *const ::std::os::raw::c_char {
+            let __args;
+            unsafe {
+                __args = $crate::byond_ffi::parse_args(_argc, _argv);
+            }
+
+            let __names: &[&str] = &[$(stringify!($arg)),*];
+            let __expected = __names.len();
+            if __args.len() != __expected {
+                let __error = format!("FAILED (expected {} arguments, got {})", __expected, __args.len());
+                return $crate::byond_ffi::byond_return(Some(__error.into()));
+            }
+
+            #[allow(unused_mut, unused_variables)]
+            let mut __args_iter = __args.iter();
+            $(
+                let $arg: $ty = match $crate::byond_ffi::FromFfiArg::from_ffi_arg(&**__args_iter.next().unwrap()) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        let __error = format!("FAILED (argument {}: {})", stringify!($arg), e);
+                        return $crate::byond_ffi::byond_return(Some(__error.into()));
+                    }
+                };
+            )*
+
+            let closure = || -> $ret { $body };
+            $crate::byond_ffi::byond_return(Some(closure().into()))
+        }
+    };
+
     ($name:ident($($arg:ident),* $(, ...$rest:ident)?) $body:block) => {
         #[no_mangle]
         #[allow(clippy::missing_safety_doc)]
@@ -69,6 +69,8 @@ pub fn shutdown() {
 }
 
 pub fn set_bytecode(proc: &Proc, mut bytecode: Vec<u32>) {
+	crate::proc::forget_callers();
+
 	let state = unsafe {
 		let ptr = BYTECODE_ALLOCATIONS.get();
 		(*ptr).as_mut().unwrap()
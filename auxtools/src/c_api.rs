@@ -0,0 +1,224 @@
+//! A C interface for DLLs that aren't written in Rust, enabled with the `c-api` feature.
+//!
+//! The declarations are in `include/auxtools.h`. Building with the feature generates them into `OUT_DIR` and warns
+//! if the committed header no longer matches.
+//!
+//! Everything in here must be called from BYOND's game thread, after `auxtools_init` has succeeded.
+//! Calls from anywhere else abort the process.
+//!
+//! Values are passed as the `tag` and `data` halves of a BYOND value. Functions return `true` on success.
+
+use crate::raw_types;
+use crate::{game_thread, Proc, StringRef, Value};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_float, c_uint};
+
+/// A BYOND value, as the two halves BYOND stores it as.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct AuxtoolsValue {
+	pub tag: c_uint,
+	pub data: c_uint,
+}
+
+// Unwinding out of these would be undefined behaviour, so abort with a message instead
+fn assert_game_thread(function: &str) {
+	if !game_thread::is_game_thread() {
+		eprintln!(
+			"auxtools: {} was called from {:?}, which isn't the game thread",
+			function,
+			std::thread::current().id()
+		);
+		std::process::abort();
+	}
+}
+
+fn value_tag(tag: c_uint) -> Option<raw_types::values::ValueTag> {
+	use raw_types::values::ValueTag;
+
+	let tags = [
+		ValueTag::Null,
+		ValueTag::Turf,
+		ValueTag::Obj,
+		ValueTag::Mob,
+		ValueTag::Area,
+		ValueTag::Client,
+		ValueTag::String,
+		ValueTag::MobTypepath,
+		ValueTag::ObjTypepath,
+		ValueTag::TurfTypepath,
+		ValueTag::AreaTypepath,
+		ValueTag::Resource,
+		ValueTag::Image,
+		ValueTag::World,
+		ValueTag::List,
+		ValueTag::MobVars,
+		ValueTag::ObjVars,
+		ValueTag::TurfVars,
+		ValueTag::AreaVars,
+		ValueTag::ClientVars,
+		ValueTag::Vars,
+		ValueTag::MobOverlays,
+		ValueTag::MobUnderlays,
+		ValueTag::ObjOverlays,
+		ValueTag::ObjUnderlays,
+		ValueTag::TurfOverlays,
+		ValueTag::TurfUnderlays,
+		ValueTag::AreaOverlays,
+		ValueTag::AreaUnderlays,
+		ValueTag::ImageVars,
+		ValueTag::WorldVars,
+		ValueTag::GlobalVars,
+		ValueTag::Datum,
+		ValueTag::SaveFile,
+		ValueTag::Number,
+		ValueTag::Appearance,
+		ValueTag::ProcId,
+	];
+
+	tags.iter().copied().find(|known| *known as c_uint == tag)
+}
+
+// Borrows the value, so the caller's reference is left alone
+fn to_value(tag: c_uint, data: c_uint) -> Option<Value> {
+	let tag = value_tag(tag)?;
+
+	unsafe {
+		Some(Value::from_raw(raw_types::values::Value {
+			tag,
+			data: raw_types::values::ValueData { id: data },
+		}))
+	}
+}
+
+// Hands our reference over to the caller
+fn from_value(value: Value) -> AuxtoolsValue {
+	let raw = value.raw;
+	std::mem::forget(value);
+
+	AuxtoolsValue {
+		tag: raw.tag as c_uint,
+		data: unsafe { raw.data.id },
+	}
+}
+
+unsafe fn to_string_ref(string: *const c_char) -> Option<StringRef> {
+	if string.is_null() {
+		return None;
+	}
+
+	StringRef::from_raw(CStr::from_ptr(string).to_bytes()).ok()
+}
+
+/// Reads the number variable `name` of a value into `out_float`.
+///
+/// # Safety
+/// `name` must be a NUL-terminated string and `out_float` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn auxtools_value_get_float(
+	tag: c_uint,
+	data: c_uint,
+	name: *const c_char,
+	out_float: *mut c_float,
+) -> bool {
+	assert_game_thread("auxtools_value_get_float");
+
+	let (value, name) = match (to_value(tag, data), to_string_ref(name)) {
+		(Some(value), Some(name)) => (value, name),
+		_ => return false,
+	};
+
+	match value.get_number(name) {
+		Ok(number) if !out_float.is_null() => {
+			*out_float = number;
+			true
+		}
+		_ => false,
+	}
+}
+
+/// Sets the variable `name` of a value to the string `string`.
+///
+/// # Safety
+/// `name` and `string` must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn auxtools_value_set_string(
+	tag: c_uint,
+	data: c_uint,
+	name: *const c_char,
+	string: *const c_char,
+) -> bool {
+	assert_game_thread("auxtools_value_set_string");
+
+	match (
+		to_value(tag, data),
+		to_string_ref(name),
+		to_string_ref(string),
+	) {
+		(Some(value), Some(name), Some(string)) => value.set(name, string).is_ok(),
+		_ => false,
+	}
+}
+
+/// Calls the global proc at `path` with `argc` arguments from `argv`.
+///
+/// On success `out_ret` holds a reference to the return value, which has to be given back with [auxtools_value_release].
+///
+/// # Safety
+/// `path` must be a NUL-terminated string, `argv` must point to `argc` values and `out_ret` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn auxtools_proc_call(
+	path: *const c_char,
+	argc: c_uint,
+	argv: *const AuxtoolsValue,
+	out_ret: *mut AuxtoolsValue,
+) -> bool {
+	assert_game_thread("auxtools_proc_call");
+
+	if path.is_null() || out_ret.is_null() || (argc > 0 && argv.is_null()) {
+		return false;
+	}
+
+	let proc = match Proc::find(CStr::from_ptr(path).to_string_lossy()) {
+		Some(proc) => proc,
+		None => return false,
+	};
+
+	let args: Option<Vec<Value>> = if argc == 0 {
+		Some(vec![])
+	} else {
+		std::slice::from_raw_parts(argv, argc as usize)
+			.iter()
+			.map(|arg| to_value(arg.tag, arg.data))
+			.collect()
+	};
+
+	let args = match args {
+		Some(args) => args,
+		None => return false,
+	};
+
+	match proc.call(&args.iter().collect::<Vec<_>>()) {
+		Ok(ret) => {
+			*out_ret = from_value(ret);
+			true
+		}
+		Err(_) => false,
+	}
+}
+
+/// Gives back a reference handed out by another function, like the return value of [auxtools_proc_call].
+///
+/// # Safety
+/// The value must be a reference the caller owns, and can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn auxtools_value_release(value: AuxtoolsValue) {
+	assert_game_thread("auxtools_value_release");
+
+	if let Some(tag) = value_tag(value.tag) {
+		drop(Value::from_raw_owned(raw_types::values::Value {
+			tag,
+			data: raw_types::values::ValueData { id: value.data },
+		}));
+	}
+}
@@ -1,4 +1,7 @@
-use crate::raw_types::{funcs, procs};
+use crate::game_thread::GameThreadGuard;
+use crate::raw_types::{funcs, procs, values};
+use crate::runtime;
+use crate::runtime::DMResult;
 use crate::Proc;
 use crate::StringRef;
 use crate::Value;
@@ -15,7 +18,8 @@ pub struct StackFrame {
 	pub locals: Vec<(StringRef, Value)>,
 	pub file_name: Option<StringRef>,
 	pub line_number: Option<u32>,
-	// pub time_to_resume: Option<u32>,
+	/// The `world.time` a sleeping proc wakes up at. Only set on the frame of the proc that called `sleep()`.
+	pub time_to_resume: Option<f32>,
 	// TODO: current instruction & bytecode offset
 }
 
@@ -24,6 +28,15 @@ pub struct CallStacks {
 	pub suspended: Vec<Vec<StackFrame>>,
 }
 
+/// A stack waiting in BYOND's sleep queue, see [CallStacks::all_sleeping].
+pub struct SleepingStack {
+	/// Path of the proc that called `sleep()`
+	pub proc: String,
+	/// The `world.time` it wakes up at
+	pub wake_time: f32,
+	pub frames: Vec<StackFrame>,
+}
+
 impl StackFrame {
 	unsafe fn from_context(context: *mut procs::ExecutionContext) -> StackFrame {
 		let instance = (*context).proc_instance;
@@ -65,9 +78,6 @@ impl StackFrame {
 			line_number = Some((*context).line);
 		}
 
-		// TODO: When set this? For all sleepers?
-		// let time_to_resume = None;
-
 		StackFrame {
 			context,
 			instance,
@@ -80,7 +90,126 @@ impl StackFrame {
 			locals,
 			file_name,
 			line_number,
-			// time_to_resume,
+			time_to_resume: None,
+		}
+	}
+
+	/// Overwrites one of the proc's arguments, both in the running proc and in this frame.
+	pub fn set_arg(&mut self, index: usize, value: Value) -> DMResult<()> {
+		unsafe {
+			if index >= (*self.instance).args_count as usize {
+				return Err(runtime!("argument index {} out of range", index));
+			}
+
+			Self::replace_raw((*self.instance).args.add(index), &value);
+		}
+
+		self.args[index].1 = value;
+		Ok(())
+	}
+
+	/// Overwrites one of the proc's local variables, both in the running proc and in this frame.
+	pub fn set_local(&mut self, index: usize, value: Value) -> DMResult<()> {
+		unsafe {
+			if index >= (*self.context).locals_count as usize {
+				return Err(runtime!("local index {} out of range", index));
+			}
+
+			Self::replace_raw((*self.context).locals.add(index), &value);
+		}
+
+		self.locals[index].1 = value;
+		Ok(())
+	}
+
+	/// Overwrites the proc's `.` variable, both in the running proc and in this frame.
+	pub fn set_dot(&mut self, value: Value) {
+		unsafe {
+			Self::replace_raw(&mut (*self.context).dot, &value);
+		}
+
+		self.dot = value;
+	}
+
+	// The slot owns a reference to whatever it holds
+	unsafe fn replace_raw(slot: *mut values::Value, value: &Value) {
+		let old = *slot;
+		funcs::inc_ref_count(value.raw);
+		*slot = value.raw;
+		funcs::dec_ref_count(old);
+	}
+}
+
+/// A read-only view of the proc BYOND is executing, handed to code that runs in the middle of it,
+/// like instruction hooks.
+///
+/// Unlike [StackFrame], nothing is read until it's asked for.
+pub struct SafeExecutionContext {
+	context: *mut procs::ExecutionContext,
+	proc: Proc,
+	_guard: GameThreadGuard,
+}
+
+impl SafeExecutionContext {
+	/// Returns `None` if `context` is null, or if this isn't the game thread.
+	///
+	/// # Safety
+	/// `context` must point to an execution context that stays alive for as long as the wrapper.
+	pub unsafe fn new(context: *mut procs::ExecutionContext) -> Option<Self> {
+		let guard = GameThreadGuard::try_acquire()?;
+
+		if context.is_null() {
+			return None;
+		}
+
+		Some(Self {
+			context,
+			proc: Proc::from_id((*(*context).proc_instance).proc)?,
+			_guard: guard,
+		})
+	}
+
+	pub fn proc(&self) -> &Proc {
+		&self.proc
+	}
+
+	/// Offset of the instruction about to be executed.
+	pub fn offset(&self) -> u32 {
+		unsafe { (*self.context).bytecode_offset as u32 }
+	}
+
+	pub fn src(&self) -> Value {
+		unsafe { Value::from_raw((*(*self.context).proc_instance).src) }
+	}
+
+	pub fn usr(&self) -> Value {
+		unsafe { Value::from_raw((*(*self.context).proc_instance).usr) }
+	}
+
+	pub fn dot(&self) -> Value {
+		unsafe { Value::from_raw((*self.context).dot) }
+	}
+
+	/// The proc's local variables by name. Any without a name get an empty one.
+	pub fn locals(&self) -> Vec<(String, Value)> {
+		let names = self.proc.local_names();
+
+		unsafe {
+			(0..(*self.context).locals_count as usize)
+				.map(|i| {
+					let name = names.get(i).map(String::from).unwrap_or_default();
+					(name, Value::from_raw(*(*self.context).locals.add(i)))
+				})
+				.collect()
+		}
+	}
+
+	/// The values on the proc's stack, from the bottom up.
+	pub fn vm_stack(&self) -> Vec<Value> {
+		unsafe {
+			(0..(*self.context).stack_size as usize)
+				.map(|i| Value::from_raw(*(*self.context).stack.add(i)))
+				.collect()
 		}
 	}
 }
@@ -92,27 +221,63 @@ enum CallStackKind {
 
 impl CallStacks {
 	pub fn new() -> CallStacks {
-		let mut suspended = vec![];
+		let guard = GameThreadGuard::acquire();
+
+		let suspended = CallStacks::sleeping_instances()
+			.into_iter()
+			.map(CallStacks::from_sleeping_instance)
+			.collect();
+
+		CallStacks {
+			active: CallStacks::from_context(
+				guard.current_execution_context(),
+				CallStackKind::Active,
+			),
+			suspended,
+		}
+	}
+
+	/// Every stack in BYOND's sleep queue, along with when it wakes up. These are the same stacks as
+	/// [CallStacks::suspended], in the same order.
+	pub fn all_sleeping() -> Vec<SleepingStack> {
+		let _guard = GameThreadGuard::acquire();
+
+		CallStacks::sleeping_instances()
+			.into_iter()
+			.map(|instance| unsafe {
+				let proc = Proc::from_id((*instance).proc).unwrap();
 
+				SleepingStack {
+					proc: proc.path,
+					wake_time: (*instance).time_to_resume,
+					frames: CallStacks::from_sleeping_instance(instance),
+				}
+			})
+			.collect()
+	}
+
+	fn sleeping_instances() -> Vec<*mut procs::ProcInstance> {
 		unsafe {
 			let buffer = (*funcs::SUSPENDED_PROCS_BUFFER).buffer;
 			let procs = funcs::SUSPENDED_PROCS;
 			let front = (*procs).front;
 			let back = (*procs).back;
 
-			for x in front..back {
-				let instance = *buffer.add(x);
-				let context = (*instance).context;
-				suspended.push(CallStacks::from_context(context, CallStackKind::Suspended));
-			}
+			(front..back).map(|x| *buffer.add(x)).collect()
 		}
+	}
 
-		CallStacks {
-			active: unsafe {
-				CallStacks::from_context(*funcs::CURRENT_EXECUTION_CONTEXT, CallStackKind::Active)
-			},
-			suspended,
+	fn from_sleeping_instance(instance: *mut procs::ProcInstance) -> Vec<StackFrame> {
+		let mut frames =
+			CallStacks::from_context(unsafe { (*instance).context }, CallStackKind::Suspended);
+
+		for frame in &mut frames {
+			if frame.instance == instance {
+				frame.time_to_resume = Some(unsafe { (*instance).time_to_resume });
+			}
 		}
+
+		frames
 	}
 
 	fn from_context(
@@ -139,3 +304,76 @@ impl CallStacks {
 		}
 	}
 }
+
+/// Reference counts of some values at one point in time, see [ref_snapshot].
+pub struct RefSnapshot {
+	entries: Vec<(Value, Option<u32>)>,
+}
+
+/// A value whose reference count changed since a [RefSnapshot] was taken.
+pub struct RefChange<'a> {
+	pub value: &'a Value,
+	pub before: u32,
+	pub after: u32,
+}
+
+/// Records the reference counts of `values`, so a test can check that a hook didn't leak or over-release them.
+///
+/// The snapshot holds its own reference to each value, which keeps them alive but doesn't affect comparisons.
+/// Values without a readable count are ignored, which currently includes every datum and, outside Windows,
+/// every list. See [Value::refcount] for what's supported.
+///
+/// # Examples:
+/// ```ignore
+/// let snapshot = debug::ref_snapshot(&[&list, &name]);
+/// my_hook(&list, &name)?;
+/// snapshot.check()?;
+/// ```
+pub fn ref_snapshot(values: &[&Value]) -> RefSnapshot {
+	let entries = values
+		.iter()
+		.map(|value| {
+			let value = (*value).clone();
+			let count = value.refcount();
+			(value, count)
+		})
+		.collect();
+
+	RefSnapshot { entries }
+}
+
+impl RefSnapshot {
+	/// Every value whose reference count is different now.
+	pub fn changes(&self) -> Vec<RefChange<'_>> {
+		self.entries
+			.iter()
+			.filter_map(|(value, before)| {
+				let before = (*before)?;
+				let after = value.refcount()?;
+
+				if before == after {
+					return None;
+				}
+
+				Some(RefChange {
+					value,
+					before,
+					after,
+				})
+			})
+			.collect()
+	}
+
+	/// Fails with a runtime describing the first value whose reference count changed.
+	pub fn check(&self) -> DMResult<()> {
+		match self.changes().first() {
+			Some(change) => Err(runtime!(
+				"reference count of {:?} changed from {} to {}",
+				change.value,
+				change.before,
+				change.after
+			)),
+			None => Ok(()),
+		}
+	}
+}
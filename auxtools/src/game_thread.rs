@@ -0,0 +1,117 @@
+//! Most of BYOND's internals may only be touched from the thread the game runs on.
+
+use crate::raw_types;
+use crate::Proc;
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+use std::thread::{self, ThreadId};
+
+lazy_static::lazy_static! {
+	// BYOND always calls auxtools_init from the game thread
+	static ref GAME_THREAD: RwLock<Option<ThreadId>> = RwLock::new(None);
+}
+
+// Bumped whenever GAME_THREAD changes, so threads know to check it again
+static GAME_THREAD_GENERATION: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+	// The generation this thread last checked GAME_THREAD in, and what it found. Every runtime!() asks, so this
+	// keeps the lock out of it.
+	static CHECKED: Cell<(usize, bool)> = Cell::new((0, false));
+}
+
+pub(crate) fn set_game_thread() {
+	*GAME_THREAD.write().unwrap() = Some(thread::current().id());
+	GAME_THREAD_GENERATION.fetch_add(1, Ordering::AcqRel);
+}
+
+/// Returns true if called from BYOND's game thread, once `auxtools_init` has run.
+pub fn is_game_thread() -> bool {
+	let generation = GAME_THREAD_GENERATION.load(Ordering::Acquire);
+
+	CHECKED.with(|checked| match checked.get() {
+		(checked_generation, result) if checked_generation == generation => result,
+		_ => {
+			let result = *GAME_THREAD.read().unwrap() == Some(thread::current().id());
+			checked.set((generation, result));
+			result
+		}
+	})
+}
+
+/// Proof that the current thread is BYOND's game thread.
+///
+/// The guard can't be sent to another thread, so code holding one knows it is safe to read BYOND's global state.
+pub struct GameThreadGuard {
+	_not_send: PhantomData<*mut ()>,
+}
+
+impl GameThreadGuard {
+	/// # Panics
+	/// If called from any other thread, or before `auxtools_init`.
+	pub fn acquire() -> Self {
+		match Self::try_acquire() {
+			Some(guard) => guard,
+			None => panic!(
+				"BYOND's state was accessed from {:?}, which isn't the game thread",
+				thread::current().id()
+			),
+		}
+	}
+
+	/// Like [acquire](GameThreadGuard::acquire), but returns `None` instead of panicking.
+	pub fn try_acquire() -> Option<Self> {
+		if !is_game_thread() {
+			return None;
+		}
+
+		Some(GameThreadGuard {
+			_not_send: PhantomData,
+		})
+	}
+
+	/// The context of the proc BYOND is currently executing, or null if there isn't one.
+	pub fn current_execution_context(&self) -> *mut raw_types::procs::ExecutionContext {
+		unsafe {
+			if raw_types::funcs::CURRENT_EXECUTION_CONTEXT.is_null() {
+				return std::ptr::null_mut();
+			}
+
+			*raw_types::funcs::CURRENT_EXECUTION_CONTEXT
+		}
+	}
+
+	/// The proc BYOND is currently executing. Hooks are never on BYOND's stack, so inside a hook that's the proc
+	/// that called the hooked proc.
+	pub fn current_proc(&self) -> Option<Proc> {
+		let ctx = self.current_execution_context();
+
+		unsafe {
+			if ctx.is_null() || (*ctx).proc_instance.is_null() {
+				return None;
+			}
+
+			Proc::from_id((*(*ctx).proc_instance).proc)
+		}
+	}
+
+	/// The line the current proc is on, as of the last `DbgLine` instruction it executed.
+	///
+	/// `None` if nothing is executing or the proc was compiled without debug info.
+	pub fn current_line(&self) -> Option<u32> {
+		let ctx = self.current_execution_context();
+		if ctx.is_null() {
+			return None;
+		}
+
+		unsafe {
+			if !(*ctx).filename.valid() {
+				return None;
+			}
+
+			Some((*ctx).line)
+		}
+	}
+}
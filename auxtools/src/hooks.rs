@@ -1,13 +1,21 @@
 use super::proc::Proc;
 use super::raw_types;
 use super::value::Value;
+use crate::runtime;
 use crate::runtime::DMResult;
 use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use detour::RawDetour;
 use std::ffi::c_void;
 use std::os::raw::c_char;
-use std::{cell::RefCell, ffi::CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::{
+	cell::{Cell, RefCell},
+	collections::HashMap,
+	ffi::CStr,
+};
 
 #[doc(hidden)]
 pub struct CompileTimeHook {
@@ -82,46 +90,184 @@ impl std::fmt::Debug for HookFailure {
 	}
 }
 
-pub fn init() -> Result<(), String> {
+/// Why [init] couldn't install its detours.
+#[derive(Debug)]
+pub enum HookInitError {
+	/// A detour couldn't be created or enabled
+	DetourFailed {
+		/// The BYOND function being detoured
+		name: &'static str,
+		/// Where the function's native code starts
+		address: usize,
+		/// Whether the function already started with a jump, which usually means something else hooked it first,
+		/// like another auxtools library loaded into the same server
+		occupied: bool,
+		/// What went wrong, as reported by the detour crate
+		reason: String,
+	},
+	/// The BYOND function with this name wasn't found when scanning for signatures
+	SignatureMissing(&'static str),
+	/// The detours are already installed. [shutdown] has to run before initializing again.
+	AlreadyInitialized,
+}
+
+impl std::fmt::Display for HookInitError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::DetourFailed {
+				name,
+				address,
+				occupied,
+				reason,
+			} => {
+				write!(f, "Couldn't detour {} at {:#x}: {}", name, address, reason)?;
+				if *occupied {
+					write!(
+						f,
+						" (it's already hooked by something else, like another auxtools library)"
+					)?;
+				}
+				Ok(())
+			}
+			Self::SignatureMissing(name) => write!(f, "Couldn't find {}", name),
+			Self::AlreadyInitialized => write!(f, "Proc hooking is already initialized"),
+		}
+	}
+}
+
+impl std::error::Error for HookInitError {}
+
+// Detours `target` to `hook`, returning the trampoline to the original code
+unsafe fn install_detour(
+	name: &'static str,
+	target: *const c_void,
+	hook: *const (),
+) -> Result<*const c_void, HookInitError> {
+	if target.is_null() {
+		return Err(HookInitError::SignatureMissing(name));
+	}
+
+	// Checked up front, before we've had a go at the code ourselves
+	let occupied = starts_with_jump(std::slice::from_raw_parts(target as *const u8, 6));
+	let failed = |e: detour::Error| HookInitError::DetourFailed {
+		name,
+		address: target as usize,
+		occupied,
+		reason: e.to_string(),
+	};
+
+	let detour = RawDetour::new(target as *const (), hook).map_err(failed)?;
+	detour.enable().map_err(failed)?;
+
+	let trampoline = detour.trampoline() as *const () as *const c_void;
+	keep_detour(detour);
+	Ok(trampoline)
+}
+
+// Whether machine code starts by jumping elsewhere, which is how detours (ours included) hook a function
+fn starts_with_jump(code: &[u8]) -> bool {
+	matches!(code, [0xE9, ..] | [0xEB, ..] | [0xFF, 0x25, ..])
+}
+
+pub fn init() -> Result<(), HookInitError> {
+	if unsafe { !call_proc_by_id_original.is_null() } {
+		return Err(HookInitError::AlreadyInitialized);
+	}
+
+	let result = install_detours();
+
+	// Don't leave half of them behind
+	if result.is_err() {
+		shutdown();
+	}
+
+	result
+}
+
+fn install_detours() -> Result<(), HookInitError> {
 	unsafe {
-		let runtime_hook = RawDetour::new(
-			raw_types::funcs::runtime_byond as *const (),
+		runtime_original = install_detour(
+			"runtime",
+			raw_types::funcs::runtime_byond as *const c_void,
 			runtime_hook as *const (),
-		)
-		.unwrap();
+		)?;
+
+		call_proc_by_id_original = install_detour(
+			"call_proc_by_id",
+			raw_types::funcs::call_proc_by_id_byond as *const c_void,
+			call_proc_by_id_hook_trampoline as *const (),
+		)?;
 
-		runtime_hook.enable().unwrap();
-		runtime_original = std::mem::transmute(runtime_hook.trampoline());
-		std::mem::forget(runtime_hook);
+		#[cfg(unix)]
 		{
-			let call_hook = RawDetour::new(
-				raw_types::funcs::call_proc_by_id_byond as *const (),
-				call_proc_by_id_hook_trampoline as *const (),
-			)
-				.unwrap();
+			call_proc_by_id_original2 = install_detour(
+				"call_proc_by_id2",
+				raw_types::funcs::call_proc_by_id2_byond as *const c_void,
+				call_proc_by_id_hook_trampoline2 as *const (),
+			)?;
+		}
+	}
+	Ok(())
+}
 
-			call_hook.enable().unwrap();
-			call_proc_by_id_original = std::mem::transmute(call_hook.trampoline());
-			std::mem::forget(call_hook);
+/// Undoes [init], restoring BYOND's original code. Proc hooks and the interceptor are removed as well.
+///
+/// The detours point into this library, so they must be gone before it is unloaded.
+pub fn shutdown() {
+	DETOURS.with(|detours| {
+		for detour in detours.borrow_mut().drain(..) {
+			unsafe {
+				if let Err(e) = detour.disable() {
+					eprintln!("auxtools couldn't remove a detour: {}", e);
+				}
+			}
 		}
+	});
+
+	unsafe {
+		runtime_original = std::ptr::null();
+		call_proc_by_id_original = std::ptr::null();
 
 		#[cfg(unix)]
 		{
-			let call_hook = RawDetour::new(
-				raw_types::funcs::call_proc_by_id2_byond as *const (),
-				call_proc_by_id_hook_trampoline2 as *const (),
-			)
-				.unwrap();
-
-			call_hook.enable().unwrap();
-			call_proc_by_id_original2 = std::mem::transmute(call_hook.trampoline());
-			std::mem::forget(call_hook);
+			call_proc_by_id_original2 = std::ptr::null();
 		}
 	}
-	Ok(())
+
+	clear_hooks();
+	catch_panics(false);
+}
+
+fn keep_detour(detour: RawDetour) {
+	DETOURS.with(|detours| detours.borrow_mut().push(detour));
 }
 
 pub type ProcHook = fn(&Value, &Value, &mut Vec<Value>) -> DMResult;
+
+/// Defines a proc hook and hooks it upon library initialization, like [hook](attr.hook.html) with a path.
+///
+/// The function is written out with the full [ProcHook] signature, and fails to compile if it doesn't match.
+///
+/// # Examples
+/// ```ignore
+/// proc! { hook "/mob/proc/attack" fn attack_hook(src: &Value, usr: &Value, args: &mut Vec<Value>) -> DMResult {
+///     src.call("gib", &[])?;
+///     Ok(Value::null())
+/// } }
+/// ```
+#[macro_export]
+macro_rules! proc {
+	(hook $path:literal fn $name:ident($($arg:ident : $ty:ty),* $(,)?) -> $ret:ty $body:block) => {
+		fn $name($($arg: $ty),*) -> $ret $body
+
+		const _: $crate::hooks::ProcHook = $name;
+
+		$crate::inventory::submit!(
+			#![crate = $crate]
+			$crate::CompileTimeHook::new($path, $name)
+		);
+	};
+}
 pub type CallProcByIdInterceptor = fn(
 	ret: *mut raw_types::values::Value,
 	usr_raw: raw_types::values::Value,
@@ -138,6 +284,106 @@ pub type CallProcByIdInterceptor = fn(
 thread_local! {
 	static PROC_HOOKS: RefCell<DashMap<raw_types::procs::ProcId, ProcHook>> = RefCell::new(DashMap::new());
 	static INTERCEPTOR: RefCell<Option<CallProcByIdInterceptor>> = RefCell::new(Option::None);
+
+	// Installed by init and removed by shutdown
+	static DETOURS: RefCell<Vec<RawDetour>> = RefCell::new(Vec::new());
+
+	// How many hooks are running with their panics being caught
+	static CATCHING_DEPTH: Cell<u32> = Cell::new(0);
+
+	// The procs whose hooks are running, innermost last
+	static RUNNING_HOOKS: RefCell<Vec<raw_types::procs::ProcId>> = RefCell::new(Vec::new());
+
+	// The next call of this proc skips its hook and observers, so call_original reaches the proc's own code
+	static BYPASS_HOOK: Cell<Option<raw_types::procs::ProcId>> = Cell::new(None);
+
+	static OBSERVERS: RefCell<HashMap<raw_types::procs::ProcId, Vec<Observer>>> = RefCell::new(HashMap::new());
+	static NEXT_OBSERVER_ID: Cell<u64> = Cell::new(0);
+
+	static PATTERN_HOOKS: RefCell<Vec<PatternHook>> = RefCell::new(Vec::new());
+}
+
+/// Called with a proc's `src`, `usr` and arguments before it runs, see [observe].
+pub type PreObserver = fn(&Value, &Value, &[Value]);
+
+/// Called with a proc's `src`, `usr`, arguments and return value after it runs, see [observe].
+pub type PostObserver = fn(&Value, &Value, &[Value], &Value);
+
+/// Identifies an observer added by [observe], for removing it with [unobserve].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ObserverId(u64);
+
+#[derive(Clone)]
+struct Observer {
+	id: ObserverId,
+	pre: Option<PreObserver>,
+	post: Option<PostObserver>,
+}
+
+// One call of hook_matching, and the procs it hooked
+struct PatternHook {
+	pattern: String,
+	hook: ProcHook,
+	watch: bool,
+	procs: Vec<raw_types::procs::ProcId>,
+}
+
+static CATCH_PANICS: AtomicBool = AtomicBool::new(false);
+static PANIC_HOOK: Once = Once::new();
+
+/// Makes panicking hooks throw a runtime instead of crashing the server. Off by default, and turned off again by [shutdown].
+///
+/// A caught panic turns into a runtime with the panic message, the hook returns null and the backtrace is written to stderr.
+/// Hooks that panic may leave whatever they were changing half done, so this is best used to keep a server up rather than as error handling.
+///
+/// Every hook call pays for an unwinding landing pad and a thread-local counter while this is on, which shows up for hooks called in tight loops.
+///
+/// Only unwinding panics are caught. Aborts, such as panics in libraries built with `panic = "abort"` or panics while unwinding, still take the process down.
+pub fn catch_panics(enable: bool) {
+	if enable {
+		PANIC_HOOK.call_once(|| {
+			let previous = panic::take_hook();
+			panic::set_hook(Box::new(move |info| {
+				if CATCHING_DEPTH.with(|depth| depth.get()) > 0 {
+					eprintln!(
+						"auxtools caught a panic in a hook: {}\n{}",
+						info,
+						std::backtrace::Backtrace::force_capture()
+					);
+				} else {
+					previous(info);
+				}
+			}));
+		});
+	}
+
+	CATCH_PANICS.store(enable, Ordering::Relaxed);
+}
+
+fn call_catching_panics(
+	proc_id: raw_types::procs::ProcId,
+	hook: ProcHook,
+	src: &Value,
+	usr: &Value,
+	args: &mut Vec<Value>,
+) -> DMResult {
+	CATCHING_DEPTH.with(|depth| depth.set(depth.get() + 1));
+	let result = panic::catch_unwind(AssertUnwindSafe(|| hook(src, usr, args)));
+	CATCHING_DEPTH.with(|depth| depth.set(depth.get() - 1));
+
+	result.unwrap_or_else(|payload| {
+		let message = if let Some(message) = payload.downcast_ref::<&str>() {
+			message
+		} else if let Some(message) = payload.downcast_ref::<String>() {
+			message.as_str()
+		} else {
+			"unknown panic"
+		};
+
+		let path =
+			Proc::from_id(proc_id).map_or_else(|| "unknown proc".to_owned(), |proc| proc.path);
+		Err(runtime!("hook for {} panicked: {}", path, message))
+	})
 }
 
 fn hook_by_id(id: raw_types::procs::ProcId, hook: ProcHook) -> Result<(), HookFailure> {
@@ -157,6 +403,8 @@ fn hook_by_id(id: raw_types::procs::ProcId, hook: ProcHook) -> Result<(), HookFa
 pub fn clear_hooks() {
 	PROC_HOOKS.with(|h| h.borrow().clear());
 	INTERCEPTOR.with(|h| h.replace(Option::None));
+	OBSERVERS.with(|o| o.borrow_mut().clear());
+	PATTERN_HOOKS.with(|p| p.borrow_mut().clear());
 }
 
 pub fn hook<S: Into<String>>(name: S, hook: ProcHook) -> Result<(), HookFailure> {
@@ -166,6 +414,343 @@ pub fn hook<S: Into<String>>(name: S, hook: ProcHook) -> Result<(), HookFailure>
 	}
 }
 
+/// Hooks every override of the proc at `path`, like each type's own `/mob/proc/Life`, and returns how many there were.
+///
+/// Nothing is hooked if any of them already is. Only the overrides that exist right now get hooked: a proc with this
+/// path that shows up later, like after a reboot into a recompiled .dmb, has to be hooked again.
+pub fn hook_all_overrides<S: Into<String>>(path: S, hook: ProcHook) -> Result<usize, HookFailure> {
+	let overrides = all_overrides(path.into());
+	if overrides.is_empty() {
+		return Err(HookFailure::ProcNotFound);
+	}
+
+	let already_hooked = PROC_HOOKS.with(|h| {
+		let map = h.borrow();
+		overrides.iter().any(|proc| map.contains_key(&proc.id))
+	});
+	if already_hooked {
+		return Err(HookFailure::AlreadyHooked);
+	}
+
+	for proc in &overrides {
+		hook_by_id(proc.id, hook)?;
+	}
+
+	Ok(overrides.len())
+}
+
+/// Removes the hooks from every override of the proc at `path`, returning how many were hooked.
+pub fn unhook_all_overrides<S: Into<String>>(path: S) -> usize {
+	let overrides = all_overrides(path.into());
+
+	PROC_HOOKS.with(|h| {
+		let map = h.borrow();
+		overrides
+			.iter()
+			.filter(|proc| map.remove(&proc.id).is_some())
+			.count()
+	})
+}
+
+// Override ids are handed out in order when procs are populated, so the first missing one is the end
+fn all_overrides(path: String) -> Vec<Proc> {
+	(0..)
+		.map(|id| Proc::find_override(path.clone(), id))
+		.take_while(Option::is_some)
+		.flatten()
+		.collect()
+}
+
+/// Hooks every proc whose path matches `pattern`, like `/obj/item/*/attack_self`, returning the paths it hooked.
+///
+/// In the pattern, `*` matches any number of characters, slashes included, and `?` matches any one character.
+/// Paths are compared without their `/proc/` and `/verb/` parts, like in [proc::procs_matching](crate::proc::procs_matching).
+///
+/// Procs hooked by something else are left alone and not returned, unless it was another pattern with the same
+/// hook, in which case the proc shares that hook instead of being hooked twice. With `watch`, procs that match and
+/// show up later, in a [proc::refresh](crate::proc::refresh), are hooked as well.
+pub fn hook_matching<S: Into<String>>(pattern: S, hook: ProcHook, watch: bool) -> Vec<String> {
+	let pattern = super::proc::strip_path(pattern.into());
+	let procs: Vec<Proc> = super::proc::known_procs()
+		.filter(|proc| glob_matches(pattern.as_bytes(), proc.path.as_bytes()))
+		.collect();
+
+	let hooked = claim_procs(hook, &procs);
+
+	let mut paths = vec![];
+	for proc in &hooked {
+		if !paths.contains(&proc.path) {
+			paths.push(proc.path.clone());
+		}
+	}
+
+	PATTERN_HOOKS.with(|p| {
+		p.borrow_mut().push(PatternHook {
+			pattern,
+			hook,
+			watch,
+			procs: hooked.iter().map(|proc| proc.id).collect(),
+		})
+	});
+
+	paths
+}
+
+/// Undoes every [hook_matching] call with this `pattern`, returning how many procs were unhooked.
+///
+/// Only the hooks those calls installed are removed, and ones still shared with another pattern stay.
+pub fn unhook_matching<S: Into<String>>(pattern: S) -> usize {
+	let pattern = super::proc::strip_path(pattern.into());
+
+	let removed: Vec<PatternHook> = PATTERN_HOOKS.with(|p| {
+		let mut pattern_hooks = p.borrow_mut();
+		let (removed, kept) = pattern_hooks
+			.drain(..)
+			.partition(|pattern_hook| pattern_hook.pattern == pattern);
+		*pattern_hooks = kept;
+		removed
+	});
+
+	let mut procs: Vec<_> = removed
+		.into_iter()
+		.flat_map(|pattern_hook| pattern_hook.procs)
+		.collect();
+	procs.sort_by_key(|id| id.0);
+	procs.dedup();
+
+	PROC_HOOKS.with(|h| {
+		let map = h.borrow();
+		procs
+			.into_iter()
+			.filter(|id| !claimed_by_pattern(*id))
+			.filter(|id| map.remove(id).is_some())
+			.count()
+	})
+}
+
+// Called by proc::refresh with the procs it added
+pub(crate) fn hook_new_procs(procs: &[Proc]) {
+	let watching: Vec<(usize, String, ProcHook)> = PATTERN_HOOKS.with(|p| {
+		p.borrow()
+			.iter()
+			.enumerate()
+			.filter(|(_, pattern_hook)| pattern_hook.watch)
+			.map(|(i, pattern_hook)| (i, pattern_hook.pattern.clone(), pattern_hook.hook))
+			.collect()
+	});
+
+	for (i, pattern, hook) in watching {
+		let matching: Vec<Proc> = procs
+			.iter()
+			.filter(|proc| glob_matches(pattern.as_bytes(), proc.path.as_bytes()))
+			.cloned()
+			.collect();
+
+		let hooked = claim_procs(hook, &matching);
+		PATTERN_HOOKS.with(|p| {
+			p.borrow_mut()[i]
+				.procs
+				.extend(hooked.iter().map(|proc| proc.id))
+		});
+	}
+}
+
+// Hooks the procs that aren't hooked yet, and shares the ones another pattern hooked with the same hook
+fn claim_procs(hook: ProcHook, procs: &[Proc]) -> Vec<Proc> {
+	procs
+		.iter()
+		.filter(|proc| {
+			let existing = PROC_HOOKS.with(|h| h.borrow().get(&proc.id).map(|hook| *hook));
+			match existing {
+				None => hook_by_id(proc.id, hook).is_ok(),
+				Some(existing) => existing as usize == hook as usize && claimed_by_pattern(proc.id),
+			}
+		})
+		.cloned()
+		.collect()
+}
+
+fn claimed_by_pattern(id: raw_types::procs::ProcId) -> bool {
+	PATTERN_HOOKS.with(|p| {
+		p.borrow()
+			.iter()
+			.any(|pattern_hook| pattern_hook.procs.contains(&id))
+	})
+}
+
+/// Whether all of `text` matches `pattern`, in which `*` matches any run of characters and `?` any single one.
+///
+/// This is how [hook_matching] matches paths.
+pub fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+	let mut p = 0;
+	let mut t = 0;
+
+	// Where to resume from if the most recent `*` needs to swallow another character
+	let mut backtrack = None;
+
+	while t < text.len() {
+		match pattern.get(p) {
+			Some(b'*') => {
+				backtrack = Some((p, t));
+				p += 1;
+			}
+
+			Some(&c) if c == b'?' || c == text[t] => {
+				p += 1;
+				t += 1;
+			}
+
+			_ => match backtrack {
+				Some((star, star_t)) => {
+					p = star + 1;
+					t = star_t + 1;
+					backtrack = Some((star, star_t + 1));
+				}
+
+				None => return false,
+			},
+		}
+	}
+
+	pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Calls the code of the proc the running hook replaced, with `args` instead of the arguments BYOND called it with.
+///
+/// Hooks own their arguments, so they can be changed before passing them on: replacing one drops the reference held
+/// to the old value. Only the outermost call made here skips the hook, so the original calling itself still goes
+/// through it.
+///
+/// # Examples
+///
+/// This hook keeps the damage `/mob/proc/take_damage` is given under 100.
+/// ```ignore
+/// proc! { hook "/mob/proc/take_damage" fn take_damage(src: &Value, usr: &Value, args: &mut Vec<Value>) -> DMResult {
+///     if let Some(amount) = args.get(0) {
+///         args[0] = Value::from(amount.as_number()?.min(100.0));
+///     }
+///     hooks::call_original(src, usr, args)
+/// } }
+/// ```
+pub fn call_original(src: &Value, usr: &Value, args: &[Value]) -> DMResult {
+	let proc_id = RUNNING_HOOKS
+		.with(|hooks| hooks.borrow().last().copied())
+		.ok_or_else(|| runtime!("call_original called outside of a hook"))?;
+
+	call_bypassing_hooks(proc_id, src, usr, args)
+}
+
+// Calls the proc's own code, skipping its hook and observers
+fn call_bypassing_hooks(
+	proc_id: raw_types::procs::ProcId,
+	src: &Value,
+	usr: &Value,
+	args: &[Value],
+) -> DMResult {
+	let mut ret = raw_types::values::Value {
+		tag: raw_types::values::ValueTag::Null,
+		data: raw_types::values::ValueData { id: 0 },
+	};
+
+	BYPASS_HOOK.with(|bypass| bypass.set(Some(proc_id)));
+
+	let called = unsafe {
+		// BYOND takes over a reference to each argument, and the hook keeps its own
+		for v in args {
+			raw_types::funcs::inc_ref_count(v.raw);
+		}
+
+		let args: Vec<_> = args.iter().map(|v| v.raw).collect();
+
+		raw_types::funcs::call_proc_by_id(
+			&mut ret,
+			usr.raw,
+			0,
+			proc_id,
+			0,
+			src.raw,
+			args.as_ptr(),
+			args.len(),
+			0,
+			0,
+		) == 1
+	};
+
+	// An interceptor may have answered the call before the bypass was used up
+	BYPASS_HOOK.with(|bypass| bypass.set(None));
+
+	if !called {
+		return Err(runtime!("External proc call failed"));
+	}
+
+	Ok(unsafe { Value::from_raw_owned(ret) })
+}
+
+/// Runs `pre` before every call of the proc at `path` and `post` after it, without replacing the proc.
+///
+/// Whatever would have run without the observer still runs in between: the proc itself, or its hook if it has one.
+/// Each call is only observed once, so a hook's [call_original] doesn't trigger the observers again. A proc can have
+/// any number of observers, which run in the order they were added.
+///
+/// # Examples
+/// ```ignore
+/// fn log_attack(src: &Value, _usr: &Value, _args: &[Value], ret: &Value) {
+///     println!("{:?} attacked and got {:?}", src, ret);
+/// }
+///
+/// let observer = hooks::observe("/mob/proc/attack", None, Some(log_attack))?;
+/// // ...
+/// hooks::unobserve(observer);
+/// ```
+pub fn observe<S: Into<String>>(
+	path: S,
+	pre: Option<PreObserver>,
+	post: Option<PostObserver>,
+) -> Result<ObserverId, HookFailure> {
+	let proc = super::proc::get_proc(path).ok_or(HookFailure::ProcNotFound)?;
+
+	let id = NEXT_OBSERVER_ID.with(|next| {
+		let id = next.get();
+		next.set(id + 1);
+		ObserverId(id)
+	});
+
+	OBSERVERS.with(|o| {
+		o.borrow_mut()
+			.entry(proc.id)
+			.or_default()
+			.push(Observer { id, pre, post })
+	});
+
+	Ok(id)
+}
+
+/// Removes an observer added by [observe], leaving any other observers and hooks of the proc alone.
+/// Returns false if it was already removed.
+pub fn unobserve(id: ObserverId) -> bool {
+	OBSERVERS.with(|o| {
+		let mut observers = o.borrow_mut();
+
+		let proc_id = observers
+			.iter()
+			.find(|(_, list)| list.iter().any(|observer| observer.id == id))
+			.map(|(proc_id, _)| *proc_id);
+
+		let proc_id = match proc_id {
+			Some(proc_id) => proc_id,
+			None => return false,
+		};
+
+		let list = observers.get_mut(&proc_id).unwrap();
+		list.retain(|observer| observer.id != id);
+		if list.is_empty() {
+			observers.remove(&proc_id);
+		}
+
+		true
+	})
+}
+
 pub fn install_interceptor(func: CallProcByIdInterceptor) {
 	INTERCEPTOR.with(|h| h.replace(Option::Some(func)));
 }
@@ -198,6 +783,9 @@ extern "C" fn call_proc_by_id_hook(
 	_unknown2: u32,
 	_unknown3: u32,
 ) -> u8 {
+	// Anything other threads wanted done on the main thread goes first
+	crate::runtime::run_deferred();
+
 	let result = INTERCEPTOR.with(|cell| {
 		cell.borrow().map_or(0, |interceptor| {
 			interceptor(
@@ -216,50 +804,193 @@ extern "C" fn call_proc_by_id_hook(
 	});
 	if result == 1 { return 1; }
 
-	match PROC_HOOKS.with(|h| match h.borrow().get(&proc_id) {
-		Some(hook) => {
-			let src;
-			let usr;
-			let mut args: Vec<Value>;
+	if BYPASS_HOOK.with(|bypass| bypass.get()) == Some(proc_id) {
+		BYPASS_HOOK.with(|bypass| bypass.set(None));
+		return 0;
+	}
 
-			unsafe {
-				src = Value::from_raw(src_raw);
-				usr = Value::from_raw(usr_raw);
-
-				// Taking ownership of args here
-				args = std::slice::from_raw_parts(args_ptr, num_args)
-					.iter()
-					.map(|v| Value::from_raw_owned(*v))
-					.collect();
-			}
+	// Copied out, so hooks and observers can add and remove others while they run
+	let hook = PROC_HOOKS.with(|h| h.borrow().get(&proc_id).map(|hook| *hook));
+	let observers = OBSERVERS.with(|o| o.borrow().get(&proc_id).cloned());
 
-			let result = hook(&src, &usr, &mut args);
+	if hook.is_none() && observers.is_none() {
+		return 0;
+	}
 
-			match result {
-				Ok(r) => {
-					let result_raw = (&r).raw;
-					// Stealing our reference out of the Value
-					std::mem::forget(r);
-					Some(result_raw)
-				}
-				Err(e) => {
-					// TODO: Some info about the hook would be useful (as the hook is never part of byond's stack, the runtime won't show it.)
-					Proc::find("/proc/auxtools_stack_trace")
-						.unwrap()
-						.call(&[&Value::from_string(e.message.as_str()).unwrap()])
-						.unwrap();
-					Some(Value::null().raw)
-				}
-			}
+	let src;
+	let usr;
+	let mut args: Vec<Value>;
+
+	unsafe {
+		src = Value::from_raw(src_raw);
+		usr = Value::from_raw(usr_raw);
+
+		// Taking ownership of args here
+		args = std::slice::from_raw_parts(args_ptr, num_args)
+			.iter()
+			.map(|v| Value::from_raw_owned(*v))
+			.collect();
+	}
+
+	let observers = observers.unwrap_or_default();
+
+	for pre in observers.iter().filter_map(|observer| observer.pre) {
+		pre(&src, &usr, &args);
+	}
+
+	let result = match hook {
+		Some(hook) => run_hook(proc_id, hook, &src, &usr, &mut args),
+		None => call_bypassing_hooks(proc_id, &src, &usr, &args),
+	};
+
+	let result = match result {
+		Ok(r) => r,
+		Err(e) => {
+			// TODO: Some info about the hook would be useful (as the hook is never part of byond's stack, the runtime won't show it.)
+			Proc::find("/proc/auxtools_stack_trace")
+				.unwrap()
+				.call(&[&Value::from_string(e.message.as_str()).unwrap()])
+				.unwrap();
+			Value::null()
 		}
-		None => None,
-	}) {
-		Some(result) => {
-			unsafe {
-				*ret = result;
+	};
+
+	for post in observers.iter().filter_map(|observer| observer.post) {
+		post(&src, &usr, &args, &result);
+	}
+
+	unsafe {
+		*ret = result.raw;
+	}
+
+	// Stealing our reference out of the Value
+	std::mem::forget(result);
+	1
+}
+
+fn run_hook(
+	proc_id: raw_types::procs::ProcId,
+	hook: ProcHook,
+	src: &Value,
+	usr: &Value,
+	args: &mut Vec<Value>,
+) -> DMResult {
+	#[cfg(feature = "tracing")]
+	let _span = {
+		let span = tracing::debug_span!(
+			"hook",
+			proc = tracing::field::Empty,
+			override_id = tracing::field::Empty
+		);
+
+		if !span.is_disabled() {
+			if let Some(proc) = Proc::from_id(proc_id) {
+				span.record("proc", &proc.path.as_str());
+				span.record("override_id", &proc.override_id());
 			}
-			1
 		}
-		None => 0,
+
+		span.entered()
+	};
+
+	RUNNING_HOOKS.with(|hooks| hooks.borrow_mut().push(proc_id));
+	let result = if CATCH_PANICS.load(Ordering::Relaxed) {
+		call_catching_panics(proc_id, hook, src, usr, args)
+	} else {
+		hook(src, usr, args)
+	};
+	RUNNING_HOOKS.with(|hooks| hooks.borrow_mut().pop());
+
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn spots_jumps() {
+		assert!(starts_with_jump(&[0xE9, 0x10, 0x20, 0x30, 0x40]));
+		assert!(starts_with_jump(&[0xEB, 0x05]));
+		assert!(starts_with_jump(&[0xFF, 0x25, 0, 0, 0, 0]));
+		assert!(!starts_with_jump(&[0x55, 0x8B, 0xEC]));
+		assert!(!starts_with_jump(&[0xFF, 0x15, 0, 0, 0, 0]));
+		assert!(!starts_with_jump(&[]));
+	}
+
+	#[test]
+	fn matches_globs() {
+		let pattern = b"/obj/item/*/attack_self";
+		assert!(glob_matches(pattern, b"/obj/item/weapon/attack_self"));
+		assert!(glob_matches(pattern, b"/obj/item/weapon/gun/attack_self"));
+		assert!(!glob_matches(pattern, b"/obj/item/attack_self"));
+		assert!(!glob_matches(pattern, b"/obj/item/weapon/attack_self_tk"));
+		assert!(glob_matches(b"/mob/?/Life", b"/mob/a/Life"));
+		assert!(!glob_matches(b"/mob/?/Life", b"/mob/ab/Life"));
+		assert!(glob_matches(b"*", b""));
+	}
+
+	fn hook_a(_: &Value, _: &Value, _: &mut Vec<Value>) -> DMResult {
+		Ok(Value::null())
+	}
+
+	fn hook_b(_: &Value, _: &Value, _: &mut Vec<Value>) -> DMResult {
+		Ok(Value::null())
+	}
+
+	// Procs that are never passed to BYOND, so they don't need a real entry
+	fn fake_proc(id: u32, path: &str) -> Proc {
+		Proc {
+			id: raw_types::procs::ProcId(id),
+			entry: std::ptr::null_mut(),
+			path: path.to_owned(),
+		}
+	}
+
+	#[test]
+	fn pattern_hooks_share_and_release() {
+		let procs = [
+			fake_proc(0, "/obj/item/gun/attack_self"),
+			fake_proc(1, "/obj/item/tool/attack_self"),
+			fake_proc(2, "/obj/item/attack_self"),
+		];
+
+		// Hooked by hand, so no pattern may take or remove it
+		hook_by_id(procs[1].id, hook_a).ok().unwrap();
+
+		let watch = |pattern: &str, hook: ProcHook| {
+			PATTERN_HOOKS.with(|p| {
+				p.borrow_mut().push(PatternHook {
+					pattern: pattern.to_owned(),
+					hook,
+					watch: true,
+					procs: vec![],
+				})
+			})
+		};
+		watch("/obj/item/*/attack_self", hook_a);
+		watch("/obj/item/gun/*", hook_a);
+		watch("/obj/*", hook_b);
+
+		hook_new_procs(&procs);
+
+		let claimed = |i: usize| PATTERN_HOOKS.with(|p| p.borrow()[i].procs.clone());
+		assert_eq!(claimed(0), vec![procs[0].id]);
+		assert_eq!(claimed(1), vec![procs[0].id]);
+		assert_eq!(claimed(2), vec![procs[2].id]);
+
+		// Still shared with the second pattern
+		assert_eq!(unhook_matching("/obj/item/*/attack_self"), 0);
+		assert_eq!(unhook_matching("/obj/item/gun/*"), 1);
+		assert_eq!(unhook_matching("/obj/*"), 1);
+
+		PROC_HOOKS.with(|h| {
+			let map = h.borrow();
+			assert!(!map.contains_key(&procs[0].id));
+			assert!(map.contains_key(&procs[1].id));
+			assert!(!map.contains_key(&procs[2].id));
+		});
+
+		clear_hooks();
 	}
 }
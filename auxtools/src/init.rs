@@ -1,3 +1,5 @@
+use std::cell::RefCell;
+
 use crate::inventory;
 
 #[derive(PartialEq, Clone, Copy)]
@@ -17,6 +19,20 @@ pub fn set_init_level(level: InitLevel) {
 	unsafe { REQUIRED_INIT = level }
 }
 
+thread_local! {
+	static INIT_INPUT: RefCell<String> = RefCell::new(String::new());
+}
+
+/// The argument DM last passed to `auxtools_init`, for initializers that take configuration from it.
+/// Empty if it wasn't given one.
+pub fn init_input() -> String {
+	INIT_INPUT.with(|input| input.borrow().clone())
+}
+
+pub(crate) fn set_init_input(input: &str) {
+	INIT_INPUT.with(|i| *i.borrow_mut() = input.to_owned());
+}
+
 //
 // Hooks that run on intiailization
 //
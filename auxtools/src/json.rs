@@ -0,0 +1,116 @@
+//! Converting values into JSON for logs and telemetry. Only available with the `serde_json` feature.
+//!
+//! Unlike the `Serialize` impl (see src/serialize.rs), this follows lists and datum vars down to a given
+//! depth. Anything past that depth, and any datum or list that contains itself, is written shallowly as
+//! `{ "tag": "Datum", "id": 12, "text": "the datum" }`.
+
+use std::collections::HashSet;
+
+use serde_json::{json, Map};
+
+use crate::raw_types::values::ValueTag;
+use crate::{List, ListEntry, StringRef, Value};
+
+impl Value {
+	/// The value as JSON, following lists and datum vars at most `max_depth` levels deep.
+	///
+	/// - null, numbers and strings become themselves
+	/// - lists become arrays. Lists where every element is associated with a string key become objects
+	///   instead, and other associations in arrays become `[key, value]` pairs.
+	/// - anything with vars becomes an object of its vars
+	pub fn to_json(&self, max_depth: usize) -> serde_json::Value {
+		convert(self, max_depth, &mut HashSet::new())
+	}
+}
+
+// `visited` holds the lists and datums being converted further up, so cycles end instead of recursing forever
+fn convert(value: &Value, depth: usize, visited: &mut HashSet<(u8, u32)>) -> serde_json::Value {
+	match value.raw.tag {
+		ValueTag::Null => return serde_json::Value::Null,
+		ValueTag::Number => return json!(unsafe { value.raw.data.number }),
+		ValueTag::String => return json!(value.to_string().unwrap_or_default()),
+		_ => {}
+	}
+
+	let key = (value.raw.tag as u8, unsafe { value.raw.data.id });
+	if depth == 0 || !visited.insert(key) {
+		return shallow(value);
+	}
+
+	let json = if List::is_list(value) {
+		List::from_value(value)
+			.and_then(|list| list.entries())
+			.map(|entries| list_to_json(entries, depth - 1, visited))
+	} else {
+		vars_to_json(value, depth - 1, visited)
+	};
+
+	visited.remove(&key);
+	json.unwrap_or_else(|_| shallow(value))
+}
+
+fn list_to_json(
+	entries: Vec<ListEntry>,
+	depth: usize,
+	visited: &mut HashSet<(u8, u32)>,
+) -> serde_json::Value {
+	let is_object = !entries.is_empty()
+		&& entries.iter().all(|entry| match entry {
+			ListEntry::Assoc { key, .. } => key.raw.tag == ValueTag::String,
+			ListEntry::Indexed(_) => false,
+		});
+
+	if is_object {
+		let mut object = Map::new();
+		for entry in entries {
+			if let ListEntry::Assoc { key, value } = entry {
+				object.insert(
+					key.to_string().unwrap_or_default(),
+					convert(&value, depth, visited),
+				);
+			}
+		}
+		return serde_json::Value::Object(object);
+	}
+
+	entries
+		.into_iter()
+		.map(|entry| match entry {
+			ListEntry::Indexed(value) => convert(&value, depth, visited),
+			ListEntry::Assoc { key, value } => json!([
+				convert(&key, depth, visited),
+				convert(&value, depth, visited)
+			]),
+		})
+		.collect()
+}
+
+fn vars_to_json(
+	value: &Value,
+	depth: usize,
+	visited: &mut HashSet<(u8, u32)>,
+) -> crate::DMResult<serde_json::Value> {
+	// Globals don't have a `vars`, so they get their own special list
+	let vars = if *value == Value::globals() {
+		Value::global_vars()
+	} else {
+		List::from_value(&value.get(crate::byond_string!("vars"))?)?
+	};
+
+	let mut object = Map::new();
+	for i in 1..=vars.len() {
+		let name = vars.get(i)?.as_string()?;
+		let var = value.get(StringRef::new(name.as_str())?)?;
+		object.insert(name, convert(&var, depth, visited));
+	}
+
+	Ok(serde_json::Value::Object(object))
+}
+
+fn shallow(value: &Value) -> serde_json::Value {
+	json!({
+		"tag": format!("{:?}", value.raw.tag),
+		"id": unsafe { value.raw.data.id },
+		"text": value.to_string().ok(),
+	})
+}
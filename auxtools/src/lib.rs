@@ -5,34 +5,48 @@
 //#[cfg(not(target_pointer_width = "32"))]
 //compile_error!("Auxtools must be compiled for a 32-bit target");
 
-mod byond_ffi;
+#[doc(hidden)]
+pub mod byond_ffi;
 mod bytecode_manager;
+#[cfg(feature = "c-api")]
+pub mod c_api;
 pub mod debug;
+pub mod game_thread;
 pub mod hooks;
 mod init;
+#[cfg(feature = "serde_json")]
+mod json;
 mod list;
 pub mod proc;
 pub mod raw_types;
-mod runtime;
+pub mod runtime;
+#[cfg(feature = "serde")]
+mod serialize;
 pub mod sigscan;
 mod string;
 mod string_intern;
+pub mod string_table;
+mod typed_value;
 mod value;
 mod value_from;
 mod version;
 
-use init::{get_init_level, set_init_level, InitLevel};
+use init::{get_init_level, set_init_input, set_init_level, InitLevel};
 
 pub use auxtools_impl::{hook, init, runtime_handler, shutdown};
+pub use byond_ffi::FromFfiArg;
 pub use hooks::{CompileTimeHook, RuntimeHook};
-pub use init::{FullInitFunc, PartialInitFunc, PartialShutdownFunc};
-pub use list::List;
+pub use init::{init_input, FullInitFunc, PartialInitFunc, PartialShutdownFunc};
+pub use list::{List, ListEntry};
 pub use proc::Proc;
 pub use raw_types::variables::VariableNameIdTable;
 pub use runtime::{DMResult, Runtime};
 use std::ffi::c_void;
 pub use string::StringRef;
 pub use string_intern::InternedString;
+pub use typed_value::{
+	AreaValue, DatumValue, ListValue, MobValue, NumberValue, ObjValue, StringValue, TurfValue,
+};
 pub use value::Value;
 
 /// Used by the [hook](attr.hook.html) macro to aggregate all compile-time hooks
@@ -93,41 +107,45 @@ signatures! {
 }
 
 macro_rules! find_function {
-	($scanner:ident, $name:ident) => {
+	($found:expr, $name:ident) => {
 		let $name: *const c_void;
-		if let Some(ptr) = $scanner.find(SIGNATURES.$name) {
+		if let Some(ptr) = $found {
 			unsafe {
 				$name = std::mem::transmute(ptr as *const c_void);
 			}
 		} else {
-			return Some(format!("FAILED (Couldn't find {})", stringify!($name)));
+			return Some(format!("FAILED (Couldn't find {} in {})", stringify!($name), BYONDCORE));
 		}
 	};
 }
 
 macro_rules! find_function_by_call {
-	($scanner:ident, $name:ident) => {
+	($found:expr, $name:ident) => {
 		let $name: *const c_void;
-		if let Some(ptr) = $scanner.find(SIGNATURES.$name) {
+		if let Some(ptr) = $found {
 			unsafe {
 				let offset = *(ptr.offset(1) as *const isize);
 				$name = ptr.offset(5).offset(offset) as *const () as *const std::ffi::c_void;
 			}
 		} else {
-			return Some(format!("FAILED (Couldn't find {})", stringify!($name)));
+			return Some(format!("FAILED (Couldn't find {} in {})", stringify!($name), BYONDCORE));
 		}
 	};
 }
 
+// Finds all of the functions with a single scan. The ones after `by_call:` are found through a call to them.
 macro_rules! with_scanner {
-	($scanner:ident, $( $name:ident),* ) => {
-		$( find_function!($scanner, $name); )*
+	($scanner:ident, $( $name:ident ),* $(; by_call: $( $call_name:ident ),* )? ) => {
+		let found = $scanner.find_many(&[ $( SIGNATURES.$name, )* $( $( SIGNATURES.$call_name, )* )? ]);
+		let mut found = found.into_iter();
+		$( find_function!(found.next().unwrap(), $name); )*
+		$( $( find_function_by_call!(found.next().unwrap(), $call_name); )* )?
 	};
 }
 
 macro_rules! with_scanner_by_call {
 	($scanner:ident, $( $name:ident),* ) => {
-		$( find_function_by_call!($scanner, $name); )*
+		$( find_function_by_call!($scanner.find(SIGNATURES.$name), $name); )*
 	};
 }
 
@@ -159,20 +177,26 @@ fn pin_dll() -> Result<(), ()> {
 	Ok(())
 }
 
-byond_ffi_fn! { auxtools_init(_input) {
+byond_ffi_fn! { auxtools_init(input) {
 	if get_init_level() == InitLevel::None {
 		return Some("SUCCESS".to_owned())
 	}
 
+	game_thread::set_game_thread();
+	set_init_input(input);
+
 	let byondcore = match sigscan::Scanner::for_module(BYONDCORE) {
 		Some(v) => v,
-		None => return Some("FAILED (Couldn't create scanner for byondcore.dll)".to_owned())
+		None => return Some(format!("FAILED (Couldn't create scanner for {})", BYONDCORE))
 	};
 
 	let mut did_full = false;
 	let mut did_partial = false;
 
 	if get_init_level() == InitLevel::Full {
+		#[cfg(feature = "tracing")]
+		tracing::info!("auxtools full init");
+
 		did_full = true;
 		if let Err(e) = version::init() {
 			return Some(format!("FAILED ({})", e));
@@ -192,10 +216,8 @@ byond_ffi_fn! { auxtools_init(_input) {
 			get_length,
 			create_list,
 			suspended_procs,
-			suspended_procs_buffer
-		}
-
-		with_scanner_by_call! { byondcore,
+			suspended_procs_buffer;
+		by_call:
 			call_proc_by_id,
 			call_proc_by_id2,
 			get_proc_array_entry,
@@ -318,8 +340,38 @@ byond_ffi_fn! { auxtools_init(_input) {
 			}
 		}
 
+		// get_string_table_entry starts with a bounds check against the table's length, followed by a load of the table
+		let string_table = unsafe {
+			let (count_offset, entries_offset) = if cfg!(windows) { (8, 15) } else { (11, 19) };
+
+			raw_types::strings::StringTable {
+				entries: *(get_string_table_entry.add(entries_offset) as *mut *mut *mut *mut raw_types::strings::StringEntry),
+				count: *(get_string_table_entry.add(count_offset) as *mut *mut u32),
+			}
+		};
+
+		// Optional, List::entries just fails without it. We haven't got a signature for it on Linux yet.
+		let mut list_table = raw_types::lists::ListTable {
+			entries: std::ptr::null_mut(),
+			count: std::ptr::null_mut(),
+		};
+		{
+			if cfg!(windows) {
+				if let Some(ptr) = byondcore.find(signature!("55 8B EC 8B 4D 08 3B 0D ?? ?? ?? ?? 73 11 A1 ?? ?? ?? ?? 8B 04 88 85 C0 74 05 FF 40 10 5D C3 33 C0 5D C3")) {
+					list_table = unsafe {
+						raw_types::lists::ListTable {
+							entries: *(ptr.add(15) as *mut *mut *mut *mut raw_types::lists::List),
+							count: *(ptr.add(8) as *mut *mut u32),
+						}
+					};
+				}
+			}
+		}
+
 		unsafe {
 			raw_types::funcs::CURRENT_EXECUTION_CONTEXT = current_execution_context;
+			raw_types::funcs::STRING_TABLE = string_table;
+			raw_types::funcs::LIST_TABLE = list_table;
 			raw_types::funcs::SUSPENDED_PROCS = *(suspended_procs.add(1) as *mut *mut raw_types::procs::SuspendedProcs);
 			raw_types::funcs::SUSPENDED_PROCS_BUFFER = *(suspended_procs_buffer.add(2) as *mut *mut raw_types::procs::SuspendedProcsBuffer);
 			raw_types::funcs::call_proc_by_id_byond = call_proc_by_id;
@@ -346,15 +398,21 @@ byond_ffi_fn! { auxtools_init(_input) {
 			return Some("FAILED (Could not pin the library in memory.)".to_owned());
 		}
 
-		if let Err(_) = hooks::init() {
-			return Some("Failed (Couldn't initialize proc hooking)".to_owned());
+		if let Err(e) = hooks::init() {
+			return Some(format!("FAILED (Couldn't initialize proc hooking: {})", e));
 		}
 
+		#[cfg(feature = "tracing")]
+		tracing::info!("auxtools installed its detours");
+
 		set_init_level(InitLevel::Partial);
 	}
 
 
 	if get_init_level() == InitLevel::Partial {
+		#[cfg(feature = "tracing")]
+		tracing::info!("auxtools partial init");
+
 		did_partial = true;
 
 		// This is a heap ptr so fetch it on partial loads
@@ -394,6 +452,12 @@ byond_ffi_fn! { auxtools_init(_input) {
 				return Some(format!("FAILED (Could not hook proc {}: {:?})", cthook.proc_path, e));
 			}
 		}
+
+		#[cfg(feature = "tracing")]
+		tracing::info!(
+			hooks = inventory::iter::<hooks::CompileTimeHook>.into_iter().count(),
+			"auxtools hooked procs"
+		);
 		set_init_level(InitLevel::None);
 	}
 
@@ -415,6 +479,13 @@ byond_ffi_fn! { auxtools_init(_input) {
 		}
 	}
 
+	if did_partial {
+		runtime::finish_reboot();
+	}
+
+	#[cfg(feature = "tracing")]
+	tracing::info!("auxtools initialized");
+
 	Some("SUCCESS".to_owned())
 } }
 
@@ -423,14 +494,18 @@ byond_ffi_fn! { auxtools_shutdown(_input) {
 	string_intern::destroy_interned_strings();
 	bytecode_manager::shutdown();
 
-	hooks::clear_hooks();
+	// Removes our detours too, so nothing in BYOND points at this library if it gets unloaded
+	hooks::shutdown();
 	proc::clear_procs();
+	runtime::clear_deferred();
+	runtime::begin_reboot();
 
 	unsafe {
 		raw_types::funcs::VARIABLE_NAMES = std::ptr::null();
 	}
 
-	set_init_level(InitLevel::Partial);
+	// The detours have to be installed again by the next auxtools_init
+	set_init_level(InitLevel::Full);
 	Some("SUCCESS".to_owned())
 } }
 
@@ -1,4 +1,5 @@
 use crate::*;
+use std::collections::HashSet;
 use std::iter::FromIterator;
 
 /// A wrapper around [Values](struct.Value.html) that make working with lists a little easier
@@ -6,6 +7,27 @@ pub struct List {
 	value: Value,
 }
 
+/// An element of a [List], as returned by [List::entry].
+pub enum ListEntry {
+	/// An element with no associated value, like those in `list(1, 2)`.
+	Indexed(Value),
+
+	/// An element with an associated value, like those in `list("a" = 1, "b" = null)`.
+	Assoc { key: Value, value: Value },
+}
+
+// What we know about which of a list's elements have associated values
+enum Associations {
+	// Lists like `vars` associate every element
+	All,
+
+	// Lists like `overlays` never do
+	None,
+
+	// The keys in the list's associative part, as (tag, data) pairs
+	Keys(HashSet<(u8, u32)>),
+}
+
 impl List {
 	pub fn from_value(val: &Value) -> DMResult<Self> {
 		if !Self::is_list(val) {
@@ -97,6 +119,119 @@ impl List {
 		}
 	}
 
+	/// Gets the element at `index` (starting at 1), along with its associated value if it has one.
+	///
+	/// Fails for plain lists where auxtools didn't find BYOND's list table, which is currently everywhere but Windows.
+	pub fn entry(&self, index: u32) -> DMResult<ListEntry> {
+		let key = self.get(index)?;
+		self.make_entry(key, &self.associations()?)
+	}
+
+	/// Gets every element of the list, in order. Cheaper than calling [entry](List::entry) for each one.
+	///
+	/// Fails where [entry](List::entry) does.
+	pub fn entries(&self) -> DMResult<Vec<ListEntry>> {
+		let associations = self.associations()?;
+
+		(1..=self.len())
+			.map(|index| self.make_entry(self.get(index)?, &associations))
+			.collect()
+	}
+
+	/// Gets every element of the list, in order. For an associative list these are its keys.
+	pub fn keys(&self) -> DMResult<Vec<Value>> {
+		(1..=self.len()).map(|index| self.get(index)).collect()
+	}
+
+	/// Gets the value associated with each element of the list, in order, or null for elements without one.
+	///
+	/// Fails where [entry](List::entry) does.
+	pub fn values(&self) -> DMResult<Vec<Value>> {
+		Ok(self
+			.entries()?
+			.into_iter()
+			.map(|entry| match entry {
+				ListEntry::Indexed(_) => Value::null(),
+				ListEntry::Assoc { value, .. } => value,
+			})
+			.collect())
+	}
+
+	fn make_entry(&self, key: Value, associations: &Associations) -> DMResult<ListEntry> {
+		let is_assoc = match associations {
+			Associations::All => true,
+			Associations::None => false,
+			Associations::Keys(keys) => unsafe {
+				keys.contains(&(key.raw.tag as u8, key.raw.data.id))
+			},
+		};
+
+		if is_assoc {
+			let value = self.get(&key)?;
+			Ok(ListEntry::Assoc { key, value })
+		} else {
+			Ok(ListEntry::Indexed(key))
+		}
+	}
+
+	fn associations(&self) -> DMResult<Associations> {
+		use raw_types::values::ValueTag;
+
+		match self.value.raw.tag {
+			ValueTag::List => {}
+
+			ValueTag::MobVars
+			| ValueTag::ObjVars
+			| ValueTag::TurfVars
+			| ValueTag::AreaVars
+			| ValueTag::ClientVars
+			| ValueTag::Vars
+			| ValueTag::ImageVars
+			| ValueTag::WorldVars
+			| ValueTag::GlobalVars => return Ok(Associations::All),
+
+			_ => return Ok(Associations::None),
+		}
+
+		unsafe {
+			// Whether an element's associated value is null says nothing about whether it has one, so don't guess
+			let list = Self::get_raw(self.value.raw.data.id).ok_or_else(|| {
+				runtime!("can't tell which elements of the list are associated without BYOND's list table")
+			})?;
+
+			// The associative part is a binary tree of every key that has been given a value
+			let mut keys = HashSet::new();
+			let mut pending = vec![(*list).assoc_part];
+
+			while let Some(node) = pending.pop() {
+				if node.is_null() {
+					continue;
+				}
+
+				keys.insert(((*node).key.tag as u8, (*node).key.data.id));
+				pending.push((*node).left);
+				pending.push((*node).right);
+			}
+
+			Ok(Associations::Keys(keys))
+		}
+	}
+
+	// Looks a list up in BYOND's list table, which we only know how to find on some platforms
+	pub(crate) unsafe fn get_raw(id: u32) -> Option<*mut raw_types::lists::List> {
+		let table = raw_types::funcs::LIST_TABLE;
+		if table.entries.is_null() || table.count.is_null() || id >= *table.count {
+			return None;
+		}
+
+		let list = *(*table.entries).add(id as usize);
+		if list.is_null() {
+			return None;
+		}
+
+		Some(list)
+	}
+
 	pub fn len(&self) -> u32 {
 		let mut length: u32 = 0;
 		unsafe {
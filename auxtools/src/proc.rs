@@ -1,7 +1,8 @@
 use crate::*;
 use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::fmt;
 
 //
@@ -27,6 +28,8 @@ use std::fmt;
 //
 //
 
+const OPCODE_DBGFILE: u32 = 0x84;
+
 /// Used to hook and call procs.
 #[derive(Clone)]
 pub struct Proc {
@@ -65,6 +68,7 @@ impl Proc {
 		})
 	}
 
+	/// Names of the proc's declared arguments, read from its compiled metadata.
 	pub fn parameter_names(&self) -> Vec<StringRef> {
 		unsafe {
 			let (data, count) = raw_types::misc::get_parameters((*self.entry).parameters);
@@ -74,6 +78,9 @@ impl Proc {
 		}
 	}
 
+	/// Names of the proc's declared local variables, read from its compiled metadata.
+	///
+	/// Unlike the locals of a `debug::StackFrame`, these are available without the proc ever running.
 	pub fn local_names(&self) -> Vec<StringRef> {
 		unsafe {
 			let (names, count) = raw_types::misc::get_locals((*self.entry).locals);
@@ -96,6 +103,23 @@ impl Proc {
 		std::slice::from_raw_parts(ptr, count as usize)
 	}
 
+	/// The file the proc was defined in, relative to the .dme.
+	///
+	/// BYOND starts the bytecode of every proc compiled with debug info with a `DbgFile` instruction, which is where this comes from.
+	/// Procs without debug info, and those whose first instruction has been replaced, don't have one.
+	pub fn source_file(&self) -> Option<String> {
+		let bytecode = unsafe { self.bytecode() };
+
+		match bytecode {
+			[OPCODE_DBGFILE, file, ..] => {
+				let file = unsafe { StringRef::from_id(raw_types::strings::StringId(*file)) };
+				Some(String::from(&file))
+			}
+
+			_ => None,
+		}
+	}
+
 	/// Calls a global proc with the given arguments.
 	///
 	/// # Examples
@@ -109,19 +133,53 @@ impl Proc {
 	/// }
 	/// ```
 	pub fn call(&self, args: &[&Value]) -> runtime::DMResult {
-		let mut ret = raw_types::values::Value {
-			tag: raw_types::values::ValueTag::Null,
-			data: raw_types::values::ValueData { id: 0 },
-		};
-
 		unsafe {
 			// Increment ref-count of args permenently before passing them on
 			for v in args {
 				raw_types::funcs::inc_ref_count(v.raw);
 			}
+		}
+
+		self.call_raw(args.iter().map(|e| e.raw).collect())
+	}
 
-			let args: Vec<_> = args.iter().map(|e| e.raw).collect();
+	/// Calls a global proc with any number of arguments, taking ownership of them.
+	///
+	/// Useful for forwarding arguments whose count isn't known until runtime, like those a hook was given.
+	/// BYOND is handed the references the values held, so their reference counts don't have to be touched.
+	///
+	/// # Examples
+	///
+	/// This function is equivalent to `return sum(1, 2, ..., count)` in DM.
+	/// ```ignore
+	/// #[hook("/proc/my_proc")]
+	/// fn my_proc_hook(count: Value) -> DMResult {
+	///     let proc = Proc::find("/proc/sum").unwrap();
+	///     let numbers = (1..=count.as_number()? as u32).map(Value::from).collect();
+	///     proc.call_variadic(numbers)
+	/// }
+	/// ```
+	pub fn call_variadic(&self, args: Vec<Value>) -> runtime::DMResult {
+		let args = args
+			.into_iter()
+			.map(|v| {
+				let raw = v.raw;
+				std::mem::forget(v);
+				raw
+			})
+			.collect();
 
+		self.call_raw(args)
+	}
+
+	// BYOND takes the arguments as a pointer and a count, and consumes a reference to each of them
+	fn call_raw(&self, args: Vec<raw_types::values::Value>) -> runtime::DMResult {
+		let mut ret = raw_types::values::Value {
+			tag: raw_types::values::ValueTag::Null,
+			data: raw_types::values::ValueData { id: 0 },
+		};
+
+		unsafe {
 			if raw_types::funcs::call_proc_by_id(
 				&mut ret,
 				Value::null().raw,
@@ -159,42 +217,87 @@ impl fmt::Debug for Proc {
 
 thread_local!(static PROCS_BY_NAME: RefCell<DashMap<String, Vec<Proc>>> = RefCell::new(DashMap::new()));
 thread_local!(static PROC_OVERRIDE_IDS: RefCell<DashMap<raw_types::procs::ProcId, u32>> = RefCell::new(DashMap::new()));
+thread_local!(static PROCS: RefCell<Vec<Proc>> = RefCell::new(Vec::new()));
+// Lookups by the path exactly as it was asked for, so hooks looking up the same proc every call skip strip_path
+thread_local!(static PROC_CACHE: RefCell<HashMap<(String, u32), Proc>> = RefCell::new(HashMap::new()));
+thread_local!(static CALLEE_SCANNER: Cell<Option<CalleeScanner>> = Cell::new(None));
+// The paths of the procs calling each statically called proc, built by the first find_callers
+thread_local!(static CALLERS: RefCell<Option<HashMap<String, Vec<String>>>> = RefCell::new(None));
 
-fn strip_path(p: String) -> String {
+pub(crate) fn strip_path(p: String) -> String {
 	p.replace("/proc/", "/").replace("/verb/", "/")
 }
 
 extern crate log;
 
-pub fn populate_procs() {
-	let mut i: u32 = 0;
-	loop {
-		let proc = Proc::from_id(raw_types::procs::ProcId(i));
-		if proc.is_none() {
-			break;
-		}
-		let proc = proc.unwrap();
-
-		PROC_OVERRIDE_IDS.with(|override_ids| {
-			let override_ids = override_ids.borrow_mut();
-
-			PROCS_BY_NAME.with(|h| {
-				match h.borrow_mut().entry(proc.path.clone()) {
-					Entry::Occupied(mut o) => {
-						let vec = o.get_mut();
-						override_ids.insert(proc.id, vec.len() as u32);
-						vec.push(proc);
-					}
-					Entry::Vacant(v) => {
-						override_ids.insert(proc.id, 0);
-						v.insert(vec![proc]);
-					}
-				};
-			});
-		});
+/// Every proc known to BYOND, in order of their ids.
+///
+/// Proc ids are contiguous, so this stops at the first id BYOND has no entry for.
+pub fn all() -> impl Iterator<Item = Proc> {
+	all_from(0)
+}
+
+fn all_from(first_id: u32) -> impl Iterator<Item = Proc> {
+	(first_id..)
+		.map(|id| Proc::from_id(raw_types::procs::ProcId(id)))
+		.take_while(Option::is_some)
+		.flatten()
+}
+
+/// The procs from [all] whose path starts with `prefix`, like `/datum/component/`.
+///
+/// Paths are compared without their `/proc/` and `/verb/` parts, in the same way as [get_proc].
+pub fn procs_matching(prefix: &str) -> impl Iterator<Item = Proc> {
+	let prefix = strip_path(prefix.to_owned());
+	all().filter(move |proc| proc.path.starts_with(&prefix))
+}
+
+// The procs in the table get_proc_override uses, which only changes on refresh, so pattern hooks and callers agree
+// with it about which procs exist
+pub(crate) fn known_procs() -> impl Iterator<Item = Proc> {
+	PROCS.with(|procs| procs.borrow().clone()).into_iter()
+}
+
+/// Adds any procs BYOND has created since init to the table, giving them the next override ids of their paths.
+///
+/// The new procs are hooked by any [hooks::hook_matching](crate::hooks::hook_matching) patterns watching for them.
+pub fn refresh() {
+	PROC_CACHE.with(|cache| cache.borrow_mut().clear());
+	forget_callers();
+	let known = PROCS.with(|procs| procs.borrow().len() as u32);
 
-		i += 1;
+	let added: Vec<Proc> = all_from(known).collect();
+	for proc in &added {
+		add_proc(proc.clone());
 	}
+
+	crate::hooks::hook_new_procs(&added);
+}
+
+pub fn populate_procs() {
+	refresh();
+}
+
+fn add_proc(proc: Proc) {
+	PROCS.with(|procs| procs.borrow_mut().push(proc.clone()));
+
+	PROC_OVERRIDE_IDS.with(|override_ids| {
+		let override_ids = override_ids.borrow_mut();
+
+		PROCS_BY_NAME.with(|h| {
+			match h.borrow_mut().entry(proc.path.clone()) {
+				Entry::Occupied(mut o) => {
+					let vec = o.get_mut();
+					override_ids.insert(proc.id, vec.len() as u32);
+					vec.push(proc);
+				}
+				Entry::Vacant(v) => {
+					override_ids.insert(proc.id, 0);
+					v.insert(vec![proc]);
+				}
+			};
+		});
+	});
 }
 
 pub fn dump_procs() {
@@ -206,12 +309,29 @@ pub fn dump_procs() {
 }
 
 pub fn clear_procs() {
+	PROC_CACHE.with(|cache| cache.borrow_mut().clear());
+	forget_callers();
+	PROCS.with(|procs| procs.borrow_mut().clear());
 	PROCS_BY_NAME.with(|h| h.borrow_mut().clear());
 	PROC_OVERRIDE_IDS.with(|override_ids| override_ids.borrow_mut().clear());
 }
 
+/// Finds the n'th override of a proc. Found procs are cached by `path` until the next [refresh], so looking up the
+/// same proc again is a single hash lookup.
 pub fn get_proc_override<S: Into<String>>(path: S, override_id: u32) -> Option<Proc> {
-	let s = strip_path(path.into());
+	let key = (path.into(), override_id);
+
+	if let Some(proc) = PROC_CACHE.with(|cache| cache.borrow().get(&key).cloned()) {
+		return Some(proc);
+	}
+
+	let proc = find_proc_override(key.0.clone(), override_id)?;
+	PROC_CACHE.with(|cache| cache.borrow_mut().insert(key, proc.clone()));
+	Some(proc)
+}
+
+fn find_proc_override(path: String, override_id: u32) -> Option<Proc> {
+	let s = strip_path(path);
 	PROCS_BY_NAME.with(|h| match h.borrow().get(&s)?.get(override_id as usize) {
 		Some(p) => Some(p.clone()),
 		None => None,
@@ -222,3 +342,143 @@ pub fn get_proc_override<S: Into<String>>(path: S, override_id: u32) -> Option<P
 pub fn get_proc<S: Into<String>>(path: S) -> Option<Proc> {
 	get_proc_override(path, 0)
 }
+
+/// Lists the paths of the procs that `proc` calls by path. Used by [find_callers], as auxtools can't decode bytecode itself.
+pub type CalleeScanner = fn(proc: &Proc) -> Vec<String>;
+
+/// Sets how [find_callers] finds the procs each proc calls, like the debug server does with its disassembler.
+pub fn set_callee_scanner(scanner: CalleeScanner) {
+	CALLEE_SCANNER.with(|s| s.set(Some(scanner)));
+	forget_callers();
+}
+
+/// Finds the procs containing a call to any override of the proc at `callee_path`.
+///
+/// Only calls that name their target at compile time (e.g. global procs) are found. Calls which
+/// are resolved at runtime, like `src.do_stuff()`, don't reference a specific proc.
+///
+/// The base proc of each caller is returned once, no matter how many of its overrides make the call. Every proc is
+/// scanned on the first call and the result is kept until the proc table or any proc's bytecode changes. Nothing
+/// is found without a [CalleeScanner].
+pub fn find_callers(callee_path: &str) -> Vec<Proc> {
+	let scanner = match CALLEE_SCANNER.with(Cell::get) {
+		Some(scanner) => scanner,
+		None => return vec![],
+	};
+
+	let callee = match get_proc(callee_path) {
+		Some(proc) => proc,
+		None => return vec![],
+	};
+
+	let paths = CALLERS.with(|callers| {
+		callers
+			.borrow_mut()
+			.get_or_insert_with(|| build_callers(scanner))
+			.get(&callee.path)
+			.cloned()
+	});
+
+	paths
+		.unwrap_or_default()
+		.into_iter()
+		.filter_map(get_proc)
+		.collect()
+}
+
+/// Throws away what [find_callers] found, for after changing bytecode some way other than [Proc::set_bytecode].
+pub fn forget_callers() {
+	CALLERS.with(|callers| *callers.borrow_mut() = None);
+}
+
+fn build_callers(scanner: CalleeScanner) -> HashMap<String, Vec<String>> {
+	let mut callers: HashMap<String, Vec<String>> = HashMap::new();
+
+	for proc in known_procs() {
+		for callee in scanner(&proc) {
+			let paths = callers.entry(strip_path(callee)).or_default();
+			if !paths.contains(&proc.path) {
+				paths.push(proc.path.clone());
+			}
+		}
+	}
+
+	callers
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Procs that are never passed to BYOND, so they don't need a real entry
+	fn add_fake_procs(paths: &[&str]) {
+		for (id, path) in paths.iter().enumerate() {
+			add_proc(Proc {
+				id: raw_types::procs::ProcId(id as u32),
+				entry: std::ptr::null_mut(),
+				path: strip_path(path.to_string()),
+			});
+		}
+	}
+
+	#[test]
+	fn caches_lookups() {
+		add_fake_procs(&["/proc/hello", "/datum/proc/hello", "/proc/hello"]);
+
+		assert_eq!(get_proc("/proc/hello").unwrap().id.0, 0);
+		assert_eq!(get_proc_override("/proc/hello", 1).unwrap().id.0, 2);
+		assert_eq!(get_proc("/datum/proc/hello").unwrap().id.0, 1);
+		assert!(get_proc_override("/proc/hello", 2).is_none());
+
+		// Hits the cache this time
+		assert_eq!(get_proc("/proc/hello").unwrap().id.0, 0);
+		assert_eq!(PROC_CACHE.with(|cache| cache.borrow().len()), 3);
+
+		clear_procs();
+		assert!(get_proc("/proc/hello").is_none());
+	}
+
+	fn fake_callees(proc: &Proc) -> Vec<String> {
+		match proc.path.as_str() {
+			"/caller" => vec!["/proc/callee".to_owned()],
+			"/datum/caller" => vec!["/proc/callee".to_owned(), "/proc/other".to_owned()],
+			_ => vec![],
+		}
+	}
+
+	#[test]
+	fn finds_callers() {
+		add_fake_procs(&[
+			"/proc/callee",
+			"/proc/caller",
+			"/datum/proc/caller",
+			"/proc/caller",
+		]);
+		assert!(find_callers("/proc/callee").is_empty());
+
+		set_callee_scanner(fake_callees);
+		let paths: Vec<String> = find_callers("/proc/callee")
+			.into_iter()
+			.map(|proc| proc.path)
+			.collect();
+		assert_eq!(paths, ["/caller", "/datum/caller"]);
+		assert!(CALLERS.with(|callers| callers.borrow().is_some()));
+
+		clear_procs();
+		assert!(CALLERS.with(|callers| callers.borrow().is_none()));
+		assert!(find_callers("/proc/callee").is_empty());
+	}
+
+	#[test]
+	fn get_proc_uses_the_cache_until_cleared() {
+		add_fake_procs(&["/proc/cached"]);
+		assert_eq!(get_proc("/proc/cached").unwrap().id.0, 0);
+
+		// With the table emptied, only the cache can still find it
+		PROCS_BY_NAME.with(|h| h.borrow_mut().clear());
+		assert_eq!(get_proc("/proc/cached").unwrap().id.0, 0);
+
+		clear_procs();
+		assert!(get_proc("/proc/cached").is_none());
+	}
+}
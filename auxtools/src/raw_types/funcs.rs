@@ -14,6 +14,14 @@ pub static mut SUSPENDED_PROCS_BUFFER: *mut procs::SuspendedProcsBuffer = std::p
 pub static mut SUSPENDED_PROCS: *mut procs::SuspendedProcs = std::ptr::null_mut();
 
 pub static mut VARIABLE_NAMES: *const variables::VariableNameIdTable = std::ptr::null();
+pub static mut STRING_TABLE: strings::StringTable = strings::StringTable {
+	entries: std::ptr::null_mut(),
+	count: std::ptr::null_mut(),
+};
+pub static mut LIST_TABLE: lists::ListTable = lists::ListTable {
+	entries: std::ptr::null_mut(),
+	count: std::ptr::null_mut(),
+};
 
 // Function pointers exported by C++ but set by Rust
 // Rust shouldn't call these so we're going to treat them as void ptrs for simplicity
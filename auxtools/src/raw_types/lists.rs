@@ -13,11 +13,11 @@ enum Color {
 
 #[repr(C)]
 pub struct AssociativeListEntry {
-	key: values::Value,
-	value: values::Value,
+	pub key: values::Value,
+	pub value: values::Value,
 	color: Color,
-	left: *mut AssociativeListEntry,
-	right: *mut AssociativeListEntry,
+	pub left: *mut AssociativeListEntry,
+	pub right: *mut AssociativeListEntry,
 }
 
 #[repr(C)]
@@ -29,3 +29,10 @@ pub struct List {
 	pub refcount: u32,
 	unknown: u32,
 }
+
+// Like the string table, the entries and their count live in two separate globals
+#[derive(Copy, Clone, Debug)]
+pub struct ListTable {
+	pub entries: *mut *mut *mut List,
+	pub count: *mut u32,
+}
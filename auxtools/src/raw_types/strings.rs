@@ -25,3 +25,10 @@ pub struct StringEntry {
 	pub unk_1: u32,
 	pub unk_2: u32,
 }
+
+// BYOND keeps the entries and their count in two separate globals, so this just points at both
+#[derive(Copy, Clone, Debug)]
+pub struct StringTable {
+	pub entries: *mut *mut *mut StringEntry,
+	pub count: *mut u32,
+}
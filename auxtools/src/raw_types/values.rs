@@ -6,7 +6,7 @@ use std::fmt;
 use std::fmt::{Debug, Formatter};
 
 #[repr(u8)]
-#[derive(PartialEq, Copy, Clone, Debug, Hash)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
 #[non_exhaustive]
 pub enum ValueTag {
 	Null = 0x00,
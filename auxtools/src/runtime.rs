@@ -1,18 +1,125 @@
+use crate::game_thread::GameThreadGuard;
+use crate::proc::Proc;
+use crate::raw_types::procs::ProcId;
 use crate::value::Value;
+use std::fmt;
 use std::result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
 
 /// Represents a byond runtime, sort of. This will probably drastically in the future.
 ///
 /// These are just simple error messages that our API and hooks can return as failure states.
+/// If a proc was executing when the runtime was created, where it was at is captured too.
+///
+/// Lots of runtimes are only ever used as ordinary errors, so only the proc's id is kept. Its path and override id
+/// are looked up when asked for.
 #[derive(Debug)]
 pub struct Runtime {
 	pub message: String,
+	proc_id: Option<ProcId>,
+	/// Bytecode offset of the instruction the proc was executing
+	pub offset: Option<u32>,
+	pub line: Option<u32>,
 }
 
 impl Runtime {
 	pub fn new<S: Into<String>>(message: S) -> Self {
-		Self {
+		let mut runtime = Self {
 			message: message.into(),
+			proc_id: None,
+			offset: None,
+			line: None,
+		};
+
+		runtime.capture_location();
+		runtime
+	}
+
+	/// The proc that was executing when the runtime was created.
+	///
+	/// Always `None` off the game thread, as the proc table can only be read from there.
+	pub fn proc(&self) -> Option<Proc> {
+		GameThreadGuard::try_acquire()?;
+		Proc::from_id(self.proc_id?)
+	}
+
+	/// The path of [proc](Runtime::proc).
+	pub fn proc_path(&self) -> Option<String> {
+		self.proc().map(|proc| proc.path)
+	}
+
+	/// The override id of [proc](Runtime::proc).
+	pub fn override_id(&self) -> Option<u32> {
+		self.proc().map(|proc| proc.override_id())
+	}
+
+	fn capture_location(&mut self) {
+		// Runtimes created on other threads (or before init) don't have a location
+		let ctx = match GameThreadGuard::try_acquire() {
+			Some(guard) => guard.current_execution_context(),
+			None => return,
+		};
+
+		unsafe {
+			if ctx.is_null() || (*ctx).proc_instance.is_null() {
+				return;
+			}
+
+			self.proc_id = Some((*(*ctx).proc_instance).proc);
+			self.offset = Some((*ctx).bytecode_offset as u32);
+
+			// Procs compiled without debug info never set a line
+			self.line = match (*ctx).line {
+				0 => None,
+				line => Some(line),
+			};
+		}
+	}
+
+	/// Formats the runtime the way BYOND reports its own runtimes in world.log:
+	///
+	/// ```text
+	/// runtime error: Cannot read null.name
+	/// proc name: attack (/mob/attack)
+	///   source file: code/mob.dm,12
+	/// ```
+	///
+	/// Lines that would need information the runtime doesn't have are left out.
+	pub fn to_byond_string(&self) -> String {
+		let mut text = format!("runtime error: {}", self.message);
+
+		let proc = match self.proc() {
+			Some(proc) => proc,
+			None => return text,
+		};
+
+		let name = proc.path.rsplit('/').next().unwrap_or(&proc.path);
+		text.push_str(&format!("\nproc name: {} ({})", name, proc.path));
+
+		let file = proc.source_file();
+
+		match (file, self.line) {
+			(Some(file), Some(line)) => {
+				text.push_str(&format!("\n  source file: {},{}", file, line))
+			}
+			(Some(file), None) => text.push_str(&format!("\n  source file: {}", file)),
+			_ => {}
+		}
+
+		text
+	}
+}
+
+impl fmt::Display for Runtime {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.message)?;
+
+		match (self.proc_path(), self.line) {
+			(Some(proc_path), Some(line)) => write!(f, " (in {}, line {})", proc_path, line),
+			(Some(proc_path), None) => write!(f, " (in {})", proc_path),
+			(None, Some(line)) => write!(f, " (line {})", line),
+			(None, None) => Ok(()),
 		}
 	}
 }
@@ -29,3 +136,115 @@ macro_rules! runtime {
 }
 
 pub type DMResult<T = Value> = result::Result<T, Runtime>;
+
+type DeferredFn = Box<dyn FnOnce() + Send>;
+
+lazy_static::lazy_static! {
+	static ref DEFERRED: Mutex<Vec<DeferredFn>> = Mutex::new(vec![]);
+}
+
+// Lets the hot path skip locking DEFERRED when nothing is queued
+static HAS_DEFERRED: AtomicBool = AtomicBool::new(false);
+
+/// Queues a closure to run on BYOND's main thread, where it is safe to use [Value]s and call procs.
+///
+/// This can be called from any thread. The closure's result is sent back through the returned channel.
+/// Queued closures run whenever BYOND calls into a proc from native code (verbs, `world.Tick()`,
+/// procs waking from `sleep`, ...). Hooks that run often can also call [run_deferred] themselves.
+///
+/// If the library shuts down before the closure gets to run, it is dropped and the channel disconnects.
+///
+/// # Examples
+///
+/// ```ignore
+/// std::thread::spawn(|| {
+///     let body = do_http_request();
+///     let result = auxtools::runtime::defer(move || {
+///         Proc::find("/proc/on_response").unwrap().call(&[&Value::from_string(body)?])
+///     });
+/// });
+/// ```
+pub fn defer<F, T>(func: F) -> mpsc::Receiver<T>
+where
+	F: FnOnce() -> T + Send + 'static,
+	T: Send + 'static,
+{
+	let (sender, receiver) = mpsc::channel();
+
+	DEFERRED.lock().unwrap().push(Box::new(move || {
+		// Nobody might be listening, that's fine
+		let _ = sender.send(func());
+	}));
+	HAS_DEFERRED.store(true, Ordering::Release);
+
+	receiver
+}
+
+/// Runs every closure queued by [defer]. Must be called from the main thread.
+pub fn run_deferred() {
+	if !HAS_DEFERRED.swap(false, Ordering::Acquire) {
+		return;
+	}
+
+	// Take the queue first, as the closures may well queue more work
+	let deferred = std::mem::take(&mut *DEFERRED.lock().unwrap());
+
+	for func in deferred {
+		func();
+	}
+}
+
+pub(crate) fn clear_deferred() {
+	DEFERRED.lock().unwrap().clear();
+	HAS_DEFERRED.store(false, Ordering::Release);
+}
+
+type RebootFn = Box<dyn FnOnce() + Send>;
+
+lazy_static::lazy_static! {
+	static ref REBOOT_CALLBACKS: Mutex<Vec<RebootFn>> = Mutex::new(vec![]);
+}
+
+// Set by auxtools_shutdown, so the next auxtools_init knows it's reattaching to a rebooted world
+static REBOOTING: AtomicBool = AtomicBool::new(false);
+
+/// Runs `callback` once auxtools has reattached to the world after it reboots.
+///
+/// By then the proc table has been read again, hooks are reinstalled and every init function has run,
+/// so the callback can put back whatever it had set up in the old world. Each callback only runs once.
+///
+/// Reboots are noticed through the usual `world/Reboot()` dance of calling `auxtools_shutdown` before
+/// rebooting and `auxtools_init` again in `world/New()`.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[shutdown]
+/// fn save_state() {
+///     let state = take_state();
+///     auxtools::runtime::on_reboot(move || restore_state(state));
+/// }
+/// ```
+pub fn on_reboot<F>(callback: F)
+where
+	F: FnOnce() + Send + 'static,
+{
+	REBOOT_CALLBACKS.lock().unwrap().push(Box::new(callback));
+}
+
+pub(crate) fn begin_reboot() {
+	REBOOTING.store(true, Ordering::Release);
+}
+
+pub(crate) fn finish_reboot() {
+	if !REBOOTING.swap(false, Ordering::Acquire) {
+		return;
+	}
+
+	// Callbacks registering more callbacks get them run on the reboot after this one
+	let callbacks = std::mem::take(&mut *REBOOT_CALLBACKS.lock().unwrap());
+
+	for callback in callbacks {
+		callback();
+	}
+}
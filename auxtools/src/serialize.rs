@@ -0,0 +1,92 @@
+//! `serde::Serialize` for values and call stacks, so snapshots of the game's state can be written out.
+//!
+//! Only available with the `serde` feature. There is no deserialization, since none of this can be recreated
+//! from its serialized form.
+//!
+//! Values serialize shallowly:
+//! - null, numbers and strings as themselves
+//! - anything else as `{ "tag": "Datum", "id": 12, "text": "the datum" }`, where `text` is what DM's `"[x]"`
+//!   would give (or null if that failed)
+
+use serde::ser::{Serialize, SerializeMap, SerializeStruct, Serializer};
+
+use crate::debug::{CallStacks, StackFrame};
+use crate::raw_types::values::ValueTag;
+use crate::{StringRef, Value};
+
+impl Serialize for Value {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match self.raw.tag {
+			ValueTag::Null => serializer.serialize_none(),
+			ValueTag::Number => serializer.serialize_f32(unsafe { self.raw.data.number }),
+			ValueTag::String => serializer.serialize_str(&self.to_string().unwrap_or_default()),
+			tag => {
+				let mut map = serializer.serialize_map(Some(3))?;
+				map.serialize_entry("tag", &format!("{:?}", tag))?;
+				map.serialize_entry("id", &unsafe { self.raw.data.id })?;
+				map.serialize_entry("text", &self.to_string().ok())?;
+				map.end()
+			}
+		}
+	}
+}
+
+// An argument or local, with its name if it has one
+struct Variable<'a> {
+	name: Option<&'a StringRef>,
+	value: &'a Value,
+}
+
+impl Serialize for Variable<'_> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(Some(2))?;
+		map.serialize_entry("name", &self.name.map(String::from))?;
+		map.serialize_entry("value", self.value)?;
+		map.end()
+	}
+}
+
+impl Serialize for StackFrame {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let args: Vec<Variable> = self
+			.args
+			.iter()
+			.map(|(name, value)| Variable {
+				name: name.as_ref(),
+				value,
+			})
+			.collect();
+
+		let locals: Vec<Variable> = self
+			.locals
+			.iter()
+			.map(|(name, value)| Variable {
+				name: Some(name),
+				value,
+			})
+			.collect();
+
+		let mut frame = serializer.serialize_struct("StackFrame", 11)?;
+		frame.serialize_field("proc", &self.proc.path)?;
+		frame.serialize_field("override_id", &self.proc.override_id())?;
+		frame.serialize_field("offset", &self.offset)?;
+		frame.serialize_field("usr", &self.usr)?;
+		frame.serialize_field("src", &self.src)?;
+		frame.serialize_field("dot", &self.dot)?;
+		frame.serialize_field("args", &args)?;
+		frame.serialize_field("locals", &locals)?;
+		frame.serialize_field("file_name", &self.file_name.as_ref().map(String::from))?;
+		frame.serialize_field("line_number", &self.line_number)?;
+		frame.serialize_field("time_to_resume", &self.time_to_resume)?;
+		frame.end()
+	}
+}
+
+impl Serialize for CallStacks {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut stacks = serializer.serialize_struct("CallStacks", 2)?;
+		stacks.serialize_field("active", &self.active)?;
+		stacks.serialize_field("suspended", &self.suspended)?;
+		stacks.end()
+	}
+}
@@ -10,6 +10,240 @@ pub use windows::Scanner;
 
 pub use auxtools_impl::convert_signature;
 
+use std::fmt;
+
+/// Why [parse_pattern] rejected a pattern. `position` is the byte offset into the pattern.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatternError {
+	/// A `\` was followed by something other than `x`, `?`, `\` or a space.
+	BadEscape { position: usize },
+	/// A `\x` wasn't followed by two hex digits.
+	BadHex { position: usize },
+	/// A `?{` wasn't followed by a number and a `}`.
+	BadRepeat { position: usize },
+	/// The pattern doesn't match anything, so every address would match it.
+	Empty,
+}
+
+impl fmt::Display for PatternError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PatternError::BadEscape { position } => write!(f, "unknown escape at {}", position),
+			PatternError::BadHex { position } => {
+				write!(f, "expected two hex digits at {}", position)
+			}
+			PatternError::BadRepeat { position } => {
+				write!(f, "expected `?{{count}}` at {}", position)
+			}
+			PatternError::Empty => write!(f, "empty pattern"),
+		}
+	}
+}
+
+impl std::error::Error for PatternError {}
+
+/// Turns a pattern for [Scanner::find_str] into the form [Scanner::find] takes.
+///
+/// In a pattern:
+/// - `\xAB` is the byte 0xAB
+/// - `?` is any one byte, so `??` is any two bytes
+/// - `?{N}` is any `N` bytes
+/// - `\?`, `\\` and `\ ` (backslash space) are those characters
+/// - whitespace is ignored, so patterns can be split up to be read more easily
+/// - any other character is its own UTF-8 bytes
+pub fn parse_pattern(pattern: &str) -> Result<Vec<Option<u8>>, PatternError> {
+	let bytes = pattern.as_bytes();
+	let mut parsed = vec![];
+	let mut i = 0;
+
+	while i < bytes.len() {
+		match bytes[i] {
+			b'\\' => match bytes.get(i + 1) {
+				Some(b'x') => {
+					let byte = pattern
+						.get(i + 2..i + 4)
+						.filter(|hex| hex.bytes().all(|c| c.is_ascii_hexdigit()))
+						.and_then(|hex| u8::from_str_radix(hex, 16).ok())
+						.ok_or(PatternError::BadHex { position: i })?;
+
+					parsed.push(Some(byte));
+					i += 4;
+				}
+
+				Some(&c) if c == b'?' || c == b'\\' || c == b' ' => {
+					parsed.push(Some(c));
+					i += 2;
+				}
+
+				_ => return Err(PatternError::BadEscape { position: i }),
+			},
+
+			b'?' if bytes.get(i + 1) == Some(&b'{') => {
+				let end = pattern[i..]
+					.find('}')
+					.map(|end| i + end)
+					.ok_or(PatternError::BadRepeat { position: i })?;
+				let count: usize = pattern[i + 2..end]
+					.trim()
+					.parse()
+					.map_err(|_| PatternError::BadRepeat { position: i })?;
+
+				parsed.extend(std::iter::repeat(None).take(count));
+				i = end + 1;
+			}
+
+			b'?' => {
+				parsed.push(None);
+				i += 1;
+			}
+
+			c if c.is_ascii_whitespace() => i += 1,
+
+			c => {
+				parsed.push(Some(c));
+				i += 1;
+			}
+		}
+	}
+
+	if parsed.iter().all(Option::is_none) {
+		return Err(PatternError::Empty);
+	}
+
+	Ok(parsed)
+}
+
+impl Scanner {
+	/// Like [find](Scanner::find), but with the pattern written in the syntax described at [parse_pattern].
+	///
+	/// # Panics
+	/// If the pattern is malformed. Use [parse_pattern] first for patterns that aren't written into the code.
+	///
+	/// # Examples
+	/// ```ignore
+	/// let ptr = scanner.find_str(r"\x55 \x8B\xEC ?{4} \xE8 ?{4}");
+	/// ```
+	pub fn find_str(&self, pattern: &str) -> Option<*mut u8> {
+		match parse_pattern(pattern) {
+			Ok(signature) => self.find(&signature),
+			Err(e) => panic!("invalid signature pattern {:?}: {}", pattern, e),
+		}
+	}
+
+	/// Looks for every signature in a single pass over the module, spread across a few threads.
+	///
+	/// The result has an entry for each signature, in the same order. Like [find](Scanner::find), a signature
+	/// that matches more than once counts as not found.
+	pub fn find_many(&self, signatures: &[&[Option<u8>]]) -> Vec<Option<*mut u8>> {
+		let memory = self.memory();
+
+		find_many_in(memory, signatures)
+			.into_iter()
+			.map(|offset| offset.map(|offset| memory[offset..].as_ptr() as *mut u8))
+			.collect()
+	}
+}
+
+// The offset of each signature's match in `data`, for the ones that match exactly once
+fn find_many_in(data: &[u8], signatures: &[&[Option<u8>]]) -> Vec<Option<usize>> {
+	// Which signatures can start at a given byte. Ones starting with a wildcard have to be tried everywhere.
+	let mut by_first_byte: Vec<Vec<usize>> = vec![vec![]; 256];
+	let mut any_first_byte = vec![];
+
+	for (index, signature) in signatures.iter().enumerate() {
+		match signature.first() {
+			Some(Some(byte)) => by_first_byte[*byte as usize].push(index),
+			Some(None) => any_first_byte.push(index),
+			// An empty signature matches everywhere, so it never has a unique match
+			None => {}
+		}
+	}
+
+	let threads = std::thread::available_parallelism()
+		.map(|n| n.get())
+		.unwrap_or(1)
+		.min(8);
+	let chunk_len = data.len() / threads + 1;
+
+	// Every thread checks the matches starting in its own chunk, but may read past the end of it
+	let chunks: Vec<Vec<Matches>> = std::thread::scope(|scope| {
+		let handles: Vec<_> = (0..threads)
+			.map(|i| {
+				let start = (i * chunk_len).min(data.len());
+				let end = ((i + 1) * chunk_len).min(data.len());
+				let by_first_byte = &by_first_byte;
+				let any_first_byte = &any_first_byte;
+
+				scope.spawn(move || {
+					let mut matches = vec![Matches::None; signatures.len()];
+
+					for offset in start..end {
+						let candidates = by_first_byte[data[offset] as usize]
+							.iter()
+							.chain(any_first_byte.iter());
+
+						for &index in candidates {
+							if matches_at(data, offset, signatures[index]) {
+								matches[index] = matches[index].add(offset);
+							}
+						}
+					}
+
+					matches
+				})
+			})
+			.collect();
+
+		handles
+			.into_iter()
+			.map(|handle| handle.join().unwrap())
+			.collect()
+	});
+
+	(0..signatures.len())
+		.map(|index| {
+			let found = chunks
+				.iter()
+				.fold(Matches::None, |found, chunk| found.merge(chunk[index]));
+
+			match found {
+				Matches::One(offset) => Some(offset),
+				_ => None,
+			}
+		})
+		.collect()
+}
+
+#[derive(Clone, Copy)]
+enum Matches {
+	None,
+	One(usize),
+	Many,
+}
+
+impl Matches {
+	fn add(self, offset: usize) -> Matches {
+		self.merge(Matches::One(offset))
+	}
+
+	fn merge(self, other: Matches) -> Matches {
+		match (self, other) {
+			(Matches::None, other) | (other, Matches::None) => other,
+			_ => Matches::Many,
+		}
+	}
+}
+
+fn matches_at(data: &[u8], offset: usize, signature: &[Option<u8>]) -> bool {
+	match data.get(offset..offset + signature.len()) {
+		Some(window) => window
+			.iter()
+			.zip(signature)
+			.all(|(byte, expected)| expected.map_or(true, |expected| expected == *byte)),
+		None => false,
+	}
+}
+
 #[macro_export]
 macro_rules! signature {
 	($sig:tt) => {
@@ -29,3 +263,125 @@ macro_rules! signatures {
 		};
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_patterns() {
+		assert_eq!(
+			parse_pattern(r"\x55 \x8B? ?? ?{3} ab\?\\\ "),
+			Ok(vec![
+				Some(0x55),
+				Some(0x8B),
+				None,
+				None,
+				None,
+				None,
+				None,
+				None,
+				Some(b'a'),
+				Some(b'b'),
+				Some(b'?'),
+				Some(b'\\'),
+				Some(b' '),
+			])
+		);
+
+		assert_eq!(parse_pattern(r"\xE8 ?{0}"), Ok(vec![Some(0xE8)]));
+	}
+
+	#[test]
+	fn rejects_bad_patterns() {
+		assert_eq!(
+			parse_pattern(r"\x5"),
+			Err(PatternError::BadHex { position: 0 })
+		);
+		assert_eq!(
+			parse_pattern(r"\xZZ"),
+			Err(PatternError::BadHex { position: 0 })
+		);
+		assert_eq!(
+			parse_pattern(r"a\n"),
+			Err(PatternError::BadEscape { position: 1 })
+		);
+		assert_eq!(
+			parse_pattern("a?{x}"),
+			Err(PatternError::BadRepeat { position: 1 })
+		);
+		assert_eq!(
+			parse_pattern("a?{4"),
+			Err(PatternError::BadRepeat { position: 1 })
+		);
+		assert_eq!(parse_pattern("?? ?{2}"), Err(PatternError::Empty));
+		assert_eq!(parse_pattern(""), Err(PatternError::Empty));
+	}
+
+	// The same search `Scanner::find` does, one signature at a time
+	fn find_in(data: &[u8], signature: &[Option<u8>]) -> Option<usize> {
+		let mut found = (0..data.len()).filter(|&offset| matches_at(data, offset, signature));
+		match (found.next(), found.next()) {
+			(Some(offset), None) => Some(offset),
+			_ => None,
+		}
+	}
+
+	// Deterministic noise, so the signatures below only match where they're planted
+	fn noise(len: usize) -> Vec<u8> {
+		let mut state = 0x2545_F491u32;
+		(0..len)
+			.map(|_| {
+				state ^= state << 13;
+				state ^= state >> 17;
+				state ^= state << 5;
+				state as u8
+			})
+			.collect()
+	}
+
+	#[test]
+	fn finds_many_signatures_at_once() {
+		let mut data = noise(1 << 16);
+		let planted = parse_pattern(r"\x55\x8B\xEC\x83\xEC\x0C\x53\x8B\x5D\x10").unwrap();
+		let repeated = parse_pattern(r"\xE8\x01\x02\x03\x04\x83\xC4\x04\x85\xC0").unwrap();
+		let wildcard = parse_pattern(r"?{2} \xAA\xBB\xCC\xDD\xEE\xFF").unwrap();
+		let missing = parse_pattern(r"\xDE\xAD\xBE\xEF\xDE\xAD\xBE\xEF").unwrap();
+
+		// Put one right across the middle, where a chunk boundary is likely to be
+		let middle = data.len() / 2 - 4;
+		data[middle..middle + planted.len()]
+			.copy_from_slice(&planted.iter().map(|b| b.unwrap()).collect::<Vec<_>>());
+
+		for offset in &[100, 40_000] {
+			data[*offset..*offset + repeated.len()]
+				.copy_from_slice(&repeated.iter().map(|b| b.unwrap()).collect::<Vec<_>>());
+		}
+
+		let end = data.len() - 6;
+		data[end..].copy_from_slice(&[0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+
+		let signatures: Vec<&[Option<u8>]> = vec![&planted, &repeated, &wildcard, &missing, &[]];
+		let found = find_many_in(&data, &signatures);
+
+		assert_eq!(found, vec![Some(middle), None, Some(end - 2), None, None]);
+
+		let one_by_one: Vec<_> = signatures.iter().map(|sig| find_in(&data, sig)).collect();
+		assert_eq!(found, one_by_one);
+	}
+
+	#[test]
+	fn find_many_matches_one_by_one() {
+		let data = noise(1 << 18);
+		let signatures: Vec<Vec<Option<u8>>> = (0..20)
+			.map(|i| {
+				let start = 1000 + i * 12_000;
+				data[start..start + 24].iter().copied().map(Some).collect()
+			})
+			.collect();
+		let signatures: Vec<&[Option<u8>]> = signatures.iter().map(Vec::as_slice).collect();
+
+		let one_by_one: Vec<_> = signatures.iter().map(|sig| find_in(&data, sig)).collect();
+		assert_eq!(find_many_in(&data, &signatures), one_by_one);
+	}
+}
@@ -51,7 +51,7 @@ impl Scanner {
 		})
 	}
 
-	pub fn find(&self, signature: &[Option<u8>]) -> Option<*mut u8> {
+	fn module_data(&self) -> CallbackData {
 		let module_name = CString::new(self.module_name.clone()).unwrap();
 		let module_name_ptr = module_name.as_ptr();
 		let data = CallbackData {
@@ -61,6 +61,16 @@ impl Scanner {
 			memory_area: None,
 		};
 		unsafe { dl_iterate_phdr(Some(dl_phdr_callback), std::mem::transmute(&data)) };
+		data
+	}
+
+	// The module's first loaded segment, which is what gets scanned
+	pub(super) fn memory(&self) -> &'static [u8] {
+		self.module_data().memory_area.unwrap_or_default()
+	}
+
+	pub fn find(&self, signature: &[Option<u8>]) -> Option<*mut u8> {
+		let data = self.module_data();
 
 		let mut data_current = data.memory_start as *mut u8;
 		let data_end = (data.memory_start + data.memory_len) as *mut u8;
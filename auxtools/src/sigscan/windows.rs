@@ -52,6 +52,14 @@ impl Scanner {
 		})
 	}
 
+	// The whole image of the module, which is what gets scanned
+	pub(super) fn memory(&self) -> &'static [u8] {
+		unsafe {
+			let len = self.data_end.offset_from(self.data_begin) as usize + 1;
+			std::slice::from_raw_parts(self.data_begin, len)
+		}
+	}
+
 	pub fn find(&self, signature: &[Option<u8>]) -> Option<*mut u8> {
 		let mut data_current = self.data_begin;
 		let data_end = self.data_end;
@@ -0,0 +1,85 @@
+//! Direct access to BYOND's string table.
+//!
+//! Everything in here panics unless called from the game thread, after `auxtools_init` has succeeded.
+
+use crate::game_thread::GameThreadGuard;
+use crate::raw_types;
+use crate::runtime;
+use crate::runtime::DMResult;
+use std::ffi::{CStr, CString};
+
+/// Iterates over the id and contents of every string currently in the table.
+///
+/// Strings created while iterating may or may not be seen.
+pub fn iter() -> impl Iterator<Item = (u32, String)> {
+	let _guard = GameThreadGuard::acquire();
+	let count = unsafe { len() };
+	(0..count).filter_map(|id| get_by_id(id).map(|string| (id, string)))
+}
+
+/// Looks up the contents of a string by its id. Returns `None` for ids that aren't in use.
+pub fn get_by_id(id: u32) -> Option<String> {
+	let _guard = GameThreadGuard::acquire();
+
+	unsafe {
+		let entry = entry(id)?;
+		Some(CStr::from_ptr((*entry).data).to_string_lossy().into_owned())
+	}
+}
+
+/// How many references the string with this id has. Returns `None` for ids that aren't in use.
+pub fn ref_count(id: u32) -> Option<u32> {
+	let _guard = GameThreadGuard::acquire();
+	unsafe { entry(id).map(|entry| (*entry).ref_count) }
+}
+
+/// How many ids the table has room for, whether they're in use or not. Every id in use is below this.
+pub fn capacity() -> u32 {
+	let _guard = GameThreadGuard::acquire();
+	unsafe { len() }
+}
+
+/// Finds or creates a string and returns its id.
+///
+/// The string is given a reference that is never released, so the id stays valid until the world shuts down.
+pub fn intern(string: &str) -> DMResult<u32> {
+	let _guard = GameThreadGuard::acquire();
+	let string = CString::new(string).map_err(|_| runtime!("string contains a null byte"))?;
+
+	unsafe {
+		let mut id = raw_types::strings::StringId(0);
+		if raw_types::funcs::get_string_id(&mut id, string.as_ptr()) != 1 {
+			return Err(runtime!("failed to create string"));
+		}
+
+		raw_types::funcs::inc_ref_count(raw_types::values::Value {
+			tag: raw_types::values::ValueTag::String,
+			data: raw_types::values::ValueData { string: id },
+		});
+
+		Ok(id.0)
+	}
+}
+
+// The entry for an id in use, which always has its contents
+unsafe fn entry(id: u32) -> Option<*mut raw_types::strings::StringEntry> {
+	if id >= len() {
+		return None;
+	}
+
+	let entry = *(*raw_types::funcs::STRING_TABLE.entries).add(id as usize);
+	if entry.is_null() || (*entry).data.is_null() {
+		return None;
+	}
+
+	Some(entry)
+}
+
+unsafe fn len() -> u32 {
+	let table = raw_types::funcs::STRING_TABLE;
+	if table.entries.is_null() || table.count.is_null() {
+		return 0;
+	}
+
+	*table.count
+}
@@ -0,0 +1,189 @@
+use crate::raw_types::values::ValueTag;
+use crate::*;
+use std::borrow::Cow;
+use std::ffi::CStr;
+
+// Declares a wrapper around a borrowed Value that can only be created when `$check` passes
+macro_rules! typed_value {
+	($(#[$attr:meta])* $name:ident, $as_fn:ident, |$value:ident| $check:expr) => {
+		$(#[$attr])*
+		#[derive(Clone, Copy)]
+		pub struct $name<'a> {
+			value: &'a Value,
+		}
+
+		impl<'a> $name<'a> {
+			/// The untyped value this wraps.
+			pub fn value(&self) -> &'a Value {
+				self.value
+			}
+		}
+
+		impl<'a> std::ops::Deref for $name<'a> {
+			type Target = Value;
+
+			fn deref(&self) -> &Value {
+				self.value
+			}
+		}
+
+		impl Value {
+			#[doc = concat!("Returns a [", stringify!($name), "] if the value has the right tag.")]
+			pub fn $as_fn(&self) -> Option<$name<'_>> {
+				let $value = self;
+				if $check {
+					Some($name { value: self })
+				} else {
+					None
+				}
+			}
+		}
+	};
+}
+
+typed_value!(
+	/// Any value with variables of its own: datums, atoms and the like.
+	DatumValue,
+	as_datum,
+	|value| matches!(
+		value.raw.tag,
+		ValueTag::Datum
+			| ValueTag::Obj
+			| ValueTag::Mob
+			| ValueTag::Turf
+			| ValueTag::Area
+			| ValueTag::Client
+			| ValueTag::Image
+	)
+);
+
+typed_value!(
+	/// A `/mob`.
+	MobValue,
+	as_mob,
+	|value| value.raw.tag == ValueTag::Mob
+);
+
+typed_value!(
+	/// An `/obj`.
+	ObjValue,
+	as_obj,
+	|value| value.raw.tag == ValueTag::Obj
+);
+
+typed_value!(
+	/// An `/area`.
+	AreaValue,
+	as_area,
+	|value| value.raw.tag == ValueTag::Area
+);
+
+typed_value!(
+	/// A `/turf`.
+	TurfValue,
+	as_turf,
+	|value| value.raw.tag == ValueTag::Turf
+);
+
+typed_value!(
+	/// A `/list`, or one of BYOND's special lists like `vars` and `overlays`.
+	ListValue,
+	as_list_value,
+	|value| List::is_list(value)
+);
+
+typed_value!(
+	/// A number.
+	NumberValue,
+	as_number_value,
+	|value| value.raw.tag == ValueTag::Number
+);
+
+typed_value!(
+	/// A string.
+	StringValue,
+	as_string_value,
+	|value| value.raw.tag == ValueTag::String
+);
+
+impl<'a> DatumValue<'a> {
+	/// Equivalent to DM's `src.type`.
+	pub fn type_path(&self) -> DMResult<String> {
+		self.value.get_type()
+	}
+}
+
+impl<'a> MobValue<'a> {
+	/// Equivalent to DM's `src.loc`.
+	pub fn loc(&self) -> DMResult {
+		self.value.get(byond_string!("loc"))
+	}
+
+	/// The mob as a [DatumValue].
+	pub fn datum(&self) -> DatumValue<'a> {
+		DatumValue { value: self.value }
+	}
+}
+
+impl<'a> ObjValue<'a> {
+	/// Equivalent to DM's `src.loc`.
+	pub fn loc(&self) -> DMResult {
+		self.value.get(byond_string!("loc"))
+	}
+
+	/// The obj as a [DatumValue].
+	pub fn datum(&self) -> DatumValue<'a> {
+		DatumValue { value: self.value }
+	}
+}
+
+impl<'a> AreaValue<'a> {
+	/// The area as a [DatumValue].
+	pub fn datum(&self) -> DatumValue<'a> {
+		DatumValue { value: self.value }
+	}
+}
+
+impl<'a> TurfValue<'a> {
+	/// The turf's ID, as accepted by [Value::turf_by_id].
+	pub fn id(&self) -> u32 {
+		unsafe { self.value.raw.data.id }
+	}
+
+	/// The turf as a [DatumValue].
+	pub fn datum(&self) -> DatumValue<'a> {
+		DatumValue { value: self.value }
+	}
+}
+
+impl<'a> ListValue<'a> {
+	pub fn list(&self) -> List {
+		List::from_value(self.value).unwrap()
+	}
+}
+
+impl<'a> NumberValue<'a> {
+	pub fn get_float(&self) -> f32 {
+		unsafe { self.value.raw.data.number }
+	}
+}
+
+impl<'a> StringValue<'a> {
+	/// The contents of the string. Only copied if it contains invalid UTF-8, which gets replaced.
+	pub fn as_str(&self) -> Cow<'a, str> {
+		String::from_utf8_lossy(self.as_bytes())
+	}
+
+	/// The raw contents of the string, without the null terminator.
+	pub fn as_bytes(&self) -> &'a [u8] {
+		// The value holds a reference to the string, so the entry lives at least as long as 'a
+		unsafe {
+			let mut entry: *mut raw_types::strings::StringEntry = std::ptr::null_mut();
+			assert_eq!(
+				raw_types::funcs::get_string_table_entry(&mut entry, self.value.raw.data.string),
+				1
+			);
+			CStr::from_ptr((*entry).data).to_bytes()
+		}
+	}
+}
@@ -56,7 +56,7 @@ impl Value {
 		}
 	}
 
-	/// Equivalent to DM's `global.vars`.
+	/// Equivalent to DM's `global`. Its variables are the game's global variables.
 	pub fn globals() -> Value {
 		Value {
 			raw: raw_types::values::Value {
@@ -67,6 +67,19 @@ impl Value {
 		}
 	}
 
+	/// Equivalent to DM's `global.vars`, the names of every global variable.
+	pub fn global_vars() -> list::List {
+		// `global` doesn't have a `vars` variable to read, BYOND uses this special list instead.
+		let vars = unsafe {
+			Value::new(
+				raw_types::values::ValueTag::GlobalVars,
+				raw_types::values::ValueData { id: 0 },
+			)
+		};
+
+		list::List::from_value(&vars).unwrap()
+	}
+
 	/// Equivalent to DM's `world`.
 	pub fn world() -> Value {
 		Value {
@@ -78,6 +91,20 @@ impl Value {
 		}
 	}
 
+	/// Reads one of `world`'s variables, like `world.time` or `world.maxx`.
+	///
+	/// Shorthand for `Value::world().get(...)` that takes the name as a plain string.
+	pub fn get_world_var(name: &str) -> DMResult {
+		Value::world().get(string::StringRef::new(name)?)
+	}
+
+	/// Writes one of `world`'s variables, like `world.name` or `world.fps`.
+	///
+	/// Read-only variables such as `world.time` fail with a runtime.
+	pub fn set_world_var(name: &str, value: &Value) -> DMResult<()> {
+		Value::world().set(string::StringRef::new(name)?, value)
+	}
+
 	/// Equivalent to DM's `null`.
 	pub fn null() -> Value {
 		Value {
@@ -154,7 +181,25 @@ impl Value {
 		name_id: raw_types::strings::StringId,
 		new_value: raw_types::values::Value,
 	) -> Result<(), runtime::Runtime> {
+		if matches!(
+			self.raw.tag,
+			raw_types::values::ValueTag::Null
+				| raw_types::values::ValueTag::Number
+				| raw_types::values::ValueTag::String
+		) {
+			let varname: String = unsafe { string::StringRef::from_id(name_id) }.into();
+			return Err(runtime!("Could not write to {}.{}: it has no variables", self, varname));
+		}
+
 		unsafe {
+			#[cfg(feature = "tracing")]
+			tracing::trace!(
+				datum = %self,
+				variable = %String::from(string::StringRef::from_id(name_id)),
+				value = %new_value,
+				"setting variable"
+			);
+
 			if raw_types::funcs::set_variable(self.raw, name_id, new_value) != 1 {
 				let varname: String = string::StringRef::from_id(name_id).into();
 				return Err(runtime!("Could not write to {}.{}", self, varname));
@@ -186,6 +231,9 @@ impl Value {
 	}
 
 	/// Sets a variable by name to a given value.
+	///
+	/// Fails with a runtime if the value can't have variables (like null or a number), if the variable
+	/// doesn't exist or if BYOND refuses the write, like for const vars.
 	pub fn set<S: Into<string::StringRef>, V: Into<Value>>(
 		&self,
 		name: S,
@@ -317,15 +365,113 @@ impl Value {
 		}
 	}
 
+	/// Reads the value's reference count, for tracking down reference counting bugs.
+	///
+	/// | Tag | Supported |
+	/// |-----|-----------|
+	/// | `String` | Everywhere |
+	/// | `List` | Only where auxtools found BYOND's list table, which is currently Windows |
+	/// | `Datum`, `Obj`, `Mob` and other objects | Not yet |
+	/// | Numbers, `null` and everything else | Never, they aren't reference counted |
+	///
+	/// Returns `None` whenever the count can't be read. Objects keep their counts in BYOND's object tables, whose
+	/// layout and location `raw_types` doesn't describe yet, so their leaks can't be caught this way.
+	pub fn refcount(&self) -> Option<u32> {
+		unsafe {
+			match self.raw.tag {
+				raw_types::values::ValueTag::String => {
+					let mut entry: *mut raw_types::strings::StringEntry = std::ptr::null_mut();
+					if raw_types::funcs::get_string_table_entry(&mut entry, self.raw.data.string)
+						!= 1 || entry.is_null()
+					{
+						return None;
+					}
+
+					Some((*entry).ref_count)
+				}
+
+				raw_types::values::ValueTag::List => {
+					list::List::get_raw(self.raw.data.id).map(|list| (*list).refcount)
+				}
+
+				_ => None,
+			}
+		}
+	}
+
+	/// Whether DM's `if` would take this value as true.
+	///
+	/// Only `null`, `0` (including `-0`) and the empty string `""` are false. Everything else is true, like the
+	/// string `"0"`, and lists and objects whatever their contents: an empty `list()` is true, as is an
+	/// associative list whose values are all null.
 	pub fn is_truthy(&self) -> bool {
 		match self.raw.tag {
 			raw_types::values::ValueTag::Null => false,
 			raw_types::values::ValueTag::Number => unsafe { self.raw.data.number != 0.0 },
+			raw_types::values::ValueTag::String => unsafe {
+				let mut entry: *mut raw_types::strings::StringEntry = std::ptr::null_mut();
+				let found =
+					raw_types::funcs::get_string_table_entry(&mut entry, self.raw.data.string) == 1;
+				!found || *(*entry).data != 0
+			},
 
 			_ => true,
 		}
 	}
 
+	/// Formats values BYOND's own `to_string` is unhelpful for: `/matrix`es as `matrix(a,b,c, d,e,f)`,
+	/// `/icon`s as `icon('file.dmi')` and color strings with their components spelled out.
+	///
+	/// Returns `None` for everything else, which should be formatted as usual.
+	pub fn format_special(&self) -> Option<String> {
+		match self.raw.tag {
+			raw_types::values::ValueTag::Datum => {
+				let type_path = self.get_type().ok()?;
+
+				if is_type_or_subtype(&type_path, "/matrix") {
+					self.format_matrix().ok()
+				} else if is_type_or_subtype(&type_path, "/icon") {
+					self.format_icon().ok()
+				} else {
+					None
+				}
+			}
+
+			raw_types::values::ValueTag::String => {
+				let string = self.as_string().ok()?;
+				let (r, g, b, a) = parse_color(&string)?;
+
+				Some(match a {
+					Some(a) => format!("{:?} (rgb({},{},{},{}))", string, r, g, b, a),
+					None => format!("{:?} (rgb({},{},{}))", string, r, g, b),
+				})
+			}
+
+			_ => None,
+		}
+	}
+
+	fn format_matrix(&self) -> DMResult<String> {
+		let a = self.get_number(crate::byond_string!("a"))?;
+		let b = self.get_number(crate::byond_string!("b"))?;
+		let c = self.get_number(crate::byond_string!("c"))?;
+		let d = self.get_number(crate::byond_string!("d"))?;
+		let e = self.get_number(crate::byond_string!("e"))?;
+		let f = self.get_number(crate::byond_string!("f"))?;
+
+		Ok(format!("matrix({},{},{}, {},{},{})", a, b, c, d, e, f))
+	}
+
+	fn format_icon(&self) -> DMResult<String> {
+		// The icon file it was created from, if there was one
+		let file = self.get(crate::byond_string!("icon"))?;
+
+		match file.raw.tag {
+			raw_types::values::ValueTag::Null => Ok("icon()".to_owned()),
+			_ => Ok(format!("icon('{}')", file.to_string()?)),
+		}
+	}
+
 	/// Creates a Value that references a byond string.
 	/// Will panic if the given string contains null bytes
 	///
@@ -379,6 +525,31 @@ impl Value {
 	}
 }
 
+fn is_type_or_subtype(type_path: &str, parent: &str) -> bool {
+	type_path == parent
+		|| (type_path.starts_with(parent) && type_path[parent.len()..].starts_with('/'))
+}
+
+// Parses `#rgb`, `#rrggbb` and `#rrggbbaa` colors
+fn parse_color(string: &str) -> Option<(u8, u8, u8, Option<u8>)> {
+	let hex = string.strip_prefix('#')?;
+	if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+		return None;
+	}
+
+	let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok();
+
+	match hex.len() {
+		3 => {
+			let short = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok().map(|x| x * 17);
+			Some((short(0)?, short(1)?, short(2)?, None))
+		}
+		6 => Some((channel(0)?, channel(1)?, channel(2)?, None)),
+		8 => Some((channel(0)?, channel(1)?, channel(2)?, Some(channel(3)?))),
+		_ => None,
+	}
+}
+
 impl Clone for Value {
 	fn clone(&self) -> Value {
 		unsafe { Value::from_raw(self.raw) }
@@ -396,3 +567,25 @@ impl fmt::Debug for Value {
 		write!(f, "{:?}", self.raw)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_colors() {
+		assert_eq!(parse_color("#ff8000"), Some((255, 128, 0, None)));
+		assert_eq!(parse_color("#F80"), Some((255, 136, 0, None)));
+		assert_eq!(parse_color("#ff800040"), Some((255, 128, 0, Some(64))));
+		assert_eq!(parse_color("ff8000"), None);
+		assert_eq!(parse_color("#ff80"), None);
+		assert_eq!(parse_color("#gg8000"), None);
+	}
+
+	#[test]
+	fn matches_subtypes() {
+		assert!(is_type_or_subtype("/matrix", "/matrix"));
+		assert!(is_type_or_subtype("/matrix/rotated", "/matrix"));
+		assert!(!is_type_or_subtype("/matrixlike", "/matrix"));
+	}
+}
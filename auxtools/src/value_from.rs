@@ -1,4 +1,5 @@
 use crate::raw_types;
+use crate::runtime;
 use crate::runtime::Runtime;
 use crate::string;
 use crate::List;
@@ -58,6 +59,55 @@ impl From<&Value> for Value {
 	}
 }
 
+impl From<()> for Value {
+	fn from(_: ()) -> Self {
+		Value::null()
+	}
+}
+
+/// BYOND strings end at the first NUL byte, so any in the string are left out. Use [Value::from_string] to get an
+/// error for them instead.
+impl From<&str> for Value {
+	fn from(string: &str) -> Self {
+		let result = if string.contains('\0') {
+			Value::from_string(string.replace('\0', ""))
+		} else {
+			Value::from_string(string)
+		};
+
+		// NUL bytes are the only thing from_string rejects
+		result.unwrap()
+	}
+}
+
+/* Back to Rust */
+
+impl TryFrom<&Value> for f32 {
+	type Error = Runtime;
+	fn try_from(value: &Value) -> Result<Self, Self::Error> {
+		value.as_number()
+	}
+}
+
+impl TryFrom<&Value> for String {
+	type Error = Runtime;
+	fn try_from(value: &Value) -> Result<Self, Self::Error> {
+		value.as_string()
+	}
+}
+
+// Only numbers and null, as those are what DM uses for booleans. Use Value::is_truthy for anything else.
+impl TryFrom<&Value> for bool {
+	type Error = Runtime;
+	fn try_from(value: &Value) -> Result<Self, Self::Error> {
+		match value.raw.tag {
+			raw_types::values::ValueTag::Null => Ok(false),
+			raw_types::values::ValueTag::Number => Ok(value.as_number()? != 0.0),
+			_ => Err(runtime!("Attempt to interpret non-number value as bool")),
+		}
+	}
+}
+
 /* List-y helpers */
 
 // This is broken due to https://github.com/rust-lang/rust/issues/50133
@@ -0,0 +1,98 @@
+//! Where the debug server listens or connects, and reading that out of the argument given to `auxtools_init`.
+//!
+//! The argument is a debugging mode and an address, in either order and both optional, separated by spaces.
+//! The address is a port, which is bound on 127.0.0.1 only, or an `ip:port` to bind somewhere else on purpose.
+//! For example `BACKGROUND 2448` or `LAUNCHED 127.0.0.1:2449`.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+pub const MODES: [&str; 4] = ["NONE", "LAUNCHED", "BACKGROUND", "BLOCK"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+	Tcp(SocketAddr),
+
+	// A unix socket path, or a named pipe name on Windows
+	Local(String),
+}
+
+impl Address {
+	pub fn localhost(port: u16) -> Self {
+		Address::Tcp(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port))
+	}
+}
+
+/// What `auxtools_init` was told to use, with None for anything it wasn't given.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct InitConfig {
+	pub mode: Option<String>,
+	pub address: Option<Address>,
+}
+
+pub fn parse_init_input(input: &str) -> Result<InitConfig, String> {
+	let mut config = InitConfig::default();
+
+	for word in input.split_whitespace() {
+		if MODES.contains(&word) {
+			if config.mode.is_some() {
+				return Err(format!("more than one mode in {:?}", input));
+			}
+
+			config.mode = Some(word.to_owned());
+			continue;
+		}
+
+		if config.address.is_some() {
+			return Err(format!("more than one address in {:?}", input));
+		}
+
+		config.address = Some(parse_address(word)?);
+	}
+
+	Ok(config)
+}
+
+fn parse_address(word: &str) -> Result<Address, String> {
+	if let Ok(port) = word.parse::<u16>() {
+		return Ok(Address::localhost(port));
+	}
+
+	word.parse::<SocketAddr>()
+		.map(Address::Tcp)
+		.map_err(|_| format!("{:?} isn't a mode, port or ip:port", word))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_init_input() {
+		assert_eq!(parse_init_input("").unwrap(), InitConfig::default());
+		assert_eq!(parse_init_input("  ").unwrap(), InitConfig::default());
+
+		let config = parse_init_input("BACKGROUND 2449").unwrap();
+		assert_eq!(config.mode.as_deref(), Some("BACKGROUND"));
+		assert_eq!(config.address, Some(Address::localhost(2449)));
+
+		let config = parse_init_input("0.0.0.0:2448 LAUNCHED").unwrap();
+		assert_eq!(config.mode.as_deref(), Some("LAUNCHED"));
+		assert_eq!(
+			config.address,
+			Some(Address::Tcp("0.0.0.0:2448".parse().unwrap()))
+		);
+
+		let config = parse_init_input("[::1]:2448").unwrap();
+		assert_eq!(config.mode, None);
+		assert_eq!(
+			config.address,
+			Some(Address::Tcp("[::1]:2448".parse().unwrap()))
+		);
+
+		assert!(parse_init_input("background").is_err());
+		assert!(parse_init_input("70000").is_err());
+		assert!(parse_init_input("localhost:2448").is_err());
+		assert!(parse_init_input("BLOCK NONE").is_err());
+		assert!(parse_init_input("2448 2449").is_err());
+	}
+}
@@ -39,19 +39,10 @@ impl dmasm::assembler::AssembleEnv for AssembleEnv {
 	// This is... pretty crazy
 	fn get_type(&mut self, path: &str) -> Option<(u8, u32)> {
 		let path = Value::from_string(path).ok()?;
-		let expr = dmasm::compiler::compile_expr("text2path(name)", &["name"]).unwrap();
-		let assembly = dmasm::assembler::assemble(&expr, &mut Self).unwrap();
-
-		let proc = Proc::find("/proc/auxtools_expr_stub")?;
-		proc.set_bytecode(assembly);
-
-		let res = proc
-			.call(&[&path])
-			.unwrap()
-			.as_list()
-			.unwrap()
+		let res = crate::eval::run_with_args("text2path(name)", &[("name", &path)])
+			.ok()?
 			.get(1)
-			.unwrap();
+			.ok()?;
 
 		if res == Value::null() {
 			return None;
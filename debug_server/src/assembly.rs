@@ -0,0 +1,108 @@
+//! Assembling the text `#disassemble` shows back into bytecode.
+//!
+//! The source is one instruction per line, in the same mnemonics the disassembler emits, with labels as jump
+//! targets. Strings that aren't interned yet are added to the game's string table while assembling.
+
+use std::fmt;
+
+use auxtools::Proc;
+
+use crate::instruction_hooking::without_hooks;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AssemblyError {
+	/// The source isn't valid assembly
+	Parse(String),
+	/// The source parsed but references something that doesn't exist, like an unknown proc or type
+	Assemble(String),
+	/// The proc's current bytecode couldn't be disassembled
+	Disassemble(String),
+	/// Assembling the disassembly of a proc gave different bytecode, starting at `offset`
+	Mismatch { offset: u32 },
+}
+
+impl fmt::Display for AssemblyError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			AssemblyError::Parse(e) => write!(f, "couldn't parse the assembly: {}", e),
+			AssemblyError::Assemble(e) => write!(f, "couldn't assemble: {}", e),
+			AssemblyError::Disassemble(e) => write!(f, "couldn't disassemble: {}", e),
+			AssemblyError::Mismatch { offset } => {
+				write!(f, "reassembled bytecode differs from offset {}", offset)
+			}
+		}
+	}
+}
+
+impl std::error::Error for AssemblyError {}
+
+/// Assembles `source` into bytecode words.
+pub fn assemble(source: &str) -> Result<Vec<u32>, AssemblyError> {
+	let nodes =
+		dmasm::parser::parse(source).map_err(|e| AssemblyError::Parse(format!("{:?}", e)))?;
+
+	dmasm::assembler::assemble(&nodes, &mut crate::assemble_env::AssembleEnv)
+		.map_err(|e| AssemblyError::Assemble(format!("{:?}", e)))
+}
+
+/// Lets procs be given new bytecode from assembly.
+pub trait ProcAssemble {
+	/// Assembles `source` and makes it the proc's bytecode. Nothing changes if assembling fails.
+	fn assemble_and_replace(&self, source: &str) -> Result<(), AssemblyError>;
+
+	/// Disassembles the proc and assembles the result again, failing unless it gives identical bytecode.
+	fn round_trip(&self) -> Result<(), AssemblyError>;
+}
+
+impl ProcAssemble for Proc {
+	fn assemble_and_replace(&self, source: &str) -> Result<(), AssemblyError> {
+		let bytecode = assemble(source)?;
+		self.set_bytecode(bytecode);
+		Ok(())
+	}
+
+	fn round_trip(&self) -> Result<(), AssemblyError> {
+		// Make sure to temporarily remove all breakpoints in this proc
+		let (original, source) = without_hooks(self, || {
+			let bytecode = unsafe { self.bytecode() };
+
+			let mut env = crate::DisassembleEnv;
+			let (nodes, error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+
+			match error {
+				Some(error) => Err(AssemblyError::Disassemble(format!("{:?}", error))),
+				None => Ok((bytecode.to_vec(), dmasm::format_disassembly(&nodes, None))),
+			}
+		})?;
+
+		let reassembled = assemble(&source)?;
+
+		match first_difference(&original, &reassembled) {
+			Some(offset) => Err(AssemblyError::Mismatch { offset }),
+			None => Ok(()),
+		}
+	}
+}
+
+// Offset of the first word that differs, including where one of them ends early
+fn first_difference(a: &[u32], b: &[u32]) -> Option<u32> {
+	match a.iter().zip(b).position(|(a, b)| a != b) {
+		Some(offset) => Some(offset as u32),
+		None if a.len() != b.len() => Some(a.len().min(b.len()) as u32),
+		None => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn finds_first_difference() {
+		assert_eq!(first_difference(&[1, 2, 3], &[1, 2, 3]), None);
+		assert_eq!(first_difference(&[1, 2, 3], &[1, 5, 3]), Some(1));
+		assert_eq!(first_difference(&[1, 2, 3], &[1, 2]), Some(2));
+		assert_eq!(first_difference(&[1], &[1, 2]), Some(1));
+		assert_eq!(first_difference(&[], &[]), None);
+	}
+}
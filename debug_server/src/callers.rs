@@ -0,0 +1,49 @@
+use auxtools::*;
+
+use crate::instruction_hooking::without_hooks;
+
+// Lets auxtools::proc::find_callers see calls, as it needs a disassembler to find them
+#[init(partial)]
+fn callers_init() -> Result<(), String> {
+	proc::set_callee_scanner(callees);
+	Ok(())
+}
+
+// Every proc reference the disassembler resolves is the target of a call
+struct CalleeCollector {
+	inner: crate::DisassembleEnv,
+	callees: Vec<String>,
+}
+
+impl dmasm::disassembler::DisassembleEnv for CalleeCollector {
+	fn get_string_data(&mut self, index: u32) -> Option<Vec<u8>> {
+		self.inner.get_string_data(index)
+	}
+
+	fn get_variable_name(&mut self, index: u32) -> Option<Vec<u8>> {
+		self.inner.get_variable_name(index)
+	}
+
+	fn get_proc_name(&mut self, index: u32) -> Option<String> {
+		let name = self.inner.get_proc_name(index)?;
+		self.callees.push(name.clone());
+		Some(name)
+	}
+
+	fn value_to_string_data(&mut self, tag: u32, data: u32) -> Option<Vec<u8>> {
+		self.inner.value_to_string_data(tag, data)
+	}
+}
+
+fn callees(proc: &Proc) -> Vec<String> {
+	// Breakpoints replace instructions, so look at the original bytecode instead
+	let mut env = CalleeCollector {
+		inner: crate::DisassembleEnv,
+		callees: vec![],
+	};
+	without_hooks(proc, || {
+		let _ = dmasm::disassembler::disassemble(unsafe { proc.bytecode() }, &mut env);
+	});
+
+	env.callees
+}
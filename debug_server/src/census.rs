@@ -0,0 +1,220 @@
+//! Counting live objects by type, for hunting leaks.
+//!
+//! BYOND doesn't tell us where its object tables are, so each kind of object is walked by id instead, reading
+//! `type` through BYOND's own get_variable. Ids that don't refer to anything make it fail rather than crash.
+//! Deleted objects leave holes behind and BYOND hands out the lowest free ids first, so a walk only ends
+//! after [MAX_HOLE] misses in a row. Objects past a bigger hole than that are missed, which is why results are
+//! flagged as approximate. Lists have no `type` to read, so they're checked for with get_length.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use auxtools::raw_types::values::{ValueData, ValueTag};
+use auxtools::*;
+
+use crate::server_types::CensusEntry;
+
+// The kinds of object that have ids of their own, in the order they're walked
+const KINDS: [ValueTag; 5] = [
+	ValueTag::Turf,
+	ValueTag::Obj,
+	ValueTag::Mob,
+	ValueTag::Area,
+	ValueTag::Datum,
+];
+
+const MAX_HOLE: u32 = 10_000;
+
+// Reading the clock costs more than looking at an object, so it's only checked this often
+pub const BATCH: u32 = 256;
+
+/// Walks every object of some kinds by id, one id at a time.
+pub struct ObjectWalk {
+	kinds: &'static [ValueTag],
+	kind: usize,
+	next_id: u32,
+	misses: u32,
+	// Whether any kind ran into MAX_HOLE after objects of it were found, so there could be more past the hole
+	approximate: bool,
+	found_any: bool,
+}
+
+impl ObjectWalk {
+	pub fn new(kinds: &'static [ValueTag]) -> Self {
+		ObjectWalk {
+			kinds,
+			kind: 0,
+			next_id: 0,
+			misses: 0,
+			approximate: false,
+			found_any: false,
+		}
+	}
+
+	/// Whether objects could have been missed, see the [module docs](self).
+	pub fn is_approximate(&self) -> bool {
+		self.approximate
+	}
+
+	pub fn is_done(&self) -> bool {
+		self.kind == self.kinds.len()
+	}
+
+	/// Looks at the next id, giving the object there and its type if there is one. Lists have a null type.
+	pub fn visit(&mut self) -> Option<(raw_types::values::Value, raw_types::values::Value)> {
+		let object = raw_types::values::Value {
+			tag: *self.kinds.get(self.kind)?,
+			data: ValueData { id: self.next_id },
+		};
+		self.next_id += 1;
+
+		let mut typepath = raw_types::values::Value {
+			tag: ValueTag::Null,
+			data: ValueData { id: 0 },
+		};
+		let found = if object.tag == ValueTag::List {
+			let mut length = 0;
+			unsafe { raw_types::funcs::get_length(&mut length, object) == 1 }
+		} else {
+			let type_var = byond_string!("type").get_id();
+			let read =
+				unsafe { raw_types::funcs::get_variable(&mut typepath, object, type_var) == 1 };
+			read && typepath.tag != ValueTag::Null
+		};
+
+		if found {
+			self.misses = 0;
+			self.found_any = true;
+			return Some((object, typepath));
+		}
+
+		self.misses += 1;
+		if self.misses >= MAX_HOLE {
+			self.approximate |= self.found_any;
+			self.kind += 1;
+			self.next_id = 0;
+			self.misses = 0;
+			self.found_any = false;
+		}
+
+		None
+	}
+}
+
+/// A census that can be carried out a bit at a time, so big worlds aren't stalled for seconds.
+pub struct Census {
+	walk: ObjectWalk,
+	// Live objects of each type, by the typepath's tag and id
+	counts: HashMap<(ValueTag, u32), u32>,
+}
+
+impl Census {
+	pub fn new() -> Self {
+		Census {
+			walk: ObjectWalk::new(&KINDS),
+			counts: HashMap::new(),
+		}
+	}
+
+	/// Counts objects for about `budget`. True once every object has been counted.
+	pub fn step(&mut self, budget: Duration) -> bool {
+		let deadline = Instant::now() + budget;
+
+		while !self.walk.is_done() {
+			for _ in 0..BATCH {
+				if let Some((_, typepath)) = self.walk.visit() {
+					let key = (typepath.tag, unsafe { typepath.data.id });
+					*self.counts.entry(key).or_default() += 1;
+				}
+			}
+
+			if Instant::now() >= deadline {
+				return self.walk.is_done();
+			}
+		}
+
+		true
+	}
+
+	/// How many objects have been counted so far.
+	pub fn counted(&self) -> u32 {
+		self.counts.values().sum()
+	}
+
+	/// Whether the count could be short, see the [module docs](self).
+	pub fn is_approximate(&self) -> bool {
+		self.walk.is_approximate()
+	}
+
+	/// The number of live objects of each type path.
+	pub fn counts(&self) -> HashMap<String, u32> {
+		let mut counts = HashMap::new();
+
+		for (&(tag, id), &count) in &self.counts {
+			let typepath = unsafe { Value::new(tag, ValueData { id }) };
+			let path = typepath
+				.to_dmstring()
+				.map(|path| String::from(&path))
+				.unwrap_or_else(|_| format!("<unknown type {:?}:{}>", tag, id));
+
+			*counts.entry(path).or_default() += count;
+		}
+
+		counts
+	}
+}
+
+/// The `top` most common types in `current`, most common first, along with how much each changed since `previous`.
+pub fn compare(
+	current: &HashMap<String, u32>,
+	previous: Option<&HashMap<String, u32>>,
+	top: usize,
+) -> Vec<CensusEntry> {
+	let mut entries: Vec<CensusEntry> = current
+		.iter()
+		.map(|(path, &count)| CensusEntry {
+			type_path: path.clone(),
+			count,
+			delta: previous
+				.map(|previous| count as i64 - previous.get(path).copied().unwrap_or(0) as i64),
+		})
+		.collect();
+
+	entries.sort_by(|a, b| {
+		b.count
+			.cmp(&a.count)
+			.then_with(|| a.type_path.cmp(&b.type_path))
+	});
+	entries.truncate(top);
+	entries
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn counts(entries: &[(&str, u32)]) -> HashMap<String, u32> {
+		entries
+			.iter()
+			.map(|(path, count)| (path.to_string(), *count))
+			.collect()
+	}
+
+	#[test]
+	fn compares_with_the_previous_census() {
+		let first = counts(&[("/obj/item", 10), ("/mob/living", 3), ("/datum/gone", 5)]);
+		let second = counts(&[("/obj/item", 12), ("/mob/living", 3), ("/datum/new", 12)]);
+
+		let entries = compare(&first, None, 10);
+		assert_eq!(entries.len(), 3);
+		assert!(entries.iter().all(|entry| entry.delta.is_none()));
+
+		let entries = compare(&second, Some(&first), 2);
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].type_path, "/datum/new");
+		assert_eq!(entries[0].delta, Some(12));
+		assert_eq!(entries[1].type_path, "/obj/item");
+		assert_eq!(entries[1].count, 12);
+		assert_eq!(entries[1].delta, Some(2));
+	}
+}
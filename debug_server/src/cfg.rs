@@ -0,0 +1,336 @@
+//! Control flow graphs of procs, built from their disassembly.
+//!
+//! Blocks start at jump targets and after any instruction that can jump somewhere, and end at the next
+//! such point. Bytecode that couldn't be disassembled, and jumps into it, end up in a single opaque block
+//! so the rest of the graph can still be shown.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use auxtools::Proc;
+use dmasm::Instruction;
+
+use crate::instruction_hooking::without_hooks;
+use crate::jump_labels::{jump_targets, JumpLabels};
+use crate::operands::OperandStyle;
+
+type Nodes<'a> = [dmasm::Node<dmasm::DebugData<'a>>];
+
+// Whether execution never continues past the instruction
+fn terminates(ins: &Instruction) -> bool {
+	matches!(
+		ins,
+		Instruction::End | Instruction::Ret | Instruction::Jmp(_) | Instruction::Jmp2(_)
+	)
+}
+
+// Whether the instruction's jump target is where execution goes when something is thrown
+fn handles_exceptions(ins: &Instruction) -> bool {
+	matches!(ins, Instruction::Try(_) | Instruction::TryNoValue(_))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+	/// The target of a jump
+	Branch,
+	/// The block right after, when execution continues past the last instruction
+	Fallthrough,
+	/// Where execution goes when a runtime happens inside a `try`
+	Exception,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+	pub from: usize,
+	pub to: usize,
+	pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+	/// Offset of the block's first instruction, if it has any
+	pub offset: Option<u32>,
+	/// The block's instructions as they appear in `#disassemble`
+	pub text: String,
+	/// Whether this block stands for bytecode that couldn't be disassembled
+	pub opaque: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ControlFlowGraph {
+	/// The entry point is the first block
+	pub blocks: Vec<BasicBlock>,
+	pub edges: Vec<Edge>,
+}
+
+// What a block does after its last instruction
+struct Exits {
+	targets: Vec<(String, EdgeKind)>,
+	falls_through: bool,
+}
+
+impl ControlFlowGraph {
+	/// Builds the graph of a disassembly. `error` is whatever stopped the disassembler early, if anything.
+	pub fn from_nodes(nodes: &Nodes, error: Option<String>) -> Self {
+		let labels = JumpLabels::new(nodes);
+
+		let mut graph = ControlFlowGraph::default();
+		let mut exits: Vec<Exits> = vec![];
+		let mut label_blocks: HashMap<String, usize> = HashMap::new();
+		let mut start = 0;
+		let mut end_offset = 0;
+
+		for (i, node) in nodes.iter().enumerate() {
+			match node {
+				dmasm::Node::Label(label) => {
+					// Something jumps here, so a new block starts unless this one is still empty
+					if start < i && contains_instructions(&nodes[start..i]) {
+						graph.push_block(&labels, &nodes[start..i]);
+						exits.push(Exits {
+							targets: vec![],
+							falls_through: true,
+						});
+						start = i;
+					}
+
+					label_blocks.insert(label.clone(), graph.blocks.len());
+				}
+
+				dmasm::Node::Instruction(ins, debug) => {
+					end_offset = debug.offset + debug.bytecode.len() as u32;
+
+					let targets = jump_targets(ins);
+					let terminates = terminates(ins);
+
+					if targets.is_empty() && !terminates {
+						continue;
+					}
+
+					let kind = if handles_exceptions(ins) {
+						EdgeKind::Exception
+					} else {
+						EdgeKind::Branch
+					};
+
+					graph.push_block(&labels, &nodes[start..=i]);
+					exits.push(Exits {
+						targets: targets.into_iter().map(|target| (target, kind)).collect(),
+						falls_through: !terminates,
+					});
+					start = i + 1;
+				}
+
+				dmasm::Node::Comment(_) => {}
+			}
+		}
+
+		if contains_instructions(&nodes[start..]) {
+			graph.push_block(&labels, &nodes[start..]);
+			exits.push(Exits {
+				targets: vec![],
+				falls_through: error.is_some(),
+			});
+		}
+
+		// Undisassembled bytecode and jumps that land in it all go to the same opaque block
+		let mut opaque = None;
+		let mut opaque_block = |graph: &mut ControlFlowGraph| {
+			*opaque.get_or_insert_with(|| {
+				graph.blocks.push(BasicBlock {
+					offset: None,
+					text: match &error {
+						Some(error) => format!(
+							"bytecode from offset {} couldn't be disassembled\n{}",
+							end_offset, error
+						),
+						None => "jump target outside of the disassembly".to_owned(),
+					},
+					opaque: true,
+				});
+				graph.blocks.len() - 1
+			})
+		};
+
+		let block_count = exits.len();
+
+		for (from, exits) in exits.into_iter().enumerate() {
+			for (target, kind) in exits.targets {
+				let to = match label_blocks.get(&target) {
+					Some(&to) if to < block_count => to,
+					_ => opaque_block(&mut graph),
+				};
+				graph.edges.push(Edge { from, to, kind });
+			}
+
+			if exits.falls_through {
+				let to = if from + 1 < block_count {
+					from + 1
+				} else {
+					opaque_block(&mut graph)
+				};
+				graph.edges.push(Edge {
+					from,
+					to,
+					kind: EdgeKind::Fallthrough,
+				});
+			}
+		}
+
+		graph
+	}
+
+	fn push_block(&mut self, labels: &JumpLabels, nodes: &Nodes) {
+		let offset = nodes.iter().find_map(|node| match node {
+			dmasm::Node::Instruction(_, debug) => Some(debug.offset),
+			_ => None,
+		});
+
+		self.blocks.push(BasicBlock {
+			offset,
+			text: labels.format(nodes, None, OperandStyle::Hidden),
+			opaque: false,
+		});
+	}
+
+	/// The graph in Graphviz's DOT language, named `name`.
+	pub fn to_dot(&self, name: &str) -> String {
+		let mut dot = String::new();
+
+		writeln!(dot, "digraph \"{}\" {{", escape(name)).unwrap();
+		writeln!(dot, "\tnode [shape=box, fontname=\"monospace\"];").unwrap();
+
+		for (id, block) in self.blocks.iter().enumerate() {
+			let mut label = escape(block.text.trim_end()).replace('\n', "\\l");
+			label.push_str("\\l");
+
+			let extra = match block.offset {
+				_ if block.opaque => ", style=dashed".to_owned(),
+				Some(offset) => format!(", tooltip=\"offset {}\"", offset),
+				None => String::new(),
+			};
+
+			writeln!(dot, "\tb{} [label=\"{}\"{}];", id, label, extra).unwrap();
+		}
+
+		for edge in &self.edges {
+			let attributes = match edge.kind {
+				EdgeKind::Branch => "[label=\"branch\"]",
+				EdgeKind::Fallthrough => "[style=dashed]",
+				EdgeKind::Exception => "[label=\"catch\", color=red]",
+			};
+
+			writeln!(dot, "\tb{} -> b{} {};", edge.from, edge.to, attributes).unwrap();
+		}
+
+		dot.push_str("}\n");
+		dot
+	}
+}
+
+/// Lets procs build their own control flow graph.
+pub trait ProcControlFlow {
+	fn control_flow_graph(&self) -> ControlFlowGraph;
+}
+
+impl ProcControlFlow for Proc {
+	fn control_flow_graph(&self) -> ControlFlowGraph {
+		// Make sure to temporarily remove all breakpoints in this proc
+		without_hooks(self, || {
+			let bytecode = unsafe { self.bytecode() };
+
+			let mut env = crate::DisassembleEnv;
+			let (nodes, error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+			ControlFlowGraph::from_nodes(&nodes, error.map(|error| format!("{:?}", error)))
+		})
+	}
+}
+
+fn contains_instructions(nodes: &Nodes) -> bool {
+	nodes
+		.iter()
+		.any(|node| matches!(node, dmasm::Node::Instruction(..)))
+}
+
+fn escape(text: &str) -> String {
+	text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use dmasm::operands::Label;
+	use dmasm::{DebugData, Instruction, Node};
+
+	fn instruction(ins: Instruction, offset: u32) -> Node<DebugData<'static>> {
+		Node::Instruction(
+			ins,
+			DebugData {
+				offset,
+				bytecode: &[0, 0],
+			},
+		)
+	}
+
+	fn edges(graph: &ControlFlowGraph) -> Vec<(usize, usize, EdgeKind)> {
+		graph
+			.edges
+			.iter()
+			.map(|edge| (edge.from, edge.to, edge.kind))
+			.collect()
+	}
+
+	#[test]
+	fn splits_blocks_at_branches_and_targets() {
+		let nodes = vec![
+			instruction(Instruction::DbgLine(1), 0),
+			instruction(Instruction::Jz(Label("LAB_0008".to_owned())), 2),
+			instruction(Instruction::DbgLine(2), 4),
+			instruction(Instruction::Jmp(Label("LAB_000A".to_owned())), 6),
+			Node::Label("LAB_0008".to_owned()),
+			instruction(Instruction::DbgLine(3), 8),
+			Node::Label("LAB_000A".to_owned()),
+			instruction(Instruction::Ret, 10),
+		];
+
+		let graph = ControlFlowGraph::from_nodes(&nodes, None);
+		let offsets: Vec<_> = graph.blocks.iter().map(|block| block.offset).collect();
+
+		assert_eq!(offsets, vec![Some(0), Some(4), Some(8), Some(10)]);
+		assert_eq!(
+			edges(&graph),
+			vec![
+				(0, 2, EdgeKind::Branch),
+				(0, 1, EdgeKind::Fallthrough),
+				(1, 3, EdgeKind::Branch),
+				(2, 3, EdgeKind::Fallthrough),
+			]
+		);
+	}
+
+	#[test]
+	fn undisassembled_bytecode_is_opaque() {
+		let nodes = vec![
+			instruction(Instruction::Jz(Label("LAB_0040".to_owned())), 0),
+			instruction(Instruction::DbgLine(1), 2),
+		];
+
+		let graph = ControlFlowGraph::from_nodes(&nodes, Some("UnknownOp".to_owned()));
+
+		assert_eq!(graph.blocks.len(), 3);
+		assert!(graph.blocks[2].opaque);
+		assert!(graph.blocks[2].text.contains("from offset 4"));
+		assert_eq!(
+			edges(&graph),
+			vec![
+				(0, 2, EdgeKind::Branch),
+				(0, 1, EdgeKind::Fallthrough),
+				(1, 2, EdgeKind::Fallthrough),
+			]
+		);
+
+		let dot = graph.to_dot("/proc/foo");
+		assert!(dot.starts_with("digraph \"/proc/foo\" {\n"));
+		assert!(dot.contains("\tb2 [label=\"bytecode from offset 4 couldn't be disassembled\\lUnknownOp\\l\", style=dashed];"));
+		assert!(dot.contains("\tb0 -> b2 [label=\"branch\"];"));
+	}
+}
@@ -5,17 +5,19 @@ use auxtools::*;
 
 static mut STRING_PTR: *mut *const c_char = std::ptr::null_mut();
 
+// This feature soft-fails. There's no signature for Linux servers yet.
 #[init(full)]
 fn ckey_override_init() -> Result<(), String> {
-	let byondcore = sigscan::Scanner::for_module(BYONDCORE).unwrap();
-
-	// This feature soft-fails
 	#[cfg(windows)]
-	if let Some(ptr) = byondcore.find(signature!(
-		"68 ?? ?? ?? ?? 50 E8 ?? ?? ?? ?? 83 C4 0C 8D 8D ?? ?? ?? ?? E8 ?? ?? ?? ?? 8B 85 ?? ?? ?? ??"
-	)) {
-		unsafe {
-			STRING_PTR = ptr.add(1) as *mut *const c_char;
+	{
+		let byondcore = sigscan::Scanner::for_module(BYONDCORE).unwrap();
+
+		if let Some(ptr) = byondcore.find(signature!(
+			"68 ?? ?? ?? ?? 50 E8 ?? ?? ?? ?? 83 C4 0C 8D 8D ?? ?? ?? ?? E8 ?? ?? ?? ?? 8B 85 ?? ?? ?? ??"
+		)) {
+			unsafe {
+				STRING_PTR = ptr.add(1) as *mut *const c_char;
+			}
 		}
 	}
 
@@ -24,11 +26,16 @@ fn ckey_override_init() -> Result<(), String> {
 
 #[derive(Debug)]
 pub enum Error {
+	UnsupportedPlatform,
 	UnsupportedByondVersion,
 	InvalidString,
 }
 
 pub fn override_guest_ckey(name: &str) -> Result<(), Error> {
+	if cfg!(unix) {
+		return Err(Error::UnsupportedPlatform);
+	}
+
 	unsafe {
 		if STRING_PTR.is_null() {
 			return Err(Error::UnsupportedByondVersion);
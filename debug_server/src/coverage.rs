@@ -0,0 +1,395 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::rc::Rc;
+
+use auxtools::raw_types::procs::ProcId;
+use auxtools::*;
+use serde::Serialize;
+
+use crate::instruction_hooking::{
+	hook_instruction_once, unhook_instruction_callback, without_hooks, HookId,
+};
+use crate::server_types::ProcRef;
+
+// Which offsets of each proc have been executed, indexed by offset. Only allocated while coverage is running.
+thread_local!(static COVERAGE: RefCell<Option<HashMap<ProcId, Vec<bool>>>> = RefCell::new(None));
+
+// Lines executed in each of the procs line coverage was started for. Only allocated while it's running.
+thread_local!(static LINE_COVERAGE: RefCell<Option<HashMap<ProcId, HashSet<u32>>>> = RefCell::new(None));
+
+// Every DbgLine hooked by hooked coverage. Only allocated while it's running.
+thread_local!(static HOOKED_COVERAGE: RefCell<Option<HookedCoverage>> = RefCell::new(None));
+
+#[shutdown]
+fn coverage_shutdown() {
+	COVERAGE.with(|coverage| *coverage.borrow_mut() = None);
+	LINE_COVERAGE.with(|coverage| *coverage.borrow_mut() = None);
+	// The instruction hooks are all removed on shutdown anyway
+	HOOKED_COVERAGE.with(|coverage| *coverage.borrow_mut() = None);
+}
+
+byond_ffi_fn! { auxtools_coverage_start() -> String {
+	match start_hooked() {
+		Ok(count) => format!("SUCCESS ({} lines)", count),
+		Err(e) => format!("FAILED ({})", e),
+	}
+} }
+
+byond_ffi_fn! { auxtools_coverage_stop(lcov_path: &str) -> String {
+	match stop_hooked() {
+		Some(report) => match std::fs::write(lcov_path, report.to_lcov()) {
+			Ok(()) => "SUCCESS".to_owned(),
+			Err(e) => format!("FAILED (couldn't write {}: {})", lcov_path, e),
+		},
+		None => "FAILED (coverage isn't running)".to_owned(),
+	}
+} }
+
+/// Starts recording which instructions are executed.
+///
+/// Recording happens in the instruction hook, so every instruction executed until the guard is stopped
+/// pays for a map lookup.
+pub fn start() -> CoverageGuard {
+	COVERAGE.with(|coverage| {
+		coverage.borrow_mut().get_or_insert_with(HashMap::new);
+	});
+
+	CoverageGuard { _private: () }
+}
+
+// Called by the instruction hook for every instruction BYOND executes
+pub fn record(ctx: *mut raw_types::procs::ExecutionContext) {
+	COVERAGE.with(|coverage| {
+		if let Some(coverage) = coverage.borrow_mut().as_mut() {
+			unsafe {
+				let proc = (*(*ctx).proc_instance).proc;
+				let offset = (*ctx).bytecode_offset as usize;
+
+				let covered = coverage.entry(proc).or_insert_with(|| {
+					let len = Proc::from_id(proc)
+						.map(|proc| proc.bytecode().len())
+						.unwrap_or(0);
+					vec![false; len]
+				});
+
+				if let Some(covered) = covered.get_mut(offset) {
+					*covered = true;
+				}
+			}
+		}
+	});
+}
+
+/// Starts recording which lines of `procs` are executed, replacing any line coverage already running.
+///
+/// Unlike [start], only `DbgLine` instructions of these procs do any work and nothing is ever paused,
+/// so this is cheap enough to leave running during real gameplay.
+pub fn start_lines(procs: &[Proc]) {
+	let procs = procs.iter().map(|proc| (proc.id, HashSet::new())).collect();
+	LINE_COVERAGE.with(|coverage| *coverage.borrow_mut() = Some(procs));
+}
+
+/// Stops recording lines and returns the ones executed in each proc given to [start_lines].
+pub fn stop_lines() -> HashMap<ProcRef, HashSet<u32>> {
+	let coverage = LINE_COVERAGE
+		.with(|coverage| coverage.borrow_mut().take())
+		.unwrap_or_default();
+
+	coverage
+		.into_iter()
+		.filter_map(|(id, lines)| {
+			let proc = Proc::from_id(id)?;
+			let proc = ProcRef {
+				override_id: proc.override_id(),
+				path: proc.path,
+			};
+			Some((proc, lines))
+		})
+		.collect()
+}
+
+pub fn is_recording_lines() -> bool {
+	LINE_COVERAGE.with(|coverage| coverage.borrow().is_some())
+}
+
+// Called by the instruction hook for every DbgLine BYOND executes
+pub fn record_line(ctx: *mut raw_types::procs::ExecutionContext, line: u32) {
+	LINE_COVERAGE.with(|coverage| {
+		if let Some(coverage) = coverage.borrow_mut().as_mut() {
+			let proc = unsafe { (*(*ctx).proc_instance).proc };
+
+			if let Some(lines) = coverage.get_mut(&proc) {
+				lines.insert(line);
+			}
+		}
+	});
+}
+
+struct HookedCoverage {
+	// The file and line of each hooked DbgLine
+	lines: HashMap<(ProcId, u32), (Rc<str>, u32)>,
+	// The hooks that haven't run yet
+	pending: HashMap<(ProcId, u32), HookId>,
+}
+
+/// Starts recording which lines of every proc run, returning how many lines there are.
+///
+/// Each DbgLine gets a hook that removes itself the first time it runs, so a line costs nothing once it has
+/// been covered. Procs without a DbgFile have nowhere to report their lines to and are left alone.
+/// Hooking everything takes a while in big codebases.
+pub fn start_hooked() -> Result<usize, String> {
+	if HOOKED_COVERAGE.with(|coverage| coverage.borrow().is_some()) {
+		return Err("Coverage is already running".to_owned());
+	}
+
+	let mut coverage = HookedCoverage {
+		lines: HashMap::new(),
+		pending: HashMap::new(),
+	};
+	let mut files: HashMap<String, Rc<str>> = HashMap::new();
+
+	for proc in auxtools::proc::all() {
+		for (offset, file, line) in debug_lines(&proc) {
+			let file = files
+				.entry(file)
+				.or_insert_with_key(|file| Rc::from(file.as_str()));
+
+			if let Ok(id) = hook_instruction_once(&proc, offset, Box::new(record_hooked)) {
+				coverage
+					.lines
+					.insert((proc.id, offset), (file.clone(), line));
+				coverage.pending.insert((proc.id, offset), id);
+			}
+		}
+	}
+
+	let count = coverage.lines.len();
+	HOOKED_COVERAGE.with(|cell| *cell.borrow_mut() = Some(coverage));
+	Ok(count)
+}
+
+fn record_hooked(ctx: &debug::SafeExecutionContext) {
+	HOOKED_COVERAGE.with(|coverage| {
+		if let Some(coverage) = coverage.borrow_mut().as_mut() {
+			coverage.pending.remove(&(ctx.proc().id, ctx.offset()));
+		}
+	});
+}
+
+/// Stops hooked coverage, removing the hooks of every line that never ran. None if it wasn't running.
+pub fn stop_hooked() -> Option<LineCoverage> {
+	let coverage = HOOKED_COVERAGE.with(|coverage| coverage.borrow_mut().take())?;
+
+	for (&(id, offset), &hook) in &coverage.pending {
+		if let Some(proc) = Proc::from_id(id) {
+			let _ = unhook_instruction_callback(&proc, offset, hook);
+		}
+	}
+
+	let mut files: BTreeMap<String, BTreeMap<u32, bool>> = BTreeMap::new();
+	for (key, (file, line)) in &coverage.lines {
+		let hit = !coverage.pending.contains_key(key);
+		let covered = files
+			.entry(file.to_string())
+			.or_default()
+			.entry(*line)
+			.or_default();
+		*covered |= hit;
+	}
+
+	Some(LineCoverage { files })
+}
+
+// (offset, file, line) of each DbgLine in the proc that comes after a DbgFile
+fn debug_lines(proc: &Proc) -> Vec<(u32, String, u32)> {
+	without_hooks(proc, || {
+		let bytecode = unsafe { proc.bytecode() };
+
+		let mut env = crate::DisassembleEnv;
+		let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+
+		let mut file = None;
+		let mut lines = vec![];
+
+		for node in &nodes {
+			match node {
+				dmasm::Node::Instruction(dmasm::Instruction::DbgFile(name), _) => {
+					file = Some(String::from_utf8_lossy(&name.0).into_owned());
+				}
+
+				dmasm::Node::Instruction(dmasm::Instruction::DbgLine(line), debug) => {
+					if let Some(file) = &file {
+						lines.push((debug.offset, file.clone(), *line));
+					}
+				}
+
+				_ => {}
+			}
+		}
+
+		lines
+	})
+}
+
+/// Which lines of each file ran, from [stop_hooked].
+pub struct LineCoverage {
+	// Whether each line ran, by file
+	files: BTreeMap<String, BTreeMap<u32, bool>>,
+}
+
+impl LineCoverage {
+	pub fn lines_found(&self) -> usize {
+		self.files.values().map(BTreeMap::len).sum()
+	}
+
+	pub fn lines_hit(&self) -> usize {
+		self.files
+			.values()
+			.map(|lines| lines.values().filter(|hit| **hit).count())
+			.sum()
+	}
+
+	/// The coverage in the lcov tracefile format. Lines that ran are counted as running once.
+	pub fn to_lcov(&self) -> String {
+		let mut lcov = String::new();
+
+		for (file, lines) in &self.files {
+			let _ = writeln!(lcov, "TN:");
+			let _ = writeln!(lcov, "SF:{}", file);
+
+			for (line, hit) in lines {
+				let _ = writeln!(lcov, "DA:{},{}", line, *hit as u32);
+			}
+
+			let _ = writeln!(lcov, "LF:{}", lines.len());
+			let _ = writeln!(lcov, "LH:{}", lines.values().filter(|hit| **hit).count());
+			let _ = writeln!(lcov, "end_of_record");
+		}
+
+		lcov
+	}
+}
+
+/// Keeps coverage running until [stop](CoverageGuard::stop) is called.
+pub struct CoverageGuard {
+	_private: (),
+}
+
+impl CoverageGuard {
+	/// Stops recording and reports on every proc that executed at least one instruction.
+	pub fn stop(self) -> CoverageReport {
+		let coverage = COVERAGE
+			.with(|coverage| coverage.borrow_mut().take())
+			.unwrap_or_default();
+
+		let mut procs: Vec<ProcCoverage> = coverage
+			.into_iter()
+			.filter_map(|(id, covered)| {
+				let proc = Proc::from_id(id)?;
+
+				let instructions = instruction_offsets(&proc)
+					.into_iter()
+					.map(|offset| InstructionCoverage {
+						offset,
+						covered: covered.get(offset as usize).copied().unwrap_or(false),
+					})
+					.collect();
+
+				Some(ProcCoverage {
+					override_id: proc.override_id(),
+					proc_path: proc.path,
+					instructions,
+				})
+			})
+			.collect();
+
+		procs.sort_by(|a, b| {
+			(&a.proc_path, a.override_id).cmp(&(&b.proc_path, b.override_id))
+		});
+
+		CoverageReport { procs }
+	}
+}
+
+fn instruction_offsets(proc: &Proc) -> Vec<u32> {
+	// Make sure to temporarily remove all breakpoints in this proc
+	without_hooks(proc, || {
+		let bytecode = unsafe { proc.bytecode() };
+
+		let mut env = crate::DisassembleEnv;
+		let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+
+		nodes
+			.iter()
+			.filter_map(|node| match node {
+				dmasm::Node::Instruction(_, debug) => Some(debug.offset),
+				_ => None,
+			})
+			.collect()
+	})
+}
+
+#[derive(Serialize)]
+pub struct CoverageReport {
+	pub procs: Vec<ProcCoverage>,
+}
+
+#[derive(Serialize)]
+pub struct ProcCoverage {
+	pub proc_path: String,
+	pub override_id: u32,
+	pub instructions: Vec<InstructionCoverage>,
+}
+
+#[derive(Serialize)]
+pub struct InstructionCoverage {
+	pub offset: u32,
+	pub covered: bool,
+}
+
+impl CoverageReport {
+	pub fn summary(&self) -> String {
+		let mut summary = String::new();
+
+		for proc in &self.procs {
+			let covered = proc.instructions.iter().filter(|x| x.covered).count();
+			summary.push_str(&format!(
+				"{} (#{}): {}/{} instructions\n",
+				proc.proc_path,
+				proc.override_id,
+				covered,
+				proc.instructions.len()
+			));
+		}
+
+		summary
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn lcov_lists_every_line() {
+		let mut files = BTreeMap::new();
+		files.insert(
+			"code/a.dm".to_owned(),
+			vec![(3, true), (4, false), (7, true)].into_iter().collect(),
+		);
+		files.insert(
+			"code/b.dm".to_owned(),
+			vec![(1, false)].into_iter().collect(),
+		);
+
+		let coverage = LineCoverage { files };
+		assert_eq!(coverage.lines_found(), 4);
+		assert_eq!(coverage.lines_hit(), 2);
+		assert_eq!(
+			coverage.to_lcov(),
+			"TN:\nSF:code/a.dm\nDA:3,1\nDA:4,0\nDA:7,1\nLF:3\nLH:2\nend_of_record\n\
+			 TN:\nSF:code/b.dm\nDA:1,0\nLF:1\nLH:0\nend_of_record\n"
+		);
+	}
+}
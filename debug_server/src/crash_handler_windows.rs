@@ -8,8 +8,14 @@ use winapi::{
 
 extern "system" fn exception_filter(_: *mut EXCEPTION_POINTERS) -> LONG {
 	unsafe {
+		// Crashes on other threads have nothing to do with DM code, and the debugger can't safely look at the game from there
+		let guard = match game_thread::GameThreadGuard::try_acquire() {
+			Some(guard) => guard,
+			None => return EXCEPTION_EXECUTE_HANDLER,
+		};
+
 		if let Some(dbg) = &mut *DEBUG_SERVER.get() {
-			let ctx = *raw_types::funcs::CURRENT_EXECUTION_CONTEXT;
+			let ctx = guard.current_execution_context();
 
 			dbg.handle_breakpoint(
 				ctx,
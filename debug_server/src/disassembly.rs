@@ -0,0 +1,95 @@
+//! Structured disassembly output for external tools.
+//!
+//! [disassemble_json] returns an array with one object per instruction:
+//!
+//! ```text
+//! [
+//!     {
+//!         "offset": 0,              // offset of the opcode within the proc's bytecode
+//!         "size": 2,                // number of bytecode words taken up by the instruction
+//!         "opcode": "DbgLine",      // name of the instruction as known to dmasm
+//!         "text": "DbgLine 12",     // the instruction as it appears in `#disassemble`
+//!         "operands": [
+//!             { "raw": 12 }
+//!         ]
+//!     },
+//!     ...
+//! ]
+//! ```
+//!
+//! Each operand word is an object with its `raw` value. When the word was resolved to something
+//! more meaningful during disassembly, one of these keys is present alongside it:
+//! - `"string"`: the contents of a string table entry
+//! - `"variable"`: the name of a variable
+//! - `"proc"`: the path of a proc
+//! - `"value"`: the string form of a constant value, attached to its tag (the word after it holds the data)
+//!
+//! Labels are also emitted as `{ "label": "name" }` elements so jump targets can be rendered.
+
+use auxtools::Proc;
+use serde_json::{json, Value};
+
+use crate::instruction_formatter::mnemonic;
+use crate::instruction_hooking::without_hooks;
+use crate::operands::{instruction_operands, InstructionOperand};
+
+fn operands_json(words: &[u32]) -> Vec<Value> {
+	let mut operands = vec![];
+
+	for operand in instruction_operands(words) {
+		match operand {
+			InstructionOperand::Raw(word) => operands.push(json!({ "raw": word })),
+			InstructionOperand::String { id, contents } => {
+				operands.push(json!({ "raw": id, "string": contents }))
+			}
+			InstructionOperand::Variable { id, name } => {
+				operands.push(json!({ "raw": id, "variable": name }))
+			}
+			InstructionOperand::Proc { id, path } => {
+				operands.push(json!({ "raw": id, "proc": path }))
+			}
+			InstructionOperand::Value { tag, data, text } => {
+				operands.push(json!({ "raw": tag, "value": text }));
+				operands.push(json!({ "raw": data }));
+			}
+		}
+	}
+
+	operands
+}
+
+/// Disassembles a proc into the structure described in the [module documentation](self).
+pub fn disassemble_json(proc: &Proc) -> Value {
+	// Make sure to temporarily remove all breakpoints in this proc
+	without_hooks(proc, || {
+		let bytecode = unsafe { proc.bytecode() };
+
+		let mut env = crate::DisassembleEnv;
+		let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+
+		let mut elements = vec![];
+
+		for node in &nodes {
+			match node {
+				dmasm::Node::Label(name) => elements.push(json!({ "label": name })),
+
+				dmasm::Node::Instruction(ins, debug) => {
+					let opcode = mnemonic(ins);
+					let text = dmasm::format_disassembly(std::slice::from_ref(node), None);
+
+					elements.push(json!({
+						"offset": debug.offset,
+						"size": debug.bytecode.len(),
+						"opcode": opcode,
+						"text": text.trim(),
+						"operands": operands_json(debug.bytecode),
+					}));
+				}
+
+				dmasm::Node::Comment(_) => {}
+			}
+		}
+
+		Value::Array(elements)
+	})
+}
@@ -0,0 +1,35 @@
+use auxtools::*;
+
+// Evaluating DM works by compiling it with dmasm and swapping the result in as the bytecode of this proc,
+// which the game has to declare as `/proc/auxtools_expr_stub()`.
+//
+// Anything DM code can do, an expression can do too: it runs with the same permissions as the game itself.
+// Only ever evaluate code from somewhere trusted, like a debug client that authenticated with AUXTOOLS_DEBUG_TOKEN.
+const STUB_PROC: &str = "/proc/auxtools_expr_stub";
+
+// Compiles and runs `code`, with each of `args` available to it as a variable of the given name.
+// Returns a list of the expression's value followed by the final values of `args`, as the expression may assign to them.
+//
+// Runtimes inside the expression don't fail this, they reach the game's usual runtime handling instead.
+pub fn run_with_args(code: &str, args: &[(&str, &Value)]) -> Result<List, Runtime> {
+	let arg_names: Vec<&str> = args.iter().map(|(name, _)| *name).collect();
+	let arg_values: Vec<&Value> = args.iter().map(|(_, value)| *value).collect();
+
+	let expr = dmasm::compiler::compile_expr(code, &arg_names).map_err(|e| runtime!("{}", e))?;
+
+	let assembly = dmasm::assembler::assemble(&expr, &mut crate::assemble_env::AssembleEnv)
+		.map_err(|e| runtime!("expression {} failed to assemble: {:#?}", code, e))?;
+
+	let proc = Proc::find(STUB_PROC)
+		.ok_or_else(|| runtime!("Couldn't find {}! DM evaluation not available.", STUB_PROC))?;
+
+	proc.set_bytecode(assembly);
+
+	let result = proc
+		.call(&arg_values)
+		.map_err(|_| runtime!("Value::call failed when evaluating expression {}", code))?;
+
+	result
+		.as_list()
+		.map_err(|_| runtime!("{} didn't return a list", STUB_PROC))
+}
@@ -0,0 +1,302 @@
+use std::error::Error;
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::server_types::{Request, Response, PROTOCOL_VERSION};
+
+// Clients wanting JSON send this before anything else.
+// Bincode clients just send their first message, so these bytes are read as its length.
+pub const JSON_MAGIC: [u8; 4] = *b"JSON";
+
+// How messages are laid out on the wire. Both directions of a connection use the same framing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Framing {
+	// A u32 (little-endian) length followed by that many bytes of bincode
+	Bincode,
+
+	// Like Bincode, but the length is followed by a MESSAGE_* byte saying whether the rest is compressed.
+	// Only messages of at least COMPRESSION_THRESHOLD bytes are worth compressing.
+	CompressedBincode,
+
+	// One JSON document per line, using serde's default representation of Request/Response
+	#[cfg(feature = "json")]
+	Json,
+}
+
+// Sent by clients that know about protocol versions, before anything else:
+// HELLO_MAGIC, then the client's PROTOCOL_VERSION and HELLO_FLAG_* bits as little-endian u32s.
+// The server answers in the same format. This layout must never change.
+pub const HELLO_MAGIC: [u8; 4] = *b"AUXD";
+pub const HELLO_FLAG_JSON: u32 = 1;
+pub const HELLO_FLAG_COMPRESSION: u32 = 2;
+
+const MESSAGE_RAW: u8 = 0;
+const MESSAGE_DEFLATE: u8 = 1;
+const COMPRESSION_THRESHOLD: usize = 64 * 1024;
+
+#[derive(Clone)]
+pub struct Handshake {
+	pub framing: Framing,
+
+	// None for clients that predate the hello
+	pub client_version: Option<u32>,
+
+	// Bincode clients that skip the hello start with the length of their first message, which gets handed back here
+	pub first_len: Option<[u8; 4]>,
+}
+
+impl Handshake {
+	// Reads the first bytes a client sends to find out how it wants to talk
+	pub fn read<S: Read + Write>(stream: &mut S) -> Result<Self, Box<dyn Error>> {
+		let mut first_bytes = [0u8; 4];
+		stream.read_exact(&mut first_bytes)?;
+
+		if first_bytes == HELLO_MAGIC {
+			return Self::read_hello(stream);
+		}
+
+		if first_bytes == JSON_MAGIC {
+			#[cfg(feature = "json")]
+			return Ok(Handshake {
+				framing: Framing::Json,
+				client_version: None,
+				first_len: None,
+			});
+
+			#[cfg(not(feature = "json"))]
+			return Err(
+				"client wants JSON but debug_server was built without the json feature".into(),
+			);
+		}
+
+		Ok(Handshake {
+			framing: Framing::Bincode,
+			client_version: None,
+			first_len: Some(first_bytes),
+		})
+	}
+
+	fn read_hello<S: Read + Write>(stream: &mut S) -> Result<Self, Box<dyn Error>> {
+		let mut version_bytes = [0u8; 4];
+		let mut flags_bytes = [0u8; 4];
+		stream.read_exact(&mut version_bytes)?;
+		stream.read_exact(&mut flags_bytes)?;
+
+		let client_version = u32::from_le_bytes(version_bytes);
+		let flags = u32::from_le_bytes(flags_bytes);
+
+		let framing = Self::choose_framing(flags);
+
+		// Always answer, even if the versions don't match, so the client can tell its user what's wrong
+		Self::write_hello(stream, framing)?;
+
+		#[cfg(not(feature = "json"))]
+		if flags & HELLO_FLAG_JSON != 0 {
+			return Err(
+				"client wants JSON but debug_server was built without the json feature".into(),
+			);
+		}
+
+		Ok(Handshake {
+			framing,
+			client_version: Some(client_version),
+			first_len: None,
+		})
+	}
+
+	// Compression only applies to bincode, as JSON is for clients that want something simple
+	fn choose_framing(flags: u32) -> Framing {
+		if flags & HELLO_FLAG_JSON != 0 {
+			#[cfg(feature = "json")]
+			return Framing::Json;
+		}
+
+		if flags & HELLO_FLAG_COMPRESSION != 0 {
+			return Framing::CompressedBincode;
+		}
+
+		Framing::Bincode
+	}
+
+	fn write_hello<S: Write>(stream: &mut S, framing: Framing) -> Result<(), Box<dyn Error>> {
+		let flags: u32 = match framing {
+			Framing::Bincode => 0,
+			Framing::CompressedBincode => HELLO_FLAG_COMPRESSION,
+			#[cfg(feature = "json")]
+			Framing::Json => HELLO_FLAG_JSON,
+		};
+
+		let mut hello = [0u8; 12];
+		hello[0..4].copy_from_slice(&HELLO_MAGIC);
+		hello[4..8].copy_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+		hello[8..12].copy_from_slice(&flags.to_le_bytes());
+
+		stream.write_all(&hello)?;
+		stream.flush()?;
+		Ok(())
+	}
+
+	pub fn is_compatible(&self) -> bool {
+		match (self.client_version, self.framing) {
+			(Some(version), _) => version == PROTOCOL_VERSION,
+
+			// JSON names every field, so clients get by without a hello as long as they stick to what they know
+			#[cfg(feature = "json")]
+			(None, Framing::Json) => true,
+
+			// Bincode clients without a hello use version 1's layout, which bincode can't tell apart from ours
+			(None, _) => false,
+		}
+	}
+}
+
+impl Framing {
+	pub fn write_response(
+		self,
+		stream: &mut dyn Write,
+		response: &Response,
+	) -> Result<(), Box<dyn Error>> {
+		stream.write_all(&self.encode(response)?[..])?;
+		stream.flush()?;
+		Ok(())
+	}
+
+	pub fn decode_request(self, data: &[u8]) -> Result<Request, Box<dyn Error>> {
+		self.decode(data)
+	}
+
+	// Produces everything that goes on the wire for one message
+	fn encode<T: Serialize>(self, message: &T) -> Result<Vec<u8>, Box<dyn Error>> {
+		match self {
+			Framing::Bincode => {
+				let data = bincode::serialize(message)?;
+				let mut frame = Vec::with_capacity(data.len() + 4);
+				frame.extend_from_slice(&(data.len() as u32).to_le_bytes());
+				frame.extend_from_slice(&data[..]);
+				Ok(frame)
+			}
+
+			Framing::CompressedBincode => {
+				let data = bincode::serialize(message)?;
+
+				let (kind, data) = if data.len() >= COMPRESSION_THRESHOLD {
+					// Favour speed, so big responses like stacks don't fall behind the ones queued after them
+					let mut encoder = DeflateEncoder::new(vec![], Compression::fast());
+					encoder.write_all(&data[..])?;
+					(MESSAGE_DEFLATE, encoder.finish()?)
+				} else {
+					(MESSAGE_RAW, data)
+				};
+
+				let mut frame = Vec::with_capacity(data.len() + 5);
+				frame.extend_from_slice(&(data.len() as u32 + 1).to_le_bytes());
+				frame.push(kind);
+				frame.extend_from_slice(&data[..]);
+				Ok(frame)
+			}
+
+			// serde_json never emits a raw newline, so the document stays on one line
+			#[cfg(feature = "json")]
+			Framing::Json => {
+				let mut frame = serde_json::to_vec(message)?;
+				frame.push(b'\n');
+				Ok(frame)
+			}
+		}
+	}
+
+	// Decodes one message, without its length prefix or trailing newline
+	fn decode<T: DeserializeOwned>(self, data: &[u8]) -> Result<T, Box<dyn Error>> {
+		match self {
+			Framing::Bincode => Ok(bincode::deserialize(data)?),
+
+			Framing::CompressedBincode => match data.split_first() {
+				Some((&MESSAGE_RAW, data)) => Ok(bincode::deserialize(data)?),
+
+				Some((&MESSAGE_DEFLATE, data)) => {
+					let mut decompressed = vec![];
+					DeflateDecoder::new(data).read_to_end(&mut decompressed)?;
+					Ok(bincode::deserialize(&decompressed[..])?)
+				}
+
+				Some((kind, _)) => Err(format!("unknown message kind {}", kind).into()),
+				None => Err("empty message".into()),
+			},
+
+			#[cfg(feature = "json")]
+			Framing::Json => Ok(serde_json::from_slice(data)?),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn large_disassembly_round_trips() {
+		let mut disassembly = String::new();
+		let mut offset = 0;
+		while disassembly.len() < 5 * 1024 * 1024 {
+			disassembly.push_str(&format!(
+				"{:04}: GetVar src.vars[\"thing_{}\"]\n{:04}: Call /datum/proc/do_thing 2\n",
+				offset,
+				offset % 97,
+				offset + 3
+			));
+			offset += 6;
+		}
+
+		let response = Response::Notification {
+			message: disassembly.clone(),
+		};
+
+		let frame = Framing::CompressedBincode.encode(&response).unwrap();
+		let uncompressed_len = bincode::serialize(&response).unwrap().len();
+		assert!(frame.len() * 4 < uncompressed_len);
+
+		let len = u32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize;
+		assert_eq!(len, frame.len() - 4);
+		assert_eq!(frame[4], MESSAGE_DEFLATE);
+
+		match Framing::CompressedBincode
+			.decode::<Response>(&frame[4..])
+			.unwrap()
+		{
+			Response::Notification { message } => assert!(message == disassembly),
+			other => panic!("decoded the wrong response: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn only_clients_on_our_version_are_compatible() {
+		let handshake = |client_version| Handshake {
+			framing: Framing::Bincode,
+			client_version,
+			first_len: None,
+		};
+
+		assert!(handshake(Some(PROTOCOL_VERSION)).is_compatible());
+		assert!(!handshake(Some(PROTOCOL_VERSION - 1)).is_compatible());
+		assert!(!handshake(None).is_compatible());
+	}
+
+	#[test]
+	fn small_messages_stay_uncompressed() {
+		let frame = Framing::CompressedBincode.encode(&Request::Pong).unwrap();
+		assert_eq!(frame[4], MESSAGE_RAW);
+
+		match Framing::CompressedBincode
+			.decode::<Request>(&frame[4..])
+			.unwrap()
+		{
+			Request::Pong => {}
+			other => panic!("decoded the wrong request: {:?}", other),
+		}
+	}
+}
@@ -0,0 +1,63 @@
+//! Printing a single disassembled instruction, for places that show one instruction rather than a whole proc.
+
+use std::fmt;
+
+use crate::operands::instruction_operands;
+
+type Node<'a> = dmasm::Node<dmasm::DebugData<'a>>;
+
+/// Displays one node of a disassembly the same way `#disassemble` shows it, on a single line.
+///
+/// dmasm's `Instruction` can't implement `Display` outside of dmasm, so this wraps the node instead.
+/// Jump targets keep dmasm's label names, as there's no whole proc to number them in.
+pub struct InstructionFormatter<'a> {
+	node: &'a Node<'a>,
+	ids: bool,
+}
+
+impl<'a> InstructionFormatter<'a> {
+	pub fn new(node: &'a Node<'a>) -> Self {
+		InstructionFormatter { node, ids: false }
+	}
+
+	/// Also shows the string, variable and proc ids the instruction refers to, like `#disassemble --ids`.
+	pub fn with_ids(mut self) -> Self {
+		self.ids = true;
+		self
+	}
+}
+
+impl fmt::Display for InstructionFormatter<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let text = dmasm::format_disassembly(std::slice::from_ref(self.node), None);
+		write!(f, "{}", text.trim())?;
+
+		if let dmasm::Node::Instruction(_, debug) = self.node {
+			if self.ids {
+				let ids = resolved_ids(debug.bytecode);
+				if !ids.is_empty() {
+					write!(f, "\t; {}", ids.join(", "))?;
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// The ids the instruction encoded by `words` refers to, each with what it resolves to.
+pub fn resolved_ids(words: &[u32]) -> Vec<String> {
+	instruction_operands(words)
+		.iter()
+		.filter_map(|operand| operand.display_resolved())
+		.collect()
+}
+
+/// The instruction's name as dmasm knows it, like `DbgLine`, without any of its operands.
+pub fn mnemonic(ins: &dmasm::Instruction) -> String {
+	let name = format!("{:?}", ins);
+	name.split(|c: char| !c.is_alphanumeric())
+		.next()
+		.unwrap_or("")
+		.to_owned()
+}
@@ -7,7 +7,8 @@ use auxtools::*;
 use detour::RawDetour;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 // Could move these to dmasm
 const OPCODE_DBGLINE: u32 = 0x85;
@@ -16,6 +17,9 @@ const OPCODE_DEBUG_OPERAND: u32 = 0x1338;
 
 static mut EXECUTE_INSTRUCTION: *const c_void = std::ptr::null();
 
+// Removed on shutdown so nothing points into this library once it's gone
+static mut EXECUTE_INSTRUCTION_DETOUR: Option<RawDetour> = None;
+
 extern "C" {
 	// Trampoline to the original un-hooked BYOND execute_instruction code
 	static mut execute_instruction_original: *const c_void;
@@ -63,9 +67,7 @@ fn instruction_hooking_init() -> Result<(), String> {
 			.map_err(|_| "Couldn't enable EXECUTE_INSTRUCTION detour")?;
 
 		execute_instruction_original = std::mem::transmute(hook.trampoline());
-
-		// We never remove or disable the hook, so just forget about it.
-		std::mem::forget(hook);
+		EXECUTE_INSTRUCTION_DETOUR = Some(hook);
 	}
 
 	Ok(())
@@ -74,9 +76,30 @@ fn instruction_hooking_init() -> Result<(), String> {
 #[shutdown]
 fn instruction_hooking_shutdown() {
 	unsafe {
+		if let Some(hook) = EXECUTE_INSTRUCTION_DETOUR.take() {
+			if let Err(e) = hook.disable() {
+				eprintln!("Couldn't remove EXECUTE_INSTRUCTION detour: {}", e);
+			}
+		}
+
 		CURRENT_ACTION = DebuggerAction::None;
 		*DEFERRED_INSTRUCTION_REPLACE.get() = None;
-		*ORIGINAL_BYTECODE.lock().unwrap() = HashMap::new();
+
+		// BYOND can't run our debug opcodes without the detour, so put the original instructions back
+		let mut hooks = HOOKS.lock().unwrap();
+		for ((id, offset), hook) in hooks.iter() {
+			if let Some(proc) = Proc::from_id(*id) {
+				if is_installed(&proc, *offset, hook) {
+					let opcode_ptr = hook.opcode_ptr.0 as *mut u32;
+					std::ptr::copy_nonoverlapping(
+						hook.original.as_ptr(),
+						opcode_ptr,
+						hook.original.len(),
+					);
+				}
+			}
+		}
+		hooks.clear();
 	}
 }
 
@@ -123,8 +146,36 @@ impl PtrKey {
 	}
 }
 
+/// Code to run whenever execution reaches a hooked instruction, before the instruction itself.
+pub type HookCallback = Box<dyn Fn(&debug::SafeExecutionContext) + Send + Sync>;
+
+/// Identifies one callback on a hooked instruction, so it can be removed without touching the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HookId(u64);
+
+static NEXT_HOOK_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone)]
+enum Callback {
+	// The debugger's own breakpoint, see hook_instruction
+	Breakpoint,
+	// Removed as soon as it has run, see hook_instruction_once
+	Once(Arc<dyn Fn(&debug::SafeExecutionContext) + Send + Sync>),
+}
+
+struct HookedInstruction {
+	// Where the hook was written. Procs given new bytecode since then don't contain it anymore.
+	opcode_ptr: PtrKey,
+	original: Vec<u32>,
+	// Run in the order they were added
+	callbacks: Vec<(HookId, Callback)>,
+}
+
 lazy_static! {
-	static ref ORIGINAL_BYTECODE: Mutex<HashMap<PtrKey, Vec<u32>>> = Mutex::new(HashMap::new());
+	// Every installed hook, so finding one never needs the proc disassembled.
+	// An entry is removed as soon as its last callback is.
+	static ref HOOKS: Mutex<HashMap<(raw_types::procs::ProcId, u32), HookedInstruction>> =
+		Mutex::new(HashMap::new());
 }
 
 fn is_generated_proc(ctx: *mut raw_types::procs::ExecutionContext) -> bool {
@@ -172,7 +223,8 @@ fn handle_breakpoint(
 	};
 
 	match action {
-		ContinueKind::Continue => DebuggerAction::None,
+		// The server has already put a temporary breakpoint on the RunTo target
+		ContinueKind::Continue | ContinueKind::RunTo { .. } => DebuggerAction::None,
 		ContinueKind::StepOver { stack_id } => {
 			let ctx = get_proc_ctx(stack_id);
 			DebuggerAction::StepOver {
@@ -275,6 +327,9 @@ extern "C" fn handle_instruction(
 		}
 	}
 
+	crate::coverage::record(ctx);
+	crate::profiler::record(ctx);
+
 	unsafe {
 		if let Some(server) = &mut *DEBUG_SERVER.get() {
 			if server.process() {
@@ -374,17 +429,58 @@ extern "C" fn handle_instruction(
 	}
 
 	if opcode == OPCODE_DEBUG_BREAK {
-		// We don't want to break twice when stepping on to a breakpoint
-		if !did_breakpoint {
-			unsafe {
-				CURRENT_ACTION = DebuggerAction::None;
-				CURRENT_ACTION = handle_breakpoint(ctx, BreakpointReason::Breakpoint);
+		// The lock can't be held while the callbacks run, as they're free to hook and unhook instructions
+		let callbacks = {
+			let key = unsafe { ((*(*ctx).proc_instance).proc, (*ctx).bytecode_offset as u32) };
+			let hooks = HOOKS.lock().unwrap();
+			match hooks
+				.get(&key)
+				.filter(|hook| hook.opcode_ptr == PtrKey::new(opcode_ptr))
+			{
+				Some(hook) => hook
+					.callbacks
+					.iter()
+					.map(|(id, callback)| (Some(*id), callback.clone()))
+					.collect(),
+				None => vec![(None, Callback::Breakpoint)],
+			}
+		};
+
+		let safe_ctx = unsafe { debug::SafeExecutionContext::new(ctx) };
+
+		for (id, callback) in callbacks {
+			match callback {
+				// Taking the hook out puts the original instruction back, which then runs as usual
+				Callback::Once(callback) => {
+					if let (Some(safe_ctx), Some(id)) = (&safe_ctx, id) {
+						callback(safe_ctx);
+						let _ = unhook_instruction_callback(safe_ctx.proc(), safe_ctx.offset(), id);
+					}
+				}
+
+				// We don't want to break twice when stepping on to a breakpoint
+				Callback::Breakpoint if !did_breakpoint => unsafe {
+					CURRENT_ACTION = DebuggerAction::None;
+					CURRENT_ACTION = handle_breakpoint(ctx, BreakpointReason::Breakpoint);
+					did_breakpoint = true;
+				},
+
+				Callback::Breakpoint => {}
 			}
 		}
+	}
 
-		// ORIGINAL_BYTECODE won't contain an entry if this breakpoint has already been removed
-		let map = ORIGINAL_BYTECODE.lock().unwrap();
-		if let Some(original) = map.get(&PtrKey::new(opcode_ptr)) {
+	// The client may have moved execution to another instruction while we were paused (see Request::GotoLine)
+	let opcode_ptr = unsafe { (*ctx).bytecode.add((*ctx).bytecode_offset as usize) };
+
+	if unsafe { *opcode_ptr } == OPCODE_DEBUG_BREAK {
+		// HOOKS won't contain an entry if this breakpoint has already been removed
+		let key = unsafe { ((*(*ctx).proc_instance).proc, (*ctx).bytecode_offset as u32) };
+		let hooks = HOOKS.lock().unwrap();
+		if let Some(HookedInstruction { original, .. }) = hooks
+			.get(&key)
+			.filter(|hook| hook.opcode_ptr == PtrKey::new(opcode_ptr))
+		{
 			unsafe {
 				let deferred_replace = DEFERRED_INSTRUCTION_REPLACE.get();
 				assert_eq!(*deferred_replace, None);
@@ -397,6 +493,11 @@ extern "C" fn handle_instruction(
 		}
 	}
 
+	// Any breakpoint has been swapped back out by now, so this sees the real instruction
+	if unsafe { *opcode_ptr } == OPCODE_DBGLINE {
+		crate::coverage::record_line(ctx, unsafe { *opcode_ptr.add(1) });
+	}
+
 	ctx
 }
 
@@ -425,12 +526,60 @@ fn find_instruction<'a>(
 	None
 }
 
+// Whether an instruction of the proc starts at `offset`, rather than it being in the middle of one
+pub fn is_instruction_start(proc: &Proc, offset: u32) -> bool {
+	let mut env = crate::disassemble_env::DisassembleEnv;
+	find_instruction(&mut env, proc, offset).is_some()
+}
+
+/// Puts a breakpoint on the instruction at `offset`. Does nothing if there's already one there.
 pub fn hook_instruction(proc: &Proc, offset: u32) -> Result<(), InstructionHookError> {
+	add_callback(proc, offset, Callback::Breakpoint).map(|_| ())
+}
+
+/// Runs `callback` the first time the instruction at `offset` is about to execute, then removes the hook.
+///
+/// Any number of callbacks can hook the same instruction, alongside a breakpoint, and they run in the order
+/// they were added. Once it has run, the instruction costs nothing extra. A hook that never ran can be removed with
+/// [unhook_instruction_callback] like any other.
+pub fn hook_instruction_once(
+	proc: &Proc,
+	offset: u32,
+	callback: HookCallback,
+) -> Result<HookId, InstructionHookError> {
+	add_callback(proc, offset, Callback::Once(Arc::from(callback)))
+}
+
+fn add_callback(
+	proc: &Proc,
+	offset: u32,
+	callback: Callback,
+) -> Result<HookId, InstructionHookError> {
 	let mut env = crate::disassemble_env::DisassembleEnv;
 	let (_, debug) =
 		find_instruction(&mut env, proc, offset).ok_or(InstructionHookError::InvalidOffset)?;
 
 	let instruction_length = debug.bytecode.len();
+	let id = HookId(NEXT_HOOK_ID.fetch_add(1, Ordering::Relaxed));
+
+	let mut hooks = HOOKS.lock().unwrap();
+
+	if let Some(hook) = hooks.get_mut(&(proc.id, offset)) {
+		if is_installed(proc, offset, hook) {
+			if let Callback::Breakpoint = callback {
+				if let Some((id, _)) = hook
+					.callbacks
+					.iter()
+					.find(|(_, callback)| matches!(callback, Callback::Breakpoint))
+				{
+					return Ok(*id);
+				}
+			}
+
+			hook.callbacks.push((id, callback));
+			return Ok(id);
+		}
+	}
 
 	let bytecode;
 	let opcode;
@@ -446,14 +595,20 @@ pub fn hook_instruction(proc: &Proc, offset: u32) -> Result<(), InstructionHookE
 		opcode = *opcode_ptr;
 	}
 
+	// A breakpoint we don't know the original instruction of, so there's nothing to hook
 	if opcode == OPCODE_DEBUG_BREAK {
-		return Ok(());
+		return Ok(id);
 	}
 
 	unsafe {
-		ORIGINAL_BYTECODE.lock().unwrap().insert(
-			PtrKey::new(opcode_ptr),
-			std::slice::from_raw_parts(opcode_ptr, instruction_length as usize).to_vec(),
+		hooks.insert(
+			(proc.id, offset),
+			HookedInstruction {
+				opcode_ptr: PtrKey::new(opcode_ptr),
+				original: std::slice::from_raw_parts(opcode_ptr, instruction_length as usize)
+					.to_vec(),
+				callbacks: vec![(id, callback)],
+			},
 		);
 	}
 
@@ -461,7 +616,7 @@ pub fn hook_instruction(proc: &Proc, offset: u32) -> Result<(), InstructionHookE
 	for i in (offset + 1)..(offset + instruction_length as u32) {
 		bytecode[i as usize] = OPCODE_DEBUG_OPERAND;
 	}
-	Ok(())
+	Ok(id)
 }
 
 #[derive(Debug)]
@@ -469,54 +624,176 @@ pub enum InstructionUnhookError {
 	InvalidOffset,
 }
 
-pub fn unhook_instruction(proc: &Proc, offset: u32) -> Result<(), InstructionUnhookError> {
-	let mut env = crate::disassemble_env::DisassembleEnv;
-	let (_, _) =
-		find_instruction(&mut env, proc, offset).ok_or(InstructionUnhookError::InvalidOffset)?;
+// Where the instruction at `offset` would be if it's still part of the proc's bytecode
+fn opcode_ptr(proc: &Proc, offset: u32) -> Option<*mut u32> {
+	let (ptr, count) = unsafe { proc.bytecode_mut_ptr() };
+	if offset >= count as u32 {
+		return None;
+	}
 
-	let opcode_ptr = unsafe {
-		let bytecode = {
-			let (ptr, count) = proc.bytecode_mut_ptr();
-			std::slice::from_raw_parts_mut(ptr, count as usize)
-		};
+	Some(unsafe { ptr.add(offset as usize) })
+}
 
-		bytecode.as_mut_ptr().add(offset as usize)
-	};
+// Whether a hook is still written into the proc's current bytecode
+fn is_installed(proc: &Proc, offset: u32, hook: &HookedInstruction) -> bool {
+	match opcode_ptr(proc, offset) {
+		Some(ptr) => hook.opcode_ptr == PtrKey::new(ptr) && unsafe { *ptr } == OPCODE_DEBUG_BREAK,
+		None => false,
+	}
+}
 
-	// ORIGINAL_BYTECODE won't contain an entry if this breakpoint has already been removed
-	let mut map = ORIGINAL_BYTECODE.lock().unwrap();
-	if let Some(original) = map.get(&PtrKey::new(opcode_ptr)) {
-		unsafe {
-			let deferred = DEFERRED_INSTRUCTION_REPLACE.get();
-			if let Some((_, dst)) = *deferred {
-				if dst == opcode_ptr {
-					deferred.replace(None);
-				}
+/// Removes the breakpoint on the instruction at `offset`. Callbacks hooking the same instruction stay.
+pub fn unhook_instruction(proc: &Proc, offset: u32) -> Result<(), InstructionUnhookError> {
+	remove_callback(proc, offset, |callback| {
+		matches!(callback, Callback::Breakpoint)
+	})
+}
+
+/// Removes a callback added by [hook_instruction_once]. Anything else hooking the instruction stays.
+pub fn unhook_instruction_callback(
+	proc: &Proc,
+	offset: u32,
+	id: HookId,
+) -> Result<(), InstructionUnhookError> {
+	let mut hooks = HOOKS.lock().unwrap();
+
+	match hooks.get_mut(&(proc.id, offset)) {
+		Some(hook) if hook.callbacks.iter().any(|(hook_id, _)| *hook_id == id) => {
+			hook.callbacks.retain(|(hook_id, _)| *hook_id != id);
+			if hook.callbacks.is_empty() {
+				restore_original(proc, offset, &mut hooks);
 			}
-			std::ptr::copy_nonoverlapping(original.as_ptr(), opcode_ptr, original.len());
+			Ok(())
 		}
 
-		map.remove(&PtrKey::new(opcode_ptr));
+		_ => Err(InstructionUnhookError::InvalidOffset),
+	}
+}
+
+fn remove_callback(
+	proc: &Proc,
+	offset: u32,
+	matches: impl Fn(&Callback) -> bool,
+) -> Result<(), InstructionUnhookError> {
+	let mut hooks = HOOKS.lock().unwrap();
+
+	let hook = match hooks.get_mut(&(proc.id, offset)) {
+		Some(hook) => hook,
+		None => {
+			// Nothing to restore, but the offset should still be valid
+			let mut env = crate::disassemble_env::DisassembleEnv;
+			return find_instruction(&mut env, proc, offset)
+				.map(|_| ())
+				.ok_or(InstructionUnhookError::InvalidOffset);
+		}
+	};
+
+	hook.callbacks.retain(|(_, callback)| !matches(callback));
+	if hook.callbacks.is_empty() {
+		restore_original(proc, offset, &mut hooks);
 	}
 
 	Ok(())
 }
 
-pub fn get_hooked_offsets(proc: &Proc) -> Vec<u32> {
-	let bytecode = unsafe { proc.bytecode() };
+// Removes the breakpoint and every callback hooking the instruction at `offset`
+pub fn clear_instruction_hooks(proc: &Proc, offset: u32) {
+	let mut hooks = HOOKS.lock().unwrap();
+	restore_original(proc, offset, &mut hooks);
+}
 
-	let mut env = crate::disassemble_env::DisassembleEnv;
-	let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+// Forgets the hook and puts the original instruction back, now that nothing hooks it
+fn restore_original(
+	proc: &Proc,
+	offset: u32,
+	hooks: &mut HashMap<(raw_types::procs::ProcId, u32), HookedInstruction>,
+) {
+	let hook = match hooks.remove(&(proc.id, offset)) {
+		Some(hook) => hook,
+		None => return,
+	};
 
-	let mut offsets = vec![];
+	// The proc has been given new bytecode since it was hooked, so there's nothing to restore
+	if !is_installed(proc, offset, &hook) {
+		return;
+	}
 
-	for node in nodes {
-		if let dmasm::Node::Instruction(ins, debug) = node {
-			if ins == dmasm::Instruction::AuxtoolsDebugBreak {
-				offsets.push(debug.offset);
+	let opcode_ptr = hook.opcode_ptr.0 as *mut u32;
+
+	unsafe {
+		let deferred = DEFERRED_INSTRUCTION_REPLACE.get();
+		if let Some((_, dst)) = *deferred {
+			if dst == opcode_ptr {
+				deferred.replace(None);
+			}
+		}
+		std::ptr::copy_nonoverlapping(hook.original.as_ptr(), opcode_ptr, hook.original.len());
+	}
+}
+
+/// Runs `f` with the original instructions of every hooked instruction in the proc put back, so it can
+/// disassemble the proc. The hooks are written again afterwards.
+pub fn without_hooks<T>(proc: &Proc, f: impl FnOnce() -> T) -> T {
+	let mut replaced = vec![];
+
+	{
+		let hooks = HOOKS.lock().unwrap();
+		for ((id, offset), hook) in hooks.iter() {
+			if *id == proc.id && is_installed(proc, *offset, hook) {
+				let opcode_ptr = hook.opcode_ptr.0 as *mut u32;
+				unsafe {
+					replaced.push((
+						opcode_ptr,
+						std::slice::from_raw_parts(opcode_ptr, hook.original.len()).to_vec(),
+					));
+					std::ptr::copy_nonoverlapping(
+						hook.original.as_ptr(),
+						opcode_ptr,
+						hook.original.len(),
+					);
+				}
 			}
 		}
 	}
 
+	let result = f();
+
+	for (opcode_ptr, hooked) in replaced {
+		unsafe {
+			std::ptr::copy_nonoverlapping(hooked.as_ptr(), opcode_ptr, hooked.len());
+		}
+	}
+
+	result
+}
+
+// Offsets of every hooked instruction in the proc, in order
+pub fn get_hooked_offsets(proc: &Proc) -> Vec<u32> {
+	let hooks = HOOKS.lock().unwrap();
+
+	let mut offsets: Vec<u32> = hooks
+		.iter()
+		.filter(|((id, offset), hook)| *id == proc.id && is_installed(proc, *offset, hook))
+		.map(|((_, offset), _)| *offset)
+		.collect();
+
+	offsets.sort_unstable();
 	offsets
 }
+
+// Every breakpoint in every proc
+pub fn get_all_hooks() -> Vec<(raw_types::procs::ProcId, u32)> {
+	let hooks = HOOKS.lock().unwrap();
+
+	let mut all: Vec<_> = hooks
+		.iter()
+		.filter(|(_, hook)| {
+			hook.callbacks
+				.iter()
+				.any(|(_, callback)| matches!(callback, Callback::Breakpoint))
+		})
+		.map(|(key, _)| *key)
+		.collect();
+	all.sort_unstable_by_key(|(id, offset)| (id.0, *offset));
+	all
+}
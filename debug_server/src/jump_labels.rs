@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use dmasm::operands::Label;
+use dmasm::Instruction;
+
+use crate::instruction_formatter::resolved_ids;
+use crate::operands::{instruction_operands, OperandStyle};
+
+type Nodes<'a> = [dmasm::Node<dmasm::DebugData<'a>>];
+
+// Gives the labels dmasm makes up for jump targets short names (L1, L2, ...) in the order they're first
+// mentioned, and remembers which instructions jump to each of them.
+pub struct JumpLabels {
+	// dmasm's name -> our name
+	names: HashMap<String, String>,
+	// Offsets of the instructions jumping to each label, by our name
+	sources: HashMap<String, Vec<u32>>,
+	// Labels that got placed somewhere. Jumps into bytecode that couldn't be disassembled have none.
+	placed: Vec<String>,
+}
+
+impl JumpLabels {
+	pub fn new(nodes: &Nodes) -> Self {
+		let mut labels = JumpLabels {
+			names: HashMap::new(),
+			sources: HashMap::new(),
+			placed: vec![],
+		};
+
+		for node in nodes {
+			match node {
+				dmasm::Node::Label(label) => {
+					let name = labels.name(label);
+					labels.placed.push(name);
+				}
+
+				dmasm::Node::Instruction(ins, debug) => {
+					for target in jump_targets(ins) {
+						let name = labels.name(&target);
+						labels.sources.entry(name).or_default().push(debug.offset);
+					}
+				}
+
+				dmasm::Node::Comment(_) => {}
+			}
+		}
+
+		labels
+	}
+
+	fn name(&mut self, label: &str) -> String {
+		let next = self.names.len() + 1;
+		self.names
+			.entry(label.to_owned())
+			.or_insert_with(|| format!("L{}", next))
+			.clone()
+	}
+
+	// Formats the disassembly like dmasm does, but with our label names. Labels list the jumps to them, and
+	// jumps to bytecode that couldn't be disassembled say so. `operands` decides whether instructions show what
+	// their operands refer to.
+	pub fn format(&self, nodes: &Nodes, highlight: Option<u32>, operands: OperandStyle) -> String {
+		let mut text = String::new();
+
+		for node in nodes {
+			match node {
+				dmasm::Node::Label(label) => {
+					let name = &self.names[label];
+					text.push_str(name);
+					text.push(':');
+
+					if let Some(sources) = self.sources.get(name) {
+						let sources: Vec<String> = sources.iter().map(u32::to_string).collect();
+						text.push_str(&format!("\t; from {}", sources.join(", ")));
+					}
+				}
+
+				dmasm::Node::Instruction(ins, debug) => {
+					let formatted =
+						dmasm::format_disassembly(std::slice::from_ref(node), highlight);
+					text.push_str(&self.rename(formatted.trim_end()));
+
+					let missing: Vec<&str> = jump_targets(ins)
+						.iter()
+						.map(|target| self.names[target].as_str())
+						.filter(|name| !self.placed.iter().any(|placed| placed == name))
+						.collect();
+
+					if !missing.is_empty() {
+						text.push_str(&format!(
+							"\t; {} not in the disassembly",
+							missing.join(", ")
+						));
+					}
+
+					match operands {
+						OperandStyle::Hidden => {}
+
+						OperandStyle::Listed => {
+							for operand in instruction_operands(debug.bytecode) {
+								text.push_str(&format!("\n\t\t{}", operand));
+							}
+						}
+
+						OperandStyle::Inline => {
+							let ids = resolved_ids(debug.bytecode);
+							if !ids.is_empty() {
+								text.push_str(&format!("\t; {}", ids.join(", ")));
+							}
+						}
+					}
+				}
+
+				dmasm::Node::Comment(_) => {
+					let formatted =
+						dmasm::format_disassembly(std::slice::from_ref(node), highlight);
+					text.push_str(formatted.trim_end());
+				}
+			}
+
+			text.push('\n');
+		}
+
+		text
+	}
+
+	// Swaps every whole-word mention of one of dmasm's labels for our name
+	fn rename(&self, text: &str) -> String {
+		let mut renamed = String::with_capacity(text.len());
+		let mut word = String::new();
+
+		for c in text.chars().chain(std::iter::once('\n')) {
+			if c.is_alphanumeric() || c == '_' {
+				word.push(c);
+				continue;
+			}
+
+			renamed.push_str(self.names.get(&word).unwrap_or(&word));
+			word.clear();
+			renamed.push(c);
+		}
+
+		renamed.pop();
+		renamed
+	}
+}
+
+// The labels an instruction can jump to
+pub fn jump_targets(ins: &Instruction) -> Vec<String> {
+	match ins {
+		Instruction::Jmp(Label(label))
+		| Instruction::Jmp2(Label(label))
+		| Instruction::Jz(Label(label))
+		| Instruction::Jnz(Label(label))
+		| Instruction::JmpOr(Label(label))
+		| Instruction::JmpAnd(Label(label))
+		| Instruction::JmpIfNull(Label(label))
+		| Instruction::JmpIfNull2(Label(label))
+		| Instruction::Spawn(Label(label))
+		| Instruction::Try(Label(label))
+		| Instruction::TryNoValue(Label(label)) => vec![label.clone()],
+
+		_ => vec![],
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn finds_and_renames_labels() {
+		assert_eq!(
+			jump_targets(&Instruction::Jz(Label("LAB_0012".to_owned()))),
+			vec!["LAB_0012".to_owned()]
+		);
+		assert!(jump_targets(&Instruction::DbgLine(12)).is_empty());
+
+		let mut labels = JumpLabels {
+			names: HashMap::new(),
+			sources: HashMap::new(),
+			placed: vec![],
+		};
+		assert_eq!(labels.name("LAB_0040"), "L1");
+		assert_eq!(labels.name("LAB_0012"), "L2");
+		assert_eq!(labels.name("LAB_0040"), "L1");
+
+		assert_eq!(
+			labels.rename("0005: Jz LAB_0012, LAB_0040 ; LAB_00123"),
+			"0005: Jz L2, L1 ; LAB_00123"
+		);
+	}
+}
@@ -1,10 +1,31 @@
+mod address;
 mod assemble_env;
+mod assembly;
+mod callers;
+mod census;
+mod cfg;
 mod ckey_override;
+mod coverage;
 mod disassemble_env;
+#[cfg(feature = "json")]
+mod disassembly;
+mod eval;
+mod framing;
+mod instruction_formatter;
 mod instruction_hooking;
+mod jump_labels;
+mod line_table;
+mod operands;
+mod patching;
+mod profiler;
+mod references;
+mod response_writer;
+mod runtime_filters;
 mod server;
 mod server_types;
 mod stddef;
+mod string_stats;
+mod transport;
 
 #[cfg(windows)]
 mod crash_handler_windows;
@@ -20,23 +41,51 @@ use mem_profiler_stub as mem_profiler;
 
 pub(crate) use disassemble_env::DisassembleEnv;
 
-use std::{
-	cell::UnsafeCell,
-	net::{IpAddr, Ipv4Addr, SocketAddr},
-};
+use std::{cell::UnsafeCell, sync::Mutex};
 
+use address::{Address, InitConfig};
 use auxtools::*;
 
 pub static mut DEBUG_SERVER: UnsafeCell<Option<server::Server>> = UnsafeCell::new(None);
 
+lazy_static::lazy_static! {
+	// Breakpoints from before the world rebooted, waiting for the debug server to be enabled again
+	static ref REBOOT_BREAKPOINTS: Mutex<Vec<server::SavedBreakpoint>> = Mutex::new(vec![]);
+
+	// The mode and address given to auxtools_init, which take priority over the environment variables
+	static ref INIT_CONFIG: Mutex<InitConfig> = Mutex::new(InitConfig::default());
+}
+
+#[init(partial)]
+fn read_init_config() -> Result<(), String> {
+	let config = address::parse_init_input(&init_input())
+		.map_err(|e| format!("bad debug address: {}", e))?;
+
+	*INIT_CONFIG.lock().unwrap() = config;
+	Ok(())
+}
+
 #[shutdown]
 fn debugger_shutdown() {
-	unsafe {
-		*DEBUG_SERVER.get() = None;
+	let server = unsafe { (*DEBUG_SERVER.get()).take() };
+
+	if let Some(mut server) = server {
+		let breakpoints = server.take_breakpoints();
+		drop(server);
+
+		if !breakpoints.is_empty() {
+			runtime::on_reboot(move || {
+				*REBOOT_BREAKPOINTS.lock().unwrap() = breakpoints;
+			});
+		}
 	}
 }
 
 fn get_default_mode() -> String {
+	if let Some(mode) = &INIT_CONFIG.lock().unwrap().mode {
+		return mode.clone();
+	}
+
 	match std::env::var("AUXTOOLS_DEBUG_MODE") {
 		Ok(val) => val,
 		Err(_) => "NONE".into(),
@@ -50,30 +99,80 @@ fn get_default_port() -> u16 {
 	}
 }
 
+// A unix socket path or named pipe name to listen on instead of a TCP port
+fn get_default_socket() -> Option<String> {
+	std::env::var("AUXTOOLS_DEBUG_SOCKET")
+		.ok()
+		.filter(|val| !val.is_empty())
+}
+
+// Clients have to authenticate with this token when it's set
+fn get_token() -> Option<String> {
+	std::env::var("AUXTOOLS_DEBUG_TOKEN")
+		.ok()
+		.filter(|val| !val.is_empty())
+}
+
+fn listen(address: &Address) -> std::io::Result<server::Server> {
+	match address {
+		Address::Tcp(addr) => server::Server::listen(addr, get_token()),
+
+		#[cfg(unix)]
+		Address::Local(path) => {
+			server::Server::listen_unix(std::path::Path::new(path), get_token())
+		}
+
+		#[cfg(windows)]
+		Address::Local(name) => server::Server::listen_pipe(name, get_token()),
+	}
+}
+
+// `port` can also be a string, which is used as a unix socket path (or named pipe name on Windows)
 #[hook("/proc/enable_debugging")]
 fn enable_debugging(mode: Value, port: Value) {
 	let mode = mode.as_string().unwrap_or_else(|_| get_default_mode());
-	let port = port
-		.as_number()
-		.map(|x| x as u16)
-		.unwrap_or_else(|_| get_default_port());
 
-	let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+	let address = if let Ok(socket) = port.as_string() {
+		Address::Local(socket)
+	} else if let Ok(port) = port.as_number() {
+		Address::localhost(port as u16)
+	} else if let Some(address) = INIT_CONFIG.lock().unwrap().address.clone() {
+		address
+	} else if let Some(socket) = get_default_socket() {
+		Address::Local(socket)
+	} else {
+		Address::localhost(get_default_port())
+	};
 
 	let server = match mode.as_str() {
 		"NONE" => {
 			return Ok(Value::null());
 		}
 
-		"LAUNCHED" => server::Server::connect(&addr)
-			.map_err(|e| runtime!("Couldn't create debug server: {}", e))?,
-
-		"BACKGROUND" => server::Server::listen(&addr)
-			.map_err(|e| runtime!("Couldn't create debug server: {}", e))?,
+		"LAUNCHED" => match &address {
+			Address::Tcp(addr) => {
+				let mut server = server::Server::connect(addr, get_token())
+					.map_err(|e| runtime!("Couldn't create debug server: {}", e))?;
+				reattach_breakpoints(&mut server);
+				server
+			}
+
+			Address::Local(_) => {
+				return Err(runtime!("LAUNCHED debugging only works over TCP"));
+			}
+		},
+
+		"BACKGROUND" => {
+			let mut server =
+				listen(&address).map_err(|e| runtime!("Couldn't create debug server: {}", e))?;
+			reattach_breakpoints(&mut server);
+			server
+		}
 
 		"BLOCK" => {
-			let mut server = server::Server::listen(&addr)
-				.map_err(|e| runtime!("Couldn't create debug server: {}", e))?;
+			let mut server =
+				listen(&address).map_err(|e| runtime!("Couldn't create debug server: {}", e))?;
+			reattach_breakpoints(&mut server);
 			server.process_until_configured(); // might never return 😳
 			server
 		}
@@ -89,3 +188,9 @@ fn enable_debugging(mode: Value, port: Value) {
 
 	Ok(Value::null())
 }
+
+// Puts back the breakpoints the previous world's debug server had. The client hears about them once it's configured.
+fn reattach_breakpoints(server: &mut server::Server) {
+	let breakpoints = std::mem::take(&mut *REBOOT_BREAKPOINTS.lock().unwrap());
+	server.reattach_breakpoints(breakpoints);
+}
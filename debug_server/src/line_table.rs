@@ -0,0 +1,209 @@
+use crate::instruction_hooking::without_hooks;
+use auxtools::Proc;
+
+struct Line {
+	// Where the DbgLine instruction is
+	offset: u32,
+	line: u32,
+	// Where the code for the line starts, just after the DbgLine. None if the DbgLine is the last instruction.
+	start: Option<u32>,
+}
+
+// The line and file information of a proc, so lookups don't need to disassemble it every time
+pub struct LineTable {
+	// The bytecode this was built from. Procs given new bytecode need a new table.
+	bytecode: (*const u32, u16),
+
+	// Sorted by offset
+	lines: Vec<Line>,
+
+	// Indices into `lines`, sorted by line and then offset
+	by_line: Vec<usize>,
+
+	// (offset of the DbgFile instruction, file), sorted by offset
+	files: Vec<(u32, String)>,
+
+	// Offset of the last instruction. Anything after it isn't part of the proc.
+	last_offset: Option<u32>,
+
+	// Offset of the first instruction that isn't a DbgFile or DbgLine
+	entry: Option<u32>,
+}
+
+impl LineTable {
+	pub fn build(proc: &Proc) -> Self {
+		// Make sure to temporarily remove all breakpoints in this proc
+		without_hooks(proc, || {
+			let (ptr, len) = unsafe { proc.bytecode_mut_ptr() };
+			let bytecode = unsafe { proc.bytecode() };
+
+			let mut env = crate::disassemble_env::DisassembleEnv;
+			let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+
+			let mut table = Self::from_instructions(nodes.iter().filter_map(|node| match node {
+				dmasm::Node::Instruction(ins, debug) => Some((debug.offset, ins)),
+				_ => None,
+			}));
+			table.bytecode = (ptr, len);
+			table
+		})
+	}
+
+	fn from_instructions<'a, I: Iterator<Item = (u32, &'a dmasm::Instruction)>>(
+		instructions: I,
+	) -> Self {
+		let mut lines: Vec<Line> = vec![];
+		let mut files = vec![];
+		let mut last_offset = None;
+		let mut entry = None;
+
+		for (offset, ins) in instructions {
+			if let Some(line) = lines.last_mut() {
+				if line.start.is_none() {
+					line.start = Some(offset);
+				}
+			}
+
+			match ins {
+				dmasm::Instruction::DbgLine(line) => lines.push(Line {
+					offset,
+					line: *line,
+					start: None,
+				}),
+
+				dmasm::Instruction::DbgFile(file) => {
+					files.push((offset, String::from_utf8_lossy(&file.0).into_owned()))
+				}
+
+				_ => {
+					entry = entry.or(Some(offset));
+				}
+			}
+
+			last_offset = Some(offset);
+		}
+
+		let mut by_line: Vec<usize> = (0..lines.len()).collect();
+		by_line.sort_by_key(|&i| (lines[i].line, lines[i].offset));
+
+		LineTable {
+			bytecode: (std::ptr::null(), 0),
+			lines,
+			by_line,
+			files,
+			last_offset,
+			entry,
+		}
+	}
+
+	// False if the proc's bytecode has been replaced since the table was built
+	pub fn is_current(&self, proc: &Proc) -> bool {
+		let (ptr, len) = unsafe { proc.bytecode_mut_ptr() };
+		self.bytecode == (ptr as *const u32, len)
+	}
+
+	// The line the instruction at `offset` belongs to. Offsets between instructions count as part of the one before.
+	pub fn line_at(&self, offset: u32) -> Option<u32> {
+		if offset > self.last_offset? {
+			return None;
+		}
+
+		let index = self.lines.partition_point(|line| line.offset <= offset);
+		index.checked_sub(1).map(|index| self.lines[index].line)
+	}
+
+	pub fn file_at(&self, offset: u32) -> Option<&str> {
+		if offset > self.last_offset? {
+			return None;
+		}
+
+		let index = self
+			.files
+			.partition_point(|(file_offset, _)| *file_offset <= offset);
+		index
+			.checked_sub(1)
+			.map(|index| self.files[index].1.as_str())
+	}
+
+	// Where the code for `line` starts. Lines without any code get the start of the next line that has some.
+	// Lines before the proc's first line aren't part of it.
+	pub fn offset_of(&self, line: u32) -> Option<u32> {
+		let first = self.by_line.first()?;
+		if line < self.lines[*first].line {
+			return None;
+		}
+
+		let index = self.by_line.partition_point(|&i| self.lines[i].line < line);
+		self.by_line[index..]
+			.iter()
+			.find_map(|&i| self.lines[i].start)
+	}
+
+	// Where the DbgLine instruction of `line` is, if the line has one. The first is used for lines that appear
+	// more than once, like the condition of a loop.
+	pub fn line_start(&self, line: u32) -> Option<u32> {
+		let index = self.by_line.partition_point(|&i| self.lines[i].line < line);
+		let found = &self.lines[*self.by_line.get(index)?];
+		(found.line == line).then(|| found.offset)
+	}
+
+	// Where the proc's own code starts, past the debug info at the top. Procs with no code have no entry.
+	pub fn entry(&self) -> Option<u32> {
+		self.entry
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use dmasm::Instruction;
+
+	fn table(instructions: &[(u32, Instruction)]) -> LineTable {
+		LineTable::from_instructions(instructions.iter().map(|(offset, ins)| (*offset, ins)))
+	}
+
+	#[test]
+	fn lookups_match_the_instructions() {
+		let file = dmasm::operands::DMString(b"code/thing.dm".to_vec());
+
+		// A two-instruction line 10, a loop back up to line 12 from line 14, and line 13 left empty
+		let table = table(&[
+			(0, Instruction::DbgFile(file)),
+			(2, Instruction::DbgLine(10)),
+			(4, Instruction::End),
+			(5, Instruction::End),
+			(6, Instruction::DbgLine(12)),
+			(8, Instruction::End),
+			(9, Instruction::DbgLine(14)),
+			(11, Instruction::End),
+			(12, Instruction::DbgLine(12)),
+			(14, Instruction::Ret),
+		]);
+
+		assert_eq!(table.line_at(0), None);
+		assert_eq!(table.line_at(2), Some(10));
+		assert_eq!(table.line_at(3), Some(10));
+		assert_eq!(table.line_at(5), Some(10));
+		assert_eq!(table.line_at(8), Some(12));
+		assert_eq!(table.line_at(11), Some(14));
+		assert_eq!(table.line_at(14), Some(12));
+		assert_eq!(table.line_at(15), None);
+
+		assert_eq!(table.file_at(4), Some("code/thing.dm"));
+		assert_eq!(table.file_at(15), None);
+
+		assert_eq!(table.offset_of(10), Some(4));
+		assert_eq!(table.offset_of(12), Some(8));
+		assert_eq!(table.offset_of(13), Some(11));
+		assert_eq!(table.offset_of(9), None);
+		assert_eq!(table.offset_of(15), None);
+
+		assert_eq!(table.line_start(10), Some(2));
+		assert_eq!(table.line_start(12), Some(6));
+		assert_eq!(table.line_start(13), None);
+		assert_eq!(table.line_start(15), None);
+
+		assert_eq!(table.entry(), Some(4));
+		assert_eq!(self::table(&[(0, Instruction::DbgLine(1))]).entry(), None);
+	}
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+
+/// One operand of a disassembled instruction, along with whatever the disassembler resolved it to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstructionOperand {
+	/// A word that didn't refer to anything the disassembler looked up, like a number or a jump offset
+	Raw(u32),
+	/// An entry of the string table
+	String { id: u32, contents: String },
+	/// A variable name
+	Variable { id: u32, name: String },
+	/// A proc called by path
+	Proc { id: u32, path: String },
+	/// A constant value. Takes up two words, its tag and its data.
+	Value { tag: u32, data: u32, text: String },
+}
+
+impl fmt::Display for InstructionOperand {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			InstructionOperand::Raw(word) => write!(f, "{}", word),
+			InstructionOperand::String { id, contents } => {
+				write!(f, "string {} {:?}", id, contents)
+			}
+			InstructionOperand::Variable { id, name } => write!(f, "var {} {}", id, name),
+			InstructionOperand::Proc { id, path } => write!(f, "proc {} {}", id, path),
+			InstructionOperand::Value { tag, data, text } => {
+				write!(f, "value {:#x}:{} {}", tag, data, text)
+			}
+		}
+	}
+}
+
+impl InstructionOperand {
+	/// The id of the string, variable or proc this refers to with what it resolved to in parentheses, like
+	/// `0x1a3f (/mob/proc/attack)`. Operands that aren't ids give `None`.
+	pub fn display_resolved(&self) -> Option<String> {
+		match self {
+			InstructionOperand::String { id, contents } => {
+				Some(format!("{:#x} ({:?})", id, contents))
+			}
+			InstructionOperand::Variable { id, name } => Some(format!("{:#x} ({})", id, name)),
+			InstructionOperand::Proc { id, path } => Some(format!("{:#x} ({})", id, path)),
+			InstructionOperand::Raw(_) | InstructionOperand::Value { .. } => None,
+		}
+	}
+}
+
+/// How a disassembly shows what each instruction's operands refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandStyle {
+	/// Only the instruction itself
+	Hidden,
+	/// Every operand on a line of its own after the instruction
+	Listed,
+	/// The ids the instruction refers to at the end of its line
+	Inline,
+}
+
+enum Resolved {
+	String(u32, String),
+	Variable(u32, String),
+	Proc(u32, String),
+	Value(u32, u32, String),
+}
+
+// Wraps the real environment to find out which operands were resolved while disassembling an instruction
+struct RecordingEnv {
+	inner: crate::DisassembleEnv,
+	resolved: Vec<Resolved>,
+}
+
+impl dmasm::disassembler::DisassembleEnv for RecordingEnv {
+	fn get_string_data(&mut self, index: u32) -> Option<Vec<u8>> {
+		let data = self.inner.get_string_data(index)?;
+		self.resolved.push(Resolved::String(
+			index,
+			String::from_utf8_lossy(&data).into_owned(),
+		));
+		Some(data)
+	}
+
+	fn get_variable_name(&mut self, index: u32) -> Option<Vec<u8>> {
+		let data = self.inner.get_variable_name(index)?;
+		self.resolved.push(Resolved::Variable(
+			index,
+			String::from_utf8_lossy(&data).into_owned(),
+		));
+		Some(data)
+	}
+
+	fn get_proc_name(&mut self, index: u32) -> Option<String> {
+		let name = self.inner.get_proc_name(index)?;
+		self.resolved.push(Resolved::Proc(index, name.clone()));
+		Some(name)
+	}
+
+	fn value_to_string_data(&mut self, tag: u32, data: u32) -> Option<Vec<u8>> {
+		let string = self.inner.value_to_string_data(tag, data)?;
+		self.resolved.push(Resolved::Value(
+			tag,
+			data,
+			String::from_utf8_lossy(&string).into_owned(),
+		));
+		Some(string)
+	}
+}
+
+/// The operands of the instruction encoded by `words`, which starts with its opcode.
+///
+/// The instruction is disassembled on its own to see what its operand words get resolved to.
+/// Each resolution is attached to the first operand word it could have come from.
+pub fn instruction_operands(words: &[u32]) -> Vec<InstructionOperand> {
+	let mut env = RecordingEnv {
+		inner: crate::DisassembleEnv,
+		resolved: vec![],
+	};
+	let _ = dmasm::disassembler::disassemble(words, &mut env);
+
+	let words = words.get(1..).unwrap_or_default();
+	let mut operands: Vec<Option<InstructionOperand>> = vec![None; words.len()];
+
+	for resolved in env.resolved {
+		let (index, operand) = match resolved {
+			Resolved::String(id, contents) => (id, InstructionOperand::String { id, contents }),
+			Resolved::Variable(id, name) => (id, InstructionOperand::Variable { id, name }),
+			Resolved::Proc(id, path) => (id, InstructionOperand::Proc { id, path }),
+			Resolved::Value(tag, data, text) => {
+				(tag, InstructionOperand::Value { tag, data, text })
+			}
+		};
+
+		let is_value = matches!(operand, InstructionOperand::Value { .. });
+		let position = words
+			.iter()
+			.enumerate()
+			.position(|(i, word)| operands[i].is_none() && *word == index);
+
+		if let Some(i) = position {
+			// Constant values are a tag followed by their data, which mustn't show up as an operand of its own
+			if is_value && i + 1 < operands.len() {
+				operands[i + 1] = Some(InstructionOperand::Raw(words[i + 1]));
+			}
+
+			operands[i] = Some(operand);
+		}
+	}
+
+	// Each value's data word has been claimed above, so leave it out
+	let mut result = vec![];
+	let mut skip = false;
+
+	for (word, operand) in words.iter().zip(operands) {
+		if skip {
+			skip = false;
+			continue;
+		}
+
+		match operand {
+			Some(operand @ InstructionOperand::Value { .. }) => {
+				skip = true;
+				result.push(operand);
+			}
+			Some(operand) => result.push(operand),
+			None => result.push(InstructionOperand::Raw(*word)),
+		}
+	}
+
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn operands_display_what_they_refer_to() {
+		let operands = vec![
+			InstructionOperand::Raw(12),
+			InstructionOperand::String {
+				id: 3,
+				contents: "hi".to_owned(),
+			},
+			InstructionOperand::Variable {
+				id: 7,
+				name: "health".to_owned(),
+			},
+			InstructionOperand::Proc {
+				id: 40,
+				path: "/proc/heal".to_owned(),
+			},
+			InstructionOperand::Value {
+				tag: 0x2A,
+				data: 0,
+				text: "0".to_owned(),
+			},
+		];
+
+		let text: Vec<String> = operands.iter().map(ToString::to_string).collect();
+		assert_eq!(
+			text,
+			vec![
+				"12",
+				"string 3 \"hi\"",
+				"var 7 health",
+				"proc 40 /proc/heal",
+				"value 0x2a:0 0",
+			]
+		);
+
+		let resolved: Vec<Option<String>> = operands
+			.iter()
+			.map(InstructionOperand::display_resolved)
+			.collect();
+		assert_eq!(
+			resolved,
+			vec![
+				None,
+				Some("0x3 (\"hi\")".to_owned()),
+				Some("0x7 (health)".to_owned()),
+				Some("0x28 (/proc/heal)".to_owned()),
+				None,
+			]
+		);
+	}
+}
@@ -0,0 +1,230 @@
+//! Overwriting instructions of a proc in place, for example to make a broken proc return early.
+//!
+//! A patch has to start and end on instruction boundaries of the proc's current bytecode, and can't cover
+//! any hooked instruction (like a breakpoint). The original words are put back when the [PatchGuard] drops.
+
+use std::fmt;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use auxtools::raw_types::procs::ProcId;
+use auxtools::Proc;
+use lazy_static::lazy_static;
+
+use crate::instruction_hooking::{clear_instruction_hooks, get_hooked_offsets, without_hooks};
+
+lazy_static! {
+	// Ranges of every patch that is still applied, so patches can't overlap
+	static ref PATCHES: Mutex<Vec<(ProcId, Range<u32>)>> = Mutex::new(vec![]);
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchError {
+	/// There's nothing to write
+	Empty,
+	/// No instruction starts at the patch's offset
+	InvalidOffset,
+	/// The patch would end in the middle of an instruction or past the end of the proc
+	InvalidLength,
+	/// The replacement isn't bytecode the disassembler understands
+	InvalidInstructions,
+	/// An instruction in the patched range is hooked, so writing over it would break the hook
+	HookedInstruction { offset: u32 },
+	/// Part of the range is already patched
+	AlreadyPatched,
+}
+
+impl fmt::Display for PatchError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PatchError::Empty => write!(f, "the patch is empty"),
+			PatchError::InvalidOffset => write!(f, "no instruction starts at that offset"),
+			PatchError::InvalidLength => write!(
+				f,
+				"the patch would end in the middle of an instruction or past the end of the proc"
+			),
+			PatchError::InvalidInstructions => write!(f, "the patch couldn't be disassembled"),
+			PatchError::HookedInstruction { offset } => {
+				write!(f, "the instruction at offset {} is hooked", offset)
+			}
+			PatchError::AlreadyPatched => write!(f, "part of that range is already patched"),
+		}
+	}
+}
+
+impl std::error::Error for PatchError {}
+
+/// An applied patch. Dropping it restores the original instructions.
+pub struct PatchGuard {
+	proc: Proc,
+	offset: u32,
+	// The proc's bytecode when it was patched. If the proc is given new bytecode, there's nothing to restore.
+	bytecode: (*mut u32, u16),
+	original: Vec<u32>,
+}
+
+impl PatchGuard {
+	pub fn proc(&self) -> &Proc {
+		&self.proc
+	}
+
+	pub fn range(&self) -> Range<u32> {
+		self.offset..self.offset + self.original.len() as u32
+	}
+}
+
+impl Drop for PatchGuard {
+	fn drop(&mut self) {
+		let range = self.range();
+		PATCHES
+			.lock()
+			.unwrap()
+			.retain(|(id, patched)| !(*id == self.proc.id && *patched == range));
+
+		if unsafe { self.proc.bytecode_mut_ptr() } != self.bytecode {
+			return;
+		}
+
+		// Hooks placed in the patched instructions go away with them
+		for offset in get_hooked_offsets(&self.proc) {
+			if range.contains(&offset) {
+				clear_instruction_hooks(&self.proc, offset);
+			}
+		}
+
+		unsafe {
+			let dst = self.bytecode.0.add(self.offset as usize);
+			std::ptr::copy_nonoverlapping(self.original.as_ptr(), dst, self.original.len());
+		}
+
+		auxtools::proc::forget_callers();
+	}
+}
+
+/// Lets procs have their instructions patched.
+pub trait ProcPatch {
+	/// Overwrites the instructions starting at `offset` with `new_instructions`, which must cover whole instructions.
+	fn patch(&self, offset: u32, new_instructions: &[u32]) -> Result<PatchGuard, PatchError>;
+}
+
+impl ProcPatch for Proc {
+	fn patch(&self, offset: u32, new_instructions: &[u32]) -> Result<PatchGuard, PatchError> {
+		if new_instructions.is_empty() {
+			return Err(PatchError::Empty);
+		}
+
+		let end = offset + new_instructions.len() as u32;
+
+		let mut env = crate::DisassembleEnv;
+		if dmasm::disassembler::disassemble(new_instructions, &mut env)
+			.1
+			.is_some()
+		{
+			return Err(PatchError::InvalidInstructions);
+		}
+
+		let breaks = get_hooked_offsets(self);
+		if let Some(offset) = breaks.iter().find(|hooked| (offset..end).contains(hooked)) {
+			return Err(PatchError::HookedInstruction { offset: *offset });
+		}
+
+		let mut patches = PATCHES.lock().unwrap();
+		if patches
+			.iter()
+			.any(|(id, range)| *id == self.id && range.start < end && offset < range.end)
+		{
+			return Err(PatchError::AlreadyPatched);
+		}
+
+		// Make sure to temporarily remove all breakpoints in this proc
+		let boundaries = without_hooks(self, || instruction_boundaries(self));
+
+		check_boundaries(&boundaries, offset, end)?;
+
+		let bytecode = unsafe { self.bytecode_mut_ptr() };
+		let original;
+
+		unsafe {
+			let dst = bytecode.0.add(offset as usize);
+			original = std::slice::from_raw_parts(dst, new_instructions.len()).to_vec();
+			std::ptr::copy_nonoverlapping(new_instructions.as_ptr(), dst, new_instructions.len());
+		}
+
+		patches.push((self.id, offset..end));
+		auxtools::proc::forget_callers();
+
+		Ok(PatchGuard {
+			proc: self.clone(),
+			offset,
+			bytecode,
+			original,
+		})
+	}
+}
+
+// Offsets every instruction of the proc starts at, followed by where the last one ends
+fn instruction_boundaries(proc: &Proc) -> Vec<u32> {
+	let bytecode = unsafe { proc.bytecode() };
+
+	let mut env = crate::DisassembleEnv;
+	let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+
+	let mut boundaries = vec![];
+	let mut end = 0;
+
+	for node in &nodes {
+		if let dmasm::Node::Instruction(_, debug) = node {
+			boundaries.push(debug.offset);
+			end = debug.offset + debug.bytecode.len() as u32;
+		}
+	}
+
+	boundaries.push(end);
+	boundaries
+}
+
+fn check_boundaries(boundaries: &[u32], start: u32, end: u32) -> Result<(), PatchError> {
+	let last = boundaries.last().copied().unwrap_or(0);
+
+	if start >= last || !boundaries.contains(&start) {
+		return Err(PatchError::InvalidOffset);
+	}
+
+	if !boundaries.contains(&end) {
+		return Err(PatchError::InvalidLength);
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn patches_must_cover_whole_instructions() {
+		// Instructions at 0..2, 2..3 and 3..6
+		let boundaries = [0, 2, 3, 6];
+
+		assert_eq!(check_boundaries(&boundaries, 0, 2), Ok(()));
+		assert_eq!(check_boundaries(&boundaries, 2, 6), Ok(()));
+		assert_eq!(check_boundaries(&boundaries, 0, 6), Ok(()));
+
+		assert_eq!(
+			check_boundaries(&boundaries, 1, 2),
+			Err(PatchError::InvalidOffset)
+		);
+		assert_eq!(
+			check_boundaries(&boundaries, 6, 7),
+			Err(PatchError::InvalidOffset)
+		);
+		assert_eq!(
+			check_boundaries(&boundaries, 0, 1),
+			Err(PatchError::InvalidLength)
+		);
+		assert_eq!(
+			check_boundaries(&boundaries, 3, 7),
+			Err(PatchError::InvalidLength)
+		);
+	}
+}
@@ -0,0 +1,684 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use auxtools::raw_types::procs::{ExecutionContext, ProcId};
+use auxtools::*;
+
+use crate::server_types::{ProcRef, ProfiledProc};
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(1);
+
+// Stacks deeper than this are cut off at the top
+const MAX_DEPTH: usize = 128;
+
+// Only allocated while call profiling is running
+thread_local!(static CALLS: RefCell<Option<Calls>> = RefCell::new(None));
+
+// Only allocated while sampling is running
+thread_local!(static SAMPLES: RefCell<Option<Samples>> = RefCell::new(None));
+
+// Set by the sampling thread every interval, cleared by the instruction hook when it takes the sample
+static SAMPLE_DUE: AtomicBool = AtomicBool::new(false);
+
+#[shutdown]
+fn profiler_shutdown() {
+	CALLS.with(|calls| *calls.borrow_mut() = None);
+	SAMPLES.with(|samples| *samples.borrow_mut() = None);
+}
+
+#[derive(Default)]
+struct Samples {
+	// How often each instruction was executing
+	offsets: HashMap<(ProcId, u16), u64>,
+
+	// How often each stack was seen, outermost proc first
+	stacks: HashMap<Vec<ProcId>, u64>,
+}
+
+/// Starts sampling what BYOND is executing every `interval`.
+///
+/// A thread of its own keeps time, and the instruction hook takes the sample at the next instruction, on the game
+/// thread. Procs run at full speed otherwise. Nothing is sampled while BYOND is idle.
+pub fn start(interval: Duration) -> ProfilerGuard {
+	SAMPLES.with(|samples| {
+		samples.borrow_mut().get_or_insert_with(Samples::default);
+	});
+	SAMPLE_DUE.store(false, Ordering::Relaxed);
+
+	let running = Arc::new(AtomicBool::new(true));
+
+	let thread = {
+		let running = running.clone();
+
+		thread::spawn(move || {
+			while running.load(Ordering::Relaxed) {
+				thread::sleep(interval);
+				SAMPLE_DUE.store(true, Ordering::Relaxed);
+			}
+		})
+	};
+
+	ProfilerGuard {
+		running,
+		thread: Some(thread),
+	}
+}
+
+fn take_sample(ctx: *mut ExecutionContext) {
+	let stack = match unsafe { sample(ctx) } {
+		Some(stack) => stack,
+		None => return,
+	};
+
+	SAMPLES.with(|samples| {
+		if let Some(samples) = samples.borrow_mut().as_mut() {
+			let (proc, offset) = stack[stack.len() - 1];
+
+			*samples.offsets.entry((proc, offset)).or_default() += 1;
+			*samples
+				.stacks
+				.entry(stack.iter().map(|(proc, _)| *proc).collect())
+				.or_default() += 1;
+		}
+	});
+}
+
+// `ctx` and its callers along with their offsets, outermost first. None if nothing is running.
+unsafe fn sample(mut ctx: *mut ExecutionContext) -> Option<Vec<(ProcId, u16)>> {
+	let mut stack = vec![];
+
+	while !ctx.is_null() && stack.len() < MAX_DEPTH {
+		let instance = (*ctx).proc_instance;
+		if instance.is_null() {
+			break;
+		}
+
+		stack.push(((*instance).proc, (*ctx).bytecode_offset));
+		ctx = (*ctx).parent_context;
+	}
+
+	if stack.is_empty() {
+		return None;
+	}
+
+	stack.reverse();
+	Some(stack)
+}
+
+/// Keeps the profiler sampling until [stop](ProfilerGuard::stop) is called or it's dropped.
+pub struct ProfilerGuard {
+	running: Arc<AtomicBool>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl ProfilerGuard {
+	/// Stops sampling and reports on every proc that was seen executing.
+	pub fn stop(mut self) -> ProfilerReport {
+		self.join();
+
+		let samples = SAMPLES
+			.with(|samples| samples.borrow_mut().take())
+			.unwrap_or_default();
+		ProfilerReport::new(samples, |id| {
+			Proc::from_id(id).map(|proc| (proc.override_id(), proc.path))
+		})
+	}
+
+	fn join(&mut self) {
+		self.running.store(false, Ordering::Relaxed);
+
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+// The sampling thread mustn't outlive the library
+impl Drop for ProfilerGuard {
+	fn drop(&mut self) {
+		self.join();
+		SAMPLES.with(|samples| *samples.borrow_mut() = None);
+	}
+}
+
+pub struct ProfilerReport {
+	/// Sorted by samples, hottest first
+	pub procs: Vec<ProcProfile>,
+
+	/// Every sampled stack in the folded format flamegraph tools read
+	folded: Vec<(String, u64)>,
+}
+
+pub struct ProcProfile {
+	pub proc_path: String,
+	pub override_id: u32,
+	pub samples: u64,
+	/// (offset, samples), sorted by offset
+	pub offsets: Vec<(u32, u64)>,
+}
+
+impl ProfilerReport {
+	// `resolve` gives the override id and path of a proc. Samples of procs it can't find are dropped.
+	fn new<F: Fn(ProcId) -> Option<(u32, String)>>(samples: Samples, resolve: F) -> Self {
+		let mut names: HashMap<ProcId, Option<(u32, String)>> = HashMap::new();
+		let mut name = |id: ProcId| names.entry(id).or_insert_with(|| resolve(id)).clone();
+
+		let mut procs: HashMap<ProcId, ProcProfile> = HashMap::new();
+		for ((id, offset), count) in samples.offsets {
+			let (override_id, proc_path) = match name(id) {
+				Some(name) => name,
+				None => continue,
+			};
+
+			let profile = procs.entry(id).or_insert_with(|| ProcProfile {
+				proc_path,
+				override_id,
+				samples: 0,
+				offsets: vec![],
+			});
+
+			profile.samples += count;
+			profile.offsets.push((offset as u32, count));
+		}
+
+		let mut procs: Vec<ProcProfile> = procs.into_iter().map(|(_, profile)| profile).collect();
+		for profile in &mut procs {
+			profile.offsets.sort_unstable();
+		}
+		procs.sort_by(|a, b| {
+			b.samples
+				.cmp(&a.samples)
+				.then_with(|| (&a.proc_path, a.override_id).cmp(&(&b.proc_path, b.override_id)))
+		});
+
+		let mut folded: Vec<(String, u64)> = samples
+			.stacks
+			.into_iter()
+			.filter_map(|(stack, count)| {
+				let frames: Option<Vec<String>> = stack
+					.into_iter()
+					.map(|id| name(id).map(|(override_id, path)| frame_name(&path, override_id)))
+					.collect();
+
+				Some((frames?.join(";"), count))
+			})
+			.collect();
+		folded.sort();
+
+		ProfilerReport { procs, folded }
+	}
+
+	/// The `n` procs that were executing most often.
+	pub fn top_procs(&self, n: usize) -> &[ProcProfile] {
+		&self.procs[..n.min(self.procs.len())]
+	}
+
+	pub fn total_samples(&self) -> u64 {
+		self.procs.iter().map(|proc| proc.samples).sum()
+	}
+
+	pub fn summary(&self, n: usize) -> String {
+		let total = self.total_samples();
+		let mut summary = format!("{} samples\n", total);
+
+		for proc in self.top_procs(n) {
+			let _ = writeln!(
+				summary,
+				"{:>6.2}% {} (#{}): {} samples",
+				proc.samples as f64 * 100.0 / total as f64,
+				proc.proc_path,
+				proc.override_id,
+				proc.samples
+			);
+		}
+
+		summary
+	}
+
+	/// The samples as collapsed stacks, one `outer;inner count` line per stack, like `stackcollapse-perf.pl` outputs.
+	/// Feed it to `flamegraph.pl` or `inferno-flamegraph` to get a flame graph.
+	pub fn folded(&self) -> String {
+		write_folded(&self.folded)
+	}
+}
+
+/// Starts timing every proc call until the guard is stopped.
+///
+/// Calls are noticed in the instruction hook, which compares each instruction's context with the one before it.
+/// Procs that sleep or runtime count as returning then. Procs that were already running when profiling
+/// started, or that wake up from a sleep, count as called the first time one of their instructions runs.
+pub fn start_calls() -> CallProfilerGuard {
+	CALLS.with(|calls| {
+		calls.borrow_mut().get_or_insert_with(Calls::default);
+	});
+
+	CallProfilerGuard { _private: () }
+}
+
+// Called by the instruction hook for every instruction BYOND executes
+pub fn record(ctx: *mut ExecutionContext) {
+	if SAMPLE_DUE.load(Ordering::Relaxed) && SAMPLE_DUE.swap(false, Ordering::Relaxed) {
+		take_sample(ctx);
+	}
+
+	CALLS.with(|calls| {
+		if let Some(calls) = calls.borrow_mut().as_mut() {
+			unsafe { calls.enter(ctx) }
+		}
+	});
+}
+
+struct Frame {
+	ctx: *mut ExecutionContext,
+	proc: ProcId,
+	entered: Instant,
+	// Time spent in the procs this one called
+	children: Duration,
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+struct CallTotals {
+	calls: u64,
+	inclusive: Duration,
+	exclusive: Duration,
+}
+
+#[derive(Default)]
+struct Calls {
+	// Procs we saw start and haven't seen return yet, outermost first
+	stack: Vec<Frame>,
+	procs: HashMap<ProcId, CallTotals>,
+	// Exclusive time spent in each stack, outermost proc first
+	stacks: HashMap<Vec<ProcId>, Duration>,
+}
+
+impl Calls {
+	// Brings the stack up to date with `ctx`, which is about to execute an instruction
+	unsafe fn enter(&mut self, ctx: *mut ExecutionContext) {
+		if matches!(self.stack.last(), Some(frame) if frame.ctx == ctx) {
+			return;
+		}
+
+		// `ctx` and its callers, innermost first
+		let mut chain = vec![];
+		let mut current = ctx;
+		while !current.is_null() && chain.len() < MAX_DEPTH {
+			let instance = (*current).proc_instance;
+			if instance.is_null() {
+				break;
+			}
+
+			chain.push((current, (*instance).proc));
+			current = (*current).parent_context;
+		}
+
+		let now = Instant::now();
+
+		// Anything that isn't part of the chain anymore has returned, slept or runtimed
+		while let Some(frame) = self.stack.last() {
+			if chain.contains(&(frame.ctx, frame.proc)) {
+				break;
+			}
+			self.pop(now);
+		}
+
+		let known = match self.stack.last() {
+			Some(frame) => chain
+				.iter()
+				.position(|entry| *entry == (frame.ctx, frame.proc))
+				.unwrap_or(0),
+			None => chain.len(),
+		};
+
+		for &(ctx, proc) in chain[..known].iter().rev() {
+			self.push(ctx, proc, now);
+		}
+	}
+
+	fn push(&mut self, ctx: *mut ExecutionContext, proc: ProcId, now: Instant) {
+		self.stack.push(Frame {
+			ctx,
+			proc,
+			entered: now,
+			children: Duration::default(),
+		});
+		self.procs.entry(proc).or_default().calls += 1;
+	}
+
+	fn pop(&mut self, now: Instant) {
+		let frame = match self.stack.pop() {
+			Some(frame) => frame,
+			None => return,
+		};
+
+		let inclusive = now.saturating_duration_since(frame.entered);
+		let exclusive = inclusive.checked_sub(frame.children).unwrap_or_default();
+
+		let stack: Vec<ProcId> = self
+			.stack
+			.iter()
+			.map(|frame| frame.proc)
+			.chain(std::iter::once(frame.proc))
+			.collect();
+		*self.stacks.entry(stack).or_default() += exclusive;
+
+		// A recursive call's time is already part of the outer call's
+		let recursive = self.stack.iter().any(|outer| outer.proc == frame.proc);
+
+		let totals = self.procs.entry(frame.proc).or_default();
+		totals.exclusive += exclusive;
+		if !recursive {
+			totals.inclusive += inclusive;
+		}
+
+		if let Some(parent) = self.stack.last_mut() {
+			parent.children += inclusive;
+		}
+	}
+}
+
+/// Keeps call profiling running until [stop](CallProfilerGuard::stop) is called or it's dropped.
+pub struct CallProfilerGuard {
+	_private: (),
+}
+
+impl CallProfilerGuard {
+	/// Stops profiling and reports on every proc that was called. Procs still running count as returning now.
+	pub fn stop(self) -> CallReport {
+		let mut calls = CALLS
+			.with(|calls| calls.borrow_mut().take())
+			.unwrap_or_default();
+
+		let now = Instant::now();
+		while !calls.stack.is_empty() {
+			calls.pop(now);
+		}
+
+		CallReport::new(calls, |id| {
+			Proc::from_id(id).map(|proc| (proc.override_id(), proc.path))
+		})
+	}
+}
+
+impl Drop for CallProfilerGuard {
+	fn drop(&mut self) {
+		CALLS.with(|calls| *calls.borrow_mut() = None);
+	}
+}
+
+pub struct CallReport {
+	/// Sorted by exclusive time, slowest first
+	pub procs: Vec<CallProfile>,
+
+	/// Exclusive microseconds spent in each stack, in the folded format flamegraph tools read
+	folded: Vec<(String, u64)>,
+}
+
+pub struct CallProfile {
+	pub proc_path: String,
+	pub override_id: u32,
+	pub calls: u64,
+	/// Time between being called and returning. Recursive calls are only counted once.
+	pub inclusive: Duration,
+	/// Time spent in the proc itself, not counting the procs it called
+	pub exclusive: Duration,
+}
+
+impl CallReport {
+	// `resolve` gives the override id and path of a proc. Calls of procs it can't find are dropped.
+	fn new<F: Fn(ProcId) -> Option<(u32, String)>>(calls: Calls, resolve: F) -> Self {
+		let mut names: HashMap<ProcId, Option<(u32, String)>> = HashMap::new();
+		let mut name = |id: ProcId| names.entry(id).or_insert_with(|| resolve(id)).clone();
+
+		let mut procs: Vec<CallProfile> = calls
+			.procs
+			.into_iter()
+			.filter_map(|(id, totals)| {
+				let (override_id, proc_path) = name(id)?;
+
+				Some(CallProfile {
+					proc_path,
+					override_id,
+					calls: totals.calls,
+					inclusive: totals.inclusive,
+					exclusive: totals.exclusive,
+				})
+			})
+			.collect();
+		procs.sort_by(|a, b| {
+			b.exclusive
+				.cmp(&a.exclusive)
+				.then_with(|| (&a.proc_path, a.override_id).cmp(&(&b.proc_path, b.override_id)))
+		});
+
+		let mut folded: Vec<(String, u64)> = calls
+			.stacks
+			.into_iter()
+			.filter_map(|(stack, time)| {
+				let frames: Option<Vec<String>> = stack
+					.into_iter()
+					.map(|id| name(id).map(|(override_id, path)| frame_name(&path, override_id)))
+					.collect();
+
+				Some((frames?.join(";"), time.as_micros() as u64))
+			})
+			.filter(|(_, micros)| *micros > 0)
+			.collect();
+		folded.sort();
+
+		CallReport { procs, folded }
+	}
+
+	pub fn summary(&self, n: usize) -> String {
+		let total: Duration = self.procs.iter().map(|proc| proc.exclusive).sum();
+		let calls: u64 = self.procs.iter().map(|proc| proc.calls).sum();
+		let mut summary = format!("{} calls in {:.3}ms\n", calls, millis(total));
+
+		for proc in &self.procs[..n.min(self.procs.len())] {
+			let _ = writeln!(
+				summary,
+				"{:>6.2}% {} (#{}): {} calls, {:.3}ms inclusive, {:.3}ms exclusive",
+				millis(proc.exclusive) * 100.0 / millis(total).max(f64::MIN_POSITIVE),
+				proc.proc_path,
+				proc.override_id,
+				proc.calls,
+				millis(proc.inclusive),
+				millis(proc.exclusive)
+			);
+		}
+
+		summary
+	}
+
+	/// Exclusive time per stack as collapsed stacks, counted in microseconds. See [ProfilerReport::folded].
+	pub fn folded(&self) -> String {
+		write_folded(&self.folded)
+	}
+}
+
+fn millis(duration: Duration) -> f64 {
+	duration.as_secs_f64() * 1000.0
+}
+
+/// A running profiler of either kind.
+pub enum Profiler {
+	Sampling(ProfilerGuard),
+	Calls(CallProfilerGuard),
+}
+
+impl Profiler {
+	pub fn stop(self) -> Report {
+		match self {
+			Profiler::Sampling(guard) => Report::Sampling(guard.stop()),
+			Profiler::Calls(guard) => Report::Calls(guard.stop()),
+		}
+	}
+}
+
+pub enum Report {
+	Sampling(ProfilerReport),
+	Calls(CallReport),
+}
+
+impl Report {
+	/// Lists the `n` procs that took the most time.
+	pub fn summary(&self, n: usize) -> String {
+		match self {
+			Report::Sampling(report) => report.summary(n),
+			Report::Calls(report) => report.summary(n),
+		}
+	}
+
+	pub fn folded(&self) -> String {
+		match self {
+			Report::Sampling(report) => report.folded(),
+			Report::Calls(report) => report.folded(),
+		}
+	}
+
+	/// Every proc in the report, in the form sent to clients
+	pub fn profiled_procs(&self) -> Vec<ProfiledProc> {
+		match self {
+			Report::Sampling(report) => report
+				.procs
+				.iter()
+				.map(|proc| ProfiledProc {
+					proc: ProcRef {
+						path: proc.proc_path.clone(),
+						override_id: proc.override_id,
+					},
+					samples: proc.samples,
+					calls: 0,
+					inclusive_micros: 0,
+					exclusive_micros: 0,
+				})
+				.collect(),
+
+			Report::Calls(report) => report
+				.procs
+				.iter()
+				.map(|proc| ProfiledProc {
+					proc: ProcRef {
+						path: proc.proc_path.clone(),
+						override_id: proc.override_id,
+					},
+					samples: 0,
+					calls: proc.calls,
+					inclusive_micros: proc.inclusive.as_micros() as u64,
+					exclusive_micros: proc.exclusive.as_micros() as u64,
+				})
+				.collect(),
+		}
+	}
+}
+
+fn write_folded(folded: &[(String, u64)]) -> String {
+	let mut text = String::new();
+
+	for (stack, count) in folded {
+		let _ = writeln!(text, "{} {}", stack, count);
+	}
+
+	text
+}
+
+// Overrides get their id, so they don't merge with the original proc in flame graphs
+fn frame_name(path: &str, override_id: u32) -> String {
+	// Semicolons and spaces separate frames and counts in the folded format
+	let path = path.replace(|c| c == ';' || c == ' ', "_");
+
+	match override_id {
+		0 => path,
+		id => format!("{}#{}", path, id),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn report_groups_samples_by_proc() {
+		let mut samples = Samples::default();
+		samples.offsets.insert((ProcId(1), 4), 3);
+		samples.offsets.insert((ProcId(1), 2), 1);
+		samples.offsets.insert((ProcId(2), 0), 6);
+		samples.offsets.insert((ProcId(3), 0), 2);
+		samples.stacks.insert(vec![ProcId(2)], 6);
+		samples.stacks.insert(vec![ProcId(2), ProcId(1)], 4);
+		samples.stacks.insert(vec![ProcId(2), ProcId(3)], 2);
+
+		// Proc 3 has gone missing
+		let report = ProfilerReport::new(samples, |id| match id.0 {
+			1 => Some((0, "/proc/inner".to_owned())),
+			2 => Some((1, "/proc/outer".to_owned())),
+			_ => None,
+		});
+
+		assert_eq!(report.total_samples(), 10);
+
+		let top = report.top_procs(5);
+		assert_eq!(top.len(), 2);
+		assert_eq!(top[0].proc_path, "/proc/outer");
+		assert_eq!(top[0].samples, 6);
+		assert_eq!(top[1].proc_path, "/proc/inner");
+		assert_eq!(top[1].offsets, vec![(2, 1), (4, 3)]);
+		assert_eq!(report.top_procs(1).len(), 1);
+
+		assert_eq!(
+			report.folded(),
+			"/proc/outer#1 6\n/proc/outer#1;/proc/inner 4\n"
+		);
+	}
+
+	#[test]
+	fn calls_split_inclusive_and_exclusive_time() {
+		let ms = Duration::from_millis;
+		let start = Instant::now();
+		let ctx = |n: usize| n as *mut ExecutionContext;
+
+		// outer calls inner, which recurses once
+		let mut calls = Calls::default();
+		calls.push(ctx(1), ProcId(1), start);
+		calls.push(ctx(2), ProcId(2), start + ms(2));
+		calls.push(ctx(3), ProcId(2), start + ms(3));
+		calls.pop(start + ms(4));
+		calls.pop(start + ms(6));
+		calls.pop(start + ms(10));
+
+		assert_eq!(
+			calls.procs[&ProcId(1)],
+			CallTotals {
+				calls: 1,
+				inclusive: ms(10),
+				exclusive: ms(6),
+			}
+		);
+		assert_eq!(
+			calls.procs[&ProcId(2)],
+			CallTotals {
+				calls: 2,
+				inclusive: ms(4),
+				exclusive: ms(4),
+			}
+		);
+
+		let report = CallReport::new(calls, |id| match id.0 {
+			1 => Some((0, "/proc/outer".to_owned())),
+			2 => Some((0, "/proc/inner".to_owned())),
+			_ => None,
+		});
+
+		assert_eq!(report.procs[0].proc_path, "/proc/outer");
+		assert_eq!(report.procs[1].calls, 2);
+		assert_eq!(
+			report.folded(),
+			"/proc/outer 6000\n/proc/outer;/proc/inner 3000\n/proc/outer;/proc/inner;/proc/inner 1000\n"
+		);
+	}
+}
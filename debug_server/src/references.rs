@@ -0,0 +1,129 @@
+//! Finding what holds a reference to an object or list, for working out why it hasn't been deleted.
+//!
+//! The global variables are looked through first, then every object and list is walked by id like in the
+//! census, looking through their variables and elements. The target is only kept as a raw tag and id, so the
+//! search doesn't keep it alive and has nothing to clean up if it's dropped partway.
+
+use std::time::{Duration, Instant};
+
+use auxtools::raw_types::values::ValueTag;
+use auxtools::*;
+
+use crate::census::{ObjectWalk, BATCH};
+
+// Everything that can hold a reference, in the order they're walked
+const KINDS: [ValueTag; 7] = [
+	ValueTag::Turf,
+	ValueTag::Obj,
+	ValueTag::Mob,
+	ValueTag::Area,
+	ValueTag::Client,
+	ValueTag::Datum,
+	ValueTag::List,
+];
+
+/// A search that can be carried out a bit at a time, like a [Census](crate::census::Census).
+pub struct ReferenceSearch {
+	target: (u8, u32),
+	walk: ObjectWalk,
+	searched_globals: bool,
+	searched: u32,
+	limit: usize,
+	// Each holder, and the variable or list index the reference is in
+	holders: Vec<(String, Value)>,
+}
+
+impl ReferenceSearch {
+	/// Finds up to `limit` holders of `target`.
+	pub fn new(target: &Value, limit: usize) -> Self {
+		ReferenceSearch {
+			target: (target.raw.tag as u8, unsafe { target.raw.data.id }),
+			walk: ObjectWalk::new(&KINDS),
+			searched_globals: false,
+			searched: 0,
+			limit,
+			holders: vec![],
+		}
+	}
+
+	/// Searches for about `budget`. True once everything has been searched or `limit` holders were found.
+	pub fn step(&mut self, budget: Duration) -> bool {
+		let deadline = Instant::now() + budget;
+
+		if !self.searched_globals {
+			self.searched_globals = true;
+			self.search_list(&Value::globals(), &Value::global_vars(), true);
+		}
+
+		while !self.is_done() {
+			for _ in 0..BATCH {
+				if let Some((object, _)) = self.walk.visit() {
+					self.search(unsafe { Value::from_raw(object) });
+				}
+			}
+
+			if Instant::now() >= deadline {
+				return self.is_done();
+			}
+		}
+
+		true
+	}
+
+	fn is_done(&self) -> bool {
+		self.walk.is_done() || self.holders.len() >= self.limit
+	}
+
+	/// How many objects and lists have been looked through so far.
+	pub fn searched(&self) -> u32 {
+		self.searched
+	}
+
+	/// The holders found so far, named after where they keep the reference.
+	pub fn into_holders(self) -> Vec<(String, Value)> {
+		self.holders
+	}
+
+	fn search(&mut self, holder: Value) {
+		self.searched += 1;
+
+		if let Ok(list) = List::from_value(&holder) {
+			self.search_list(&holder, &list, false);
+		} else if let Ok(vars) = holder.get_list(byond_string!("vars")) {
+			self.search_list(&holder, &vars, true);
+		}
+	}
+
+	// With `by_name`, the list is of variables, and the holder is named after the variable instead of the index
+	fn search_list(&mut self, holder: &Value, list: &List, by_name: bool) {
+		let entries = match list.entries() {
+			Ok(entries) => entries,
+			Err(_) => return,
+		};
+
+		for (i, entry) in entries.into_iter().enumerate() {
+			let found = match &entry {
+				ListEntry::Indexed(value) => self.is_target(value),
+				ListEntry::Assoc { value, .. } if by_name => self.is_target(value),
+				ListEntry::Assoc { key, value } => self.is_target(key) || self.is_target(value),
+			};
+
+			if !found || self.holders.len() >= self.limit {
+				continue;
+			}
+
+			let name = match &entry {
+				ListEntry::Assoc { key, .. } if by_name => {
+					key.as_string().unwrap_or_else(|_| format!("[{}]", i + 1))
+				}
+				_ => format!("[{}]", i + 1),
+			};
+
+			self.holders.push((name, holder.clone()));
+		}
+	}
+
+	fn is_target(&self, value: &Value) -> bool {
+		(value.raw.tag as u8, unsafe { value.raw.data.id }) == self.target
+	}
+}
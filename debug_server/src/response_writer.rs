@@ -0,0 +1,166 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::framing::Framing;
+use crate::server_types::Response;
+use crate::transport::Stream;
+
+enum Message {
+	Response(Response),
+
+	// Answered once everything queued before it has been written
+	Flush(mpsc::Sender<()>),
+
+	// Sends a Disconnect and closes the stream after everything queued before it
+	Close,
+}
+
+// Serializes and writes responses on its own thread, so a slow client can't hold up the game thread.
+// One of these exists per connection.
+pub struct ResponseWriter {
+	messages: mpsc::Sender<Message>,
+
+	// Set by the thread when a write fails, after which nothing else gets written
+	failed: Arc<AtomicBool>,
+
+	thread: JoinHandle<()>,
+
+	// Disconnects when the thread finishes
+	finished: mpsc::Receiver<()>,
+
+	// Another handle to the stream, for giving up on a write that's stuck
+	stream: Option<Stream>,
+}
+
+impl ResponseWriter {
+	pub fn spawn(stream: Stream, framing: Framing) -> Self {
+		let (sender, receiver) = mpsc::channel();
+		let failed = Arc::new(AtomicBool::new(false));
+
+		let (finished_sender, finished) = mpsc::channel::<()>();
+		let shutdown_stream = stream.try_clone().ok();
+
+		let thread_failed = failed.clone();
+		let thread = thread::spawn(move || {
+			Self::run(stream, framing, receiver, &thread_failed);
+			drop(finished_sender);
+		});
+
+		ResponseWriter {
+			messages: sender,
+			failed,
+			thread,
+			finished,
+			stream: shutdown_stream,
+		}
+	}
+
+	// Queues a response. Returns false if the connection is known to be dead.
+	pub fn send(&self, response: Response) -> bool {
+		if self.has_failed() {
+			return false;
+		}
+
+		self.messages.send(Message::Response(response)).is_ok()
+	}
+
+	// Blocks until everything queued so far has been written. Returns false if any of it couldn't be.
+	pub fn flush(&self) -> bool {
+		let (sender, receiver) = mpsc::channel();
+
+		if self.messages.send(Message::Flush(sender)).is_err() {
+			return false;
+		}
+
+		receiver.recv().is_ok() && !self.has_failed()
+	}
+
+	pub fn has_failed(&self) -> bool {
+		self.failed.load(Ordering::Acquire)
+	}
+
+	// Lets the thread finish writing in the background, then disconnect the client
+	pub fn close(self) {
+		let _ = self.messages.send(Message::Close);
+	}
+
+	// Like close, but waits up to `timeout` for the thread to finish. If a client stops reading, the write it's
+	// stuck on is abandoned by shutting the stream down, and the thread is left to exit on its own.
+	// Returns false if it didn't finish in time.
+	pub fn close_and_wait(self, timeout: Duration) -> bool {
+		let _ = self.messages.send(Message::Close);
+
+		match self.finished.recv_timeout(timeout) {
+			Err(mpsc::RecvTimeoutError::Timeout) => {
+				if let Some(stream) = &self.stream {
+					stream.shutdown();
+				}
+				false
+			}
+
+			_ => {
+				let _ = self.thread.join();
+				true
+			}
+		}
+	}
+
+	fn run(
+		mut stream: Stream,
+		framing: Framing,
+		messages: mpsc::Receiver<Message>,
+		failed: &AtomicBool,
+	) {
+		for message in messages {
+			match message {
+				Message::Response(response) => {
+					if let Err(e) = framing.write_response(&mut stream, &response) {
+						eprintln!("Debug server failed to send message: {}", e);
+						failed.store(true, Ordering::Release);
+						break;
+					}
+				}
+
+				Message::Flush(done) => {
+					let _ = done.send(());
+				}
+
+				Message::Close => {
+					let _ = framing.write_response(&mut stream, &Response::Disconnect);
+					break;
+				}
+			}
+		}
+
+		// Also stops the ServerThread reading from this connection
+		stream.shutdown();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::net::{TcpListener, TcpStream};
+	use std::time::Instant;
+
+	#[test]
+	fn closing_gives_up_on_clients_that_stop_reading() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+		let (stream, _) = listener.accept().unwrap();
+
+		// Far more than the socket buffers hold, and the client never reads any of it
+		let writer = ResponseWriter::spawn(Box::new(stream), Framing::Bincode);
+		for _ in 0..64 {
+			writer.send(Response::Notification {
+				message: "x".repeat(1024 * 1024),
+			});
+		}
+
+		let start = Instant::now();
+		assert!(!writer.close_and_wait(Duration::from_millis(200)));
+		assert!(start.elapsed() < Duration::from_secs(5));
+	}
+}
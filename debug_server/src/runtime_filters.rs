@@ -0,0 +1,111 @@
+use auxtools::hooks;
+use std::fmt;
+
+// Decides which runtimes are worth pausing for.
+//
+// Include/exclude patterns are matched against both the runtime's message and the path of the proc that threw it.
+// Proc patterns (from Request::CatchRuntimes) are only matched against the proc's path.
+// A pattern containing `*` or `?` is treated as a glob that must match the whole text, anything else is a substring.
+#[derive(Default)]
+pub struct RuntimeFilters {
+	include: Vec<String>,
+	exclude: Vec<String>,
+	procs: Option<Vec<String>>,
+}
+
+impl RuntimeFilters {
+	pub fn set_patterns(&mut self, include: Vec<String>, exclude: Vec<String>) {
+		self.include = include;
+		self.exclude = exclude;
+	}
+
+	// None catches runtimes from every proc
+	pub fn set_procs(&mut self, procs: Option<Vec<String>>) {
+		self.procs = procs;
+	}
+
+	// An empty include list means everything is included
+	pub fn should_break(&self, message: &str, proc_path: Option<&str>) -> bool {
+		if let Some(procs) = &self.procs {
+			let in_procs = proc_path.map_or(false, |path| {
+				procs.iter().any(|pattern| pattern_matches(pattern, path))
+			});
+
+			if !in_procs {
+				return false;
+			}
+		}
+
+		let matches = |pattern: &String| {
+			pattern_matches(pattern, message)
+				|| proc_path.map_or(false, |path| pattern_matches(pattern, path))
+		};
+
+		if !self.include.is_empty() && !self.include.iter().any(matches) {
+			return false;
+		}
+
+		!self.exclude.iter().any(matches)
+	}
+}
+
+impl fmt::Display for RuntimeFilters {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "procs:")?;
+		match &self.procs {
+			Some(procs) => {
+				for pattern in procs {
+					writeln!(f, "\t{}", pattern)?;
+				}
+			}
+			None => writeln!(f, "\t(all)")?,
+		}
+
+		writeln!(f, "include:")?;
+		if self.include.is_empty() {
+			writeln!(f, "\t(everything)")?;
+		}
+		for pattern in &self.include {
+			writeln!(f, "\t{}", pattern)?;
+		}
+
+		writeln!(f, "exclude:")?;
+		if self.exclude.is_empty() {
+			writeln!(f, "\t(nothing)")?;
+		}
+		for pattern in &self.exclude {
+			writeln!(f, "\t{}", pattern)?;
+		}
+
+		Ok(())
+	}
+}
+
+// Also used to search the string table
+pub fn pattern_matches(pattern: &str, text: &str) -> bool {
+	if pattern.contains(|c: char| c == '*' || c == '?') {
+		hooks::glob_matches(pattern.as_bytes(), text.as_bytes())
+	} else {
+		text.contains(pattern)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn proc_filter_only_checks_the_proc() {
+		let mut filters = RuntimeFilters::default();
+		assert!(filters.should_break("bad index", Some("/mob/proc/attack")));
+
+		filters.set_procs(Some(vec!["/mob/proc/attack".to_owned(), "/obj/*".to_owned()]));
+		assert!(filters.should_break("bad index", Some("/mob/proc/attack")));
+		assert!(filters.should_break("bad index", Some("/obj/item/proc/use")));
+		assert!(!filters.should_break("/mob/proc/attack", Some("/turf/proc/enter")));
+		assert!(!filters.should_break("bad index", None));
+
+		filters.set_procs(None);
+		assert!(filters.should_break("bad index", Some("/turf/proc/enter")));
+	}
+}
@@ -1,9 +1,30 @@
+use crate::assembly::{self, ProcAssemble};
+use crate::census::{self, Census};
+use crate::cfg::ProcControlFlow;
+use crate::coverage;
+use crate::framing::{Framing, Handshake};
+use crate::instruction_formatter::{mnemonic, InstructionFormatter};
+use crate::jump_labels::JumpLabels;
+use crate::line_table::LineTable;
 use crate::mem_profiler;
-
-use super::instruction_hooking::{get_hooked_offsets, hook_instruction, unhook_instruction};
-use std::io::{Read, Write};
+use crate::operands::{instruction_operands, OperandStyle};
+use crate::patching::{PatchGuard, ProcPatch};
+use crate::profiler;
+use crate::references::ReferenceSearch;
+use crate::response_writer::ResponseWriter;
+use crate::runtime_filters::{pattern_matches, RuntimeFilters};
+use crate::string_stats;
+use crate::transport::{Listener, Stream};
+
+use super::instruction_hooking::{
+	get_all_hooks, hook_instruction, is_instruction_start, unhook_instruction, without_hooks,
+};
+use std::io::{BufReader, Read};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::mpsc;
 use std::thread;
+use std::time::{Duration, Instant};
 use std::{cell::RefCell, error::Error};
 use std::{
 	collections::HashMap,
@@ -14,7 +35,7 @@ use std::{
 use clap::{App, AppSettings, Arg};
 
 use super::server_types::*;
-use auxtools::raw_types::values::{ValueData, ValueTag};
+use auxtools::raw_types::values::ValueTag;
 use auxtools::*;
 
 #[derive(Clone, Hash, PartialEq, Eq)]
@@ -26,16 +47,61 @@ enum Variables {
 	ListPair { key: Value, value: Value },
 }
 
+// A VariablesRef is only valid during the pause it was handed out in. Its top bits hold which pause
+// that was, so that references the client kept from an earlier pause can't be mistaken for new ones.
+// This wraps around every 512 pauses.
+const REF_INDEX_BITS: u32 = 22;
+const REF_INDEX_MASK: i32 = (1 << REF_INDEX_BITS) - 1;
+const REF_GENERATION_MASK: u32 = (1 << (31 - REF_INDEX_BITS)) - 1;
+
+static PAUSE_GENERATION: AtomicU32 = AtomicU32::new(0);
+
+// How long each #census or InstanceCensus request spends counting
+const CENSUS_BUDGET: Duration = Duration::from_millis(50);
+
+// How long a reference search runs between checks for requests, and how often it reports its progress
+const REFERENCES_BUDGET: Duration = Duration::from_millis(50);
+const REFERENCES_PROGRESS: Duration = Duration::from_secs(1);
+
+// How long a client has to start the handshake, so one that connects and says nothing can't hold up the game or
+// the clients after it
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long dropping the server waits for the responses still queued to be written
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
 struct State {
 	stacks: debug::CallStacks,
+	generation: u32,
+	// The proc we paused in is being unwound by a runtime, so it can't be moved with GotoLine
+	runtime: bool,
 	variables: RefCell<Vec<Variables>>,
 	variables_to_refs: RefCell<HashMap<Variables, VariablesRef>>,
 }
 
+fn make_ref(generation: u32, index: usize) -> VariablesRef {
+	VariablesRef(((generation << REF_INDEX_BITS) as i32) | (index as i32 + 1))
+}
+
+// The generation and index a reference was made from, if it could have been made at all
+fn split_ref(reference: &VariablesRef) -> Option<(u32, usize)> {
+	if reference.0 <= 0 {
+		return None;
+	}
+
+	let generation = (reference.0 >> REF_INDEX_BITS) as u32;
+	let index = ((reference.0 & REF_INDEX_MASK) as usize).checked_sub(1)?;
+	Some((generation, index))
+}
+
 impl State {
 	fn new() -> Self {
+		let generation = PAUSE_GENERATION.fetch_add(1, Ordering::Relaxed) & REF_GENERATION_MASK;
+
 		Self {
 			stacks: debug::CallStacks::new(),
+			generation,
+			runtime: false,
 			variables: RefCell::new(vec![]),
 			variables_to_refs: RefCell::new(HashMap::new()),
 		}
@@ -49,7 +115,7 @@ impl State {
 		let mut variables_to_refs = self.variables_to_refs.borrow_mut();
 		let mut variables = self.variables.borrow_mut();
 		(*variables_to_refs.entry(vars.clone()).or_insert_with(|| {
-			let reference = VariablesRef(variables.len() as i32 + 1);
+			let reference = make_ref(self.generation, variables.len());
 			variables.push(vars);
 			reference
 		}))
@@ -57,10 +123,21 @@ impl State {
 	}
 
 	fn get_variables(&self, reference: VariablesRef) -> Option<Variables> {
-		let variables = self.variables.borrow();
-		variables
-			.get(reference.0 as usize - 1)
-			.map(|x| (*x).clone())
+		match split_ref(&reference) {
+			Some((generation, index)) if generation == self.generation => {
+				self.variables.borrow().get(index).map(|x| (*x).clone())
+			}
+
+			_ => None,
+		}
+	}
+
+	// Whether the reference was handed out during an earlier pause
+	fn is_expired(&self, reference: &VariablesRef) -> bool {
+		match split_ref(reference) {
+			Some((generation, _)) => generation != self.generation,
+			None => false,
+		}
 	}
 }
 
@@ -69,38 +146,163 @@ impl State {
 // ServerThread = networking-thread code
 //
 // We've got a couple of channels going on between Server/ServerThread
-// connection: a TcpStream sent from the ServerThread for the Server to send responses on
+// connection: a Stream sent from the ServerThread for the Server to send responses on
 // requests: requests from the debug-client for the Server to handle
 //
-// Limitations: only ever accepts one connection
+// A listening server keeps accepting connections, one at a time. When a client goes away the
+// ServerThread sends a Request::Disconnect and the Server goes back to waiting for the next one.
+// If the Server drops a client first, that Disconnect is still on its way and has to be skipped over.
 //
 
 enum ServerStream {
 	// The server is waiting for a Stream to be sent on the connection channel
-	Waiting(mpsc::Receiver<TcpStream>),
+	Waiting,
 
-	Connected(TcpStream),
+	// Responses are written by the ResponseWriter's thread
+	Connected(ResponseWriter),
 
 	// The server has finished being used
 	Disconnected,
 }
 
+/// A breakpoint that outlives the server it was set on. It's kept by line, as the proc's bytecode (and with it
+/// every offset) can change before it's put back.
+pub struct SavedBreakpoint {
+	proc: ProcRef,
+	line: u32,
+	condition: Option<String>,
+}
+
 pub struct Server {
 	requests: mpsc::Receiver<Request>,
+	connections: Option<mpsc::Receiver<(Stream, Handshake)>>,
 	stream: ServerStream,
 	_thread: JoinHandle<()>,
 	should_catch_runtimes: bool,
+	runtime_filters: RuntimeFilters,
 	state: Option<State>,
 	in_eval: bool,
 	eval_error: Option<String>,
 	conditional_breakpoints: HashMap<(raw_types::procs::ProcId, u16), String>,
+	// Every breakpoint currently set (and its condition), kept around for clients that reconnect
+	breakpoints: HashMap<InstructionRef, Option<String>>,
+	// The temporary breakpoint placed by ContinueKind::RunTo. Only set while we own the hook, so
+	// removing it never takes out a breakpoint the client set on the same instruction.
+	run_to: Option<InstructionRef>,
+	coverage: Option<coverage::CoverageGuard>,
+	profiler: Option<profiler::Profiler>,
+	// The census in progress, and the counts from the last one that finished
+	census: Option<Census>,
+	last_census: Option<HashMap<String, u32>>,
+	// The FindReferences search in progress, and when it last reported how far it got
+	reference_search: Option<(ReferenceSearch, Instant)>,
+	// Patches applied with #patch, undone when they're dropped
+	patches: Vec<PatchGuard>,
+	// The bytecode hot patched procs had before they were first patched
+	hot_patches: HashMap<ProcRef, Vec<u32>>,
+	// The ServerThread hasn't yet reported the end of a connection we dropped ourselves
+	stale_connection: bool,
+	// Clients have to send a Request::Authenticate with this before anything else
+	token: Option<String>,
+	authenticated: bool,
+	// Whether BreakpointHit includes the active stack, see Request::StackOnBreakpoint
+	stack_on_breakpoint: bool,
+	line_tables: RefCell<HashMap<raw_types::procs::ProcId, LineTable>>,
+	disassemblies: HashMap<(raw_types::procs::ProcId, DisassemblyFormat), CachedDisassembly>,
+	// The unix socket we're listening on, removed when we're dropped
+	socket_path: Option<std::path::PathBuf>,
+	keep_alive: Option<KeepAlive>,
+	// How long to wait for a client to connect before giving up, see set_keep_alive
+	connect_timeout: Option<Duration>,
+	// process() runs before every instruction, so it only checks on the keep-alive every KEEP_ALIVE_INTERVAL calls
+	keep_alive_countdown: u32,
 	app: App<'static, 'static>,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum DisassemblyFormat {
+	Text,
+	// Text with the resolved operands of each instruction listed after it
+	Operands,
+	// Text with the string and proc ids each instruction refers to at the end of its line
+	Ids,
+	Json,
+	// Graphviz DOT of the proc's control flow graph
+	Dot,
+}
+
+// Breakpoints are removed before disassembling, so they don't make this stale. New bytecode does.
+struct CachedDisassembly {
+	bytecode: (*mut u32, u16),
+	text: String,
+}
+
+// The part of a disassembly covering the offsets in `range`. BYOND's instructions are different lengths, so
+// finding where they start means disassembling from the beginning of the proc anyway. If the range starts in
+// the middle of an instruction, it's snapped back to the start of that instruction.
+fn nodes_in_range<'a, 'b>(
+	nodes: &'b [dmasm::Node<dmasm::DebugData<'a>>],
+	range: Range<u32>,
+) -> &'b [dmasm::Node<dmasm::DebugData<'a>>] {
+	let ends_after = |node: &dmasm::Node<dmasm::DebugData>, offset: u32| match node {
+		dmasm::Node::Instruction(_, debug) => debug.offset + debug.bytecode.len() as u32 > offset,
+		_ => false,
+	};
+
+	let first = match nodes.iter().position(|node| ends_after(node, range.start)) {
+		Some(first) => first,
+		None => return &[],
+	};
+
+	// Any instruction starting at or after the end isn't in the range
+	let mut last = nodes[first..]
+		.iter()
+		.position(|node| match node {
+			dmasm::Node::Instruction(_, debug) => debug.offset >= range.end,
+			_ => false,
+		})
+		.map_or(nodes.len(), |i| first + i);
+
+	// Labels belong to the instruction after them
+	let mut first = first;
+	while first > 0 && !matches!(nodes[first - 1], dmasm::Node::Instruction(..)) {
+		first -= 1;
+	}
+	while last > first && !matches!(nodes[last - 1], dmasm::Node::Instruction(..)) {
+		last -= 1;
+	}
+
+	&nodes[first..last]
+}
+
+// Pinging a client that has gone quiet, to notice one that went away without disconnecting
+struct KeepAlive {
+	// How long the client can go without sending anything before it's pinged
+	idle_timeout: Duration,
+	// How long it then has to answer
+	answer_timeout: Duration,
+	last_heard: Instant,
+	ping_sent: Option<Instant>,
+}
+
+// Reading the clock costs more than most instructions, see Server::process
+const KEEP_ALIVE_INTERVAL: u32 = 1024;
+
 struct ServerThread {
 	requests: mpsc::Sender<Request>,
 }
 
+// Turns on the keep-alive for servers started by enable_debugging, pinging clients that have been quiet for this
+// long and giving them as long again to answer. Disabled unless AUXTOOLS_DEBUG_HEARTBEAT is set to a number of
+// seconds, as older clients don't answer pings.
+fn get_heartbeat_timeout() -> Option<Duration> {
+	std::env::var("AUXTOOLS_DEBUG_HEARTBEAT")
+		.ok()
+		.and_then(|val| val.parse::<u64>().ok())
+		.filter(|secs| *secs > 0)
+		.map(Duration::from_secs)
+}
+
 impl Server {
 	pub fn setup_app() -> App<'static, 'static> {
 		App::new("Auxtools Debug Server")
@@ -116,6 +318,117 @@ impl Server {
 				AppSettings::DisableHelpFlags,
 			])
 			.usage("#<SUBCOMMAND>")
+			.subcommand(
+				App::new("breakpoint")
+					.alias("break")
+					.about("Sets a breakpoint on a line of a proc")
+					.arg(
+						Arg::with_name("proc")
+							.help("Path of the proc (e.g. /proc/do_stuff)")
+							.takes_value(true)
+							.required(true),
+					)
+					.arg(
+						Arg::with_name("line")
+							.help("Line number within the proc's file")
+							.takes_value(true)
+							.required(true),
+					)
+					.arg(
+						Arg::with_name("id")
+							.help("Id of the proc (for when multiple procs are defined with the same path)")
+							.takes_value(true),
+					)
+			)
+			.subcommand(
+				App::new("breakpoints")
+					.about("Lists every installed breakpoint")
+					.subcommand(
+						App::new("clear")
+							.about("Removes every breakpoint")
+					)
+			)
+			.subcommand(
+				App::new("call")
+					.about("Calls a proc while execution is paused and shows what it returned")
+					.arg(
+						Arg::with_name("proc")
+							.help("Path of the proc (e.g. /proc/do_stuff or /mob/proc/do_stuff)")
+							.takes_value(true)
+							.required(true),
+					)
+					.arg(
+						Arg::with_name("args")
+							.help("DM expressions to pass as arguments, without spaces. Procs that aren't global take src first")
+							.multiple(true),
+					)
+			)
+			.subcommand(
+				App::new("callers")
+					.about("Lists the procs containing a call to the given proc")
+					.arg(
+						Arg::with_name("proc")
+							.help("Path of the called proc (e.g. /proc/do_stuff)")
+							.takes_value(true)
+							.required(true),
+					)
+			)
+			.subcommand(
+				App::new("census")
+					.about("Counts live objects by type, along with how that changed since the last census")
+					.after_help("Big worlds are counted a bit at a time. Run the command again until it reports the counts.")
+					.arg(
+						Arg::with_name("top")
+							.long("top")
+							.help("How many types to report (default 20)")
+							.takes_value(true),
+					)
+			)
+			.subcommand(
+				App::new("clear")
+					.about("Removes a breakpoint from a line of a proc")
+					.arg(
+						Arg::with_name("proc")
+							.help("Path of the proc (e.g. /proc/do_stuff)")
+							.takes_value(true)
+							.required(true),
+					)
+					.arg(
+						Arg::with_name("line")
+							.help("Line number within the proc's file")
+							.takes_value(true)
+							.required(true),
+					)
+					.arg(
+						Arg::with_name("id")
+							.help("Id of the proc (for when multiple procs are defined with the same path)")
+							.takes_value(true),
+					)
+			)
+			.subcommand(
+				App::new("coverage")
+					.about("Records which instructions are executed")
+					.subcommand(
+						App::new("start")
+							.about("Starts recording instruction coverage")
+							.arg(
+								Arg::with_name("lines")
+									.long("lines")
+									.help("Only record which lines of these procs run (and all of their overrides), which is cheap enough for real gameplay")
+									.takes_value(true)
+									.multiple(true),
+							)
+					)
+					.subcommand(
+						App::new("stop")
+							.about("Stops recording and reports the coverage of every proc that ran")
+							.arg(
+								Arg::with_name("json")
+									.long("json")
+									.help("Output the report as JSON for use by external tools"),
+							)
+					)
+			)
 			.subcommand(
 				App::new("disassemble")
 					.alias("dis")
@@ -131,6 +444,58 @@ impl Server {
 							.help("Id of the proc to disassemble (for when multiple procs are defined with the same path)")
 							.takes_value(true),
 					)
+					.arg(
+						Arg::with_name("json")
+							.long("json")
+							.help("Output the disassembly as JSON for use by external tools"),
+					)
+					.arg(
+						Arg::with_name("fresh")
+							.long("fresh")
+							.help("Disassemble the proc again instead of reusing an earlier disassembly"),
+					)
+					.arg(
+						Arg::with_name("operands")
+							.long("operands")
+							.conflicts_with("json")
+							.help("List what each instruction's operands refer to, like strings, variables and procs"),
+					)
+					.arg(
+						Arg::with_name("dot")
+							.long("dot")
+							.conflicts_with_all(&["json", "operands"])
+							.help("Output the proc's control flow graph in Graphviz's DOT language"),
+					)
+					.arg(
+						Arg::with_name("ids")
+							.long("ids")
+							.conflicts_with_all(&["json", "operands", "dot"])
+							.help("Show the ids of the strings, variables and procs each instruction refers to, with what they resolve to"),
+					)
+					.arg(
+						Arg::with_name("all")
+							.long("all")
+							.conflicts_with_all(&["id", "json", "dot"])
+							.help("Disassemble every override of the proc, one after another"),
+					)
+					.arg(
+						Arg::with_name("range")
+							.long("range")
+							.number_of_values(2)
+							.value_names(&["start", "end"])
+							.conflicts_with_all(&["json", "dot", "all"])
+							.help("Only show the instructions between these offsets, starting with the one containing the first"),
+					)
+					.arg(
+						Arg::with_name("current")
+							.long("current")
+							.conflicts_with_all(&["proc", "json", "operands", "dot", "all", "range"])
+							.help("Only show the instruction the selected stack frame is about to execute"),
+					)
+			)
+			.subcommand(
+				App::new("filters")
+					.about("Lists the patterns deciding which runtimes pause execution")
 			)
 			.subcommand(
 				App::new("guest_override")
@@ -157,57 +522,286 @@ impl Server {
 							.about("Finishes current memory profiler.")
 					)
 			)
+			.subcommand(
+				App::new("profiler")
+					.about("Samples which procs are executing, or times their calls")
+					.subcommand(
+						App::new("start")
+							.about("Starts sampling")
+							.arg(
+								Arg::with_name("interval")
+									.help("Milliseconds between samples (default 1)")
+									.takes_value(true),
+							)
+							.arg(
+								Arg::with_name("calls")
+									.long("calls")
+									.conflicts_with("interval")
+									.help("Time every proc call instead of sampling. Everything runs slower until the profiler stops."),
+							)
+					)
+					.subcommand(
+						App::new("stop")
+							.about("Stops sampling and reports the hottest procs")
+							.arg(
+								Arg::with_name("top")
+									.long("top")
+									.help("How many procs to report (default 20)")
+									.takes_value(true),
+							)
+							.arg(
+								Arg::with_name("folded")
+									.long("folded")
+									.help("Also write the sampled stacks to this file in the folded format flamegraph tools read")
+									.takes_value(true),
+							)
+					)
+			)
+			.subcommand(
+				App::new("patch")
+					.about("Overwrites instructions of a proc in place, for example to make it return early")
+					.subcommand(
+						App::new("apply")
+							.about("Writes bytecode words over whole instructions of a proc")
+							.arg(
+								Arg::with_name("proc")
+									.help("Path of the proc to patch (e.g. /proc/do_stuff)")
+									.required(true),
+							)
+							.arg(
+								Arg::with_name("offset")
+									.help("Offset of the first instruction to overwrite, as shown by #disassemble")
+									.required(true),
+							)
+							.arg(
+								Arg::with_name("words")
+									.help("The new bytecode words, in decimal or 0x-prefixed hex")
+									.required(true)
+									.multiple(true),
+							)
+							.arg(
+								Arg::with_name("id")
+									.long("id")
+									.help("Id of the proc to patch (for when multiple procs are defined with the same path)")
+									.takes_value(true),
+							)
+					)
+					.subcommand(
+						App::new("revert")
+							.about("Puts back the original instructions of every patch")
+					)
+			)
+			.subcommand(
+				App::new("assemble")
+					.about("Assembles instructions in the format #disassemble shows")
+					.subcommand(
+						App::new("replace")
+							.about("Replaces all of a proc's bytecode with the given instructions")
+							.arg(
+								Arg::with_name("proc")
+									.help("Path of the proc to replace (e.g. /proc/do_stuff)")
+									.required(true),
+							)
+							.arg(
+								Arg::with_name("source")
+									.help("The new instructions, separated by ; (e.g. PushVal 1; Ret)")
+									.required(true)
+									.multiple(true),
+							)
+							.arg(
+								Arg::with_name("id")
+									.long("id")
+									.help("Id of the proc to replace (for when multiple procs are defined with the same path)")
+									.takes_value(true),
+							)
+					)
+					.subcommand(
+						App::new("check")
+							.about("Checks that disassembling and assembling procs again gives identical bytecode")
+							.arg(
+								Arg::with_name("proc")
+									.help("Path of the proc to check (default every proc)"),
+							)
+					)
+			)
+			.subcommand(
+				App::new("strings")
+					.about("Sizes up the string table, and lists the strings matching a pattern")
+					.arg(
+						Arg::with_name("pattern")
+							.help("Substring to look for, or a glob if it contains * or ? (default none)"),
+					)
+					.arg(
+						Arg::with_name("limit")
+							.long("limit")
+							.help("How many matching strings to list (default 20)")
+							.takes_value(true),
+					)
+			)
 	}
 
-	pub fn connect(addr: &SocketAddr) -> std::io::Result<Server> {
-		let stream = TcpStream::connect_timeout(&addr, std::time::Duration::from_secs(5))?;
+	pub fn connect(addr: &SocketAddr, token: Option<String>) -> std::io::Result<Server> {
+		let mut stream = TcpStream::connect_timeout(&addr, std::time::Duration::from_secs(5))?;
+		stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+		let handshake = Handshake::read(&mut stream)
+			.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+		stream.set_read_timeout(None)?;
+		let stream: Stream = Box::new(stream);
 		let (requests_sender, requests_receiver) = mpsc::channel();
 
-		let server_thread = ServerThread {
+		let mut server_thread = ServerThread {
 			requests: requests_sender,
 		};
 
 		let cloned_stream = stream.try_clone().unwrap();
+		let thread_handshake = handshake.clone();
 		let thread = thread::spawn(move || {
-			server_thread.run(cloned_stream);
+			server_thread.run(cloned_stream, thread_handshake);
 		});
 
 		let mut server = Server {
 			requests: requests_receiver,
-			stream: ServerStream::Connected(stream),
+			connections: None,
+			stream: ServerStream::Waiting,
 			_thread: thread,
 			should_catch_runtimes: true,
+			runtime_filters: RuntimeFilters::default(),
 			state: None,
 			in_eval: false,
 			eval_error: None,
 			conditional_breakpoints: HashMap::new(),
+			breakpoints: HashMap::new(),
+			run_to: None,
+			coverage: None,
+			profiler: None,
+			census: None,
+			last_census: None,
+			reference_search: None,
+			patches: vec![],
+			hot_patches: HashMap::new(),
+			stale_connection: false,
+			authenticated: token.is_none(),
+			token,
+			stack_on_breakpoint: true,
+			line_tables: RefCell::new(HashMap::new()),
+			disassemblies: HashMap::new(),
+			socket_path: None,
+			keep_alive: None,
+			connect_timeout: None,
+			keep_alive_countdown: 0,
 			app: Self::setup_app(),
 		};
 
+		if let Some(timeout) = get_heartbeat_timeout() {
+			server.start_keep_alive(timeout, timeout);
+		}
+
+		server.accept_connection(stream, handshake);
 		server.process_until_configured();
 		return Ok(server);
 	}
 
-	pub fn listen(addr: &SocketAddr) -> std::io::Result<Server> {
+	pub fn listen(addr: &SocketAddr, token: Option<String>) -> std::io::Result<Server> {
+		Self::listen_on(Listener::Tcp(TcpListener::bind(addr)?), token)
+	}
+
+	/// Listens on a TCP port, giving up on clients that take longer than `connect_timeout` to connect or go quiet for
+	/// `idle_timeout` and then don't answer a ping within `connect_timeout`. See [set_keep_alive](Server::set_keep_alive).
+	pub fn listen_with_timeout(
+		addr: &SocketAddr,
+		connect_timeout: Duration,
+		idle_timeout: Duration,
+	) -> std::io::Result<Server> {
+		let mut server = Self::listen(addr, None)?;
+		server.set_keep_alive(connect_timeout, idle_timeout);
+		Ok(server)
+	}
+
+	/// Once the client has sent nothing for `idle_timeout`, sends it a Response::Ping and disconnects it unless it
+	/// answers within `connect_timeout`. Waiting for a client to connect also gives up after `connect_timeout`.
+	///
+	/// Only clients that know to answer Response::Ping can be used with this.
+	pub fn set_keep_alive(&mut self, connect_timeout: Duration, idle_timeout: Duration) {
+		self.connect_timeout = Some(connect_timeout);
+		self.start_keep_alive(idle_timeout, connect_timeout);
+	}
+
+	fn start_keep_alive(&mut self, idle_timeout: Duration, answer_timeout: Duration) {
+		self.keep_alive = Some(KeepAlive {
+			idle_timeout,
+			answer_timeout,
+			last_heard: Instant::now(),
+			ping_sent: None,
+		});
+	}
+
+	/// Listens on a TCP port, only taking requests from clients that send a Request::Authenticate with `token` first
+	pub fn listen_with_auth(addr: &SocketAddr, token: &str) -> std::io::Result<Server> {
+		Self::listen(addr, Some(token.to_owned()))
+	}
+
+	/// Listens on a unix domain socket instead of a TCP port, so only the current user can connect.
+	/// The socket file is removed when the server is dropped.
+	#[cfg(unix)]
+	pub fn listen_unix(path: &std::path::Path, token: Option<String>) -> std::io::Result<Server> {
+		let mut server = Self::listen_on(Listener::bind_unix(path)?, token)?;
+		server.socket_path = Some(path.to_owned());
+		Ok(server)
+	}
+
+	/// Listens on a named pipe instead of a TCP port, so only local clients can connect
+	#[cfg(windows)]
+	pub fn listen_pipe(name: &str, token: Option<String>) -> std::io::Result<Server> {
+		Self::listen_on(Listener::bind_pipe(name)?, token)
+	}
+
+	fn listen_on(listener: Listener, token: Option<String>) -> std::io::Result<Server> {
 		let (connection_sender, connection_receiver) = mpsc::channel();
 		let (requests_sender, requests_receiver) = mpsc::channel();
 
 		let thread = ServerThread {
 			requests: requests_sender,
 		}
-		.spawn_listener(TcpListener::bind(addr)?, connection_sender);
+		.spawn_listener(listener, connection_sender);
 
-		Ok(Server {
+		let mut server = Server {
 			requests: requests_receiver,
-			stream: ServerStream::Waiting(connection_receiver),
+			connections: Some(connection_receiver),
+			stream: ServerStream::Waiting,
 			_thread: thread,
 			should_catch_runtimes: true,
+			runtime_filters: RuntimeFilters::default(),
 			state: None,
 			in_eval: false,
 			eval_error: None,
 			conditional_breakpoints: HashMap::new(),
+			breakpoints: HashMap::new(),
+			run_to: None,
+			coverage: None,
+			profiler: None,
+			census: None,
+			last_census: None,
+			reference_search: None,
+			patches: vec![],
+			hot_patches: HashMap::new(),
+			stale_connection: false,
+			authenticated: token.is_none(),
+			token,
+			stack_on_breakpoint: true,
+			line_tables: RefCell::new(HashMap::new()),
+			disassemblies: HashMap::new(),
+			socket_path: None,
+			keep_alive: None,
+			connect_timeout: None,
+			keep_alive_countdown: 0,
 			app: Self::setup_app(),
-		})
+		};
+
+		if let Some(timeout) = get_heartbeat_timeout() {
+			server.start_keep_alive(timeout, timeout);
+		}
+
+		Ok(server)
 	}
 
 	pub fn is_in_eval(&self) -> bool {
@@ -218,79 +812,37 @@ impl Server {
 		self.eval_error = Some(err);
 	}
 
-	fn get_line_number(&self, proc: ProcRef, offset: u32) -> Option<u32> {
-		match auxtools::Proc::find_override(proc.path, proc.override_id) {
-			Some(proc) => {
-				let mut current_line_number = None;
-				let mut reached_offset = false;
-
-				let bytecode = unsafe { proc.bytecode() };
+	// Calls `f` with the proc's line table, building it first if there isn't an up to date one
+	fn with_line_table<R, F: FnOnce(&LineTable) -> R>(&self, proc: ProcRef, f: F) -> Option<R> {
+		let proc = auxtools::Proc::find_override(proc.path, proc.override_id)?;
+		let mut line_tables = self.line_tables.borrow_mut();
 
-				let mut env = crate::disassemble_env::DisassembleEnv;
-				let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, &mut env);
-
-				for node in nodes {
-					if let dmasm::Node::Instruction(ins, debug) = node {
-						if debug.offset > offset {
-							reached_offset = true;
-							break;
-						}
+		let is_current = line_tables
+			.get(&proc.id)
+			.map_or(false, |table| table.is_current(&proc));
 
-						if let dmasm::Instruction::DbgLine(line) = ins {
-							current_line_number = Some(line);
-						}
+		if !is_current {
+			line_tables.insert(proc.id, LineTable::build(&proc));
+		}
 
-						if debug.offset == offset {
-							reached_offset = true;
-							break;
-						}
-					}
-				}
+		Some(f(&line_tables[&proc.id]))
+	}
 
-				if reached_offset {
-					return current_line_number;
-				} else {
-					return None;
-				}
-			}
+	fn get_line_number(&self, proc: ProcRef, offset: u32) -> Option<u32> {
+		self.with_line_table(proc, |table| table.line_at(offset))?
+	}
 
-			None => None,
-		}
+	// The file and line the instruction at `offset` came from
+	fn get_source_location(&self, proc: ProcRef, offset: u32) -> (Option<String>, Option<u32>) {
+		self.with_line_table(proc, |table| {
+			let file = table.file_at(offset).map(str::to_owned);
+			(file, table.line_at(offset))
+		})
+		.unwrap_or((None, None))
 	}
 
 	fn get_offset(&self, proc: ProcRef, line: u32) -> Option<u32> {
-		match auxtools::Proc::find_override(proc.path, proc.override_id) {
-			Some(proc) => {
-				let mut offset = None;
-				let mut at_offset = false;
-
-				let bytecode = unsafe { proc.bytecode() };
-
-				let mut env = crate::disassemble_env::DisassembleEnv;
-				let (nodes, _error) = dmasm::disassembler::disassemble(bytecode, &mut env);
-
-				for node in nodes {
-					if let dmasm::Node::Instruction(ins, debug) = node {
-						if at_offset {
-							offset = Some(debug.offset);
-							break;
-						}
-
-						if let dmasm::Instruction::DbgLine(current_line) = ins {
-							if current_line == line {
-								at_offset = true;
-							}
-						}
-					}
-				}
-
-				return offset;
-			}
-
-			None => {
-				return None;
-			}
-		}
+		self.with_line_table(proc, |table| table.offset_of(line))?
 	}
 
 	fn is_object(value: &Value) -> bool {
@@ -306,26 +858,40 @@ impl Server {
 		if List::is_list(value) {
 			match List::from_value(value) {
 				Ok(list) => format!("/list {{len = {}}}", list.len()),
-				Err(Runtime { message }) => format!("/list (failed to get len: {:?})", message),
+				Err(e) => format!("/list (failed to get len: {})", e),
 			}
+		} else if let Some(special) = value.format_special() {
+			special
 		} else {
 			match value.to_string() {
 				Ok(v) if v.is_empty() => value.raw.to_string(),
 				Ok(value) => value,
-				Err(Runtime { message }) => {
-					format!("{} -- stringify error: {:?}", value.raw, message)
-				}
+				Err(e) => format!("{} -- stringify error: {}", value.raw, e),
 			}
 		}
 	}
 
+	// What kind of value this is, for clients to show alongside it
+	fn value_type(value: &Value) -> Option<String> {
+		match value.raw.tag {
+			ValueTag::Null => Some("null".to_owned()),
+			ValueTag::Number => Some("number".to_owned()),
+			ValueTag::String => Some("string".to_owned()),
+			_ if List::is_list(value) => Some("/list".to_owned()),
+			_ if Self::is_object(value) => value.get_type().ok(),
+			_ => None,
+		}
+	}
+
 	fn value_to_variable(&self, name: String, value: &Value) -> Variable {
 		let stringified = Self::stringify(value);
+		let value_type = Self::value_type(value);
 		let variables = self.value_to_variables_ref(value);
 
 		Variable {
 			name,
 			value: stringified,
+			value_type,
 			variables,
 		}
 	}
@@ -345,43 +911,45 @@ impl Server {
 	}
 
 	fn list_to_variables(&mut self, value: &Value) -> Result<Vec<Variable>, Runtime> {
-		let state = self.state.as_ref().unwrap();
+		let state = self
+			.state
+			.as_ref()
+			.ok_or_else(|| runtime!("execution isn't paused"))?;
 		let list = List::from_value(value)?;
-		let len = list.len();
+
+		// Where lists can't tell which of their elements are associated, at least show the elements
+		let entries = match list.entries() {
+			Ok(entries) => entries,
+			Err(_) => list.keys()?.into_iter().map(ListEntry::Indexed).collect(),
+		};
 
 		let mut variables = vec![];
 
-		for i in 1..=len {
-			let key = list.get(i)?;
+		for (i, entry) in entries.into_iter().enumerate() {
+			let name = format!("[{}]", i + 1);
 
-			if let Ok(value) = list.get(&key) {
-				if value.raw.tag != raw_types::values::ValueTag::Null {
-					// assoc entry
-					variables.push(Variable {
-						name: format!("[{}]", i),
-						value: format!("{} = {}", Self::stringify(&key), Self::stringify(&value)),
-						variables: Some(state.get_ref(Variables::ListPair { key, value })),
-					});
-					continue;
-				}
-			}
+			match entry {
+				ListEntry::Assoc { key, value } => variables.push(Variable {
+					name,
+					value: format!("{} = {}", Self::stringify(&key), Self::stringify(&value)),
+					value_type: None,
+					variables: Some(state.get_ref(Variables::ListPair { key, value })),
+				}),
 
-			// non-assoc entry
-			variables.push(self.value_to_variable(format!("[{}]", i), &key));
+				ListEntry::Indexed(value) => variables.push(self.value_to_variable(name, &value)),
+			}
 		}
 
 		return Ok(variables);
 	}
 
 	fn object_to_variables(&mut self, value: &Value) -> Result<Vec<Variable>, Runtime> {
-		// Grab `value.vars`. We have a little hack for globals which use a special type.
-		let vars = List::from_value(&unsafe {
-			if value.raw.tag == ValueTag::World && value.raw.data.id == 1 {
-				Value::new(ValueTag::GlobalVars, ValueData { id: 0 })
-			} else {
-				value.get(byond_string!("vars"))?
-			}
-		})?;
+		// Grab `value.vars`. Globals don't have one, so they get their own special list.
+		let vars = if *value == Value::globals() {
+			Value::global_vars()
+		} else {
+			List::from_value(&value.get(byond_string!("vars"))?)?
+		};
 
 		let mut variables = vec![];
 		let mut top_variables = vec![]; // These fields get displayed on top of all others
@@ -462,28 +1030,44 @@ impl Server {
 		None
 	}
 
-	fn get_args(&mut self, frame_index: u32) -> Vec<Variable> {
-		match self.get_stack_frame(frame_index) {
-			Some(frame) => {
-				let mut vars = vec![
-					self.value_to_variable("src".to_owned(), &frame.src),
-					self.value_to_variable("usr".to_owned(), &frame.usr),
-				];
+	fn get_stack_frame_mut(&mut self, frame_index: u32) -> Option<&mut debug::StackFrame> {
+		let mut frame_index = frame_index as usize;
+		let stacks = match &mut self.state {
+			Some(state) => &mut state.stacks,
+			None => return None,
+		};
 
-				let mut unnamed_count = 0;
-				for (name, value) in &frame.args {
-					let name = match name {
-						Some(name) => String::from(name),
-						None => {
-							unnamed_count += 1;
-							format!("undefined argument #{}", unnamed_count)
-						}
-					};
-					vars.push(self.value_to_variable(name, value));
-				}
+		if frame_index < stacks.active.len() {
+			return Some(&mut stacks.active[frame_index]);
+		}
 
-				vars
-			}
+		frame_index -= stacks.active.len();
+
+		for frame in &mut stacks.suspended {
+			if frame_index < frame.len() {
+				return Some(&mut frame[frame_index]);
+			}
+
+			frame_index -= frame.len();
+		}
+
+		None
+	}
+
+	fn get_args(&mut self, frame_index: u32) -> Vec<Variable> {
+		match self.get_stack_frame(frame_index) {
+			Some(frame) => {
+				let mut vars = vec![
+					self.value_to_variable("src".to_owned(), &frame.src),
+					self.value_to_variable("usr".to_owned(), &frame.usr),
+				];
+
+				for (index, (name, value)) in frame.args.iter().enumerate() {
+					vars.push(self.value_to_variable(Self::arg_name(index, name), value));
+				}
+
+				vars
+			}
 
 			None => {
 				self.notify(format!(
@@ -495,6 +1079,14 @@ impl Server {
 		}
 	}
 
+	// Arguments past the ones the proc declares have no name, so they go by where DM code would find them
+	fn arg_name(index: usize, name: &Option<StringRef>) -> String {
+		match name {
+			Some(name) => String::from(name),
+			None => format!("args[{}]", index + 1),
+		}
+	}
+
 	fn get_locals(&mut self, frame_index: u32) -> Vec<Variable> {
 		match self.get_stack_frame(frame_index) {
 			Some(frame) => {
@@ -517,68 +1109,752 @@ impl Server {
 		}
 	}
 
-	fn handle_breakpoint_set(&mut self, instruction: InstructionRef, condition: Option<String>) {
+	// Parses the DM literals a user can type in: null, numbers, "strings" and /type/paths
+	fn parse_literal(literal: &str) -> DMResult {
+		let literal = literal.trim();
+
+		if literal == "null" {
+			return Ok(Value::null());
+		}
+
+		if let Ok(number) = literal.parse::<f32>() {
+			return Ok(Value::from(number));
+		}
+
+		if literal.len() >= 2 && literal.starts_with('"') && literal.ends_with('"') {
+			let mut string = String::new();
+			let mut chars = literal[1..literal.len() - 1].chars();
+
+			while let Some(c) = chars.next() {
+				if c != '\\' {
+					string.push(c);
+					continue;
+				}
+
+				match chars.next() {
+					Some('n') => string.push('\n'),
+					Some('t') => string.push('\t'),
+					Some(c) => string.push(c),
+					None => string.push('\\'),
+				}
+			}
+
+			return Value::from_string(string);
+		}
+
+		if literal.starts_with('/') {
+			use dmasm::assembler::AssembleEnv;
+
+			let (tag, data) = crate::assemble_env::AssembleEnv
+				.get_type(literal)
+				.ok_or_else(|| runtime!("unknown type path {}", literal))?;
+
+			return Ok(unsafe { Value::new(std::mem::transmute(tag), std::mem::transmute(data)) });
+		}
+
+		Err(runtime!("couldn't parse {:?} as a DM literal", literal))
+	}
+
+	// Returns the value that ended up stored, which BYOND may have coerced
+	fn set_variable(
+		&mut self,
+		frame_id: u32,
+		scope: VariableScope,
+		name: &str,
+		value: &str,
+	) -> DMResult {
+		let value = Self::parse_literal(value)?;
+		self.store_variable(frame_id, scope, name, value)
+	}
+
+	fn store_variable(
+		&mut self,
+		frame_id: u32,
+		scope: VariableScope,
+		name: &str,
+		value: Value,
+	) -> DMResult {
+		match scope {
+			VariableScope::Arguments => {
+				let frame = self
+					.get_stack_frame_mut(frame_id)
+					.ok_or_else(|| runtime!("invalid frame id: {}", frame_id))?;
+
+				let index = (0..frame.args.len())
+					.find(|&index| Self::arg_name(index, &frame.args[index].0) == name)
+					.ok_or_else(|| runtime!("no argument named {}", name))?;
+
+				frame.set_arg(index, value)?;
+				Ok(frame.args[index].1.clone())
+			}
+
+			VariableScope::Locals => {
+				let frame = self
+					.get_stack_frame_mut(frame_id)
+					.ok_or_else(|| runtime!("invalid frame id: {}", frame_id))?;
+
+				if name == "." {
+					frame.set_dot(value);
+					return Ok(frame.dot.clone());
+				}
+
+				let index = frame
+					.locals
+					.iter()
+					.position(|(local_name, _)| String::from(local_name) == name)
+					.ok_or_else(|| runtime!("no local named {}", name))?;
+
+				frame.set_local(index, value)?;
+				Ok(frame.locals[index].1.clone())
+			}
+
+			VariableScope::Object(vars) => {
+				let state = self
+					.state
+					.as_ref()
+					.ok_or_else(|| runtime!("execution isn't paused"))?;
+
+				let object = match state.get_variables(vars.clone()) {
+					Some(Variables::ObjectVars(object)) => object,
+					Some(_) => return Err(runtime!("variables reference isn't an object")),
+					None if state.is_expired(&vars) => {
+						return Err(runtime!("variables reference is from an earlier pause"))
+					}
+					None => return Err(runtime!("unknown variables reference")),
+				};
+
+				let name = StringRef::new(name)?;
+				object.set(name.clone(), value)?;
+				object.get(name)
+			}
+
+			VariableScope::Frame => {
+				let frame = self
+					.get_stack_frame(frame_id)
+					.ok_or_else(|| runtime!("invalid frame id: {}", frame_id))?;
+
+				let is_local = name == "."
+					|| frame
+						.locals
+						.iter()
+						.any(|(local_name, _)| String::from(local_name) == name);
+				let is_arg = (0..frame.args.len())
+					.any(|index| Self::arg_name(index, &frame.args[index].0) == name);
+
+				if is_local {
+					return self.store_variable(frame_id, VariableScope::Locals, name, value);
+				}
+
+				if is_arg {
+					return self.store_variable(frame_id, VariableScope::Arguments, name, value);
+				}
+
+				// Fails for a null src and for variables it doesn't have, so nothing new gets created
+				let src = frame.src.clone();
+				let name = StringRef::new(name)?;
+				src.get(name.clone())?;
+				src.set(name.clone(), value)?;
+				src.get(name)
+			}
+		}
+	}
+
+	// Moves the proc paused in frame `frame_id` to the DbgLine of `line`, returning the new offset
+	fn goto_line(&mut self, frame_id: u32, line: u32) -> Result<u32, String> {
+		match &self.state {
+			Some(state) if state.runtime => {
+				return Err("cannot jump out of a runtime".to_owned());
+			}
+			Some(_) => {}
+			None => return Err("execution isn't paused".to_owned()),
+		}
+
+		// Every other frame is either waiting on a proc it called or sleeping
+		if frame_id != 0 {
+			return Err("cannot jump in a frame that isn't executing".to_owned());
+		}
+
+		let (ctx, proc) = match self.state.as_ref().and_then(|state| state.stacks.active.first()) {
+			Some(frame) => (frame.context, frame.proc.clone()),
+			None => return Err("no proc is executing".to_owned()),
+		};
+
+		let proc_ref = ProcRef {
+			path: proc.path.clone(),
+			override_id: proc.override_id(),
+		};
+
+		let offset = self
+			.with_line_table(proc_ref, |table| table.line_start(line))
+			.flatten()
+			.ok_or_else(|| "line has no code".to_owned())?;
+
+		if !is_instruction_start(&proc, offset) {
+			return Err(format!("offset {} isn't the start of an instruction", offset));
+		}
+
+		#[cfg(feature = "tracing")]
+		tracing::info!(proc = %proc.path, line, offset, "jumping to line");
+
+		unsafe {
+			(*ctx).bytecode_offset = offset as u16;
+			(*ctx).line = line;
+		}
+
+		if let Some(frame) = self.get_stack_frame_mut(frame_id) {
+			frame.offset = offset as u16;
+			frame.line_number = Some(line);
+		}
+
+		Ok(offset)
+	}
+
+	// Carries on with the census for a moment. Gives the `top` entries once it's done, along with whether objects
+	// could have been missed, or how many objects have been counted so far if it isn't. Either way, the number of
+	// objects counted comes along.
+	fn census_step(&mut self, top: usize) -> Result<(Vec<CensusEntry>, u32, bool), u32> {
+		let census = self.census.get_or_insert_with(Census::new);
+		if !census.step(CENSUS_BUDGET) {
+			return Err(census.counted());
+		}
+
+		let counted = census.counted();
+		let approximate = census.is_approximate();
+		let counts = census.counts();
+		self.census = None;
+
+		let entries = census::compare(&counts, self.last_census.as_ref(), top);
+		self.last_census = Some(counts);
+		Ok((entries, counted, approximate))
+	}
+
+	// The procs with paths matching `filter`, from `offset` on, along with how many matched altogether
+	fn list_procs(filter: Option<&str>, offset: usize, limit: usize) -> (Vec<ProcRef>, u32) {
+		let matching: Vec<Proc> = auxtools::proc::all()
+			.filter(|proc| filter.map_or(true, |filter| pattern_matches(filter, &proc.path)))
+			.collect();
+
+		let procs = matching
+			.iter()
+			.skip(offset)
+			.take(limit)
+			.map(|proc| ProcRef {
+				path: proc.path.clone(),
+				override_id: proc.override_id(),
+			})
+			.collect();
+
+		(procs, matching.len() as u32)
+	}
+
+	// Starts looking for what holds the object or list `target` is the variables of. The search is carried on
+	// by the pause loop.
+	fn start_reference_search(&mut self, target: VariablesRef, limit: u32) -> Result<(), String> {
+		let state = self
+			.state
+			.as_ref()
+			.ok_or_else(|| "execution isn't paused".to_owned())?;
+
+		if self.reference_search.is_some() {
+			return Err("already finding references".to_owned());
+		}
+
+		let value = match state.get_variables(target.clone()) {
+			Some(Variables::ObjectVars(value)) | Some(Variables::ListContents(value)) => value,
+			Some(_) => return Err("variables reference isn't an object or list".to_owned()),
+			None if state.is_expired(&target) => {
+				return Err("variables reference is from an earlier pause".to_owned())
+			}
+			None => return Err("unknown variables reference".to_owned()),
+		};
+
+		self.reference_search =
+			Some((ReferenceSearch::new(&value, limit as usize), Instant::now()));
+		Ok(())
+	}
+
+	// Carries on with the reference search for a moment, answering the FindReferences request once it's done
+	fn reference_search_step(&mut self) {
+		let (search, last_progress) = match &mut self.reference_search {
+			Some(search) => search,
+			None => return,
+		};
+
+		if search.step(REFERENCES_BUDGET) {
+			self.finish_reference_search(true);
+			return;
+		}
+
+		if last_progress.elapsed() >= REFERENCES_PROGRESS {
+			*last_progress = Instant::now();
+			let searched = search.searched();
+			self.notify(format!(
+				"Finding references: looked through {} objects and lists so far",
+				searched
+			));
+		}
+	}
+
+	// Answers the FindReferences request with whatever the search found
+	fn finish_reference_search(&mut self, complete: bool) {
+		let search = match self.reference_search.take() {
+			Some((search, _)) => search,
+			None => return,
+		};
+
+		let searched = search.searched();
+		let holders = search
+			.into_holders()
+			.into_iter()
+			.map(|(name, holder)| self.value_to_variable(name, &holder))
+			.collect();
+
+		self.send_or_disconnect(Response::FindReferences {
+			holders,
+			searched,
+			complete,
+			error: None,
+		});
+	}
+
+	// Answers the FindReferences request with nothing, because its results couldn't be used anymore
+	fn abandon_reference_search(&mut self, error: &str) {
+		if let Some((search, _)) = self.reference_search.take() {
+			self.send_or_disconnect(Response::FindReferences {
+				holders: vec![],
+				searched: search.searched(),
+				complete: false,
+				error: Some(error.to_owned()),
+			});
+		}
+	}
+
+	fn start_profiler(&mut self, mode: ProfilerMode) -> Result<(), String> {
+		if self.profiler.is_some() {
+			return Err("Profiler is already running".to_owned());
+		}
+
+		self.profiler = Some(match mode {
+			ProfilerMode::Sampling { interval_ms: 0 } => {
+				return Err("interval must be at least 1ms".to_owned());
+			}
+			ProfilerMode::Sampling { interval_ms } => {
+				profiler::Profiler::Sampling(profiler::start(Duration::from_millis(interval_ms)))
+			}
+			ProfilerMode::Calls => profiler::Profiler::Calls(profiler::start_calls()),
+		});
+
+		Ok(())
+	}
+
+	// Replaces the proc's bytecode with assembly, keeping what it had before the first hot patch so it can be
+	// reverted. Breakpoints are carried over by line, see replace_bytecode.
+	fn hot_patch(&mut self, proc_ref: ProcRef, source: &str) -> Result<(), String> {
+		let proc = auxtools::Proc::find_override(proc_ref.path.clone(), proc_ref.override_id)
+			.ok_or_else(|| "proc not found".to_owned())?;
+
+		let bytecode = assembly::assemble(source).map_err(|e| e.to_string())?;
+
+		if !self.hot_patches.contains_key(&proc_ref) {
+			let original = without_hooks(&proc, || unsafe { proc.bytecode() }.to_vec());
+			self.hot_patches.insert(proc_ref.clone(), original);
+		}
+
+		#[cfg(feature = "tracing")]
+		tracing::info!(proc = %proc.path, "hot patching proc");
+
+		self.replace_bytecode(&proc, &proc_ref, bytecode, "hot patch");
+		Ok(())
+	}
+
+	// Gives a hot patched proc back the bytecode it had before it was first patched
+	fn hot_patch_revert(&mut self, proc_ref: ProcRef) -> Result<(), String> {
+		let proc = auxtools::Proc::find_override(proc_ref.path.clone(), proc_ref.override_id)
+			.ok_or_else(|| "proc not found".to_owned())?;
+
+		let original = self
+			.hot_patches
+			.remove(&proc_ref)
+			.ok_or_else(|| "proc isn't hot patched".to_owned())?;
+
+		self.replace_bytecode(&proc, &proc_ref, original, "hot patch revert");
+		Ok(())
+	}
+
+	// Gives the proc new bytecode. Breakpoints are tied to offsets, which mean nothing in the new bytecode, so
+	// they're carried over by line and the client is told where they ended up. `what` names the change in
+	// notifications about breakpoints that couldn't be carried over.
+	fn replace_bytecode(
+		&mut self,
+		proc: &Proc,
+		proc_ref: &ProcRef,
+		bytecode: Vec<u32>,
+		what: &str,
+	) {
+		let breakpoints: Vec<_> = self
+			.breakpoints
+			.iter()
+			.filter(|(instruction, _)| instruction.proc == *proc_ref)
+			.map(|(instruction, condition)| (instruction.clone(), condition.clone()))
+			.collect();
+
+		let mut lines = vec![];
+		for (instruction, condition) in breakpoints {
+			let line = self.get_line_number(proc_ref.clone(), instruction.offset);
+			self.unset_breakpoint(instruction);
+			lines.push((line, condition));
+		}
+
+		if self
+			.run_to
+			.as_ref()
+			.map_or(false, |instruction| instruction.proc == *proc_ref)
+		{
+			self.clear_run_to();
+		}
+
+		proc.set_bytecode(bytecode);
+		self.line_tables.borrow_mut().remove(&proc.id);
+
+		for (line, condition) in lines {
+			let offset = line.and_then(|line| self.get_offset(proc_ref.clone(), line));
+
+			let instruction = match offset {
+				Some(offset) => InstructionRef {
+					proc: proc_ref.clone(),
+					offset,
+				},
+				None => {
+					self.notify(format!(
+						"{}: dropped a breakpoint in {} whose line has no code anymore",
+						what, proc_ref.path
+					));
+					continue;
+				}
+			};
+
+			match self.set_breakpoint(instruction.clone(), condition.clone()) {
+				BreakpointSetResult::Success { line, .. } => {
+					self.send_or_disconnect(Response::BreakpointRestored {
+						instruction,
+						line,
+						condition,
+					});
+				}
+
+				BreakpointSetResult::Failed => {
+					self.notify(format!(
+						"{}: couldn't put back a breakpoint at offset {} of {}",
+						what, instruction.offset, proc_ref.path
+					));
+				}
+			}
+		}
+	}
+
+	fn handle_set_variable(&mut self, frame_id: u32, scope: VariableScope, name: &str, value: &str) {
+		if self.state.is_none() {
+			self.notify("received SetVariable request when not paused");
+			self.send_or_disconnect(Response::SetVariableResult {
+				success: false,
+				new_value: "execution isn't paused".to_owned(),
+			});
+			return;
+		}
+
+		#[cfg(feature = "tracing")]
+		tracing::info!(frame_id, ?scope, name, value, "setting variable");
+
+		let response = match self.set_variable(frame_id, scope, name, value) {
+			Ok(new_value) => Response::SetVariableResult {
+				success: true,
+				new_value: Self::stringify(&new_value),
+			},
+
+			Err(e) => Response::SetVariableResult {
+				success: false,
+				new_value: e.to_string(),
+			},
+		};
+
+		self.send_or_disconnect(response);
+	}
+
+	fn set_breakpoint(
+		&mut self,
+		instruction: InstructionRef,
+		condition: Option<String>,
+	) -> BreakpointSetResult {
 		let line = self.get_line_number(instruction.proc.clone(), instruction.offset);
 
 		let proc = match auxtools::Proc::find_override(
-			instruction.proc.path,
+			instruction.proc.path.clone(),
 			instruction.proc.override_id,
 		) {
 			Some(proc) => proc,
-			None => {
-				self.send_or_disconnect(Response::BreakpointSet {
-					result: BreakpointSetResult::Failed,
-				});
-				return;
-			}
+			None => return BreakpointSetResult::Failed,
 		};
 
-		match hook_instruction(&proc, instruction.offset) {
+		let offset = instruction.offset;
+
+		match hook_instruction(&proc, offset) {
 			Ok(()) => {
-				if let Some(condition) = condition {
+				// The hook is the client's now, so finishing the RunTo mustn't remove it
+				if self.run_to.as_ref() == Some(&instruction) {
+					self.run_to = None;
+				}
+
+				if let Some(condition) = &condition {
 					self.conditional_breakpoints
-						.insert((proc.id, instruction.offset as u16), condition);
+						.insert((proc.id, offset as u16), condition.clone());
 				}
 
-				self.send_or_disconnect(Response::BreakpointSet {
-					result: BreakpointSetResult::Success { line },
+				self.breakpoints.insert(instruction, condition);
+				BreakpointSetResult::Success { line, offset }
+			}
+
+			Err(_) => BreakpointSetResult::Failed,
+		}
+	}
+
+	// Puts a temporary breakpoint on the instruction, unless there's already one there
+	fn set_run_to(&mut self, proc_ref: ProcRef, offset: u32) {
+		self.clear_run_to();
+
+		let proc = match auxtools::Proc::find_override(proc_ref.path.clone(), proc_ref.override_id)
+		{
+			Some(proc) => proc,
+			None => {
+				self.notify(format!("run to: couldn't find proc {}", proc_ref.path));
+				return;
+			}
+		};
+
+		if get_all_hooks().contains(&(proc.id, offset)) {
+			return;
+		}
+
+		match hook_instruction(&proc, offset) {
+			Ok(()) => {
+				self.run_to = Some(InstructionRef {
+					proc: proc_ref,
+					offset,
 				});
 			}
 
 			Err(_) => {
-				self.send_or_disconnect(Response::BreakpointSet {
-					result: BreakpointSetResult::Failed,
-				});
+				self.notify(format!(
+					"run to: no instruction at offset {} of {}",
+					offset, proc_ref.path
+				));
 			}
 		}
 	}
 
-	fn handle_breakpoint_unset(&mut self, instruction: InstructionRef) {
-		let proc = match auxtools::Proc::find_override(
-			instruction.proc.path,
+	fn clear_run_to(&mut self) {
+		let instruction = match self.run_to.take() {
+			Some(instruction) => instruction,
+			None => return,
+		};
+
+		if let Some(proc) =
+			auxtools::Proc::find_override(instruction.proc.path, instruction.proc.override_id)
+		{
+			let _ = unhook_instruction(&proc, instruction.offset);
+		}
+	}
+
+	// returns None if the proc doesn't exist
+	fn unset_breakpoint(&mut self, instruction: InstructionRef) -> Option<bool> {
+		let proc = auxtools::Proc::find_override(
+			instruction.proc.path.clone(),
 			instruction.proc.override_id,
-		) {
-			Some(proc) => proc,
+		)?;
+
+		self.conditional_breakpoints
+			.remove(&(proc.id, instruction.offset as u16));
+		self.breakpoints.remove(&instruction);
+
+		Some(unhook_instruction(&proc, instruction.offset).is_ok())
+	}
+
+	fn handle_breakpoint_set(&mut self, instruction: InstructionRef, condition: Option<String>) {
+		let result = self.set_breakpoint(instruction, condition);
+		self.send_or_disconnect(Response::BreakpointSet { result });
+	}
+
+	fn handle_function_breakpoint_set(&mut self, proc: ProcRef) {
+		let entry = self
+			.with_line_table(proc.clone(), LineTable::entry)
+			.flatten();
+
+		let result = match entry {
+			Some(offset) => self.set_breakpoint(InstructionRef { proc, offset }, None),
+			None => BreakpointSetResult::Failed,
+		};
+
+		self.send_or_disconnect(Response::FunctionBreakpointSet { result });
+	}
+
+	fn handle_breakpoint_unset(&mut self, instruction: InstructionRef) {
+		match self.unset_breakpoint(instruction) {
+			Some(success) => {
+				self.send_or_disconnect(Response::BreakpointUnset { success });
+			}
+
 			None => {
 				self.send_or_disconnect(Response::BreakpointSet {
 					result: BreakpointSetResult::Failed,
 				});
-				return;
 			}
-		};
+		}
+	}
 
-		self.conditional_breakpoints
-			.remove(&(proc.id, instruction.offset as u16));
+	// A table of every hooked instruction, and of any breakpoint the client set that isn't installed anymore
+	fn list_breakpoints(&self) -> String {
+		let mut rows = vec![];
+		let mut installed = vec![];
 
-		match unhook_instruction(&proc, instruction.offset) {
-			Ok(()) => {
-				self.send_or_disconnect(Response::BreakpointUnset { success: true });
+		for (id, offset) in get_all_hooks() {
+			let proc = match Proc::from_id(id) {
+				Some(proc) => proc,
+				None => {
+					rows.push((
+						format!("<missing proc {}>", id.0),
+						offset,
+						None,
+						"proc not found".to_owned(),
+					));
+					continue;
+				}
+			};
+
+			let proc_ref = ProcRef {
+				path: proc.path.clone(),
+				override_id: proc.override_id(),
+			};
+
+			let line = self.get_line_number(proc_ref.clone(), offset);
+			let condition = self
+				.conditional_breakpoints
+				.get(&(id, offset as u16))
+				.map(|condition| format!("if {}", condition))
+				.unwrap_or_default();
+
+			rows.push((
+				format!("{} #{}", proc_ref.path, proc_ref.override_id),
+				offset,
+				line,
+				condition,
+			));
+			installed.push(InstructionRef {
+				proc: proc_ref,
+				offset,
+			});
+		}
+
+		for instruction in self.breakpoints.keys() {
+			if !installed.contains(instruction) {
+				rows.push((
+					format!("{} #{}", instruction.proc.path, instruction.proc.override_id),
+					instruction.offset,
+					None,
+					"not installed, proc not found".to_owned(),
+				));
 			}
+		}
 
-			Err(_) => {
-				self.send_or_disconnect(Response::BreakpointUnset { success: false });
+		if rows.is_empty() {
+			return "No breakpoints".to_owned();
+		}
+
+		let width = rows.iter().map(|(proc, ..)| proc.len()).max().unwrap_or(0);
+
+		rows.iter()
+			.map(|(proc, offset, line, note)| {
+				let line = line.map_or_else(|| "?".to_owned(), |line| line.to_string());
+				format!(
+					"{:<width$}  offset {:<5}  line {:<5}  {}",
+					proc,
+					offset,
+					line,
+					note,
+					width = width
+				)
+				.trim_end()
+				.to_owned()
+			})
+			.collect::<Vec<_>>()
+			.join("\n")
+	}
+
+	// Removes every hooked instruction and lets the client know about the breakpoints it set
+	fn clear_breakpoints(&mut self) -> String {
+		let mut removed = 0;
+
+		for (id, offset) in get_all_hooks() {
+			let proc = match Proc::from_id(id) {
+				Some(proc) => proc,
+				None => continue,
+			};
+
+			let instruction = InstructionRef {
+				proc: ProcRef {
+					path: proc.path.clone(),
+					override_id: proc.override_id(),
+				},
+				offset,
+			};
+
+			if unhook_instruction(&proc, offset).is_ok() {
+				removed += 1;
+			}
+
+			self.conditional_breakpoints.remove(&(id, offset as u16));
+			if self.breakpoints.remove(&instruction).is_some() {
+				self.send_or_disconnect(Response::BreakpointRemoved { instruction });
 			}
 		}
+
+		// Whatever is left couldn't be found, but the client shouldn't keep showing it either
+		let forgotten: Vec<_> = self
+			.breakpoints
+			.drain()
+			.map(|(instruction, _)| instruction)
+			.collect();
+		for instruction in forgotten {
+			self.send_or_disconnect(Response::BreakpointRemoved { instruction });
+		}
+
+		self.conditional_breakpoints.clear();
+
+		format!("Removed {} breakpoints", removed)
+	}
+
+	// Turns the arguments of the breakpoint console commands into the instruction they refer to
+	fn command_instruction(&self, matches: &clap::ArgMatches) -> Result<InstructionRef, String> {
+		let path = matches.value_of("proc").unwrap();
+		let line = matches
+			.value_of("line")
+			.and_then(|x| x.parse::<u32>().ok())
+			.ok_or_else(|| "line must be a number".to_owned())?;
+		let override_id = matches
+			.value_of("id")
+			.and_then(|x| x.parse::<u32>().ok())
+			.unwrap_or(0);
+
+		let proc = match auxtools::Proc::find_override(path, override_id) {
+			Some(proc) => ProcRef {
+				path: proc.path,
+				override_id,
+			},
+			None => return Err("Proc not found".to_owned()),
+		};
+
+		match self.get_offset(proc.clone(), line) {
+			Some(offset) => Ok(InstructionRef { proc, offset }),
+			None => Err(format!("No instruction found for line {} of {}", line, path)),
+		}
 	}
 
 	fn handle_stacks(&mut self) {
@@ -588,12 +1864,15 @@ impl Server {
 				ret.push(Stack {
 					id: 0,
 					name: state.stacks.active[0].proc.path.clone(),
+					wake_time: None,
 				});
 
+				// Suspended stacks are the ones in BYOND's sleep queue
 				for (idx, stack) in state.stacks.suspended.iter().enumerate() {
 					ret.push(Stack {
 						id: (idx + 1) as u32,
 						name: stack[0].proc.path.clone(),
+						wake_time: stack.iter().find_map(|frame| frame.time_to_resume),
 					});
 				}
 
@@ -606,43 +1885,56 @@ impl Server {
 		self.send_or_disconnect(Response::Stacks { stacks });
 	}
 
-	fn handle_stack_frames(&mut self, stack_id: u32, start_frame: Option<u32>, count: Option<u32>) {
-		let response = match self.get_stack(stack_id) {
-			Some(stack) => {
-				let frame_base = self.get_stack_base_frame_id(stack_id);
-				let start_frame = start_frame.unwrap_or(0);
-				let end_frame = start_frame + count.unwrap_or(stack.len() as u32);
-
-				let start_frame = start_frame as usize;
-				let end_frame = end_frame as usize;
+	// Returns the requested frames along with how many frames the stack has in total
+	fn get_stack_frames(
+		&self,
+		stack_id: u32,
+		start_frame: Option<u32>,
+		count: Option<u32>,
+	) -> Option<(Vec<StackFrame>, u32)> {
+		let stack = self.get_stack(stack_id)?;
+		let frame_base = self.get_stack_base_frame_id(stack_id);
+		let start_frame = start_frame.unwrap_or(0);
+		let end_frame = start_frame + count.unwrap_or(stack.len() as u32);
+
+		let start_frame = start_frame as usize;
+		let end_frame = end_frame as usize;
+
+		let mut frames = vec![];
+
+		for i in start_frame..end_frame {
+			if i >= stack.len() {
+				break;
+			}
 
-				let mut frames = vec![];
+			let proc_ref = ProcRef {
+				path: stack[i].proc.path.to_owned(),
+				override_id: stack[i].proc.override_id(),
+			};
 
-				for i in start_frame..end_frame {
-					if i >= stack.len() {
-						break;
-					}
+			let offset = stack[i].offset as u32;
+			let (source_file, line) = self.get_source_location(proc_ref.clone(), offset);
 
-					let proc_ref = ProcRef {
-						path: stack[i].proc.path.to_owned(),
-						override_id: stack[i].proc.override_id(),
-					};
+			frames.push(StackFrame {
+				id: frame_base + (i as u32),
+				instruction: InstructionRef {
+					proc: proc_ref,
+					offset,
+				},
+				line,
+				source_file,
+			});
+		}
 
-					frames.push(StackFrame {
-						id: frame_base + (i as u32),
-						instruction: InstructionRef {
-							proc: proc_ref.clone(),
-							offset: stack[i].offset as u32,
-						},
-						line: self.get_line_number(proc_ref, stack[i].offset as u32),
-					});
-				}
+		Some((frames, stack.len() as u32))
+	}
 
-				Response::StackFrames {
-					frames,
-					total_count: stack.len() as u32,
-				}
-			}
+	fn handle_stack_frames(&mut self, stack_id: u32, start_frame: Option<u32>, count: Option<u32>) {
+		let response = match self.get_stack_frames(stack_id, start_frame, count) {
+			Some((frames, total_count)) => Response::StackFrames {
+				frames,
+				total_count,
+			},
 
 			None => {
 				self.notify("received StackFrames request when not paused");
@@ -657,18 +1949,18 @@ impl Server {
 	}
 
 	fn handle_scopes(&mut self, frame_id: u32) {
-		if self.state.is_none() {
-			let response = Response::Scopes {
-				arguments: None,
-				locals: None,
-				globals: None,
-			};
-
-			self.send_or_disconnect(response);
-			return;
-		}
-
-		let state = self.state.as_ref().unwrap();
+		let state = match &self.state {
+			Some(state) => state,
+			None => {
+				self.notify("received Scopes request when not paused");
+				self.send_or_disconnect(Response::Scopes {
+					arguments: None,
+					locals: None,
+					globals: None,
+				});
+				return;
+			}
+		};
 
 		let arguments = Variables::Arguments { frame: frame_id };
 		let locals = Variables::Locals { frame: frame_id };
@@ -686,7 +1978,7 @@ impl Server {
 
 	fn handle_variables(&mut self, vars: VariablesRef) {
 		let response = match &self.state {
-			Some(state) => match state.get_variables(vars) {
+			Some(state) => match state.get_variables(vars.clone()) {
 				Some(vars) => match vars {
 					Variables::Arguments { frame } => Response::Variables {
 						vars: self.get_args(frame),
@@ -725,6 +2017,11 @@ impl Server {
 					},
 				},
 
+				None if state.is_expired(&vars) => {
+					self.notify("received expired VariableRef in Variables request (it's from an earlier pause)");
+					Response::Variables { vars: vec![] }
+				}
+
 				None => {
 					self.notify("received unknown VariableRef in Variables request");
 					Response::Variables { vars: vec![] }
@@ -748,20 +2045,231 @@ impl Server {
 		{
 			Ok(matches) => {
 				match matches.subcommand() {
+					("breakpoint", Some(matches)) => match self.command_instruction(matches) {
+						Ok(instruction) => match self.set_breakpoint(instruction, None) {
+							BreakpointSetResult::Success { line: Some(line), offset } => {
+								format!("Breakpoint set on line {} (offset {})", line, offset)
+							}
+							BreakpointSetResult::Success { line: None, offset } => {
+								format!("Breakpoint set at offset {}", offset)
+							}
+							BreakpointSetResult::Failed => "Failed to set breakpoint".to_owned(),
+						},
+						Err(e) => e,
+					},
+
+					("breakpoints", Some(matches)) => match matches.subcommand() {
+						("clear", Some(_)) => self.clear_breakpoints(),
+						_ => self.list_breakpoints(),
+					},
+
+					("call", Some(matches)) => {
+						let proc = matches.value_of("proc").unwrap();
+						let args = matches.values_of("args").map(|args| args.collect()).unwrap_or_default();
+						self.handle_call(frame_id, proc, args)
+					}
+
+					("callers", Some(matches)) => {
+						let callee = matches.value_of("proc").unwrap();
+						let callers = proc::find_callers(callee);
+
+						if callers.is_empty() {
+							format!("No callers found for {}", callee)
+						} else {
+							callers
+								.iter()
+								.map(|proc| proc.path.as_str())
+								.collect::<Vec<_>>()
+								.join("\n")
+						}
+					}
+
+					("census", Some(matches)) => {
+						let top = matches
+							.value_of("top")
+							.and_then(|x| x.parse::<usize>().ok())
+							.unwrap_or(20);
+
+						match self.census_step(top) {
+							Ok((entries, counted, approximate)) => {
+								let mut text = if approximate {
+									format!("At least {} objects\n", counted)
+								} else {
+									format!("{} objects\n", counted)
+								};
+								for entry in entries {
+									let delta = match entry.delta {
+										Some(delta) => format!("{:+}", delta),
+										None => "".to_owned(),
+									};
+									text.push_str(&format!(
+										"{:>8} {:>8} {}\n",
+										entry.count, delta, entry.type_path
+									));
+								}
+								text
+							}
+
+							Err(counted) => format!(
+								"Counted {} objects so far, run #census again to continue",
+								counted
+							),
+						}
+					}
+
+					("clear", Some(matches)) => match self.command_instruction(matches) {
+						Ok(instruction) => match self.unset_breakpoint(instruction) {
+							Some(true) => "Breakpoint removed".to_owned(),
+							_ => "Failed to remove breakpoint".to_owned(),
+						},
+						Err(e) => e,
+					},
+
+					("coverage", Some(matches)) => match matches.subcommand() {
+						("start", Some(_))
+							if self.coverage.is_some() || coverage::is_recording_lines() =>
+						{
+							"Coverage is already running".to_owned()
+						}
+
+						("start", Some(matches)) => match matches.values_of("lines") {
+							Some(paths) => {
+								let mut procs = vec![];
+
+								for path in paths {
+									let overrides = (0..)
+										.map(|id| auxtools::Proc::find_override(path, id))
+										.take_while(Option::is_some)
+										.flatten();
+									procs.extend(overrides);
+								}
+
+								if procs.is_empty() {
+									"None of those procs exist".to_owned()
+								} else {
+									coverage::start_lines(&procs);
+									format!("Line coverage started for {} procs", procs.len())
+								}
+							}
+
+							None => {
+								self.coverage = Some(coverage::start());
+								"Coverage started".to_owned()
+							}
+						},
+
+						("stop", Some(_)) if coverage::is_recording_lines() => {
+							let mut procs: Vec<_> = coverage::stop_lines().into_iter().collect();
+							procs.sort_by(|(a, _), (b, _)| {
+								(&a.path, a.override_id).cmp(&(&b.path, b.override_id))
+							});
+
+							let mut summary = String::new();
+							for (proc, lines) in procs {
+								let mut lines: Vec<u32> = lines.into_iter().collect();
+								lines.sort_unstable();
+
+								let lines: Vec<String> = lines.iter().map(u32::to_string).collect();
+								summary.push_str(&format!(
+									"{} (#{}): lines {}\n",
+									proc.path,
+									proc.override_id,
+									lines.join(", ")
+								));
+							}
+
+							summary
+						}
+
+						("stop", Some(matches)) => match self.coverage.take() {
+							Some(guard) => {
+								let report = guard.stop();
+								if matches.is_present("json") {
+									Self::coverage_json(&report)
+								} else {
+									report.summary()
+								}
+							}
+							None => "Coverage isn't running".to_owned(),
+						},
+
+						_ => "unknown coverage sub-command".to_owned(),
+					},
+
 					("disassemble", Some(matches)) => {
-						if let Some(proc) = matches.value_of("proc") {
+						let format = if matches.is_present("json") {
+							DisassemblyFormat::Json
+						} else if matches.is_present("operands") {
+							DisassemblyFormat::Operands
+						} else if matches.is_present("dot") {
+							DisassemblyFormat::Dot
+						} else if matches.is_present("ids") {
+							DisassemblyFormat::Ids
+						} else {
+							DisassemblyFormat::Text
+						};
+						let fresh = matches.is_present("fresh");
+
+						let range = match matches.values_of("range") {
+							Some(values) => {
+								let values: Result<Vec<u32>, _> =
+									values.map(|value| value.parse::<u32>()).collect();
+
+								match values.as_deref() {
+									Ok([start, end]) => Some(*start..*end),
+									_ => return "range offsets must be whole numbers".to_owned(),
+								}
+							}
+							None => None,
+						};
+
+						if matches.is_present("current") {
+							match frame_id.and_then(|frame_id| self.get_stack_frame(frame_id)) {
+								Some(frame) => Self::instruction_text(
+									&frame.proc,
+									frame.offset as u32,
+									matches.is_present("ids"),
+								),
+								None => "couldn't find stack frame (is execution not paused?)"
+									.to_owned(),
+							}
+						} else if matches.is_present("all") {
+							let frame =
+								frame_id.and_then(|frame_id| self.get_stack_frame(frame_id));
+							let current = frame.map(|frame| (frame.proc.id, frame.offset as u32));
+							let path = match (matches.value_of("proc"), frame) {
+								(Some(proc), _) => Some(proc.to_owned()),
+								(None, Some(frame)) => Some(frame.proc.path.clone()),
+								(None, None) => None,
+							};
+
+							match path {
+								Some(path) => {
+									self.handle_disassemble_all(&path, format, current, fresh)
+								}
+								None => "no proc given and no execution frame selected".to_owned(),
+							}
+						} else if let Some(proc) = matches.value_of("proc") {
 							// Default id to 0 in the worst way possible
 							let id = matches
 								.value_of("id")
 								.and_then(|x| x.parse::<u32>().ok())
 								.unwrap_or(0);
 
-							self.handle_disassemble(proc, id)
+							self.handle_disassemble(proc, id, format, None, range, fresh)
 						} else if let Some(frame_id) = frame_id {
 							if let Some(frame) = self.get_stack_frame(frame_id) {
 								let proc = frame.proc.path.clone();
 								let id = frame.proc.override_id();
-								self.handle_disassemble(&proc, id)
+								let offset = frame.offset as u32;
+								self.handle_disassemble(
+									&proc,
+									id,
+									format,
+									Some(offset),
+									range,
+									fresh,
+								)
 							} else {
 								"couldn't find stack frame (is execution not paused?)".to_owned()
 							}
@@ -770,6 +2278,8 @@ impl Server {
 						}
 					}
 
+					("filters", Some(_)) => format!("{}", self.runtime_filters),
+
 					("guest_override", Some(matches)) => match matches.value_of("ckey") {
 						Some(ckey) => match crate::ckey_override::override_guest_ckey(ckey) {
 							Ok(()) => "Success".to_owned(),
@@ -799,6 +2309,141 @@ impl Server {
 						_ => "unknown memory profiler sub-command".to_owned(),
 					},
 
+					("profiler", Some(matches)) => match matches.subcommand() {
+						("start", Some(matches)) => {
+							let mode = match matches.value_of("interval") {
+								_ if matches.is_present("calls") => Some(ProfilerMode::Calls),
+								Some(interval) => match interval.parse::<u64>() {
+									Ok(ms) if ms > 0 => {
+										Some(ProfilerMode::Sampling { interval_ms: ms })
+									}
+									_ => None,
+								},
+								None => Some(ProfilerMode::Sampling {
+									interval_ms: profiler::DEFAULT_INTERVAL.as_millis() as u64,
+								}),
+							};
+
+							match mode {
+								Some(mode) => match self.start_profiler(mode) {
+									Ok(()) => "Profiler started".to_owned(),
+									Err(e) => e,
+								},
+								None => "interval must be a whole number of milliseconds".to_owned(),
+							}
+						}
+
+						("stop", Some(matches)) => match self.profiler.take() {
+							Some(guard) => {
+								let report = guard.stop();
+								let top = matches
+									.value_of("top")
+									.and_then(|x| x.parse::<usize>().ok())
+									.unwrap_or(20);
+								let summary = report.summary(top);
+
+								match matches.value_of("folded") {
+									Some(path) => match std::fs::write(path, report.folded()) {
+										Ok(()) => format!("{}Stacks written to {}", summary, path),
+										Err(e) => format!("{}Couldn't write {}: {}", summary, path, e),
+									},
+									None => summary,
+								}
+							}
+							None => "Profiler isn't running".to_owned(),
+						},
+
+						_ => "unknown profiler sub-command".to_owned(),
+					},
+
+					("patch", Some(matches)) => match matches.subcommand() {
+						("apply", Some(matches)) => self.handle_patch(matches),
+
+						("revert", Some(_)) => {
+							let mut text = format!("Reverted {} patches", self.patches.len());
+
+							while let Some(guard) = self.patches.pop() {
+								let range = guard.range();
+								text.push_str(&format!(
+									"\n{:?} {}..{}",
+									guard.proc(),
+									range.start,
+									range.end
+								));
+							}
+
+							text
+						}
+
+						_ => "unknown patch sub-command".to_owned(),
+					},
+
+					("assemble", Some(matches)) => match matches.subcommand() {
+						("replace", Some(matches)) => {
+							let path = matches.value_of("proc").unwrap();
+							let id = matches
+								.value_of("id")
+								.and_then(|x| x.parse::<u32>().ok())
+								.unwrap_or(0);
+
+							match auxtools::Proc::find_override(path, id) {
+								Some(proc) => {
+									let source = matches
+										.values_of("source")
+										.unwrap()
+										.collect::<Vec<_>>()
+										.join(" ")
+										.replace(';', "\n");
+
+									match proc.assemble_and_replace(&source) {
+										Ok(()) => format!("Replaced the bytecode of {:?}", proc),
+										Err(e) => format!("Couldn't replace {:?}: {}", proc, e),
+									}
+								}
+								None => "Proc not found".to_owned(),
+							}
+						}
+
+						("check", Some(matches)) => {
+							Self::handle_assemble_check(matches.value_of("proc"))
+						}
+
+						_ => "unknown assemble sub-command".to_owned(),
+					},
+
+					("strings", Some(matches)) => {
+						let limit = matches
+							.value_of("limit")
+							.and_then(|x| x.parse::<usize>().ok())
+							.unwrap_or(20);
+
+						let pattern = matches.value_of("pattern").unwrap_or("");
+						let stats = string_stats::search(pattern, limit);
+						let mut text = format!(
+							"{} strings using about {} KiB, with room for {}\n",
+							stats.entries,
+							stats.approximate_bytes / 1024,
+							stats.capacity
+						);
+
+						if stats.total_matches > 0 {
+							text.push_str(&format!(
+								"{} matching, showing {}:\n",
+								stats.total_matches,
+								stats.matches.len()
+							));
+						}
+
+						for string in stats.matches {
+							text.push_str(&format!(
+								"{:>8} {:>6} refs {:?}\n",
+								string.id, string.ref_count, string.value
+							));
+						}
+
+						text
+					}
+
 					_ => "unknown command".to_owned(),
 				}
 			}
@@ -857,109 +2502,117 @@ impl Server {
 			}
 		};
 
-		let arg_names: Vec<&str> = args.iter().map(|(name, _, _)| name.as_str()).collect();
-		let arg_values: Vec<&Value> = args.iter().map(|(_, value, _)| value).collect();
+		let named_args: Vec<(&str, &Value)> = args
+			.iter()
+			.map(|(name, value, _)| (name.as_str(), value))
+			.collect();
 
-		let expr = match dmasm::compiler::compile_expr(command, &arg_names) {
-			Ok(expr) => expr,
-			Err(err) => {
-				self.notify(format!("{}", err));
-				return None;
-			}
-		};
+		self.in_eval = true;
+		self.eval_error = None;
 
-		let assembly =
-			match dmasm::assembler::assemble(&expr, &mut crate::assemble_env::AssembleEnv) {
-				Ok(assembly) => assembly,
-				Err(err) => {
-					self.notify(format!(
-						"expression {} failed to assemble: {:#?}",
-						command, err
-					));
-					return None;
+		let result = match crate::eval::run_with_args(command, &named_args) {
+			Ok(list) => {
+				// The rest are the potentially mutated parameters. We need to commit them to the function that called us.
+				// TODO: This sucks, obviously.
+				let len = list.len();
+				for i in 2..=len {
+					let value = list.get(i).unwrap();
+					let slot = &args[i as usize - 2].2;
+
+					unsafe {
+						match slot {
+							ArgType::Dot => {
+								let _ = Value::from_raw_owned((*ctx).dot);
+								(*ctx).dot = value.raw;
+							}
+							ArgType::Usr => {
+								let _ = Value::from_raw_owned((*instance).usr);
+								(*instance).usr = value.raw;
+							}
+							ArgType::Src => {
+								let _ = Value::from_raw_owned((*instance).src);
+								(*instance).src = value.raw;
+							}
+							ArgType::Arg(idx) => {
+								let args = (*instance).args;
+								let arg = args.add(*idx as usize);
+								let _ = Value::from_raw_owned(*arg);
+								(*arg) = value.raw;
+							}
+							ArgType::Local(idx) => {
+								let locals = (*ctx).locals;
+								let local = locals.add(*idx as usize);
+								let _ = Value::from_raw_owned(*local);
+								(*local) = value.raw;
+							}
+						}
+					}
+
+					std::mem::forget(value);
 				}
-			};
 
-		let proc = match Proc::find("/proc/auxtools_expr_stub") {
-			Some(proc) => proc,
-			None => {
-				self.notify("Couldn't find /proc/auxtools_expr_stub! DM evaluation not available.");
-				return None;
+				Some(list.get(1).unwrap())
+			}
+
+			Err(e) => {
+				self.notify(e.message);
+				None
 			}
 		};
 
-		proc.set_bytecode(assembly);
+		self.in_eval = false;
 
-		self.in_eval = true;
-		self.eval_error = None;
+		if let Some(err) = self.eval_error.take() {
+			self.notify(format!(
+				"runtime occured when executing expression: {}",
+				err
+			));
+		}
 
-		let result = match proc.call(&arg_values) {
-			Ok(res) => {
-				if let Ok(list) = res.as_list() {
-					// The rest are the potentially mutated parameters. We need to commit them to the function that called us.
-					// TODO: This sucks, obviously.
-					let len = list.len();
-					for i in 2..=len {
-						let value = list.get(i).unwrap();
-						let slot = &args[i as usize - 2].2;
-
-						unsafe {
-							match slot {
-								ArgType::Dot => {
-									let _ = Value::from_raw_owned((*ctx).dot);
-									(*ctx).dot = value.raw;
-								}
-								ArgType::Usr => {
-									let _ = Value::from_raw_owned((*instance).usr);
-									(*instance).usr = value.raw;
-								}
-								ArgType::Src => {
-									let _ = Value::from_raw_owned((*instance).src);
-									(*instance).src = value.raw;
-								}
-								ArgType::Arg(idx) => {
-									let args = (*instance).args;
-									let arg = args.add(*idx as usize);
-									let _ = Value::from_raw_owned(*arg);
-									(*arg) = value.raw;
-								}
-								ArgType::Local(idx) => {
-									let locals = (*ctx).locals;
-									let local = locals.add(*idx as usize);
-									let _ = Value::from_raw_owned(*local);
-									(*local) = value.raw;
-								}
-							}
-						}
+		result
+	}
 
-						std::mem::forget(value);
-					}
+	fn handle_call(&mut self, frame_id: Option<u32>, proc_path: &str, args: Vec<&str>) -> String {
+		// Calling procs while the game is running would happen in the middle of whatever it was doing
+		if self.state.is_none() {
+			return "execution must be paused to call a proc".to_owned();
+		}
 
-					Some(list.get(1).unwrap())
-				} else {
-					None
-				}
+		let mut values = vec![];
+		for arg in &args {
+			match self.eval_expr(frame_id, arg) {
+				Some(value) => values.push(value),
+				None => return format!("couldn't evaluate argument {}", arg),
 			}
+		}
+
+		self.in_eval = true;
+		self.eval_error = None;
 
-			Err(_) => {
-				self.notify(format!(
-					"Value::call failed when evaluating expression {}",
-					command
-				));
-				None
+		let result = if proc_path.starts_with("/proc/") {
+			match Proc::find(proc_path) {
+				Some(proc) => proc.call(&values.iter().collect::<Vec<_>>()),
+				None => Err(runtime!("couldn't find proc {}", proc_path)),
+			}
+		} else {
+			// Type procs get called on their first argument
+			let name = proc_path.rsplit('/').next().unwrap_or(proc_path);
+			match values.split_first() {
+				Some((src, args)) => src.call(name, &args.iter().collect::<Vec<_>>()),
+				None => Err(runtime!("{} needs an object to be called on", proc_path)),
 			}
 		};
 
 		self.in_eval = false;
 
 		if let Some(err) = self.eval_error.take() {
-			self.notify(format!(
-				"runtime occured when executing expression: {}",
-				err
-			));
+			return format!("runtime in {}: {}", proc_path, err);
 		}
 
-		result
+		match result {
+			Ok(value) => Self::stringify(&value),
+			Err(e) => format!("call failed: {}", e),
+		}
 	}
 
 	fn handle_eval(&mut self, frame_id: Option<u32>, command: &str, context: Option<String>) {
@@ -994,53 +2647,314 @@ impl Server {
 		}
 	}
 
-	fn handle_disassemble(&mut self, path: &str, id: u32) -> String {
-		let response = match auxtools::Proc::find_override(path, id) {
-			Some(proc) => {
-				// Make sure to temporarily remove all breakpoints in this proc
-				let breaks = get_hooked_offsets(&proc);
+	#[cfg(feature = "json")]
+	fn coverage_json(report: &coverage::CoverageReport) -> String {
+		serde_json::to_string(report).unwrap()
+	}
 
-				for offset in &breaks {
-					unhook_instruction(&proc, *offset).unwrap();
-				}
+	#[cfg(not(feature = "json"))]
+	fn coverage_json(_report: &coverage::CoverageReport) -> String {
+		"debug_server was built without the json feature".to_owned()
+	}
 
-				let bytecode = unsafe { proc.bytecode() };
+	#[cfg(feature = "json")]
+	fn disassemble_json(proc: &auxtools::Proc) -> String {
+		crate::disassembly::disassemble_json(proc).to_string()
+	}
 
-				let mut env = crate::DisassembleEnv;
-				let (nodes, error) = dmasm::disassembler::disassemble(bytecode, &mut env);
-				let dism = dmasm::format_disassembly(&nodes, None);
+	#[cfg(not(feature = "json"))]
+	fn disassemble_json(_proc: &auxtools::Proc) -> String {
+		"debug_server was built without the json feature".to_owned()
+	}
 
-				for offset in &breaks {
-					hook_instruction(&proc, *offset).unwrap();
-				}
+	// With a `range`, only the instructions in it are shown. Labels are still named after the whole proc.
+	fn disassemble_text(
+		proc: &auxtools::Proc,
+		highlight: Option<u32>,
+		range: Option<Range<u32>>,
+		operands: OperandStyle,
+	) -> String {
+		// Make sure to temporarily remove all breakpoints in this proc
+		let (dism, error) = without_hooks(proc, || {
+			let bytecode = unsafe { proc.bytecode() };
+
+			let mut env = crate::DisassembleEnv;
+			let (nodes, error) = dmasm::disassembler::disassemble(bytecode, &mut env);
+			let shown = match &range {
+				Some(range) => nodes_in_range(&nodes, range.clone()),
+				None => &nodes[..],
+			};
+			(JumpLabels::new(&nodes).format(shown, highlight, operands), error)
+		});
 
-				match error {
-					Some(error) => {
-						format!("Dism for {:?}\n{}\n\tError: {:?}", proc, dism, error)
-					}
+		match error {
+			Some(error) => {
+				format!("Dism for {:?}\n{}\n\tError: {:?}", proc, dism, error)
+			}
 
-					None => {
-						format!("Dism for {:?}\n{}", proc, dism)
-					}
+			None => {
+				format!("Dism for {:?}\n{}", proc, dism)
+			}
+		}
+	}
+
+	fn disassemble_instructions(proc: &auxtools::Proc) -> Vec<DisassembledInstruction> {
+		// Make sure to temporarily remove all breakpoints in this proc
+		without_hooks(proc, || {
+			let bytecode = unsafe { proc.bytecode() };
+
+			let mut env = crate::DisassembleEnv;
+			let (nodes, _) = dmasm::disassembler::disassemble(bytecode, &mut env);
+
+			nodes
+				.iter()
+				.filter_map(|node| match node {
+					dmasm::Node::Instruction(ins, debug) => Some(DisassembledInstruction {
+						offset: debug.offset,
+						bytes: debug.bytecode.to_vec(),
+						mnemonic: mnemonic(ins),
+						operands: instruction_operands(debug.bytecode)
+							.iter()
+							.map(|operand| operand.to_string())
+							.collect(),
+					}),
+					_ => None,
+				})
+				.collect()
+		})
+	}
+
+	fn instruction_text(proc: &auxtools::Proc, offset: u32, ids: bool) -> String {
+		let text = without_hooks(proc, || {
+			let bytecode = unsafe { proc.bytecode() };
+
+			let mut env = crate::DisassembleEnv;
+			let (nodes, _) = dmasm::disassembler::disassemble(bytecode, &mut env);
+
+			let node = nodes.iter().find(
+				|node| matches!(node, dmasm::Node::Instruction(_, debug) if debug.offset == offset),
+			)?;
+
+			let formatter = InstructionFormatter::new(node);
+			if ids {
+				Some(formatter.with_ids().to_string())
+			} else {
+				Some(formatter.to_string())
+			}
+		});
+
+		match text {
+			Some(text) => format!("{} #{}: {}", proc.path, proc.override_id(), text),
+			None => format!("no instruction starts at offset {} of {:?}", offset, proc),
+		}
+	}
+
+	// `highlight` marks the instruction at that offset. Those disassemblies depend on where execution is, so they aren't cached.
+	// JSON and DOT output don't mark anything, and always cover the whole proc.
+	fn handle_disassemble(
+		&mut self,
+		path: &str,
+		id: u32,
+		format: DisassemblyFormat,
+		highlight: Option<u32>,
+		range: Option<Range<u32>>,
+		fresh: bool,
+	) -> String {
+		let proc = match auxtools::Proc::find_override(path, id) {
+			Some(proc) => proc,
+			None => return "Proc not found".to_owned(),
+		};
+
+		let bytecode = unsafe { proc.bytecode_mut_ptr() };
+		let cacheable = matches!(format, DisassemblyFormat::Json | DisassemblyFormat::Dot)
+			|| (highlight.is_none() && range.is_none());
+
+		if cacheable && !fresh {
+			if let Some(cached) = self.disassemblies.get(&(proc.id, format)) {
+				if cached.bytecode == bytecode {
+					return cached.text.clone();
 				}
 			}
+		}
+
+		let text = match format {
+			DisassemblyFormat::Text => {
+				Self::disassemble_text(&proc, highlight, range, OperandStyle::Hidden)
+			}
+			DisassemblyFormat::Operands => {
+				Self::disassemble_text(&proc, highlight, range, OperandStyle::Listed)
+			}
+			DisassemblyFormat::Ids => {
+				Self::disassemble_text(&proc, highlight, range, OperandStyle::Inline)
+			}
+			DisassemblyFormat::Json => Self::disassemble_json(&proc),
+			DisassemblyFormat::Dot => proc.control_flow_graph().to_dot(&proc.path),
+		};
+
+		if cacheable {
+			self.disassemblies.insert(
+				(proc.id, format),
+				CachedDisassembly {
+					bytecode,
+					text: text.clone(),
+				},
+			);
+		}
+
+		text
+	}
+
+	fn handle_patch(&mut self, matches: &clap::ArgMatches) -> String {
+		let path = matches.value_of("proc").unwrap();
+		let id = matches
+			.value_of("id")
+			.and_then(|x| x.parse::<u32>().ok())
+			.unwrap_or(0);
+
+		let proc = match auxtools::Proc::find_override(path, id) {
+			Some(proc) => proc,
+			None => return "Proc not found".to_owned(),
+		};
+
+		let offset = match matches.value_of("offset").unwrap().parse::<u32>() {
+			Ok(offset) => offset,
+			Err(_) => return "offset must be a whole number".to_owned(),
+		};
+
+		let words: Result<Vec<u32>, _> = matches
+			.values_of("words")
+			.unwrap()
+			.map(|word| match word.strip_prefix("0x") {
+				Some(hex) => u32::from_str_radix(hex, 16),
+				None => word.parse::<u32>(),
+			})
+			.collect();
+
+		let words = match words {
+			Ok(words) => words,
+			Err(_) => return "words must be numbers in decimal or 0x-prefixed hex".to_owned(),
+		};
+
+		match proc.patch(offset, &words) {
+			Ok(guard) => {
+				let range = guard.range();
+				self.patches.push(guard);
+				format!(
+					"Patched offsets {}..{} of {:?}",
+					range.start, range.end, proc
+				)
+			}
+			Err(e) => format!("Couldn't patch {:?}: {}", proc, e),
+		}
+	}
 
-			None => "Proc not found".to_owned(),
+	// Round-trips every override of `path`, or every proc there is, listing the ones that don't come out identical
+	fn handle_assemble_check(path: Option<&str>) -> String {
+		let procs: Vec<auxtools::Proc> = match path {
+			Some(path) => (0..)
+				.map(|id| auxtools::Proc::find_override(path, id))
+				.take_while(Option::is_some)
+				.flatten()
+				.collect(),
+			None => auxtools::proc::all().collect(),
 		};
 
-		return response;
+		if procs.is_empty() {
+			return "Proc not found".to_owned();
+		}
+
+		let mut failures = vec![];
+		for proc in &procs {
+			if let Err(e) = proc.round_trip() {
+				failures.push(format!("{:?}: {}", proc, e));
+			}
+		}
+
+		let mut text = format!(
+			"{} of {} procs assembled back to identical bytecode",
+			procs.len() - failures.len(),
+			procs.len()
+		);
+
+		for failure in failures {
+			text.push('\n');
+			text.push_str(&failure);
+		}
+
+		text
+	}
+
+	// Disassembles every override of `path`, each under a header with its id and source file.
+	// `current` is the proc and offset of the selected frame, which gets pointed out if it's one of them.
+	fn handle_disassemble_all(
+		&mut self,
+		path: &str,
+		format: DisassemblyFormat,
+		current: Option<(raw_types::procs::ProcId, u32)>,
+		fresh: bool,
+	) -> String {
+		let mut text = String::new();
+
+		// Override ids are handed out in order when procs are populated, so the first missing one is the end
+		for id in 0.. {
+			let proc = match auxtools::Proc::find_override(path, id) {
+				Some(proc) => proc,
+				None => break,
+			};
+
+			let file = proc.source_file().unwrap_or_else(|| "unknown file".to_owned());
+			let highlight = match current {
+				Some((proc_id, offset)) if proc_id == proc.id => Some(offset),
+				_ => None,
+			};
+
+			text.push_str(&format!("=== Override {} ({})", id, file));
+			if highlight.is_some() {
+				text.push_str(" - the selected frame is running this one");
+			}
+			text.push_str(" ===\n");
+
+			text.push_str(&self.handle_disassemble(path, id, format, highlight, None, fresh));
+			text.push_str("\n\n");
+		}
+
+		if text.is_empty() {
+			return "Proc not found".to_owned();
+		}
+
+		text.truncate(text.trim_end().len());
+		text
 	}
 
 	// returns true if we need to break
 	fn handle_request(&mut self, request: Request) -> bool {
+		if self.reject_unauthenticated(&request) {
+			return false;
+		}
+
 		match request {
-			Request::Disconnect => unreachable!(),
-			Request::CatchRuntimes { should_catch } => self.should_catch_runtimes = should_catch,
+			Request::Disconnect => {
+				self.handle_disconnect();
+
+				// Pick up the next client straight away in case its requests are already queued
+				self.is_connected();
+			}
+			Request::CatchRuntimes {
+				should_catch,
+				procs,
+			} => {
+				self.should_catch_runtimes = should_catch;
+				self.runtime_filters.set_procs(procs);
+			}
+			Request::RuntimeFilters { include, exclude } => {
+				self.runtime_filters.set_patterns(include, exclude)
+			}
 			Request::BreakpointSet {
 				instruction,
 				condition,
 			} => self.handle_breakpoint_set(instruction, condition),
 			Request::BreakpointUnset { instruction } => self.handle_breakpoint_unset(instruction),
+			Request::FunctionBreakpointSet { proc } => self.handle_function_breakpoint_set(proc),
 			Request::Stacks => self.handle_stacks(),
 			Request::Scopes { frame_id } => self.handle_scopes(frame_id),
 			Request::Variables { vars } => self.handle_variables(vars),
@@ -1057,9 +2971,8 @@ impl Server {
 			} => self.handle_stack_frames(stack_id, start_frame, count),
 
 			Request::LineNumber { proc, offset } => {
-				self.send_or_disconnect(Response::LineNumber {
-					line: self.get_line_number(proc, offset),
-				});
+				let (file, line) = self.get_source_location(proc, offset);
+				self.send_or_disconnect(Response::LineNumber { line, file });
 			}
 
 			Request::Offset { proc, line } => {
@@ -1068,8 +2981,30 @@ impl Server {
 				});
 			}
 
+			// Hearing from the client at all is what keeps it connected
+			Request::Pong => {}
+
+			Request::SetVariable {
+				frame_id,
+				scope,
+				name,
+				value,
+			} => self.handle_set_variable(frame_id, scope, &name, &value),
+
+			Request::Unsupported { error } => {
+				eprintln!("Debug server received an unsupported request: {}", error);
+				self.send_or_disconnect(Response::Unsupported { error });
+			}
+
+			Request::Authenticate { token } => self.handle_authenticate(&token),
+
+			Request::StackOnBreakpoint { enabled } => {
+				self.stack_on_breakpoint = enabled;
+				self.send_ack();
+			}
+
 			Request::Pause => {
-				self.send_or_disconnect(Response::Ack);
+				self.send_ack();
 				return true;
 			}
 
@@ -1088,29 +3023,370 @@ impl Server {
 						offset: frame.offset as u32,
 					}),
 
-					None => None,
+					None => {
+						if self.state.is_none() {
+							self.notify("received CurrentInstruction request when not paused");
+						}
+						None
+					}
 				};
 
 				self.send_or_disconnect(Response::CurrentInstruction(response));
 			}
 
+			Request::Configured => {
+				self.send_ack();
+				self.restore_breakpoints();
+			}
+
+			Request::GotoLine { frame_id, line } => {
+				let response = match self.goto_line(frame_id, line) {
+					Ok(offset) => Response::GotoLineResult {
+						offset: Some(offset),
+						error: None,
+					},
+
+					Err(error) => Response::GotoLineResult {
+						offset: None,
+						error: Some(error),
+					},
+				};
+
+				self.send_or_disconnect(response);
+			}
+
+			Request::ProfilerStart { mode } => {
+				let response = match self.start_profiler(mode) {
+					Ok(()) => Response::ProfilerStartResult {
+						success: true,
+						error: None,
+					},
+
+					Err(error) => Response::ProfilerStartResult {
+						success: false,
+						error: Some(error),
+					},
+				};
+
+				self.send_or_disconnect(response);
+			}
+
+			Request::FindReferences { target, limit } => {
+				if let Err(error) = self.start_reference_search(target, limit) {
+					self.send_or_disconnect(Response::FindReferences {
+						holders: vec![],
+						searched: 0,
+						complete: false,
+						error: Some(error),
+					});
+				}
+			}
+
+			Request::FindReferencesCancel => {
+				self.finish_reference_search(false);
+				self.send_ack();
+			}
+
+			Request::StringSearch { pattern, limit } => {
+				let stats = string_stats::search(&pattern, limit as usize);
+				self.send_or_disconnect(Response::StringSearch {
+					entries: stats.entries,
+					capacity: stats.capacity,
+					approximate_bytes: stats.approximate_bytes,
+					matches: stats.matches,
+					total_matches: stats.total_matches,
+				});
+			}
+
+			Request::ListProcs {
+				filter,
+				offset,
+				limit,
+			} => {
+				let limit = limit.map_or(usize::MAX, |limit| limit as usize);
+				let (procs, total) = Self::list_procs(filter.as_deref(), offset as usize, limit);
+				self.send_or_disconnect(Response::ProcList { procs, total });
+			}
+
+			Request::HotPatch { proc, new_source } => {
+				let response = match self.hot_patch(proc, &new_source) {
+					Ok(()) => Response::HotPatchResult {
+						success: true,
+						error: None,
+					},
+
+					Err(error) => Response::HotPatchResult {
+						success: false,
+						error: Some(error),
+					},
+				};
+
+				self.send_or_disconnect(response);
+			}
+
+			Request::HotPatchRevert { proc } => {
+				let response = match self.hot_patch_revert(proc) {
+					Ok(()) => Response::HotPatchResult {
+						success: true,
+						error: None,
+					},
+
+					Err(error) => Response::HotPatchResult {
+						success: false,
+						error: Some(error),
+					},
+				};
+
+				self.send_or_disconnect(response);
+			}
+
+			Request::Disassemble { proc } => {
+				let instructions = auxtools::Proc::find_override(&proc.path, proc.override_id)
+					.map(|proc| Self::disassemble_instructions(&proc))
+					.unwrap_or_default();
+
+				self.send_or_disconnect(Response::Disassembly { instructions });
+			}
+
+			Request::InstanceCensus { top } => {
+				let response = match self.census_step(top as usize) {
+					Ok((entries, counted, approximate)) => Response::InstanceCensus {
+						done: true,
+						counted,
+						entries,
+						approximate,
+					},
+
+					Err(counted) => Response::InstanceCensus {
+						done: false,
+						counted,
+						entries: vec![],
+						approximate: false,
+					},
+				};
+
+				self.send_or_disconnect(response);
+			}
+
+			Request::CoverageStart => {
+				let response = match coverage::start_hooked() {
+					Ok(_) => Response::CoverageStartResult {
+						success: true,
+						error: None,
+					},
+
+					Err(error) => Response::CoverageStartResult {
+						success: false,
+						error: Some(error),
+					},
+				};
+
+				self.send_or_disconnect(response);
+			}
+
+			Request::CoverageStop { lcov_path } => {
+				let response = match coverage::stop_hooked() {
+					Some(report) => {
+						let error = lcov_path.and_then(|path| {
+							std::fs::write(&path, report.to_lcov())
+								.err()
+								.map(|e| format!("Couldn't write {}: {}", path, e))
+						});
+
+						Response::CoverageData {
+							lines_found: report.lines_found() as u32,
+							lines_hit: report.lines_hit() as u32,
+							error,
+						}
+					}
+
+					None => Response::CoverageData {
+						lines_found: 0,
+						lines_hit: 0,
+						error: Some("Coverage isn't running".to_owned()),
+					},
+				};
+
+				self.send_or_disconnect(response);
+			}
+
+			Request::ProfilerStop { folded_path } => {
+				let response = match self.profiler.take() {
+					Some(profiler) => {
+						let report = profiler.stop();
+						let error = folded_path.and_then(|path| {
+							std::fs::write(&path, report.folded())
+								.err()
+								.map(|e| format!("Couldn't write {}: {}", path, e))
+						});
+
+						Response::ProfileData {
+							procs: report.profiled_procs(),
+							error,
+						}
+					}
+
+					None => Response::ProfileData {
+						procs: vec![],
+						error: Some("Profiler isn't running".to_owned()),
+					},
+				};
+
+				self.send_or_disconnect(response);
+			}
+
+			Request::Restart => {
+				self.send_ack();
+
+				// Rebooting shuts the debug server down, so it can't happen while we're in the middle of handling a request
+				let _ = auxtools::runtime::defer(|| {
+					if let Err(e) = Value::world().call("Reboot", &[]) {
+						eprintln!("Debug server couldn't reboot the world: {}", e);
+					}
+				});
+			}
+
 			// The following requests are special cases and handled outside of this function
-			Request::Configured | Request::Continue { .. } => {
-				self.send_or_disconnect(Response::Ack);
+			Request::Continue { kind } => {
+				if let ContinueKind::RunTo { proc, offset } = kind {
+					self.set_run_to(proc, offset);
+				}
+
+				self.send_ack();
 			}
 		}
 
 		false
 	}
 
-	fn check_connected(&mut self) -> bool {
+	// Drops clients that try to do anything before authenticating. Returns true if the request was rejected.
+	fn reject_unauthenticated(&mut self, request: &Request) -> bool {
+		match request {
+			_ if self.authenticated => false,
+			Request::Authenticate { .. } | Request::Disconnect | Request::Pong => false,
+			_ => {
+				// Anything else left over from a client we already dropped is ignored quietly
+				if let ServerStream::Connected(_) = self.stream {
+					eprintln!("Debug client sent a request before authenticating");
+					self.send_or_disconnect(Response::AuthFailed);
+					self.disconnect();
+				}
+
+				true
+			}
+		}
+	}
+
+	fn handle_authenticate(&mut self, token: &str) {
+		let accepted = match &self.token {
+			Some(expected) => tokens_match(expected, token),
+			None => true,
+		};
+
+		if !accepted {
+			eprintln!("Debug client sent the wrong token");
+			self.send_or_disconnect(Response::AuthFailed);
+			self.disconnect();
+			return;
+		}
+
+		self.authenticated = true;
+		self.send_ack();
+	}
+
+	/// Removes every breakpoint the client set and hands them over, so they can outlive this server.
+	/// Ones that aren't on any line can't be put back, so they're dropped.
+	pub fn take_breakpoints(&mut self) -> Vec<SavedBreakpoint> {
+		let breakpoints: Vec<_> = self
+			.breakpoints
+			.iter()
+			.map(|(instruction, condition)| (instruction.clone(), condition.clone()))
+			.collect();
+
+		let mut saved = vec![];
+		for (instruction, condition) in breakpoints {
+			match self.get_line_number(instruction.proc.clone(), instruction.offset) {
+				Some(line) => saved.push(SavedBreakpoint {
+					proc: instruction.proc.clone(),
+					line,
+					condition,
+				}),
+				None => eprintln!(
+					"Debug server dropped the breakpoint at offset {} of {}, which isn't on any line",
+					instruction.offset, instruction.proc.path
+				),
+			}
+
+			self.unset_breakpoint(instruction);
+		}
+
+		saved
+	}
+
+	/// Installs breakpoints taken from a server that existed before the world rebooted. They're found again by
+	/// line, like after a hot patch. Ones whose procs are gone or whose lines have no code anymore are dropped.
+	pub fn reattach_breakpoints(&mut self, breakpoints: Vec<SavedBreakpoint>) {
+		for breakpoint in breakpoints {
+			let offset = self.get_offset(breakpoint.proc.clone(), breakpoint.line);
+
+			let result = match offset {
+				Some(offset) => self.set_breakpoint(
+					InstructionRef {
+						proc: breakpoint.proc.clone(),
+						offset,
+					},
+					breakpoint.condition,
+				),
+				None => BreakpointSetResult::Failed,
+			};
+
+			if let BreakpointSetResult::Failed = result {
+				eprintln!(
+					"Debug server couldn't put back the breakpoint on line {} of {} after the reboot",
+					breakpoint.line, breakpoint.proc.path
+				);
+			}
+		}
+	}
+
+	// Lets a (re)connected client know about the breakpoints that are still installed
+	fn restore_breakpoints(&mut self) {
+		let breakpoints: Vec<_> = self
+			.breakpoints
+			.iter()
+			.map(|(instruction, condition)| (instruction.clone(), condition.clone()))
+			.collect();
+
+		for (instruction, condition) in breakpoints {
+			let line = self.get_line_number(instruction.proc.clone(), instruction.offset);
+			self.send_or_disconnect(Response::BreakpointRestored {
+				instruction,
+				line,
+				condition,
+			});
+		}
+	}
+
+	/// Whether a client is connected, picking up a waiting one if there is one
+	pub fn is_connected(&mut self) -> bool {
 		match &self.stream {
 			ServerStream::Disconnected => false,
-			ServerStream::Connected(_) => true,
-			ServerStream::Waiting(receiver) => {
-				if let Ok(stream) = receiver.try_recv() {
-					self.stream = ServerStream::Connected(stream);
-					true
+			ServerStream::Connected(writer) => {
+				if writer.has_failed() {
+					self.disconnect();
+					return false;
+				}
+
+				true
+			}
+			ServerStream::Waiting => {
+				let stream = self
+					.connections
+					.as_ref()
+					.and_then(|receiver| receiver.try_recv().ok());
+
+				if let Some((stream, handshake)) = stream {
+					self.accept_connection(stream, handshake)
 				} else {
 					false
 				}
@@ -1118,46 +3394,161 @@ impl Server {
 		}
 	}
 
-	fn wait_for_connection(&mut self) {
-		match &self.stream {
-			ServerStream::Waiting(receiver) => {
-				if let Ok(stream) = receiver.recv() {
-					self.stream = ServerStream::Connected(stream);
-				}
+	// Gives up at `deadline`, or after the connect timeout without one
+	fn wait_for_connection(&mut self, deadline: Option<Instant>) {
+		if let ServerStream::Waiting = self.stream {
+			let deadline = deadline.or_else(|| {
+				self.connect_timeout
+					.map(|connect_timeout| Instant::now() + connect_timeout)
+			});
+
+			let stream = self
+				.connections
+				.as_ref()
+				.and_then(|receiver| match deadline {
+					Some(deadline) => receiver
+						.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+						.ok(),
+					None => receiver.recv().ok(),
+				});
+
+			if let Some((stream, handshake)) = stream {
+				self.accept_connection(stream, handshake);
 			}
+		}
+	}
+
+	fn heard_from_client(&mut self) {
+		if let Some(keep_alive) = &mut self.keep_alive {
+			keep_alive.last_heard = Instant::now();
+			keep_alive.ping_sent = None;
+		}
+	}
+
+	// When keep_alive_check next has something to do
+	fn keep_alive_deadline(&self) -> Option<Instant> {
+		let keep_alive = self.keep_alive.as_ref()?;
+
+		Some(match keep_alive.ping_sent {
+			Some(sent) => sent + keep_alive.answer_timeout,
+			None => keep_alive.last_heard + keep_alive.idle_timeout,
+		})
+	}
+
+	// Pings a client that has been quiet for too long, and disconnects one that didn't answer in time.
+	// Returns false if the client was disconnected.
+	fn keep_alive_check(&mut self) -> bool {
+		let (answer_timeout, ping_sent) = match &self.keep_alive {
+			Some(keep_alive) => (keep_alive.answer_timeout, keep_alive.ping_sent),
+			None => return true,
+		};
+
+		let due = match self.keep_alive_deadline() {
+			Some(deadline) => Instant::now() >= deadline,
+			None => false,
+		};
+
+		if !due {
+			return true;
+		}
+
+		if ping_sent.is_some() {
+			eprintln!(
+				"Debug client didn't answer a ping within {:?}, disconnecting",
+				answer_timeout
+			);
+			self.disconnect();
+			return false;
+		}
+
+		if let Some(keep_alive) = &mut self.keep_alive {
+			keep_alive.ping_sent = Some(Instant::now());
+		}
+
+		self.send_or_disconnect(Response::Ping);
+		self.is_connected()
+	}
 
-			_ => (),
+	// Returns false if the client was turned away
+	fn accept_connection(&mut self, stream: Stream, handshake: Handshake) -> bool {
+		self.discard_stale_requests();
+		self.heard_from_client();
+		self.authenticated = self.token.is_none();
+		self.stack_on_breakpoint = true;
+		self.stream = ServerStream::Connected(ResponseWriter::spawn(stream, handshake.framing));
+
+		if !handshake.is_compatible() {
+			// Notification has kept its layout since before the hello, so even old clients can show this
+			let message = match handshake.client_version {
+				Some(client_version) => format!(
+					"Debug client uses protocol version {} but the debug server uses version {}",
+					client_version, PROTOCOL_VERSION
+				),
+				None => format!(
+					"Debug client is too old to send a protocol version, the debug server uses version {}",
+					PROTOCOL_VERSION
+				),
+			};
+
+			eprintln!("{}", message);
+			self.send_or_disconnect(Response::Notification { message });
+			self.disconnect();
+			return false;
 		}
+
+		true
 	}
 
 	pub fn notify<T: Into<String>>(&mut self, message: T) {
 		let message = message.into();
+
+		#[cfg(feature = "tracing")]
+		tracing::warn!(%message, "debug server notification");
+
+		#[cfg(not(feature = "tracing"))]
 		eprintln!("Debug Server: {:?}", message);
 
-		if !self.check_connected() {
+		if !self.is_connected() || !self.authenticated {
 			return;
 		}
 
 		self.send_or_disconnect(Response::Notification { message });
 	}
 
+	// Any code we ran may have paused and resumed again underneath us, which clears the state
+	fn refresh_state(&mut self) {
+		match self.state.as_mut() {
+			Some(state) => state.invalidate_stacks(),
+			None => self.state = Some(State::new()),
+		}
+	}
+
 	pub fn handle_breakpoint(
 		&mut self,
 		_ctx: *mut raw_types::procs::ExecutionContext,
 		reason: BreakpointReason,
 	) -> ContinueKind {
-		// Ignore all breakpoints unless we're connected
-		if !self.check_connected() {
+		// Ignore all breakpoints unless we're connected to a client we trust
+		if !self.is_connected() || !self.authenticated {
+			self.clear_run_to();
 			return ContinueKind::Continue;
 		}
 
-		if let BreakpointReason::Runtime(_) = reason {
+		if let BreakpointReason::Runtime(message) = &reason {
 			if !self.should_catch_runtimes {
 				return ContinueKind::Continue;
 			}
+
+			let proc = unsafe { Proc::from_id((*(*_ctx).proc_instance).proc) };
+			let proc_path = proc.as_ref().map(|proc| proc.path.as_str());
+			if !self.runtime_filters.should_break(message, proc_path) {
+				return ContinueKind::Continue;
+			}
 		}
 
-		self.state = Some(State::new());
+		let mut state = State::new();
+		state.runtime = matches!(reason, BreakpointReason::Runtime(_));
+		self.state = Some(state);
 
 		// Exit now if this is a conditional breakpoint and the condition doesn't pass!
 		if let BreakpointReason::Breakpoint = reason {
@@ -1177,18 +3568,94 @@ impl Server {
 				}
 
 				// We might have just executed some code so invalidate the stacks we already fetched
-				self.state.as_mut().unwrap().invalidate_stacks();
+				self.refresh_state();
 			}
 		}
 
+		// Whatever paused us, the RunTo is over
+		self.clear_run_to();
+
+		#[cfg(feature = "tracing")]
+		tracing::info!(
+			?reason,
+			proc = %unsafe { Proc::from_id((*(*_ctx).proc_instance).proc) }
+				.map_or_else(String::new, |proc| proc.path),
+			offset = unsafe { (*_ctx).bytecode_offset },
+			"breakpoint hit"
+		);
+
 		self.notify(format!("Pausing execution (reason: {:?})", reason));
-		self.send_or_disconnect(Response::BreakpointHit { reason });
+		// Saves simple clients from having to ask where we stopped
+		let stack = if self.stack_on_breakpoint {
+			self.get_stack_frames(0, None, None).map(|(frames, _)| frames)
+		} else {
+			None
+		};
+
+		self.send_or_disconnect(Response::BreakpointHit { reason, stack });
+
+		loop {
+			// A reference search carries on whenever there's no request waiting
+			let request = if self.reference_search.is_some() {
+				match self.requests.try_recv() {
+					Ok(request) => request,
+
+					Err(mpsc::TryRecvError::Empty) => {
+						self.reference_search_step();
+						continue;
+					}
+
+					Err(mpsc::TryRecvError::Disconnected) => break,
+				}
+			} else if let Some(deadline) = self.keep_alive_deadline() {
+				match self
+					.requests
+					.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+				{
+					Ok(request) => request,
+
+					Err(mpsc::RecvTimeoutError::Timeout) => {
+						if !self.keep_alive_check() {
+							eprintln!("Debug client went away, resuming execution");
+							break;
+						}
+
+						continue;
+					}
+
+					Err(mpsc::RecvTimeoutError::Disconnected) => break,
+				}
+			} else {
+				match self.requests.recv() {
+					Ok(request) => request,
+					Err(_) => break,
+				}
+			};
+
+			self.heard_from_client();
+
+			if self.reject_unauthenticated(&request) {
+				break;
+			}
+
+			// The client went away while we were paused, so there's nobody left to resume us
+			if let Request::Disconnect = request {
+				self.handle_disconnect();
+				break;
+			}
 
-		while let Ok(request) = self.requests.recv() {
 			// Hijack and handle any Continue requests
 			if let Request::Continue { kind } = request {
-				self.send_or_disconnect(Response::Ack);
+				// Anything the search found would be handed out as references that are about to expire
+				self.abandon_reference_search("execution resumed before the search finished");
+				self.send_ack();
 				self.state = None;
+
+				if let ContinueKind::RunTo { proc, offset } = kind {
+					self.set_run_to(proc, offset);
+					return ContinueKind::Continue;
+				}
+
 				return kind;
 			}
 
@@ -1200,7 +3667,7 @@ impl Server {
 			} = request
 			{
 				self.handle_eval(frame_id, &command, context);
-				self.state.as_mut().unwrap().invalidate_stacks();
+				self.refresh_state();
 				continue;
 			}
 
@@ -1209,6 +3676,7 @@ impl Server {
 		}
 
 		// Client disappeared?
+		self.reference_search = None;
 		self.state = None;
 		ContinueKind::Continue
 	}
@@ -1216,110 +3684,234 @@ impl Server {
 	// returns true if we need to pause
 	pub fn process(&mut self) -> bool {
 		// Don't do anything until we're connected
-		if !self.check_connected() {
+		if !self.is_connected() {
 			return false;
 		}
 
 		let mut should_pause = false;
 
 		while let Ok(request) = self.requests.try_recv() {
+			self.heard_from_client();
 			should_pause = should_pause || self.handle_request(request);
 		}
 
+		if self.keep_alive.is_some() {
+			if self.keep_alive_countdown == 0 {
+				self.keep_alive_countdown = KEEP_ALIVE_INTERVAL;
+				self.keep_alive_check();
+			}
+			self.keep_alive_countdown -= 1;
+		}
+
 		should_pause
 	}
 
 	/// Block while processing all received requests normally until the debug client is configured
 	pub fn process_until_configured(&mut self) {
-		self.wait_for_connection();
+		self.process_until_configured_before(None);
+	}
+
+	/// Like [process_until_configured](Server::process_until_configured), but gives up after `timeout`.
+	/// Returns whether the client was configured in time.
+	pub fn process_until_configured_timeout(&mut self, timeout: Duration) -> bool {
+		self.process_until_configured_before(Some(Instant::now() + timeout))
+	}
+
+	fn process_until_configured_before(&mut self, deadline: Option<Instant>) -> bool {
+		loop {
+			// Listening servers keep waiting if a client goes away (or is turned away) early
+			self.wait_for_connection(deadline);
+
+			// Nobody connected in time
+			if let ServerStream::Disconnected | ServerStream::Waiting = self.stream {
+				return false;
+			}
+
+			let request = match deadline {
+				Some(deadline) => match self
+					.requests
+					.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+				{
+					Ok(request) => request,
+					Err(_) => return false,
+				},
+
+				None => match self.requests.recv() {
+					Ok(request) => request,
+					Err(_) => return false,
+				},
+			};
+
+			self.heard_from_client();
+
+			if self.reject_unauthenticated(&request) {
+				continue;
+			}
 
-		while let Ok(request) = self.requests.recv() {
 			if let Request::Configured = request {
-				self.send_or_disconnect(Response::Ack);
-				break;
+				self.send_ack();
+				self.restore_breakpoints();
+				return true;
 			}
 
 			self.handle_request(request);
 		}
 	}
 
+	// Only queues the response, the ResponseWriter reports any failure to write it later on
 	fn send_or_disconnect(&mut self, response: Response) {
-		match self.stream {
-			ServerStream::Connected(_) => match self.send(response) {
-				Ok(_) => {}
-				Err(e) => {
-					eprintln!("Debug server failed to send message: {}", e);
+		match &self.stream {
+			ServerStream::Connected(writer) => {
+				if !writer.send(response) {
 					self.disconnect();
 				}
-			},
+			}
+
+			// The client has gone away, nobody is around to receive the response
+			ServerStream::Waiting | ServerStream::Disconnected => {}
+		}
+	}
+
+	// An Ack tells the client its request has taken effect, so it (and everything before it) has to be
+	// written before we carry on. This also catches a dead client before execution resumes.
+	fn send_ack(&mut self) {
+		self.send_or_disconnect(Response::Ack);
 
-			ServerStream::Waiting(_) | ServerStream::Disconnected => {
-				unreachable!("Debug Server is not connected")
+		if let ServerStream::Connected(writer) = &self.stream {
+			if !writer.flush() {
+				self.disconnect();
 			}
 		}
 	}
 
-	fn disconnect(&mut self) {
-		if let ServerStream::Connected(stream) = &mut self.stream {
-			eprintln!("Debug server disconnecting");
-			let data = bincode::serialize(&Response::Disconnect).unwrap();
-			let _ = stream.write_all(&(data.len() as u32).to_le_bytes());
-			let _ = stream.write_all(&data[..]);
-			let _ = stream.flush();
-			let _ = stream.shutdown(std::net::Shutdown::Both);
+	fn handle_disconnect(&mut self) {
+		if self.stale_connection {
+			// This is the end of a connection we already dropped
+			self.stale_connection = false;
+		} else {
+			self.disconnect();
+			self.stale_connection = false;
+		}
+	}
+
+	// Throws away anything left over from a connection we dropped, up to and including its Disconnect
+	fn discard_stale_requests(&mut self) {
+		if !self.stale_connection {
+			return;
+		}
+
+		while let Ok(request) = self.requests.recv() {
+			if let Request::Disconnect = request {
+				break;
+			}
 		}
 
-		self.stream = ServerStream::Disconnected;
+		self.stale_connection = false;
 	}
 
-	fn send(&mut self, response: Response) -> Result<(), Box<dyn std::error::Error>> {
-		if let ServerStream::Connected(stream) = &mut self.stream {
-			let data = bincode::serialize(&response)?;
-			stream.write_all(&(data.len() as u32).to_le_bytes())?;
-			stream.write_all(&data[..])?;
-			stream.flush()?;
-			return Ok(());
+	fn disconnect(&mut self) {
+		self.authenticated = self.token.is_none();
+		self.clear_run_to();
+
+		// Listening servers go on to accept a new client
+		let next = match self.connections {
+			Some(_) => ServerStream::Waiting,
+			None => ServerStream::Disconnected,
+		};
+
+		if let ServerStream::Connected(writer) = std::mem::replace(&mut self.stream, next) {
+			self.stale_connection = true;
+
+			eprintln!("Debug server disconnecting");
+			writer.close();
 		}
+	}
+}
 
-		unreachable!();
+// Takes as long wherever the first difference is, so a token can't be guessed a byte at a time
+fn tokens_match(expected: &str, given: &str) -> bool {
+	let expected = expected.as_bytes();
+	let given = given.as_bytes();
+
+	if expected.len() != given.len() {
+		return false;
 	}
+
+	expected
+		.iter()
+		.zip(given)
+		.fold(0u8, |diff, (a, b)| diff | (a ^ b))
+		== 0
 }
 
 impl Drop for Server {
 	fn drop(&mut self) {
-		self.disconnect();
+		let stream = std::mem::replace(&mut self.stream, ServerStream::Disconnected);
+
+		if let ServerStream::Connected(writer) = stream {
+			eprintln!("Debug server disconnecting");
+
+			// The writer's thread shouldn't outlive us, and the client should hear about the disconnect. This runs
+			// on the game thread during shutdown and reboots, so a client that stopped reading can't hold them up.
+			if !writer.close_and_wait(CLOSE_TIMEOUT) {
+				eprintln!("Debug server gave up waiting for the client to read its last responses");
+			}
+		}
+
+		if let Some(path) = &self.socket_path {
+			let _ = std::fs::remove_file(path);
+		}
 	}
 }
 
 impl ServerThread {
 	fn spawn_listener(
 		self,
-		listener: TcpListener,
-		connection_sender: mpsc::Sender<TcpStream>,
+		mut listener: Listener,
+		connection_sender: mpsc::Sender<(Stream, Handshake)>,
 	) -> JoinHandle<()> {
-		thread::spawn(move || match listener.accept() {
-			Ok((stream, _)) => {
-				match connection_sender.send(stream.try_clone().unwrap()) {
-					Ok(_) => {}
-					Err(e) => {
-						eprintln!("Debug server thread failed to pass cloned TcpStream: {}", e);
-						return;
+		let mut server_thread = self;
+
+		thread::spawn(move || loop {
+			match listener.accept() {
+				Ok(mut stream) => {
+					let handshake = match Self::read_handshake(&mut stream) {
+						Ok(handshake) => handshake,
+						Err(e) => {
+							eprintln!("Debug server couldn't agree on framing with client: {}", e);
+							stream.shutdown();
+							continue;
+						}
+					};
+
+					match connection_sender.send((stream.try_clone().unwrap(), handshake.clone())) {
+						Ok(_) => {}
+						Err(e) => {
+							eprintln!("Debug server thread failed to pass cloned stream: {}", e);
+							return;
+						}
 					}
-				}
 
-				self.run(stream);
-			}
+					server_thread.run(stream, handshake);
+				}
 
-			Err(e) => {
-				eprintln!("Debug server failed to accept connection: {}", e);
+				Err(e) => {
+					eprintln!("Debug server failed to accept connection: {}", e);
+					return;
+				}
 			}
 		})
 	}
 
-	// returns true if we should disconnect
-	fn handle_request(&mut self, data: &[u8]) -> Result<bool, Box<dyn Error>> {
-		let request = bincode::deserialize::<Request>(data)?;
+	fn read_handshake(stream: &mut Stream) -> Result<Handshake, Box<dyn Error>> {
+		stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+		let handshake = Handshake::read(stream)?;
+		stream.set_read_timeout(None)?;
+		Ok(handshake)
+	}
 
+	// returns true if we should disconnect
+	fn handle_request(&mut self, request: Request) -> Result<bool, Box<dyn Error>> {
 		if let Request::Disconnect = request {
 			return Ok(true);
 		}
@@ -1328,32 +3920,88 @@ impl ServerThread {
 		Ok(false)
 	}
 
-	fn run(mut self, mut stream: TcpStream) {
-		let mut buf = vec![];
+	// A u32 followed by a bincode-encoded Request.
+	// The length of the first message was already read while negotiating framing.
+	fn read_bincode_message<R: Read>(
+		&self,
+		stream: &mut R,
+		len_bytes: Option<[u8; 4]>,
+		buf: &mut Vec<u8>,
+	) -> std::io::Result<()> {
+		let len_bytes = match len_bytes {
+			Some(len_bytes) => len_bytes,
+			None => {
+				let mut len_bytes = [0u8; 4];
+				stream.read_exact(&mut len_bytes)?;
+				len_bytes
+			}
+		};
+
+		buf.resize(u32::from_le_bytes(len_bytes) as usize, 0);
+		stream.read_exact(buf)
+	}
 
-		// The incoming stream is a u32 followed by a bincode-encoded Request.
+	// A line of JSON. Blank lines are skipped.
+	#[cfg(feature = "json")]
+	fn read_json_message<R: std::io::BufRead>(&self, stream: &mut R, buf: &mut Vec<u8>) -> std::io::Result<()> {
 		loop {
-			let mut len_bytes = [0u8; 4];
-			let len = match stream.read_exact(&mut len_bytes) {
-				Ok(_) => u32::from_le_bytes(len_bytes),
+			buf.clear();
 
-				Err(e) => {
-					eprintln!("Debug server thread read error: {}", e);
-					break;
+			if stream.read_until(b'\n', buf)? == 0 || buf.pop() != Some(b'\n') {
+				return Err(std::io::ErrorKind::UnexpectedEof.into());
+			}
+
+			if buf.ends_with(b"\r") {
+				buf.pop();
+			}
+
+			if !buf.is_empty() {
+				return Ok(());
+			}
+		}
+	}
+
+	fn run(&mut self, stream: Stream, handshake: Handshake) {
+		// The Server turns incompatible clients away, and nothing they send can be trusted to decode properly
+		if handshake.is_compatible() {
+			self.read_requests(stream, handshake);
+		}
+
+		// Tell the Server the client is gone, even if the client didn't
+		let _ = self.requests.send(Request::Disconnect);
+		eprintln!("Debug server thread finished");
+	}
+
+	fn read_requests(&mut self, stream: Stream, handshake: Handshake) {
+		let framing = handshake.framing;
+		let mut first_len = handshake.first_len;
+		let mut buf = vec![];
+		let mut stream = BufReader::new(stream);
+
+		loop {
+			let message = match framing {
+				Framing::Bincode | Framing::CompressedBincode => {
+					self.read_bincode_message(&mut stream, first_len.take(), &mut buf)
 				}
+
+				#[cfg(feature = "json")]
+				Framing::Json => self.read_json_message(&mut stream, &mut buf),
 			};
 
-			buf.resize(len as usize, 0);
-			match stream.read_exact(&mut buf) {
-				Ok(_) => (),
+			if let Err(e) = message {
+				eprintln!("Debug server thread read error: {}", e);
+				break;
+			}
 
-				Err(e) => {
-					eprintln!("Debug server thread read error: {}", e);
-					break;
-				}
+			// Messages are delimited, so one we can't decode doesn't stop us reading the next
+			let request = match framing.decode_request(&buf[..]) {
+				Ok(request) => request,
+				Err(e) => Request::Unsupported {
+					error: e.to_string(),
+				},
 			};
 
-			match self.handle_request(&buf[..]) {
+			match self.handle_request(request) {
 				Ok(requested_disconnect) => {
 					if requested_disconnect {
 						eprintln!("Debug client disconnected");
@@ -1367,7 +4015,435 @@ impl ServerThread {
 				}
 			}
 		}
+	}
+}
 
-		eprintln!("Debug server thread finished");
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Write;
+	use std::net::{TcpListener, TcpStream};
+
+	// Connects and exchanges hellos, as clients without one are turned away
+	fn connect(addr: std::net::SocketAddr) -> TcpStream {
+		let mut client = TcpStream::connect(addr).unwrap();
+		client
+			.set_read_timeout(Some(Duration::from_secs(10)))
+			.unwrap();
+
+		let mut hello = [0u8; 12];
+		hello[0..4].copy_from_slice(&crate::framing::HELLO_MAGIC);
+		hello[4..8].copy_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+		client.write_all(&hello).unwrap();
+		client.read_exact(&mut hello).unwrap();
+
+		client
+	}
+
+	fn send(client: &mut TcpStream, request: &Request) {
+		let data = bincode::serialize(request).unwrap();
+		client.write_all(&(data.len() as u32).to_le_bytes()).unwrap();
+		client.write_all(&data).unwrap();
+	}
+
+	// Skips over the notifications explaining why a request got an empty answer
+	fn recv(client: &mut TcpStream) -> Response {
+		loop {
+			let mut len = [0u8; 4];
+			client.read_exact(&mut len).unwrap();
+
+			let mut data = vec![0u8; u32::from_le_bytes(len) as usize];
+			client.read_exact(&mut data).unwrap();
+
+			match bincode::deserialize(&data).unwrap() {
+				Response::Notification { .. } => continue,
+				response => return response,
+			}
+		}
+	}
+
+	fn handle(server: &mut Server, client: &mut TcpStream, request: Request) -> Response {
+		send(client, &request);
+		server.wait_for_connection(None);
+
+		let request = server
+			.requests
+			.recv_timeout(Duration::from_secs(10))
+			.unwrap();
+		server.handle_request(request);
+
+		recv(client)
+	}
+
+	#[test]
+	fn disassembly_ranges_snap_to_instructions() {
+		use dmasm::{DebugData, Instruction, Node};
+
+		let bytecode = [0; 8];
+		let nodes = vec![
+			Node::Instruction(
+				Instruction::DbgLine(1),
+				DebugData {
+					offset: 0,
+					bytecode: &bytecode[0..2],
+				},
+			),
+			Node::Label("LAB_0002".to_owned()),
+			Node::Instruction(
+				Instruction::DbgLine(2),
+				DebugData {
+					offset: 2,
+					bytecode: &bytecode[2..4],
+				},
+			),
+			Node::Label("LAB_0004".to_owned()),
+			Node::Instruction(
+				Instruction::Ret,
+				DebugData {
+					offset: 4,
+					bytecode: &bytecode[4..5],
+				},
+			),
+		];
+
+		// Offset 3 is in the middle of the second instruction
+		assert_eq!(nodes_in_range(&nodes, 3..4), &nodes[1..3]);
+		assert_eq!(nodes_in_range(&nodes, 2..5), &nodes[1..5]);
+		assert_eq!(nodes_in_range(&nodes, 0..2), &nodes[0..1]);
+		assert_eq!(nodes_in_range(&nodes, 0..100), &nodes[..]);
+		assert!(nodes_in_range(&nodes, 5..10).is_empty());
+	}
+
+	#[test]
+	fn variables_refs_remember_their_pause() {
+		let newest = REF_GENERATION_MASK;
+		let reference = make_ref(newest, REF_INDEX_MASK as usize - 1);
+		assert!(reference.0 > 0);
+		assert_eq!(split_ref(&reference), Some((newest, REF_INDEX_MASK as usize - 1)));
+
+		let reference = make_ref(3, 0);
+		assert_eq!(split_ref(&reference), Some((3, 0)));
+		assert_ne!(reference, make_ref(4, 0));
+
+		assert_eq!(split_ref(&VariablesRef(0)), None);
+		assert_eq!(split_ref(&VariablesRef(-1)), None);
+	}
+
+	#[test]
+	fn paused_only_requests_are_answered_while_running() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		let mut server = Server::listen_on(Listener::Tcp(listener), None).unwrap();
+
+		let mut client = connect(addr);
+
+		let response = handle(&mut server, &mut client, Request::Stacks);
+		assert!(matches!(response, Response::Stacks { stacks } if stacks.is_empty()));
+
+		let request = Request::StackFrames {
+			stack_id: 0,
+			start_frame: None,
+			count: None,
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::StackFrames { frames, .. } if frames.is_empty()));
+
+		let response = handle(&mut server, &mut client, Request::Scopes { frame_id: 0 });
+		assert!(matches!(
+			response,
+			Response::Scopes {
+				arguments: None,
+				locals: None,
+				globals: None,
+			}
+		));
+
+		// References from an earlier pause, or made up entirely
+		for vars in vec![VariablesRef(0), VariablesRef(1)] {
+			let request = Request::Variables { vars };
+			let response = handle(&mut server, &mut client, request);
+			assert!(matches!(response, Response::Variables { vars } if vars.is_empty()));
+		}
+
+		let request = Request::CurrentInstruction { frame_id: 0 };
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::CurrentInstruction(None)));
+
+		let request = Request::SetVariable {
+			frame_id: 0,
+			scope: VariableScope::Locals,
+			name: "x".to_owned(),
+			value: "1".to_owned(),
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::SetVariableResult { success: false, .. }));
+
+		let request = Request::SetVariable {
+			frame_id: 0,
+			scope: VariableScope::Frame,
+			name: "x".to_owned(),
+			value: "/datum".to_owned(),
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::SetVariableResult { success: false, .. }));
+
+		let request = Request::GotoLine {
+			frame_id: 0,
+			line: 1,
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::GotoLineResult { offset: None, error: Some(_) }));
+
+		let request = Request::HotPatch {
+			proc: ProcRef {
+				path: "/proc/missing".to_owned(),
+				override_id: 0,
+			},
+			new_source: "Ret".to_owned(),
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::HotPatchResult { success: false, error: Some(_) }));
+
+		let request = Request::HotPatchRevert {
+			proc: ProcRef {
+				path: "/proc/missing".to_owned(),
+				override_id: 0,
+			},
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::HotPatchResult { success: false, error: Some(_) }));
+
+		let request = Request::Disassemble {
+			proc: ProcRef {
+				path: "/proc/missing".to_owned(),
+				override_id: 0,
+			},
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(
+			matches!(response, Response::Disassembly { instructions } if instructions.is_empty())
+		);
+
+		let request = Request::ProfilerStop { folded_path: None };
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::ProfileData { error: Some(_), .. }));
+
+		let request = Request::ProfilerStart {
+			mode: ProfilerMode::Calls,
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::ProfilerStartResult { success: true, error: None }));
+
+		let request = Request::ProfilerStart {
+			mode: ProfilerMode::Sampling { interval_ms: 1 },
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::ProfilerStartResult { success: false, error: Some(_) }));
+
+		// Nothing ran, so nothing was called
+		let request = Request::ProfilerStop { folded_path: None };
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::ProfileData { procs, error: None } if procs.is_empty()));
+
+		let request = Request::CoverageStop { lcov_path: None };
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::CoverageData { error: Some(_), .. }));
+
+		let request = Request::FindReferences {
+			target: VariablesRef(1),
+			limit: 10,
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::FindReferences { complete: false, error: Some(_), .. }));
+
+		let response = handle(&mut server, &mut client, Request::FindReferencesCancel);
+		assert!(matches!(response, Response::Ack));
+
+		let request = Request::FunctionBreakpointSet {
+			proc: ProcRef {
+				path: "/proc/missing".to_owned(),
+				override_id: 0,
+			},
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::FunctionBreakpointSet { result: BreakpointSetResult::Failed }));
+
+		let request = Request::Continue {
+			kind: ContinueKind::Continue,
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::Ack));
+
+		let request = Request::Continue {
+			kind: ContinueKind::RunTo {
+				proc: ProcRef {
+					path: "/proc/missing".to_owned(),
+					override_id: 0,
+				},
+				offset: 0,
+			},
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::Ack));
+		assert!(server.run_to.is_none());
+
+		let request = Request::StackOnBreakpoint { enabled: false };
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::Ack));
+		assert!(!server.stack_on_breakpoint);
+
+		// Still answering after all that
+		let request = Request::StackOnBreakpoint { enabled: true };
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::Ack));
+	}
+	#[test]
+	fn breakpoints_command_lists_and_clears() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		let mut server = Server::listen_on(Listener::Tcp(listener), None).unwrap();
+
+		let mut client = connect(addr);
+
+		let request = Request::StackOnBreakpoint { enabled: true };
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::Ack));
+
+		assert_eq!(server.handle_command(None, "breakpoints"), "No breakpoints");
+
+		// Set by the client on a proc that has since gone away
+		let instruction = InstructionRef {
+			proc: ProcRef {
+				path: "/proc/gone".to_owned(),
+				override_id: 0,
+			},
+			offset: 12,
+		};
+		server.breakpoints.insert(instruction.clone(), None);
+
+		let listing = server.handle_command(None, "breakpoints");
+		assert!(listing.starts_with("/proc/gone #0"));
+		assert!(listing.contains("offset 12"));
+		assert!(listing.ends_with("not installed, proc not found"));
+
+		assert_eq!(
+			server.handle_command(None, "breakpoints clear"),
+			"Removed 0 breakpoints"
+		);
+		assert!(server.breakpoints.is_empty());
+		assert!(matches!(
+			recv(&mut client),
+			Response::BreakpointRemoved { instruction: removed } if removed == instruction
+		));
+	}
+
+	#[test]
+	fn clients_need_the_token() {
+		let connect = |token: &str| {
+			let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+			let addr = listener.local_addr().unwrap();
+			let server =
+				Server::listen_on(Listener::Tcp(listener), Some(token.to_owned())).unwrap();
+
+			let client = connect(addr);
+
+			(server, client)
+		};
+
+		let (mut server, mut client) = connect("secret");
+		let request = Request::Authenticate {
+			token: "guess".to_owned(),
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::AuthFailed));
+		assert!(!server.authenticated);
+
+		let (mut server, mut client) = connect("secret");
+		let request = Request::StackOnBreakpoint { enabled: true };
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::AuthFailed));
+
+		let (mut server, mut client) = connect("secret");
+		let request = Request::Authenticate {
+			token: "secret".to_owned(),
+		};
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::Ack));
+
+		let request = Request::StackOnBreakpoint { enabled: true };
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::Ack));
+	}
+
+	#[test]
+	fn silent_clients_dont_hold_up_the_next() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		let mut server = Server::listen_on(Listener::Tcp(listener), None).unwrap();
+
+		// Never sends its handshake, so it's dropped after HANDSHAKE_TIMEOUT
+		let _silent = TcpStream::connect(addr).unwrap();
+
+		let mut client = connect(addr);
+
+		let request = Request::StackOnBreakpoint { enabled: true };
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::Ack));
+	}
+
+	#[test]
+	fn quiet_clients_are_pinged_then_dropped() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		let mut server = Server::listen_on(Listener::Tcp(listener), None).unwrap();
+		server.set_keep_alive(Duration::from_millis(200), Duration::from_millis(200));
+
+		let mut client = connect(addr);
+
+		let request = Request::StackOnBreakpoint { enabled: true };
+		let response = handle(&mut server, &mut client, request);
+		assert!(matches!(response, Response::Ack));
+
+		// The keep-alive is only looked at every so often, as process() runs before every instruction
+		let process = |server: &mut Server| {
+			for _ in 0..KEEP_ALIVE_INTERVAL {
+				server.process();
+			}
+		};
+
+		// Answering the ping keeps the client around
+		thread::sleep(Duration::from_millis(300));
+		process(&mut server);
+		assert!(matches!(recv(&mut client), Response::Ping));
+
+		send(&mut client, &Request::Pong);
+		thread::sleep(Duration::from_millis(100));
+		process(&mut server);
+		assert!(server.is_connected());
+
+		// Staying quiet doesn't
+		thread::sleep(Duration::from_millis(300));
+		process(&mut server);
+		assert!(matches!(recv(&mut client), Response::Ping));
+
+		thread::sleep(Duration::from_millis(300));
+		process(&mut server);
+		assert!(!server.is_connected());
+
+		// Nobody else connects, so this gives up instead of blocking
+		assert!(!server.process_until_configured_timeout(Duration::from_millis(100)));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn unix_socket_is_removed_on_drop() {
+		let path = std::env::temp_dir().join(format!("auxtools-test-{}.sock", std::process::id()));
+
+		let server = Server::listen_unix(&path, None).unwrap();
+		assert!(path.exists());
+		assert!(std::os::unix::net::UnixStream::connect(&path).is_ok());
+
+		drop(server);
+		assert!(!path.exists());
 	}
 }
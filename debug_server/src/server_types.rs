@@ -5,6 +5,22 @@ use serde::{Deserialize, Serialize};
 #[allow(dead_code)]
 pub const DEFAULT_PORT: u16 = 2448;
 
+// Exchanged in the hello at the start of every connection. Bump this whenever Request or Response
+// change in a way that existing clients can't decode.
+//
+// 1: Bincode clients that don't send the hello, which all predate it
+// 2: BreakpointSetResult::Success has the offset
+// 3: CatchRuntimes has procs
+//    Response::LineNumber has file
+//    Response::BreakpointHit has stack
+//    Stack has wake_time
+//    StackFrame has source_file
+//    Variable has value_type
+//    Request::RuntimeFilters moved to the end, after every request version 1 had
+//    Response::InstanceCensus has approximate
+//    Request::Ping and Response::Pong removed, moving everything after them up
+pub const PROTOCOL_VERSION: u32 = 3;
+
 // Message from client -> server
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Request {
@@ -26,8 +42,10 @@ pub enum Request {
 	BreakpointUnset {
 		instruction: InstructionRef,
 	},
+	// `procs` limits catching to runtimes thrown by matching procs (see RuntimeFilters), None means all of them
 	CatchRuntimes {
 		should_catch: bool,
+		procs: Option<Vec<String>>,
 	},
 	LineNumber {
 		proc: ProcRef,
@@ -53,6 +71,123 @@ pub enum Request {
 		kind: ContinueKind,
 	},
 	Pause,
+	SetVariable {
+		frame_id: u32,
+		scope: VariableScope,
+		name: String,
+		value: String,
+	},
+
+	// Clients don't send this. The ServerThread hands it to the Server in place of a message it
+	// couldn't decode (probably a request from a newer client) so that it can be answered.
+	Unsupported {
+		error: String,
+	},
+
+	// Servers started with a token ignore everything else until this arrives with a matching token.
+	// Answered with an Ack, or an AuthFailed and a disconnect if the token is wrong.
+	Authenticate {
+		token: String,
+	},
+
+	// Whether BreakpointHit should include the active stack. Enabled for every new connection.
+	StackOnBreakpoint {
+		enabled: bool,
+	},
+
+	// Reboots the world. Answered with an Ack before rebooting. The server's breakpoints are put back
+	// once the new world enables debugging again.
+	Restart,
+
+	// Moves the paused proc to the start of `line` so it runs from there when execution continues.
+	// Only the innermost frame of the active stack can jump, and only within its own proc.
+	GotoLine {
+		frame_id: u32,
+		line: u32,
+	},
+
+	// Only one profiler can run at a time. Answered with ProfilerStartResult.
+	ProfilerStart {
+		mode: ProfilerMode,
+	},
+
+	// Answered with ProfileData. With `folded_path`, the profile is also written there as collapsed stacks
+	// for flamegraph tools like inferno.
+	ProfilerStop {
+		folded_path: Option<String>,
+	},
+
+	// Records which lines of every proc run, hooking each line until it first runs. Answered with CoverageStartResult.
+	CoverageStart,
+
+	// Answered with CoverageData. With `lcov_path`, the lines are also written there as an lcov tracefile.
+	CoverageStop {
+		lcov_path: Option<String>,
+	},
+
+	// Counts live objects by type. A census is only carried on for a moment at a time, so this has to be sent
+	// again until the InstanceCensus response says it's done.
+	InstanceCensus {
+		top: u32,
+	},
+
+	// Breaks whenever the proc is entered, by hooking its first instruction past the debug info. The breakpoint
+	// is removed with BreakpointUnset, using the offset from the response.
+	FunctionBreakpointSet {
+		proc: ProcRef,
+	},
+
+	// Looks for up to `limit` objects and lists that hold a reference to the object or list `target` is the
+	// variables of. Only works while paused. The search carries on in between other requests, with progress
+	// sent as notifications, and is answered with a FindReferences response once it's done.
+	FindReferences {
+		target: VariablesRef,
+		limit: u32,
+	},
+	// Stops a FindReferences search early, which then answers with what it found so far. Answered with Ack.
+	FindReferencesCancel,
+
+	// Sizes up the string table, along with up to `limit` strings matching `pattern`. Patterns work like those
+	// of RuntimeFilters, and an empty one matches nothing.
+	StringSearch {
+		pattern: String,
+		limit: u32,
+	},
+
+	// Lists the procs whose path matches `filter`, in the same way as the patterns of RuntimeFilters, or every
+	// proc without one. `offset` and `limit` pick out a page of them.
+	ListProcs {
+		filter: Option<String>,
+		offset: u32,
+		limit: Option<u32>,
+	},
+
+	// Replaces the proc's bytecode with `new_source`, in the same assembly #disassemble shows. The bytecode it
+	// had before the first patch is kept until HotPatchRevert. Answered with HotPatchResult.
+	HotPatch {
+		proc: ProcRef,
+		new_source: String,
+	},
+
+	// Puts back the bytecode a proc had before it was hot patched. Answered with HotPatchResult.
+	HotPatchRevert {
+		proc: ProcRef,
+	},
+
+	// Answers a Response::Ping from a server with keep-alive enabled
+	Pong,
+
+	// The proc's instructions one by one, for clients that show disassembly themselves. Answered with
+	// Disassembly, which is empty if the proc doesn't exist.
+	Disassemble {
+		proc: ProcRef,
+	},
+
+	// Patterns matched against the message and proc path of caught runtimes, see CatchRuntimes
+	RuntimeFilters {
+		include: Vec<String>,
+		exclude: Vec<String>,
+	},
 }
 
 // Message from server -> client
@@ -70,6 +205,8 @@ pub enum Response {
 	},
 	LineNumber {
 		line: Option<u32>,
+		// The file the line is in, as passed to the compiler. None for procs without debug info.
+		file: Option<String>,
 	},
 	Offset {
 		offset: Option<u32>,
@@ -96,8 +233,115 @@ pub enum Response {
 	Notification {
 		message: String,
 	},
+	// `stack` holds every frame of the active stack, unless the client turned it off with StackOnBreakpoint
 	BreakpointHit {
 		reason: BreakpointReason,
+		stack: Option<Vec<StackFrame>>,
+	},
+
+	// Sent after Configured for every breakpoint that was set by a previous client and is still installed
+	BreakpointRestored {
+		instruction: InstructionRef,
+		line: Option<u32>,
+		condition: Option<String>,
+	},
+
+	SetVariableResult {
+		success: bool,
+		new_value: String,
+	},
+
+	// Reply to a request the server didn't understand. The connection stays open.
+	Unsupported {
+		error: String,
+	},
+
+	// Sent when a breakpoint the client set is removed by something other than BreakpointUnset, like `#breakpoints clear`
+	BreakpointRemoved {
+		instruction: InstructionRef,
+	},
+
+	// `offset` is where execution will continue from, or None if the jump failed because of `error`
+	GotoLineResult {
+		offset: Option<u32>,
+		error: Option<String>,
+	},
+
+	ProfilerStartResult {
+		success: bool,
+		error: Option<String>,
+	},
+
+	// `procs` is empty if the profiler wasn't running. `error` also covers failing to write the folded stacks.
+	ProfileData {
+		procs: Vec<ProfiledProc>,
+		error: Option<String>,
+	},
+
+	CoverageStartResult {
+		success: bool,
+		error: Option<String>,
+	},
+
+	// Both counts are 0 if coverage wasn't running. `error` also covers failing to write the tracefile.
+	CoverageData {
+		lines_found: u32,
+		lines_hit: u32,
+		error: Option<String>,
+	},
+
+	// `entries` holds the `top` most common types once `done`, and is empty until then. `approximate` is set when
+	// objects could have been missed, as the walk has to guess where BYOND's object tables end.
+	InstanceCensus {
+		done: bool,
+		counted: u32,
+		entries: Vec<CensusEntry>,
+		approximate: bool,
+	},
+
+	// `line` in a successful result is the line the proc's code starts on
+	FunctionBreakpointSet {
+		result: BreakpointSetResult,
+	},
+
+	// Each holder is named after the variable or list index the reference is in, and can be expanded.
+	// `complete` is false if the search was cancelled or failed.
+	FindReferences {
+		holders: Vec<Variable>,
+		searched: u32,
+		complete: bool,
+		error: Option<String>,
+	},
+
+	// `total_matches` also counts the matches past the limit
+	StringSearch {
+		entries: u32,
+		capacity: u32,
+		approximate_bytes: u64,
+		matches: Vec<StringMatch>,
+		total_matches: u32,
+	},
+
+	// `total` is how many procs matched, for paging through the rest
+	ProcList {
+		procs: Vec<ProcRef>,
+		total: u32,
+	},
+
+	// Sent right before the server disconnects a client that gave the wrong token, or none at all
+	AuthFailed,
+
+	// Servers with keep-alive enabled send this when the client has been quiet for a while. Clients have to
+	// answer with a Request::Pong (or any other request) in time to stay connected.
+	Ping,
+
+	HotPatchResult {
+		success: bool,
+		error: Option<String>,
+	},
+
+	Disassembly {
+		instructions: Vec<DisassembledInstruction>,
 	},
 }
 
@@ -127,12 +371,50 @@ pub enum ContinueKind {
 	StepOver { stack_id: u32 },
 	StepInto { stack_id: u32 },
 	StepOut { stack_id: u32 },
+	// Continues until the instruction at `offset` runs, or until anything else pauses execution first
+	RunTo { proc: ProcRef, offset: u32 },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ProfilerMode {
+	// Looks at what's executing every `interval_ms` from another thread
+	Sampling { interval_ms: u64 },
+	// Times every proc call. Slows down everything that runs while profiling.
+	Calls,
+}
+
+// Sampling profiles only fill in `samples`, call profiles everything else
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProfiledProc {
+	pub proc: ProcRef,
+	pub samples: u64,
+	pub calls: u64,
+	pub inclusive_micros: u64,
+	pub exclusive_micros: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CensusEntry {
+	pub type_path: String,
+	pub count: u32,
+	// How many more there are than in the last census, None for the first one
+	pub delta: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StringMatch {
+	pub id: u32,
+	// Cut short if it's very long
+	pub value: String,
+	pub ref_count: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Stack {
 	pub id: u32,
 	pub name: String,
+	// The world.time a sleeping stack wakes up at. None for the active stack.
+	pub wake_time: Option<f32>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -140,24 +422,51 @@ pub struct StackFrame {
 	pub id: u32,
 	pub instruction: InstructionRef,
 	pub line: Option<u32>,
+	// The .dm file the frame's current line is in, relative to the .dme
+	pub source_file: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum BreakpointSetResult {
-	Success { line: Option<u32> },
+	// `offset` is where the breakpoint actually went, which the client should move its marker to
+	Success { line: Option<u32>, offset: u32 },
 	Failed,
 }
 
 #[derive(Clone, Hash, Eq, PartialEq, Serialize, Deserialize, Debug)]
 pub struct VariablesRef(pub i32);
 
+// Where a variable being set lives
+#[derive(Serialize, Deserialize, Debug)]
+pub enum VariableScope {
+	Arguments,
+	Locals,
+	// The variables of an object previously sent to the client
+	Object(VariablesRef),
+	// Whichever of the frame's locals, arguments or src's variables has the name, looked for in that order
+	Frame,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Variable {
 	pub name: String,
 	pub value: String,
+	// "null", "number", "string", or the type path of an object or list. None for anything else.
+	pub value_type: Option<String>,
 	pub variables: Option<VariablesRef>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DisassembledInstruction {
+	pub offset: u32,
+	// The bytecode words the instruction takes up, starting with its opcode
+	pub bytes: Vec<u32>,
+	// The instruction's name, like "DbgLine"
+	pub mnemonic: String,
+	// Each operand as #disassemble --operands lists it. Constant values are one operand but two words.
+	pub operands: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EvalResponse {
 	pub value: String,
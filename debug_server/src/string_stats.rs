@@ -0,0 +1,84 @@
+//! Looking through BYOND's string table, for finding out what's filling it up.
+//!
+//! Worlds that build lots of unique strings, like ones with numbers or refs baked in, keep every one of them
+//! around for as long as anything refers to it, so the table can grow to take up a lot of memory.
+
+use std::mem::size_of;
+
+use auxtools::raw_types::strings::StringEntry;
+use auxtools::string_table;
+
+use crate::runtime_filters::pattern_matches;
+use crate::server_types::StringMatch;
+
+// Matching strings longer than this are cut short, so one huge string can't blow up a response
+const MAX_MATCH_LENGTH: usize = 200;
+
+pub struct StringStats {
+	pub entries: u32,
+	pub capacity: u32,
+	// Roughly how much memory the table takes up: each entry, its contents and its slot in the table
+	pub approximate_bytes: u64,
+	// Up to `limit` of the strings that matched, by id
+	pub matches: Vec<StringMatch>,
+	// How many strings matched, including those past `limit`
+	pub total_matches: u32,
+}
+
+/// Looks through every string, gathering up to `limit` of those matching `pattern`. Patterns are matched like
+/// runtime filters: a glob with `*` or `?`, a substring otherwise. An empty pattern only gathers the stats.
+pub fn search(pattern: &str, limit: usize) -> StringStats {
+	let capacity = string_table::capacity();
+	let mut stats = StringStats {
+		entries: 0,
+		capacity,
+		approximate_bytes: capacity as u64 * size_of::<*mut StringEntry>() as u64,
+		matches: vec![],
+		total_matches: 0,
+	};
+
+	for (id, value) in string_table::iter() {
+		stats.entries += 1;
+		stats.approximate_bytes += (size_of::<StringEntry>() + value.len() + 1) as u64;
+
+		if pattern.is_empty() || !pattern_matches(pattern, &value) {
+			continue;
+		}
+
+		stats.total_matches += 1;
+		if stats.matches.len() < limit {
+			stats.matches.push(StringMatch {
+				id,
+				ref_count: string_table::ref_count(id).unwrap_or(0),
+				value: shorten(value),
+			});
+		}
+	}
+
+	stats
+}
+
+fn shorten(value: String) -> String {
+	match value.char_indices().nth(MAX_MATCH_LENGTH) {
+		Some((end, _)) => format!("{}...", &value[..end]),
+		None => value,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn shortens_long_strings() {
+		assert_eq!(shorten("short".to_owned()), "short");
+
+		let long = "é".repeat(MAX_MATCH_LENGTH + 10);
+		let shortened = shorten(long);
+		assert!(shortened.ends_with("..."));
+		assert_eq!(shortened.chars().count(), MAX_MATCH_LENGTH + 3);
+
+		let exact = "x".repeat(MAX_MATCH_LENGTH);
+		assert_eq!(shorten(exact.clone()), exact);
+	}
+}
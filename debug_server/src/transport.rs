@@ -0,0 +1,335 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+
+// A connection to a debug client, whatever it happens to be carried over
+pub trait Connection: Read + Write + Send {
+	// Both halves refer to the same connection, so shutting one down ends the other
+	fn try_clone(&self) -> io::Result<Stream>;
+
+	// Reads that time out fail with ErrorKind::TimedOut or ErrorKind::WouldBlock
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+	// Closes the connection, including for anyone blocked reading from it
+	fn shutdown(&self);
+}
+
+pub type Stream = Box<dyn Connection>;
+
+impl Connection for TcpStream {
+	fn try_clone(&self) -> io::Result<Stream> {
+		Ok(Box::new(TcpStream::try_clone(self)?))
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		TcpStream::set_read_timeout(self, timeout)
+	}
+
+	fn shutdown(&self) {
+		let _ = TcpStream::shutdown(self, std::net::Shutdown::Both);
+	}
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+	fn try_clone(&self) -> io::Result<Stream> {
+		Ok(Box::new(UnixStream::try_clone(self)?))
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		UnixStream::set_read_timeout(self, timeout)
+	}
+
+	fn shutdown(&self) {
+		let _ = UnixStream::shutdown(self, std::net::Shutdown::Both);
+	}
+}
+
+// Where a listening server accepts its clients
+pub enum Listener {
+	Tcp(TcpListener),
+
+	#[cfg(unix)]
+	Unix(UnixListener),
+
+	#[cfg(windows)]
+	Pipe(pipe::PipeListener),
+}
+
+impl Listener {
+	// Only the current user can connect to the socket.
+	// A socket file left behind by a server that's no longer running gets replaced.
+	#[cfg(unix)]
+	pub fn bind_unix(path: &Path) -> io::Result<Self> {
+		use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+		if let Ok(metadata) = std::fs::symlink_metadata(path) {
+			if !metadata.file_type().is_socket() {
+				return Err(io::Error::new(
+					io::ErrorKind::AlreadyExists,
+					format!("{} exists and isn't a socket", path.display()),
+				));
+			}
+
+			if UnixStream::connect(path).is_ok() {
+				return Err(io::Error::new(
+					io::ErrorKind::AddrInUse,
+					format!("{} is already being listened on", path.display()),
+				));
+			}
+
+			std::fs::remove_file(path)?;
+		}
+
+		let listener = UnixListener::bind(path)?;
+		std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+		Ok(Listener::Unix(listener))
+	}
+
+	// Names without the \\.\pipe\ prefix get it added. Remote clients are rejected.
+	#[cfg(windows)]
+	pub fn bind_pipe(name: &str) -> io::Result<Self> {
+		Ok(Listener::Pipe(pipe::PipeListener::bind(name)?))
+	}
+
+	pub fn accept(&mut self) -> io::Result<Stream> {
+		match self {
+			Listener::Tcp(listener) => Ok(Box::new(listener.accept()?.0)),
+
+			#[cfg(unix)]
+			Listener::Unix(listener) => Ok(Box::new(listener.accept()?.0)),
+
+			#[cfg(windows)]
+			Listener::Pipe(listener) => Ok(Box::new(listener.accept()?)),
+		}
+	}
+}
+
+#[cfg(windows)]
+mod pipe {
+	use super::{Connection, Stream};
+	use std::ffi::OsStr;
+	use std::io::{self, Read, Write};
+	use std::os::windows::ffi::OsStrExt;
+	use std::sync::{Arc, Mutex};
+	use std::time::Duration;
+
+	use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+	use winapi::shared::winerror::{ERROR_IO_PENDING, ERROR_PIPE_CONNECTED, WAIT_TIMEOUT};
+	use winapi::um::errhandlingapi::GetLastError;
+	use winapi::um::fileapi::{ReadFile, WriteFile};
+	use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+	use winapi::um::ioapiset::{CancelIoEx, GetOverlappedResult};
+	use winapi::um::minwinbase::OVERLAPPED;
+	use winapi::um::namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe};
+	use winapi::um::synchapi::{CreateEventW, WaitForSingleObject};
+	use winapi::um::winbase::{
+		FILE_FLAG_FIRST_PIPE_INSTANCE, FILE_FLAG_OVERLAPPED, INFINITE, PIPE_ACCESS_DUPLEX,
+		PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES,
+		PIPE_WAIT,
+	};
+	use winapi::um::winnt::HANDLE;
+
+	const BUFFER_SIZE: DWORD = 64 * 1024;
+
+	struct Handle(HANDLE);
+
+	// Only ever used through the Win32 API, which doesn't care about threads
+	unsafe impl Send for Handle {}
+	unsafe impl Sync for Handle {}
+
+	impl Drop for Handle {
+		fn drop(&mut self) {
+			unsafe {
+				CloseHandle(self.0);
+			}
+		}
+	}
+
+	pub struct PipeListener {
+		name: Vec<u16>,
+
+		// Created ahead of time so the name is ours from bind onwards
+		next: Option<Handle>,
+	}
+
+	impl PipeListener {
+		pub fn bind(name: &str) -> io::Result<Self> {
+			let name = if name.starts_with(r"\\.\pipe\") {
+				name.to_owned()
+			} else {
+				format!(r"\\.\pipe\{}", name)
+			};
+
+			let name: Vec<u16> = OsStr::new(&name).encode_wide().chain(Some(0)).collect();
+
+			// Fails if someone else already owns the name, so nobody can sit in front of us
+			let first = Self::create_instance(&name, FILE_FLAG_FIRST_PIPE_INSTANCE)?;
+
+			Ok(PipeListener {
+				name,
+				next: Some(first),
+			})
+		}
+
+		fn create_instance(name: &[u16], flags: DWORD) -> io::Result<Handle> {
+			let handle = unsafe {
+				CreateNamedPipeW(
+					name.as_ptr(),
+					PIPE_ACCESS_DUPLEX | FILE_FLAG_OVERLAPPED | flags,
+					PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+					PIPE_UNLIMITED_INSTANCES,
+					BUFFER_SIZE,
+					BUFFER_SIZE,
+					0,
+					std::ptr::null_mut(),
+				)
+			};
+
+			if handle == INVALID_HANDLE_VALUE {
+				return Err(io::Error::last_os_error());
+			}
+
+			Ok(Handle(handle))
+		}
+
+		pub fn accept(&mut self) -> io::Result<PipeStream> {
+			let handle = match self.next.take() {
+				Some(handle) => handle,
+				None => Self::create_instance(&self.name, 0)?,
+			};
+
+			let connected = overlapped(&handle, None, |overlapped| unsafe {
+				ConnectNamedPipe(handle.0, overlapped)
+			});
+
+			match connected {
+				Ok(_) => {}
+
+				// The client showed up between creating the pipe and waiting for it
+				Err(e) if e.raw_os_error() == Some(ERROR_PIPE_CONNECTED as i32) => {}
+
+				Err(e) => return Err(e),
+			}
+
+			Ok(PipeStream {
+				inner: Arc::new(PipeInner {
+					handle,
+					read_timeout: Mutex::new(None),
+				}),
+			})
+		}
+	}
+
+	struct PipeInner {
+		handle: Handle,
+		read_timeout: Mutex<Option<Duration>>,
+	}
+
+	// The pipe is opened for overlapped IO, as otherwise a blocked read would also block any writes
+	pub struct PipeStream {
+		inner: Arc<PipeInner>,
+	}
+
+	// Starts an IO operation and waits for it to finish, cancelling it if it takes longer than `timeout`
+	fn overlapped<F: FnOnce(*mut OVERLAPPED) -> BOOL>(
+		handle: &Handle,
+		timeout: Option<Duration>,
+		start: F,
+	) -> io::Result<usize> {
+		unsafe {
+			let event = CreateEventW(std::ptr::null_mut(), TRUE, FALSE, std::ptr::null());
+			if event.is_null() {
+				return Err(io::Error::last_os_error());
+			}
+			let event = Handle(event);
+
+			let mut overlapped: OVERLAPPED = std::mem::zeroed();
+			overlapped.hEvent = event.0;
+
+			if start(&mut overlapped) == FALSE && GetLastError() != ERROR_IO_PENDING {
+				return Err(io::Error::last_os_error());
+			}
+
+			let wait = timeout.map_or(INFINITE, |timeout| timeout.as_millis() as DWORD);
+			let timed_out = WaitForSingleObject(event.0, wait) == WAIT_TIMEOUT;
+			if timed_out {
+				CancelIoEx(handle.0, &mut overlapped);
+			}
+
+			// Waits for the cancellation too, as the operation owns `overlapped` until it's over
+			let mut transferred: DWORD = 0;
+			if GetOverlappedResult(handle.0, &mut overlapped, &mut transferred, TRUE) == FALSE {
+				if timed_out {
+					return Err(io::ErrorKind::TimedOut.into());
+				}
+
+				return Err(io::Error::last_os_error());
+			}
+
+			Ok(transferred as usize)
+		}
+	}
+
+	impl Read for PipeStream {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			let timeout = *self.inner.read_timeout.lock().unwrap();
+			let handle = &self.inner.handle;
+
+			overlapped(handle, timeout, |overlapped| unsafe {
+				ReadFile(
+					handle.0,
+					buf.as_mut_ptr() as _,
+					buf.len().min(DWORD::MAX as usize) as DWORD,
+					std::ptr::null_mut(),
+					overlapped,
+				)
+			})
+		}
+	}
+
+	impl Write for PipeStream {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			let handle = &self.inner.handle;
+
+			overlapped(handle, None, |overlapped| unsafe {
+				WriteFile(
+					handle.0,
+					buf.as_ptr() as _,
+					buf.len().min(DWORD::MAX as usize) as DWORD,
+					std::ptr::null_mut(),
+					overlapped,
+				)
+			})
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	impl Connection for PipeStream {
+		fn try_clone(&self) -> io::Result<Stream> {
+			Ok(Box::new(PipeStream {
+				inner: self.inner.clone(),
+			}))
+		}
+
+		fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+			*self.inner.read_timeout.lock().unwrap() = timeout;
+			Ok(())
+		}
+
+		fn shutdown(&self) {
+			unsafe {
+				DisconnectNamedPipe(self.inner.handle.0);
+				CancelIoEx(self.inner.handle.0, std::ptr::null_mut());
+			}
+		}
+	}
+}
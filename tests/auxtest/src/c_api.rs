@@ -0,0 +1,70 @@
+use auxtools::c_api::*;
+use auxtools::*;
+use std::ffi::CString;
+
+fn to_c(value: &Value) -> AuxtoolsValue {
+	AuxtoolsValue {
+		tag: value.raw.tag as u32,
+		data: unsafe { value.raw.data.id },
+	}
+}
+
+#[hook("/proc/auxtest_c_api")]
+fn test_c_api() {
+	let world = to_c(&Value::world());
+	let maxx = CString::new("maxx").unwrap();
+	let name = CString::new("name").unwrap();
+
+	let mut number = -1.0;
+	if !unsafe { auxtools_value_get_float(world.tag, world.data, maxx.as_ptr(), &mut number) }
+		|| number != Value::world().get_number(byond_string!("maxx"))?
+	{
+		return Err(runtime!(
+			"test_c_api: get_float(world.maxx) gave {}",
+			number
+		));
+	}
+
+	let old_name = Value::world().get(byond_string!("name"))?;
+	let new_name = CString::new("auxtest_c_api").unwrap();
+	if !unsafe {
+		auxtools_value_set_string(world.tag, world.data, name.as_ptr(), new_name.as_ptr())
+	} {
+		return Err(runtime!("test_c_api: set_string(world.name) failed"));
+	}
+	let set_name = Value::world().get_string(byond_string!("name"))?;
+	Value::world().set(byond_string!("name"), old_name)?;
+
+	if set_name != "auxtest_c_api" {
+		return Err(runtime!("test_c_api: world.name is {:?}", set_name));
+	}
+
+	let a = Value::from_string("a")?;
+	let b = Value::from_string("b")?;
+	let args = [to_c(&a), to_c(&b)];
+	let path = CString::new("/proc/concat_strings").unwrap();
+	let mut ret = to_c(&Value::null());
+
+	if !unsafe { auxtools_proc_call(path.as_ptr(), 2, args.as_ptr(), &mut ret) } {
+		return Err(runtime!("test_c_api: proc_call(concat_strings) failed"));
+	}
+
+	// concat_strings returns a string, so it can be read before the reference is given back
+	if ret.tag != raw_types::values::ValueTag::String as u32 {
+		unsafe { auxtools_value_release(ret) };
+		return Err(runtime!(
+			"test_c_api: concat_strings returned tag {}",
+			ret.tag
+		));
+	}
+
+	let result: String =
+		unsafe { StringRef::from_id(raw_types::strings::StringId(ret.data)) }.into();
+	unsafe { auxtools_value_release(ret) };
+
+	if result != "ab" {
+		return Err(runtime!("test_c_api: concat_strings returned {:?}", result));
+	}
+
+	Ok(Value::from(true))
+}
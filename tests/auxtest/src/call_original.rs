@@ -0,0 +1,42 @@
+use auxtools::*;
+
+const PATH: &str = "/proc/auxtest_damage";
+
+fn clamp_damage(src: &Value, usr: &Value, args: &mut Vec<Value>) -> DMResult {
+	if let Some(amount) = args.get(0) {
+		args[0] = Value::from(amount.as_number()?.min(100.0));
+	}
+
+	hooks::call_original(src, usr, args)
+}
+
+#[hook("/proc/auxtest_call_original")]
+fn test_call_original() {
+	hooks::hook(PATH, clamp_damage)
+		.map_err(|e| runtime!("test_call_original: hooking failed: {:?}", e))?;
+
+	let proc = Proc::find(PATH).ok_or_else(|| runtime!("test_call_original: {} missing", PATH))?;
+
+	// Twice, to make sure the hook is still there after the original ran
+	for _ in 0..2 {
+		let damage = proc.call(&[&Value::from(150)])?.as_number()?;
+		if damage != 100.0 {
+			return Err(runtime!(
+				"test_call_original: the original saw {} instead of 100",
+				damage
+			));
+		}
+	}
+
+	let damage = proc.call(&[&Value::from(20)])?.as_number()?;
+	if damage != 20.0 {
+		return Err(runtime!(
+			"test_call_original: the original saw {} instead of 20",
+			damage
+		));
+	}
+
+	hooks::unhook_all_overrides(PATH);
+
+	Ok(Value::from(true))
+}
@@ -0,0 +1,50 @@
+use auxtools::*;
+
+const WITH_ARG: &str = "/proc/auxtest_call_stacks_with_arg";
+
+// Called by auxtest_call_stacks_with_arg, so the stacks are seen from inside a proc that has an argument
+#[hook("/proc/auxtest_call_stacks_json")]
+fn call_stacks_json() {
+	let json = serde_json::to_string(&debug::CallStacks::new())
+		.map_err(|e| runtime!("test_call_stacks: {}", e))?;
+
+	Value::from_string(json)
+}
+
+#[hook("/proc/auxtest_call_stacks")]
+fn test_call_stacks() {
+	let stacks = serde_json::to_value(&debug::CallStacks::new())
+		.map_err(|e| runtime!("test_call_stacks: {}", e))?;
+
+	// Hooks are never on BYOND's stack, so the top frame is whatever called us
+	if stacks["active"][0]["proc"] != "/do_tests" {
+		return Err(runtime!(
+			"test_call_stacks: top frame is {}",
+			stacks["active"][0]
+		));
+	}
+
+	if !stacks["suspended"].is_array() {
+		return Err(runtime!(
+			"test_call_stacks: no suspended stacks in {}",
+			stacks
+		));
+	}
+
+	let proc =
+		Proc::find(WITH_ARG).ok_or_else(|| runtime!("test_call_stacks: {} missing", WITH_ARG))?;
+	let json = proc.call(&[&Value::from_string("arg")?])?.as_string()?;
+	let stacks: serde_json::Value =
+		serde_json::from_str(&json).map_err(|e| runtime!("test_call_stacks: {}", e))?;
+
+	let frame = &stacks["active"][0];
+	if frame["proc"] != "/auxtest_call_stacks_with_arg" {
+		return Err(runtime!("test_call_stacks: top frame is {}", frame["proc"]));
+	}
+
+	if frame["args"][0]["name"] != "a" || frame["args"][0]["value"] != "arg" {
+		return Err(runtime!("test_call_stacks: args are {}", frame["args"]));
+	}
+
+	Ok(Value::from(true))
+}
@@ -0,0 +1,30 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_current_location")]
+fn test_current_location() {
+	let guard = game_thread::GameThreadGuard::acquire();
+
+	let proc = guard
+		.current_proc()
+		.ok_or_else(|| runtime!("test_current_location: no current proc"))?;
+
+	// Hooks aren't on BYOND's stack, so this is where we were called from
+	if proc.id != Proc::find("/proc/do_tests").unwrap().id {
+		return Err(runtime!(
+			"test_current_location: current proc is {:?}",
+			proc
+		));
+	}
+
+	// Both read the same context, so they have to agree
+	let frame_line = debug::CallStacks::new().active[0].line_number;
+	if guard.current_line() != frame_line {
+		return Err(runtime!(
+			"test_current_location: current line is {:?} but the top frame is on {:?}",
+			guard.current_line(),
+			frame_line
+		));
+	}
+
+	Ok(Value::from(true))
+}
@@ -0,0 +1,23 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_defer")]
+fn test_defer() {
+	let result = std::thread::spawn(|| {
+		runtime::defer(|| Proc::find("/proc/concat_strings").is_some())
+	})
+	.join()
+	.unwrap();
+
+	// Nothing runs until the main thread gets around to it
+	if result.try_recv().is_ok() {
+		return Err(runtime!("test_defer: deferred closure ran early"));
+	}
+
+	runtime::run_deferred();
+
+	match result.try_recv() {
+		Ok(true) => Ok(Value::from(true)),
+		Ok(false) => Err(runtime!("test_defer: deferred closure couldn't find proc")),
+		Err(_) => Err(runtime!("test_defer: deferred closure never ran")),
+	}
+}
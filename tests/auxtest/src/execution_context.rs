@@ -0,0 +1,44 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_execution_context")]
+fn test_execution_context() {
+	let guard = game_thread::GameThreadGuard::acquire();
+	let ctx = unsafe { debug::SafeExecutionContext::new(guard.current_execution_context()) }
+		.ok_or_else(|| runtime!("test_execution_context: no current context"))?;
+
+	// Both read the same context, so they have to agree
+	let frame = debug::CallStacks::new().active.remove(0);
+
+	if ctx.proc().id != frame.proc.id || ctx.offset() != frame.offset as u32 {
+		return Err(runtime!(
+			"test_execution_context: context is {:?} at {}, frame is {:?} at {}",
+			ctx.proc(),
+			ctx.offset(),
+			frame.proc,
+			frame.offset
+		));
+	}
+
+	if ctx.src() != frame.src || ctx.usr() != frame.usr || ctx.dot() != frame.dot {
+		return Err(runtime!(
+			"test_execution_context: src, usr or . differ from the frame"
+		));
+	}
+
+	let locals: Vec<String> = ctx.locals().into_iter().map(|(name, _)| name).collect();
+	let frame_locals: Vec<String> = frame
+		.locals
+		.iter()
+		.map(|(name, _)| String::from(name))
+		.collect();
+
+	if locals != frame_locals {
+		return Err(runtime!(
+			"test_execution_context: locals are {:?}, frame has {:?}",
+			locals,
+			frame_locals
+		));
+	}
+
+	Ok(Value::from(true))
+}
@@ -0,0 +1,5 @@
+use auxtools::*;
+
+byond_ffi_fn! { auxtest_ffi_repeat(count: u32, text: &str) -> String {
+	text.repeat(count as usize)
+} }
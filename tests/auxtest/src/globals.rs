@@ -0,0 +1,44 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_globals")]
+fn test_globals() {
+	let vars = Value::global_vars();
+
+	let mut found = false;
+	for i in 1..=vars.len() {
+		if vars.get(i)?.as_string()? == "auxtest_global" {
+			found = true;
+			break;
+		}
+	}
+
+	if !found {
+		return Err(runtime!("test_globals: auxtest_global not in global_vars"));
+	}
+
+	if Value::globals().get_number(byond_string!("auxtest_global"))? != 42.0 {
+		return Err(runtime!("test_globals: global.auxtest_global != 42"));
+	}
+
+	if Value::world().get_number(byond_string!("maxx"))? < 0.0 {
+		return Err(runtime!("test_globals: world.maxx < 0"));
+	}
+
+	if Value::get_world_var("maxx")? != Value::world().get(byond_string!("maxx"))? {
+		return Err(runtime!("test_globals: get_world_var(maxx) != world.maxx"));
+	}
+
+	let name = Value::get_world_var("name")?;
+	Value::set_world_var("name", &Value::from_string("auxtest")?)?;
+	let new_name = Value::get_world_var("name")?.as_string()?;
+	Value::set_world_var("name", &name)?;
+
+	if new_name != "auxtest" {
+		return Err(runtime!(
+			"test_globals: world.name is {:?} after setting it to auxtest",
+			new_name
+		));
+	}
+
+	Ok(Value::from(true))
+}
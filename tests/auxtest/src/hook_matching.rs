@@ -0,0 +1,44 @@
+use auxtools::*;
+
+fn hooked(_: &Value, _: &Value, _: &mut Vec<Value>) -> DMResult {
+	Ok(Value::from(10))
+}
+
+fn call(path: &str) -> DMResult<f32> {
+	let proc = Proc::find(path).ok_or_else(|| runtime!("test_hook_matching: {} missing", path))?;
+	proc.call(&[])?.as_number()
+}
+
+#[hook("/proc/auxtest_hook_matching")]
+fn test_hook_matching() {
+	let paths = hooks::hook_matching("/proc/auxtest_matched_?", hooked, false);
+	if paths.len() != 2 {
+		return Err(runtime!("test_hook_matching: hooked {:?}", paths));
+	}
+
+	// Overlaps with the first pattern, so auxtest_matched_a shares its hook
+	let paths = hooks::hook_matching("/proc/auxtest_matched_a*", hooked, false);
+	if paths.len() != 1 {
+		return Err(runtime!("test_hook_matching: hooked {:?} again", paths));
+	}
+
+	if call("/proc/auxtest_matched_a")? != 10.0 || call("/proc/auxtest_matched_b")? != 10.0 {
+		return Err(runtime!("test_hook_matching: procs aren't hooked"));
+	}
+
+	let count = hooks::unhook_matching("/proc/auxtest_matched_?");
+	if count != 1 {
+		return Err(runtime!("test_hook_matching: unhooked {} procs", count));
+	}
+
+	if call("/proc/auxtest_matched_a")? != 10.0 || call("/proc/auxtest_matched_b")? != 2.0 {
+		return Err(runtime!("test_hook_matching: unhooked the wrong procs"));
+	}
+
+	hooks::unhook_matching("/proc/auxtest_matched_a*");
+	if call("/proc/auxtest_matched_a")? != 1.0 {
+		return Err(runtime!("test_hook_matching: still hooked after unhooking"));
+	}
+
+	Ok(Value::from(true))
+}
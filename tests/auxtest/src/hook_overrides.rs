@@ -0,0 +1,48 @@
+use auxtools::*;
+
+const PATH: &str = "/proc/auxtest_overridden";
+
+fn hooked(_: &Value, _: &Value, _: &mut Vec<Value>) -> DMResult {
+	Ok(Value::from(10))
+}
+
+#[hook("/proc/auxtest_hook_overrides")]
+fn test_hook_overrides() {
+	let count = hooks::hook_all_overrides(PATH, hooked)
+		.map_err(|e| runtime!("test_hook_overrides: hooking failed: {:?}", e))?;
+	if count != 2 {
+		return Err(runtime!("test_hook_overrides: hooked {} overrides", count));
+	}
+
+	if hooks::hook_all_overrides(PATH, hooked).is_ok() {
+		return Err(runtime!("test_hook_overrides: hooked the same procs twice"));
+	}
+
+	for id in 0..count as u32 {
+		let proc = Proc::find_override(PATH, id)
+			.ok_or_else(|| runtime!("test_hook_overrides: override {} missing", id))?;
+		if proc.call(&[])?.as_number()? != 10.0 {
+			return Err(runtime!(
+				"test_hook_overrides: override {} isn't hooked",
+				id
+			));
+		}
+	}
+
+	let count = hooks::unhook_all_overrides(PATH);
+	if count != 2 {
+		return Err(runtime!(
+			"test_hook_overrides: unhooked {} overrides",
+			count
+		));
+	}
+
+	let proc = Proc::find(PATH).ok_or_else(|| runtime!("test_hook_overrides: {} missing", PATH))?;
+	if proc.call(&[])?.as_number()? != 1.0 {
+		return Err(runtime!(
+			"test_hook_overrides: still hooked after unhooking"
+		));
+	}
+
+	Ok(Value::from(true))
+}
@@ -1,7 +1,30 @@
 use auxtools::*;
 
+mod c_api;
+mod call_original;
+mod call_stacks;
+mod current_location;
+mod defer;
+mod execution_context;
+mod ffi;
+mod globals;
+mod hook_matching;
+mod hook_overrides;
 mod lists;
+mod observers;
+mod proc_macro;
+mod procs;
+mod reboot;
+mod refcounts;
+mod runtimes;
+mod set_variables;
+mod sleeping;
+mod special_values;
+mod string_table;
 mod strings;
+mod to_json;
+mod truthiness;
+mod typed_values;
 mod value_from;
 
 #[hook("/proc/auxtest_inc_counter")]
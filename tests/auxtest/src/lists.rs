@@ -52,5 +52,90 @@ fn test_lists() {
 		}
 	}
 
+	// list("a" = 1, "b" = 2, 3)
+	let list_c = List::new();
+	list_c.set(byond_string!("a"), 1)?;
+	list_c.set(byond_string!("b"), 2)?;
+	list_c.append(3);
+
+	let keys = vec![
+		Value::from_string("a")?,
+		Value::from_string("b")?,
+		Value::from(3),
+	];
+	if list_c.keys()? != keys {
+		return Err(runtime!("test_lists: list_c has the wrong keys"));
+	}
+
+	// Without BYOND's list table, lists won't guess which of their elements are associated
+	if !cfg!(windows) {
+		if list_c.entries().is_ok() {
+			return Err(runtime!(
+				"test_lists: list_c's entries were read without the list table"
+			));
+		}
+
+		return Ok(Value::from(true));
+	}
+
+	// list_a is now list(101, 103, "key" = "value")
+	match list_a.entry(1)? {
+		ListEntry::Indexed(value) if value == Value::from(101) => {}
+		_ => return Err(runtime!("test_lists: list_a[1] isn't an indexed 101")),
+	}
+
+	match list_a.entry(3)? {
+		ListEntry::Assoc { value, .. } if value.as_string()? == "value" => {}
+		_ => {
+			return Err(runtime!(
+				"test_lists: list_a[3] isn't associated with \"value\""
+			))
+		}
+	}
+
+	if list_a.entries()?.len() != 3 {
+		return Err(runtime!(
+			"test_lists: list_a has the wrong number of entries"
+		));
+	}
+
+	// Numbers are never keys, even where they'd be valid indices of the list
+	for numbers in &[vec![1, 2, 5], vec![3, 1]] {
+		let list = List::new();
+		for n in numbers {
+			list.append(*n);
+		}
+
+		for (entry, n) in list.entries()?.into_iter().zip(numbers) {
+			match entry {
+				ListEntry::Indexed(value) if value == Value::from(*n) => {}
+				_ => return Err(runtime!("test_lists: {} isn't an indexed element", n)),
+			}
+		}
+	}
+
+	let values = vec![Value::from(1), Value::from(2), Value::null()];
+	if list_c.values()? != values {
+		return Err(runtime!("test_lists: list_c has the wrong values"));
+	}
+
+	// list(3, 1) has nothing associated, even though 1 and 3 could be read as indices
+	let list_d = List::new();
+	list_d.append(3);
+	list_d.append(1);
+
+	if list_d.values()? != vec![Value::null(), Value::null()] {
+		return Err(runtime!("test_lists: list_d has the wrong values"));
+	}
+
+	// list("a" = null) is still associated, the value just happens to be null
+	let list_e = List::new();
+	list_e.set(byond_string!("a"), Value::null())?;
+
+	match list_e.entry(1)? {
+		ListEntry::Assoc { value, .. } if value == Value::null() => {}
+		_ => return Err(runtime!("test_lists: list_e[1] isn't associated with null")),
+	}
+
 	Ok(Value::from(true))
 }
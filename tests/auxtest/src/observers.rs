@@ -0,0 +1,91 @@
+use auxtools::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+const PATH: &str = "/proc/auxtest_observed";
+
+static PRE_CALLS: AtomicU32 = AtomicU32::new(0);
+static LAST_RETURN: AtomicU32 = AtomicU32::new(0);
+
+fn count_call(_src: &Value, _usr: &Value, _args: &[Value]) {
+	PRE_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn record_return(_src: &Value, _usr: &Value, _args: &[Value], ret: &Value) {
+	let ret = ret.as_number().unwrap_or(-1.0);
+	LAST_RETURN.store(ret as u32, Ordering::Relaxed);
+}
+
+fn return_100(_src: &Value, _usr: &Value, _args: &mut Vec<Value>) -> DMResult {
+	Ok(Value::from(100))
+}
+
+// Calls the observed proc with 21 and checks what it and the observers saw
+fn check(proc: &Proc, expected: u32, expected_calls: u32) -> DMResult<()> {
+	let ret = proc.call(&[&Value::from(21)])?.as_number()?;
+	if ret as u32 != expected {
+		return Err(runtime!(
+			"test_observers: expected {}, got {}",
+			expected,
+			ret
+		));
+	}
+
+	if PRE_CALLS.load(Ordering::Relaxed) != expected_calls {
+		return Err(runtime!(
+			"test_observers: pre observer ran {} times instead of {}",
+			PRE_CALLS.load(Ordering::Relaxed),
+			expected_calls
+		));
+	}
+
+	if LAST_RETURN.load(Ordering::Relaxed) != expected {
+		return Err(runtime!(
+			"test_observers: post observer saw {} instead of {}",
+			LAST_RETURN.load(Ordering::Relaxed),
+			expected
+		));
+	}
+
+	Ok(())
+}
+
+#[hook("/proc/auxtest_observers")]
+fn test_observers() {
+	PRE_CALLS.store(0, Ordering::Relaxed);
+	LAST_RETURN.store(0, Ordering::Relaxed);
+
+	let proc = Proc::find(PATH).ok_or_else(|| runtime!("test_observers: {} missing", PATH))?;
+
+	let pre = hooks::observe(PATH, Some(count_call), None)
+		.map_err(|e| runtime!("test_observers: observing failed: {:?}", e))?;
+	let post = hooks::observe(PATH, None, Some(record_return))
+		.map_err(|e| runtime!("test_observers: observing failed: {:?}", e))?;
+
+	// The proc itself still runs
+	check(&proc, 42, 1)?;
+
+	// The observers see the hook's result instead
+	hooks::hook(PATH, return_100)
+		.map_err(|e| runtime!("test_observers: hooking failed: {:?}", e))?;
+	check(&proc, 100, 2)?;
+	hooks::unhook_all_overrides(PATH);
+
+	// Removing one observer leaves the other
+	if !hooks::unobserve(pre) || hooks::unobserve(pre) {
+		return Err(runtime!(
+			"test_observers: unobserve didn't remove the observer once"
+		));
+	}
+	check(&proc, 42, 2)?;
+
+	hooks::unobserve(post);
+	LAST_RETURN.store(0, Ordering::Relaxed);
+	proc.call(&[&Value::from(21)])?;
+	if LAST_RETURN.load(Ordering::Relaxed) != 0 {
+		return Err(runtime!(
+			"test_observers: post observer ran after unobserve"
+		));
+	}
+
+	Ok(Value::from(true))
+}
@@ -0,0 +1,14 @@
+use auxtools::*;
+
+proc! { hook "/proc/auxtest_proc_macro" fn test_proc_macro(_src: &Value, _usr: &Value, args: &mut Vec<Value>) -> DMResult {
+	if args.len() != 2 {
+		return Err(runtime!("proc_macro: expected 2 arguments, got {}", args.len()));
+	}
+
+	let sum = args[0].as_number()? + args[1].as_number()?;
+	if sum != 3.0 {
+		return Err(runtime!("proc_macro: expected 1 + 2 to be 3, got {}", sum));
+	}
+
+	Ok(Value::from(true))
+} }
@@ -0,0 +1,53 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_procs")]
+fn test_procs() {
+	let proc = Proc::find("/proc/concat_strings")
+		.ok_or_else(|| runtime!("test_procs: couldn't find /proc/concat_strings"))?;
+
+	let file = proc.source_file();
+	if file.as_deref() != Some("auxtest_host.dm") {
+		return Err(runtime!(
+			"test_procs: expected concat_strings to be in auxtest_host.dm, got {:?}",
+			file
+		));
+	}
+
+	if !proc::all().any(|p| p.id == proc.id) {
+		return Err(runtime!(
+			"test_procs: /proc/concat_strings missing from proc::all()"
+		));
+	}
+
+	if !proc::procs_matching("/proc/concat_").any(|p| p.id == proc.id) {
+		return Err(runtime!(
+			"test_procs: /proc/concat_strings missing from proc::procs_matching(\"/proc/concat_\")"
+		));
+	}
+
+	// Adding concat_strings to the table again would make it its own override
+	proc::refresh();
+	if proc::get_proc_override("/proc/concat_strings", 1).is_some() {
+		return Err(runtime!(
+			"test_procs: proc::refresh() changed the procs without any being created"
+		));
+	}
+
+	let sum_args = Proc::find("/proc/sum_args")
+		.ok_or_else(|| runtime!("test_procs: couldn't find /proc/sum_args"))?;
+
+	// More arguments than any proc in the test world declares
+	let sum = sum_args.call_variadic((1..=50).map(Value::from).collect())?;
+	if sum.as_number()? != 1275.0 {
+		return Err(runtime!(
+			"test_procs: expected sum_args(1, ..., 50) to be 1275, got {:?}",
+			sum.as_number()
+		));
+	}
+
+	if sum_args.call_variadic(vec![])?.as_number()? != 0.0 {
+		return Err(runtime!("test_procs: expected sum_args() to be 0"));
+	}
+
+	Ok(Value::from(true))
+}
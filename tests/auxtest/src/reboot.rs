@@ -0,0 +1,19 @@
+use auxtools::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// The library stays loaded between reboots, so these carry over
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+static CALLBACK_RAN: AtomicBool = AtomicBool::new(false);
+
+#[hook("/proc/auxtest_reboot")]
+fn test_reboot() {
+	// The callback from the previous world should have run during this world's auxtools_init
+	if REGISTERED.swap(false, Ordering::SeqCst) && !CALLBACK_RAN.swap(false, Ordering::SeqCst) {
+		return Err(runtime!("reboot: on_reboot callback didn't run"));
+	}
+
+	runtime::on_reboot(|| CALLBACK_RAN.store(true, Ordering::SeqCst));
+	REGISTERED.store(true, Ordering::SeqCst);
+
+	Ok(Value::from(true))
+}
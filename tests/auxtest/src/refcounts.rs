@@ -0,0 +1,29 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_refcounts")]
+fn test_refcounts() {
+	if Value::from(1.0).refcount().is_some() {
+		return Err(runtime!(
+			"test_refcounts: numbers shouldn't have a refcount"
+		));
+	}
+
+	let string = Value::from_string("auxtest refcount string")?;
+	if string.refcount() != Some(1) {
+		return Err(runtime!("test_refcounts: new string's refcount != 1"));
+	}
+
+	let snapshot = debug::ref_snapshot(&[&string]);
+
+	let copy = string.clone();
+	if snapshot.changes().len() != 1 {
+		return Err(runtime!(
+			"test_refcounts: snapshot didn't notice a new reference"
+		));
+	}
+
+	drop(copy);
+	snapshot.check()?;
+
+	Ok(Value::from(true))
+}
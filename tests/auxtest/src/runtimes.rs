@@ -0,0 +1,40 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_runtimes")]
+fn test_runtimes() {
+	let err = runtime!("test_runtimes: {}", 42);
+
+	if err.message != "test_runtimes: 42" {
+		return Err(runtime!("test_runtimes: message was {:?}", err.message));
+	}
+
+	// We're being called from do_tests, so there's a proc to blame
+	let proc_path = match err.proc_path() {
+		Some(proc_path) => proc_path,
+		None => return Err(runtime!("test_runtimes: no proc_path captured")),
+	};
+
+	let display = err.to_string();
+	if !display.starts_with(&err.message) || !display.contains(&proc_path) {
+		return Err(runtime!("test_runtimes: bad display {:?}", display));
+	}
+
+	if err.override_id() != Some(0) || err.offset.is_none() {
+		return Err(runtime!(
+			"test_runtimes: override_id {:?}, offset {:?}",
+			err.override_id(),
+			err.offset
+		));
+	}
+
+	let byond = err.to_byond_string();
+	let expected_start = format!(
+		"runtime error: {}\nproc name: do_tests ({})",
+		err.message, proc_path
+	);
+	if !byond.starts_with(&expected_start) || !byond.contains("source file: auxtest_host.dm,") {
+		return Err(runtime!("test_runtimes: bad BYOND format {:?}", byond));
+	}
+
+	Ok(Value::from(true))
+}
@@ -0,0 +1,39 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_set_variables")]
+fn test_set_variables(datum: Value) {
+	datum.set(byond_string!("writable"), 5)?;
+	if datum.get_number(byond_string!("writable"))? != 5.0 {
+		return Err(runtime!("set_variables: writable != 5 after setting it"));
+	}
+
+	datum.set(byond_string!("writable"), String::from("text").as_str())?;
+	if datum.get_string(byond_string!("writable"))? != "text" {
+		return Err(runtime!("set_variables: writable != text after setting it"));
+	}
+
+	datum.set(byond_string!("writable"), &Value::null())?;
+	if datum.get(byond_string!("writable"))? != Value::null() {
+		return Err(runtime!("set_variables: writable != null after setting it"));
+	}
+
+	if datum.set(byond_string!("constant"), 3).is_ok() {
+		return Err(runtime!("set_variables: setting a const var succeeded"));
+	}
+
+	if datum.set(byond_string!("not_a_var"), 3).is_ok() {
+		return Err(runtime!("set_variables: setting a missing var succeeded"));
+	}
+
+	if Value::null().set(byond_string!("writable"), 3).is_ok() {
+		return Err(runtime!("set_variables: setting a var on null succeeded"));
+	}
+
+	if Value::from(1).set(byond_string!("writable"), 3).is_ok() {
+		return Err(runtime!(
+			"set_variables: setting a var on a number succeeded"
+		));
+	}
+
+	Ok(Value::from(true))
+}
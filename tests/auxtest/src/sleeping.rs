@@ -0,0 +1,44 @@
+use auxtools::*;
+
+// do_tests starts /proc/auxtest_sleeper right before calling this, which sleeps for 5 seconds
+#[hook("/proc/auxtest_sleeping")]
+fn test_sleeping() {
+	let now = Value::world().get_number(byond_string!("time"))?;
+
+	let sleeper = debug::CallStacks::all_sleeping()
+		.into_iter()
+		.find(|stack| stack.proc == "/auxtest_sleeper")
+		.ok_or_else(|| runtime!("test_sleeping: auxtest_sleeper isn't sleeping"))?;
+
+	if sleeper.wake_time < now + 50.0 {
+		return Err(runtime!(
+			"test_sleeping: wakes up at {}, but it's {} and it sleeps for 50",
+			sleeper.wake_time,
+			now
+		));
+	}
+
+	let frame = sleeper
+		.frames
+		.iter()
+		.find(|frame| frame.time_to_resume.is_some())
+		.ok_or_else(|| runtime!("test_sleeping: no frame has a wake time"))?;
+
+	if frame.proc.path != "/auxtest_sleeper" {
+		return Err(runtime!(
+			"test_sleeping: {} has the wake time",
+			frame.proc.path
+		));
+	}
+
+	let suspended = debug::CallStacks::new().suspended;
+	if !suspended.iter().any(|stack| {
+		stack
+			.iter()
+			.any(|frame| frame.proc.path == "/auxtest_sleeper")
+	}) {
+		return Err(runtime!("test_sleeping: auxtest_sleeper isn't suspended"));
+	}
+
+	Ok(Value::from(true))
+}
@@ -0,0 +1,31 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_special_values")]
+fn test_special_values(matrix: Value, icon: Value, color: Value) {
+	match matrix.format_special() {
+		Some(s) if s == "matrix(2,0,0, 0,1,3)" => {}
+		s => return Err(runtime!("special_values: matrix formatted as {:?}", s)),
+	}
+
+	match icon.format_special() {
+		Some(s) if s == "icon()" => {}
+		s => return Err(runtime!("special_values: icon formatted as {:?}", s)),
+	}
+
+	match color.format_special() {
+		Some(s) if s == "\"#ff8000\" (rgb(255,128,0))" => {}
+		s => return Err(runtime!("special_values: color formatted as {:?}", s)),
+	}
+
+	if Value::from(1.0).format_special().is_some()
+		|| Value::from_string("not a color")?
+			.format_special()
+			.is_some()
+	{
+		return Err(runtime!(
+			"special_values: plain value was formatted specially"
+		));
+	}
+
+	Ok(Value::from(true))
+}
@@ -0,0 +1,54 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_string_table")]
+fn test_string_table() {
+	let id = string_table::intern("auxtest string table entry")?;
+
+	if string_table::get_by_id(id).as_deref() != Some("auxtest string table entry") {
+		return Err(runtime!(
+			"test_string_table: get_by_id didn't return the interned string"
+		));
+	}
+
+	if string_table::intern("auxtest string table entry")? != id {
+		return Err(runtime!(
+			"test_string_table: interning twice gave different ids"
+		));
+	}
+
+	let string = StringRef::new("auxtest string table entry")?;
+	if string.get_id().0 != id {
+		return Err(runtime!(
+			"test_string_table: StringRef::new gave a different id"
+		));
+	}
+
+	if !string_table::iter()
+		.any(|(iter_id, value)| iter_id == id && value == "auxtest string table entry")
+	{
+		return Err(runtime!(
+			"test_string_table: iter didn't include the interned string"
+		));
+	}
+
+	// Interning gives the string a reference of its own
+	if !matches!(string_table::ref_count(id), Some(count) if count >= 1) {
+		return Err(runtime!(
+			"test_string_table: ref_count didn't count the interned string's reference"
+		));
+	}
+
+	if string_table::capacity() <= id {
+		return Err(runtime!(
+			"test_string_table: capacity is smaller than an id in use"
+		));
+	}
+
+	if string_table::get_by_id(u32::MAX).is_some() || string_table::ref_count(u32::MAX).is_some() {
+		return Err(runtime!(
+			"test_string_table: an out of range id was accepted"
+		));
+	}
+
+	Ok(Value::from(true))
+}
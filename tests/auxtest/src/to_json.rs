@@ -0,0 +1,27 @@
+use auxtools::*;
+use serde_json::json;
+
+#[hook("/proc/auxtest_to_json")]
+fn test_to_json(datum: Value) {
+	let value = datum.to_json(3);
+
+	if value["number"] != json!(1.0) || value["text"] != json!("a") {
+		return Err(runtime!("test_to_json: vars are {}", value));
+	}
+
+	if value["items"] != json!([1.0, "b"]) || value["assoc"] != json!({ "k": 2.0 }) {
+		return Err(runtime!("test_to_json: lists are {}", value));
+	}
+
+	// `other` points back at us, so we must be cut short there
+	if !value["other"]["other"]["tag"].is_string() {
+		return Err(runtime!("test_to_json: cycle gave {}", value["other"]));
+	}
+
+	let shallow = datum.to_json(0);
+	if !shallow["tag"].is_string() || !shallow["text"].is_string() {
+		return Err(runtime!("test_to_json: depth 0 gave {}", shallow));
+	}
+
+	Ok(Value::from(true))
+}
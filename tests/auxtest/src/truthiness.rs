@@ -0,0 +1,34 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_truthiness")]
+fn test_truthiness(datum: Value) {
+	let falsy = [
+		("null", Value::null()),
+		("0", Value::from(0)),
+		("-0", Value::from(-0.0)),
+		("\"\"", Value::from_string("")?),
+	];
+
+	for (name, value) in &falsy {
+		if value.is_truthy() {
+			return Err(runtime!("test_truthiness: {} is truthy", name));
+		}
+	}
+
+	let truthy = [
+		("1", Value::from(1)),
+		("-0.5", Value::from(-0.5)),
+		("\"0\"", Value::from_string("0")?),
+		("\" \"", Value::from_string(" ")?),
+		("list()", Value::from(List::new())),
+		("a datum", datum.clone()),
+	];
+
+	for (name, value) in &truthy {
+		if !value.is_truthy() {
+			return Err(runtime!("test_truthiness: {} isn't truthy", name));
+		}
+	}
+
+	Ok(Value::from(true))
+}
@@ -0,0 +1,36 @@
+use auxtools::*;
+
+#[hook("/proc/auxtest_typed_values")]
+fn test_typed_values() {
+	let number = Value::from(1.5);
+	match number.as_number_value() {
+		Some(number) if number.get_float() == 1.5 => {}
+		_ => return Err(runtime!("test_typed_values: as_number_value failed on 1.5")),
+	}
+
+	if number.as_string_value().is_some() || number.as_datum().is_some() {
+		return Err(runtime!(
+			"test_typed_values: number converted to the wrong type"
+		));
+	}
+
+	let string = Value::from_string("typed value string")?;
+	match string.as_string_value() {
+		Some(string) if string.as_str() == "typed value string" => {}
+		_ => return Err(runtime!("test_typed_values: as_string_value failed")),
+	}
+
+	let list = Value::from(List::new());
+	match list.as_list_value() {
+		Some(list) if list.list().len() == 0 => {}
+		_ => return Err(runtime!("test_typed_values: as_list_value failed")),
+	}
+
+	if Value::null().as_mob().is_some() || list.as_turf().is_some() {
+		return Err(runtime!(
+			"test_typed_values: value converted to the wrong type"
+		));
+	}
+
+	Ok(Value::from(true))
+}
@@ -42,6 +42,48 @@ fn test_value_from() {
 	let value = Value::try_from(&hashmap)?;
 	assert_meow_equals_one(value)?;
 
+	/* Round trips */
+	let value = Value::from(3.5);
+	if f32::try_from(&value)? != 3.5 {
+		return Err(runtime!("value_from: f32 did not survive a round trip"));
+	}
+
+	let value = Value::from(-7);
+	if f32::try_from(&value)? != -7.0 {
+		return Err(runtime!("value_from: i32 did not survive a round trip"));
+	}
+
+	let value = Value::from("meow");
+	if String::try_from(&value)? != "meow" {
+		return Err(runtime!("value_from: &str did not survive a round trip"));
+	}
+
+	let value = Value::from("me\0ow\0");
+	if String::try_from(&value)? != "meow" {
+		return Err(runtime!("value_from: &str kept its NUL bytes"));
+	}
+
+	if !bool::try_from(&Value::from(true))? || bool::try_from(&Value::from(false))? {
+		return Err(runtime!("value_from: bool did not survive a round trip"));
+	}
+
+	let value = Value::from(());
+	if value != Value::null() {
+		return Err(runtime!("value_from: () did not become null"));
+	}
+
+	if bool::try_from(&value)? {
+		return Err(runtime!("value_from: null became true"));
+	}
+
+	if f32::try_from(&value).is_ok() || String::try_from(&value).is_ok() {
+		return Err(runtime!("value_from: null converted to a number or string"));
+	}
+
+	if bool::try_from(&Value::from("meow")).is_ok() {
+		return Err(runtime!("value_from: string converted to a bool"));
+	}
+
 	/* Todo: Other stuff */
 
 	Ok(Value::from(true))